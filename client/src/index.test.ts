@@ -24,3 +24,33 @@ test("Side effects", () => {
   handle["id"] = 1919810;
   expect(listener.modified).toBe(true);
 });
+
+test("Patch value", () => {
+  const poca = new Poca("localhost:1145");
+  const handle = poca.reactive_with_default("todo", {
+    title: "write tests",
+    tags: ["work"],
+  });
+  poca.patch("todo", [
+    { op: "add", path: "/tags/-", value: "urgent" },
+    { op: "replace", path: "/title", value: "write more tests" },
+  ]);
+  expect(handle["title"]).toBe("write more tests");
+  expect(handle["tags"]).toEqual(["work", "urgent"]);
+});
+
+test("Apply patches from the server", () => {
+  const poca = new Poca("localhost:1145");
+  const handle = poca.reactive_with_default("point", { x: 1, y: 2 });
+  // Patch messages as the server sends them
+  (poca as any).handle_message({
+    message_type: 9,
+    key: "point",
+    data: JSON.stringify([
+      { op: "remove", path: "/y" },
+      { op: "copy", from: "/x", path: "/z" },
+    ]),
+    version: 1,
+  });
+  expect(handle).toEqual({ x: 1, z: 1 });
+});
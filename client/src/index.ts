@@ -3,6 +3,23 @@ enum WSMessageType {
   Emit = 2,
   Get = 3,
   Error = 4,
+  Subscribe = 5,
+  Unsubscribe = 6,
+  Snapshot = 7,
+  MergePatch = 8,
+  Patch = 9,
+  Increment = 10,
+  TextOps = 11,
+  CompareAndSet = 12,
+  Batch = 13,
+  Remove = 14,
+  Keys = 15,
+  Event = 16,
+  Request = 17,
+  Response = 18,
+  Ack = 19,
+  Schema = 20,
+  Checksums = 21,
 }
 
 export enum ConnectionState {
@@ -10,23 +27,211 @@ export enum ConnectionState {
   Down,
 }
 
+interface CharId {
+  counter: number;
+  site: number;
+}
+
+type TextOp =
+  | {op: "insert"; id: CharId; after: CharId | null; ch: string}
+  | {op: "delete"; id: CharId};
+
+// mirrors the RGA in server/src/text.rs
+interface Text {
+  chars: {id: CharId; ch: string; deleted: boolean}[];
+  clock: number;
+}
+
+function compare_ids(a: CharId, b: CharId): number {
+  return a.counter != b.counter ? a.counter - b.counter : a.site - b.site;
+}
+
+function same_id(a: CharId, b: CharId): boolean {
+  return a.counter == b.counter && a.site == b.site;
+}
+
+function apply_text_op(text: Text, op: TextOp) {
+  if (op.op == "delete") {
+    const target = text.chars.find((each) => same_id(each.id, op.id));
+    if (target) target.deleted = true;
+    return;
+  }
+  if (text.chars.some((each) => same_id(each.id, op.id))) return;
+  let position = 0;
+  if (op.after) {
+    const after = op.after;
+    position = text.chars.findIndex((each) => same_id(each.id, after)) + 1;
+  }
+  while (
+    position < text.chars.length &&
+    compare_ids(text.chars[position].id, op.id) > 0
+  ) {
+    position++;
+  }
+  text.chars.splice(position, 0, {id: op.id, ch: op.ch, deleted: false});
+  text.clock = Math.max(text.clock, op.id.counter);
+}
+
+export type PatchOp =
+  | {op: "add" | "replace" | "test"; path: string; value: any}
+  | {op: "remove"; path: string}
+  | {op: "move" | "copy"; from: string; path: string};
+
+// a single reference token of a JSON Pointer, mirrors server/src/patch.rs
+export function pointer(token: string): string {
+  return "/" + token.replace(/~/g, "~0").replace(/\//g, "~1");
+}
+
+function tokens(path: string): string[] {
+  return path
+    .split("/")
+    .slice(1)
+    .map((token) => token.replace(/~1/g, "/").replace(/~0/g, "~"));
+}
+
+function walk(value: any, path: string[]): any {
+  return path.reduce((current, token) => {
+    if (current === null || typeof current != "object" || !(token in current)) {
+      throw "Path /" + path.join("/") + " does not exist";
+    }
+    return current[token];
+  }, value);
+}
+
+function patch_add(value: any, path: string[], item: any): any {
+  if (path.length == 0) return item;
+  const parent = walk(value, path.slice(0, -1));
+  const last = path[path.length - 1];
+  if (Array.isArray(parent)) {
+    parent.splice(last == "-" ? parent.length : Number(last), 0, item);
+  } else {
+    parent[last] = item;
+  }
+  return value;
+}
+
+// the rest of the value and the removed part
+function patch_remove(value: any, path: string[]): [any, any] {
+  if (path.length == 0) return [undefined, value];
+  const parent = walk(value, path.slice(0, -1));
+  const last = path[path.length - 1];
+  const removed = walk(parent, [last]);
+  if (Array.isArray(parent)) {
+    parent.splice(Number(last), 1);
+  } else {
+    delete parent[last];
+  }
+  return [value, removed];
+}
+
+// RFC 6902, the value is changed in place unless an op replaces all of it
+function apply_patch(value: any, ops: PatchOp[]): any {
+  const clone = (item: any) => JSON.parse(JSON.stringify(item));
+  for (const op of ops) {
+    const path = tokens(op.path);
+    switch (op.op) {
+      case "add":
+        value = patch_add(value, path, clone(op.value));
+        break;
+      case "remove":
+        value = patch_remove(value, path)[0];
+        break;
+      case "replace":
+        value = patch_add(patch_remove(value, path)[0], path, clone(op.value));
+        break;
+      case "move":
+        const [rest, moved] = patch_remove(value, tokens(op.from));
+        value = patch_add(rest, path, moved);
+        break;
+      case "copy":
+        value = patch_add(value, path, clone(walk(value, tokens(op.from))));
+        break;
+      case "test":
+        if (canonical(walk(value, path)) != canonical(op.value)) {
+          throw "Test failed at " + op.path;
+        }
+        break;
+    }
+  }
+  return value;
+}
+
+// mirrors server/src/checksum.rs
+function canonical(value: any): string {
+  if (Array.isArray(value)) {
+    return "[" + value.map(canonical).join(",") + "]";
+  }
+  if (value !== null && typeof value == "object") {
+    const fields = Object.keys(value)
+      .sort()
+      .map((key) => JSON.stringify(key) + ":" + canonical(value[key]));
+    return "{" + fields.join(",") + "}";
+  }
+  return JSON.stringify(value);
+}
+
+function checksum(value: any): string {
+  let hash = 0x811c9dc5;
+  new TextEncoder().encode(canonical(value)).forEach((byte) => {
+    hash = Math.imul(hash ^ byte, 0x01000193);
+  });
+  return ("0000000" + (hash >>> 0).toString(16)).slice(-8);
+}
+
+// mirrors the subprotocols in server/src/encoding.rs
+const JSON_SUBPROTOCOL = "poca.json";
+const DEFLATE_SUBPROTOCOL = "poca.json+deflate";
+
+// Not permessage-deflate: binary frames of the deflate subprotocol
+// hold the raw DEFLATE stream of a JSON message.
+async function inflate(data: ArrayBuffer): Promise<string> {
+  const stream = new Blob([data])
+    .stream()
+    .pipeThrough(new (globalThis as any).DecompressionStream("deflate-raw"));
+  return new Response(stream).text();
+}
+
 interface WSMessage {
   message_type: WSMessageType;
   key?: string;
   data?: string;
+  // lets the server detect writes based on an outdated value
+  version?: number;
+  // correlates a request with its response
+  id?: number;
+  // lets a reconnecting client receive only the messages it missed
+  seq?: number;
+  // the server waits for the client to acknowledge the message
+  ack?: boolean;
 }
 
 export class Poca {
   private identifier!: symbol;
   private ws?: WebSocket;
   private raw: {[key: string]: any} = {};
+  private versions: {[key: string]: number} = {};
+  private last_seq?: number;
+  private event_listeners: {[event: string]: ((payload: any) => void)[]} = {};
+  private next_request_id = 0;
+  private pending_requests: {
+    [id: number]: {resolve: (value: any) => void; reject: (reason: string) => void};
+  } = {};
+  private request_handlers: {[method: string]: (request: any) => Promise<any>} = {};
+  private keys_queue: ((keys: {[key: string]: string}) => void)[] = [];
+  private schema_queue: ((schema: any) => void)[] = [];
   private work_pool: string[] = [];
   private get_queue: {
     [key: string]: ((value: string | PromiseLike<string>) => void)[];
   } = {};
   state: ConnectionState = ConnectionState.Down;
+  // frames are handled in order, even while an earlier one is being inflated
+  private inbound: Promise<void> = Promise.resolve();
+  // identifies this client's text edits, 0 is the server
+  private site: number = 1 + Math.floor(Math.random() * (Number.MAX_SAFE_INTEGER - 1));
 
-  constructor(readonly addr: string) {
+  // with compression the server sends large frames deflated,
+  // it falls back to plain JSON if it has no threshold configured
+  constructor(readonly addr: string, readonly compression: boolean = false) {
     this.identifier = Symbol();
     effect_callbacks[this.identifier] = {};
   }
@@ -35,29 +240,19 @@ export class Poca {
     let that = this;
     new Promise((resolve) => {
       that.ws?.close();
-      that.ws = new WebSocket("ws://" + this.addr);
+      const resume =
+        this.last_seq !== undefined ? "?resume=" + this.last_seq : "";
+      // servers accept connections on /ws by default
+      const path = this.addr.includes("/") ? "" : "/ws";
+      that.ws = new WebSocket(
+        "ws://" + this.addr + path + resume,
+        this.compression ? [DEFLATE_SUBPROTOCOL, JSON_SUBPROTOCOL] : []
+      );
+      that.ws.binaryType = "arraybuffer";
       that.ws.onopen = () => {
         that.state = ConnectionState.Up;
-        that.ws!.onmessage = (event: MessageEvent<any>) => {
-          const message: WSMessage = JSON.parse(event.data);
-          switch (message.message_type) {
-            case WSMessageType.Get:
-              if (this.get_queue[message.key!].length > 0) {
-                this.get_queue[message.key!].shift()?.(message.data!);
-              }
-              break;
-            case WSMessageType.Set:
-              this.raw[message.key!] = JSON.parse(message.data!);
-              //only call callbacks if values are different
-              //or should I
-              effect_callbacks[this.identifier][message.key!]?.forEach(
-                (callback) => callback()
-              );
-              break;
-            default:
-              console.log("Unimplemented message: " + message);
-          }
-        };
+        that.ws!.onmessage = (event: MessageEvent<any>) =>
+          this.receive(event.data);
         that.work_pool.forEach((key) => {
           let message: WSMessage = {
             message_type: WSMessageType.Get,
@@ -71,6 +266,140 @@ export class Poca {
     });
   }
 
+  private receive(data: string | ArrayBuffer) {
+    this.inbound = this.inbound
+      .then(async () => {
+        const text = typeof data == "string" ? data : await inflate(data);
+        this.handle_message(JSON.parse(text));
+      })
+      .catch((error) => console.error("Failed to read frame: " + error));
+  }
+
+  private handle_message(message: WSMessage) {
+    if (message.seq !== undefined) {
+      // retransmitted messages keep their original number
+      this.last_seq = Math.max(this.last_seq ?? 0, message.seq);
+      if (message.ack) {
+        const ack: WSMessage = {message_type: WSMessageType.Ack, seq: message.seq};
+        this.ws?.send(JSON.stringify(ack));
+      }
+    }
+    if (message.key && message.version !== undefined) {
+      this.versions[message.key] = message.version;
+    }
+    switch (message.message_type) {
+      case WSMessageType.Get:
+        if (this.get_queue[message.key!]?.length > 0) {
+          this.get_queue[message.key!].shift()?.(message.data!);
+          break;
+        }
+        // asked for after a checksum mismatch
+        this.raw[message.key!] = JSON.parse(JSON.parse(message.data!));
+        effect_callbacks[this.identifier][message.key!]?.forEach(
+          (callback) => callback()
+        );
+        break;
+      case WSMessageType.Checksums:
+        const checksums: {[key: string]: string} = JSON.parse(message.data!);
+        for (const key in checksums) {
+          if (key in this.raw && checksum(this.raw[key]) == checksums[key]) continue;
+          const get: WSMessage = {message_type: WSMessageType.Get, key};
+          this.ws?.send(JSON.stringify(get));
+        }
+        break;
+      case WSMessageType.Set:
+        this.raw[message.key!] = JSON.parse(message.data!);
+        //only call callbacks if values are different
+        //or should I
+        effect_callbacks[this.identifier][message.key!]?.forEach(
+          (callback) => callback()
+        );
+        break;
+      case WSMessageType.MergePatch:
+        Object.assign(this.raw[message.key!], JSON.parse(message.data!));
+        effect_callbacks[this.identifier][message.key!]?.forEach(
+          (callback) => callback()
+        );
+        break;
+      case WSMessageType.Patch:
+        this.raw[message.key!] = apply_patch(
+          this.raw[message.key!],
+          JSON.parse(message.data!)
+        );
+        effect_callbacks[this.identifier][message.key!]?.forEach(
+          (callback) => callback()
+        );
+        break;
+      case WSMessageType.Increment:
+        this.raw[message.key!] += Number(message.data!);
+        effect_callbacks[this.identifier][message.key!]?.forEach(
+          (callback) => callback()
+        );
+        break;
+      case WSMessageType.TextOps:
+        const ops: TextOp[] = JSON.parse(message.data!);
+        ops.forEach((op) => apply_text_op(this.raw[message.key!], op));
+        effect_callbacks[this.identifier][message.key!]?.forEach(
+          (callback) => callback()
+        );
+        break;
+      case WSMessageType.Response:
+        this.pending_requests[message.id!]?.resolve(JSON.parse(message.data!));
+        delete this.pending_requests[message.id!];
+        break;
+      case WSMessageType.Request:
+        this.answer_request(message);
+        break;
+      case WSMessageType.Error:
+        if (message.id !== undefined && this.pending_requests[message.id]) {
+          this.pending_requests[message.id].reject(message.data!);
+          delete this.pending_requests[message.id];
+          break;
+        }
+        console.error(
+          "Server rejected message" +
+            (message.key ? " for key " + message.key : "") +
+            ": " +
+            message.data
+        );
+        break;
+      case WSMessageType.Snapshot:
+        const snapshot: {[key: string]: any} = JSON.parse(message.data!);
+        for (const key in snapshot) {
+          this.raw[key] = snapshot[key];
+          effect_callbacks[this.identifier][key]?.forEach((callback) =>
+            callback()
+          );
+        }
+        break;
+      case WSMessageType.Remove:
+        delete this.raw[message.key!];
+        delete this.versions[message.key!];
+        effect_callbacks[this.identifier][message.key!]?.forEach((callback) =>
+          callback()
+        );
+        break;
+      case WSMessageType.Keys:
+        this.keys_queue.shift()?.(JSON.parse(message.data!));
+        break;
+      case WSMessageType.Schema:
+        this.schema_queue.shift()?.(JSON.parse(message.data!));
+        break;
+      case WSMessageType.Event:
+        const payload = JSON.parse(message.data!);
+        this.event_listeners[message.key!]?.forEach((listener) =>
+          listener(payload)
+        );
+        break;
+      case WSMessageType.Batch:
+        const batch: WSMessage[] = JSON.parse(message.data!);
+        batch.forEach((each) => this.handle_message(each));
+        break;
+      default:
+        console.log("Unimplemented message: " + message);
+    }
+  }
+
   close() {
     this.ws?.close();
     this.state = ConnectionState.Down;
@@ -99,11 +428,14 @@ export class Poca {
       message_type: WSMessageType.Set,
       key,
       data: value,
+      version: this.versions[key],
     };
     this.ws?.send(JSON.stringify(message));
   }
 
-  async reactive<T extends Object, K extends keyof T>(key: string): Promise<T> {
+  async reactive<T extends Object, K extends keyof T = keyof T>(
+    key: string
+  ): Promise<T> {
     const that = this;
     const data = await this.get_data(key);
     const value: T = JSON.parse(JSON.parse(data));
@@ -118,11 +450,7 @@ export class Poca {
           return that.raw[key][prop as K];
         },
         set(target, prop, value) {
-          target[prop as K] = value;
-          that.set_data(key, JSON.stringify(target));
-          effect_callbacks[that.identifier][key].forEach((callback) =>
-            callback()
-          );
+          that.set_field(key, target, prop, value);
           return true;
         },
       });
@@ -130,7 +458,7 @@ export class Poca {
     });
   }
 
-  reactive_with_default<T extends Object, K extends keyof T>(
+  reactive_with_default<T extends Object, K extends keyof T = keyof T>(
     key: string,
     initial_value: T
   ): T {
@@ -146,21 +474,184 @@ export class Poca {
         return target[prop as K];
       },
       set(target, prop, value) {
-        target[prop as K] = value;
-        that.set_data(key, JSON.stringify(target));
-        effect_callbacks[that.identifier][key].forEach((callback) =>
-          callback()
-        );
+        that.set_field(key, target, prop, value);
         return true;
       },
     });
     return result;
   }
 
-  emit(key: string) {
+  // objects only send the changed field, arrays are sent whole
+  private set_field(key: string, target: any, prop: string | symbol, value: any) {
+    if (Array.isArray(target) || typeof prop == "symbol") {
+      target[prop] = value;
+      this.set_data(key, JSON.stringify(target));
+      effect_callbacks[this.identifier][key].forEach((callback) => callback());
+      return;
+    }
+    // adding an existing field replaces it
+    this.patch(key, [{op: "add", path: pointer(prop), value}]);
+  }
+
+  // applied locally right away, the server answers with an Error if it fails there
+  patch(key: string, ops: PatchOp[]) {
+    this.raw[key] = apply_patch(this.raw[key], ops);
+    const message: WSMessage = {
+      message_type: WSMessageType.Patch,
+      key,
+      data: JSON.stringify(ops),
+      version: this.versions[key],
+    };
+    this.ws?.send(JSON.stringify(message));
+    effect_callbacks[this.identifier][key]?.forEach((callback) => callback());
+  }
+
+  // the server answers with a Set on success and an Error otherwise
+  compare_and_set<T>(key: string, expected: T, value: T) {
+    const message: WSMessage = {
+      message_type: WSMessageType.CompareAndSet,
+      key,
+      data: JSON.stringify({expected, new: value}),
+    };
+    this.ws?.send(JSON.stringify(message));
+  }
+
+  // registered keys and the names of their types on the server
+  async keys(): Promise<{[key: string]: string}> {
+    const message: WSMessage = {
+      message_type: WSMessageType.Keys,
+    };
+    this.ws?.send(JSON.stringify(message));
+    return new Promise((resolve) => this.keys_queue.push(resolve));
+  }
+
+  // JSON Schema with a property for every key, to validate incoming data
+  async schema(): Promise<any> {
+    const message: WSMessage = {
+      message_type: WSMessageType.Schema,
+    };
+    this.ws?.send(JSON.stringify(message));
+    return new Promise((resolve) => this.schema_queue.push(resolve));
+  }
+
+  subscribe(key: string) {
+    const message: WSMessage = {
+      message_type: WSMessageType.Subscribe,
+      key,
+    };
+    this.ws?.send(JSON.stringify(message));
+  }
+
+  unsubscribe(key: string) {
+    const message: WSMessage = {
+      message_type: WSMessageType.Unsubscribe,
+      key,
+    };
+    this.ws?.send(JSON.stringify(message));
+  }
+
+  increment(key: string, by: number = 1) {
+    this.raw[key] = (this.raw[key] ?? 0) + by;
+    const message: WSMessage = {
+      message_type: WSMessageType.Increment,
+      key,
+      data: String(by),
+    };
+    this.ws?.send(JSON.stringify(message));
+    effect_callbacks[this.identifier][key]?.forEach((callback) => callback());
+  }
+
+  text_value(key: string): string {
+    const text: Text = this.raw[key];
+    return text.chars
+      .filter((each) => !each.deleted)
+      .map((each) => each.ch)
+      .join("");
+  }
+
+  text_insert(key: string, index: number, value: string) {
+    const text: Text = this.raw[key];
+    const visible = text.chars.filter((each) => !each.deleted);
+    let after = index > 0 ? visible[index - 1].id : null;
+    const ops: TextOp[] = [];
+    for (const ch of value) {
+      const id = {counter: text.clock + 1, site: this.site};
+      const op: TextOp = {op: "insert", id, after, ch};
+      apply_text_op(text, op);
+      ops.push(op);
+      after = id;
+    }
+    this.send_text_ops(key, ops);
+  }
+
+  text_delete(key: string, index: number, length: number) {
+    const text: Text = this.raw[key];
+    const ops: TextOp[] = text.chars
+      .filter((each) => !each.deleted)
+      .slice(index, index + length)
+      .map((each) => ({op: "delete", id: each.id}));
+    ops.forEach((op) => apply_text_op(text, op));
+    this.send_text_ops(key, ops);
+  }
+
+  private send_text_ops(key: string, ops: TextOp[]) {
+    const message: WSMessage = {
+      message_type: WSMessageType.TextOps,
+      key,
+      data: JSON.stringify(ops),
+    };
+    this.ws?.send(JSON.stringify(message));
+    effect_callbacks[this.identifier][key]?.forEach((callback) => callback());
+  }
+
+  // resolves with the response of the server's `on_request` handler
+  request(method: string, payload?: any): Promise<any> {
+    const id = ++this.next_request_id;
+    const message: WSMessage = {
+      message_type: WSMessageType.Request,
+      key: method,
+      data: payload === undefined ? undefined : JSON.stringify(payload),
+      id,
+    };
+    this.ws?.send(JSON.stringify(message));
+    return new Promise((resolve, reject) => {
+      this.pending_requests[id] = {resolve, reject};
+    });
+  }
+
+  // answers calls made by the server, a thrown error is sent back as the reason
+  handle(method: string, handler: (request: any) => Promise<any>) {
+    this.request_handlers[method] = handler;
+  }
+
+  private async answer_request(request: WSMessage) {
+    const reply: WSMessage = {
+      message_type: WSMessageType.Response,
+      id: request.id,
+    };
+    try {
+      const handler = this.request_handlers[request.key!];
+      if (!handler) throw "Method " + request.key + " does not exist";
+      reply.data = JSON.stringify(await handler(JSON.parse(request.data!)));
+    } catch (error) {
+      reply.message_type = WSMessageType.Error;
+      reply.data = String(error);
+    }
+    this.ws?.send(JSON.stringify(reply));
+  }
+
+  // events emitted by the server, they are not part of the synced data
+  on(event: string, listener: (payload: any) => void) {
+    this.event_listeners[event] = this.event_listeners[event] || [];
+    this.event_listeners[event].push(listener);
+  }
+
+  // the payload is handed to the server's `on_event` handlers
+  emit(key: string, payload?: any) {
     const message: WSMessage = {
       message_type: WSMessageType.Emit,
-      key
+      key,
+      data: payload === undefined ? undefined : JSON.stringify(payload),
     };
     this.ws?.send(JSON.stringify(message));
   }
@@ -38,6 +38,6 @@ async fn main() {
         }
     });
     POCA.event("close", || POCA.stop());
-    POCA.start().await;
+    POCA.start().await.expect("Failed to start server");
     POCA.show_window();
 }
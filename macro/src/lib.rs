@@ -6,17 +6,69 @@ use std::{
 
 use proc_macro2::{Span, TokenStream};
 use quote::{quote, quote_spanned};
+use syn::{
+    parse_macro_input, parse_quote, Attribute, DeriveInput, Error, Lit, Meta, MetaNameValue,
+    NestedMeta,
+};
+
+// Synchronizable is implemented for every serde-compatible type already,
+// so the derive implements a marker trait that requires it instead,
+// turning a missing bound into an error pointing at the type.
+// The path of poca can be changed with #[synchronizable(crate = "...")].
+#[proc_macro_derive(Synchronizable, attributes(synchronizable))]
+pub fn derive_synchronizable(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(item as DeriveInput);
+    let krate = match crate_path(&input.attrs) {
+        Ok(krate) => krate,
+        Err(error) => return error.to_compile_error().into(),
+    };
+    let name = &input.ident;
+    input
+        .generics
+        .make_where_clause()
+        .predicates
+        .push(parse_quote!(Self: #krate::Synchronizable));
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    quote_spanned! {name.span()=>
+        impl #impl_generics #krate::_DeriveSynchronizable for #name #type_generics #where_clause {}
+    }
+    .into()
+}
+
+fn crate_path(attrs: &[Attribute]) -> syn::Result<syn::Path> {
+    let mut krate = parse_quote!(::poca);
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("synchronizable")) {
+        let nested = match attr.parse_meta()? {
+            Meta::List(list) => list.nested,
+            other => return Err(Error::new_spanned(other, EXPECTED_CRATE)),
+        };
+        for nested in nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    path,
+                    lit: Lit::Str(value),
+                    ..
+                })) if path.is_ident("crate") => krate = value.parse()?,
+                other => return Err(Error::new_spanned(other, EXPECTED_CRATE)),
+            }
+        }
+    }
+    Ok(krate)
+}
+
+const EXPECTED_CRATE: &str = "expected `synchronizable(crate = \"...\")`";
 
 #[proc_macro]
 pub fn include_app_dir(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = item.to_string();
     let input = input.split(",").collect::<Vec<&str>>();
 
-    let dir_input = input.get(0).expect("No path provided").trim_matches('\"');
+    let dir_input = input.first().expect("No path provided").trim_matches('\"');
     let path = Path::new(&dir_input);
     let project_root = env::var("CARGO_MANIFEST_DIR")
         .expect("Failed to resolve CARGO_MANIFEST_DIR environment variable");
-    let full_path = Path::new(&project_root).join(&path);
+    let full_path = Path::new(&project_root).join(path);
 
     let default_file_name = match input.len() {
         1 => {
@@ -48,7 +100,7 @@ pub fn include_app_dir(item: proc_macro::TokenStream) -> proc_macro::TokenStream
 fn process_file(path: PathBuf) -> TokenStream {
     let file_name = path
         .file_name()
-        .expect(format!("Failed to get filename for {:?}", &path).as_str())
+        .unwrap_or_else(|| panic!("Failed to get filename for {:?}", &path))
         .to_string_lossy()
         .to_string();
     let path = path.to_string_lossy().to_string();
@@ -61,7 +113,7 @@ fn process_file(path: PathBuf) -> TokenStream {
 fn process_directory(path: PathBuf, default_file_name: &Vec<&str>) -> TokenStream {
     let file_name = path
         .file_name()
-        .expect(format!("Failed to get filename for {:?}", &path).as_str())
+        .unwrap_or_else(|| panic!("Failed to get filename for {:?}", &path))
         .to_string_lossy()
         .to_string();
     let path = path.to_string_lossy().to_string();
@@ -72,24 +124,23 @@ fn process_directory(path: PathBuf, default_file_name: &Vec<&str>) -> TokenStrea
 
     let mut result = Vec::new();
 
-    for sub_entry in
-        read_dir(&path).expect(format!("Failed to read directory:{:?}", &path).as_str())
+    for sub_entry in read_dir(&path)
+        .unwrap_or_else(|_| panic!("Failed to read directory:{:?}", &path))
+        .flatten()
     {
-        if let Ok(sub_entry) = sub_entry {
-            let sub_file_name = sub_entry.file_name().to_string_lossy().to_string();
-            let sub_file_path = sub_entry.path();
-            let sub_file_path_string = sub_file_path.to_string_lossy().to_string();
-
-            if default_file_name.contains(&sub_file_name.as_str()) {
-                default_content = quote! {
-                    include_bytes!(#sub_file_path_string)
-                };
-            }
-            if sub_file_path.is_dir() {
-                result.push(process_directory(sub_file_path, default_file_name));
-            } else {
-                result.push(process_file(sub_file_path));
-            }
+        let sub_file_name = sub_entry.file_name().to_string_lossy().to_string();
+        let sub_file_path = sub_entry.path();
+        let sub_file_path_string = sub_file_path.to_string_lossy().to_string();
+
+        if default_file_name.contains(&sub_file_name.as_str()) {
+            default_content = quote! {
+                include_bytes!(#sub_file_path_string)
+            };
+        }
+        if sub_file_path.is_dir() {
+            result.push(process_directory(sub_file_path, default_file_name));
+        } else {
+            result.push(process_file(sub_file_path));
         }
     }
 
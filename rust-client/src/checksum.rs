@@ -0,0 +1,52 @@
+use serde_json::Value;
+
+// mirrors server/src/checksum.rs
+pub fn checksum(value: &Value) -> String {
+    let mut json = String::new();
+    canonical(value, &mut json);
+    let hash = json.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+    format!("{:08x}", hash)
+}
+
+// Keys sorted and integral floats written as integers, like `JSON.stringify`
+// of a parsed value. Values written differently anyway only cost a refetch.
+fn canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Number(number) => match number.as_f64() {
+            Some(float)
+                if number.is_f64() && float.fract() == 0.0 && float.abs() < 2f64.powi(53) =>
+            {
+                // without the sign of -0
+                out.push_str(&format!("{:.0}", float + 0.0));
+            }
+            _ => out.push_str(&number.to_string()),
+        },
+        Value::Array(values) => {
+            out.push('[');
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                canonical(value, out);
+            }
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            let mut fields = fields.iter().collect::<Vec<_>>();
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (index, (key, value)) in fields.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                canonical(value, out);
+            }
+            out.push('}');
+        }
+        value => out.push_str(&value.to_string()),
+    }
+}
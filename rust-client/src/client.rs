@@ -0,0 +1,289 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use parking_lot::{Mutex, RwLock};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::native::Connection;
+#[cfg(target_arch = "wasm32")]
+use crate::wasm::Connection;
+use crate::{
+    checksum::checksum,
+    data_handle::ClientDataHandle,
+    message::{WSMessage, WSMessageType},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type ChangeCallback = Arc<dyn Fn(&Value) + Send + Sync>;
+#[cfg(target_arch = "wasm32")]
+pub(crate) type ChangeCallback = Arc<dyn Fn(&Value)>;
+
+// identifies a callback of a key, see `ClientDataHandle::remove_on_change`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CallbackId(u64);
+
+impl CallbackId {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        CallbackId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+// answers a call of the server, see `PocaClient::handle`
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type RequestHandler = Arc<dyn Fn(Value) -> Result<String, String> + Send + Sync>;
+#[cfg(target_arch = "wasm32")]
+pub(crate) type RequestHandler = Arc<dyn Fn(Value) -> Result<String, String>>;
+
+// with the key and the reason of a message the server rejected
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type ErrorCallback = Arc<dyn Fn(Option<&str>, &str) + Send + Sync>;
+#[cfg(target_arch = "wasm32")]
+pub(crate) type ErrorCallback = Arc<dyn Fn(Option<&str>, &str)>;
+
+// Send + Sync natively, anything in the single-threaded browser, where
+// callbacks hold UI state
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSend: Send + Sync {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send + Sync> MaybeSend for T {}
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSend {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+pub(crate) const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+pub(crate) const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// what the client knows about the store, kept across reconnections
+#[derive(Default)]
+pub(crate) struct ClientState {
+    pub values: RwLock<HashMap<String, Value>>,
+    pub versions: Mutex<HashMap<String, u64>>,
+    pub callbacks: RwLock<HashMap<String, Vec<(CallbackId, ChangeCallback)>>>,
+    handlers: RwLock<HashMap<String, RequestHandler>>,
+    error_callbacks: RwLock<Vec<ErrorCallback>>,
+    // sent again after reconnecting
+    pub subscriptions: Mutex<HashSet<String>>,
+    // of the latest message received, so a reconnection only gets what it missed
+    last_seq: Mutex<Option<u64>>,
+    pub connected: AtomicBool,
+}
+
+// A connection to a Poca server. Reconnects with a growing delay when the
+// connection is lost, writes made in the meantime are sent afterwards.
+// `connect` lives next to the connection of the platform, see `native` and
+// `wasm`.
+#[derive(Clone)]
+pub struct PocaClient {
+    pub(crate) state: Arc<ClientState>,
+    pub(crate) outgoing: mpsc::UnboundedSender<WSMessage>,
+    pub(crate) connection: Arc<Connection>,
+}
+
+impl PocaClient {
+    // subscribes to the key, only subscribed keys receive changes
+    pub fn data<T: Serialize + DeserializeOwned>(&self, key: &str) -> ClientDataHandle<T> {
+        if self.state.subscriptions.lock().insert(key.to_string()) {
+            let message = WSMessage::new(WSMessageType::Subscribe, Some(key.to_string()), None);
+            self.outgoing.send(message).ok();
+        }
+        ClientDataHandle::new(key.to_string(), self.state.clone(), self.outgoing.clone())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state.connected.load(Ordering::SeqCst)
+    }
+
+    // Answers calls of the server to the method, see `Poca::call`. The error is
+    // sent back as the reason.
+    pub fn handle<T, R>(
+        &self,
+        method: &str,
+        handler: impl Fn(T) -> Result<R, String> + MaybeSend + 'static,
+    ) where
+        T: DeserializeOwned,
+        R: Serialize,
+    {
+        let handler = move |request: Value| {
+            let request = serde_json::from_value(request).map_err(|error| error.to_string())?;
+            serde_json::to_string(&handler(request)?).map_err(|error| error.to_string())
+        };
+        self.state
+            .handlers
+            .write()
+            .insert(method.to_string(), Arc::new(handler));
+    }
+
+    // called for messages the server rejected, with their key if they had one
+    pub fn on_error(&self, callback: impl Fn(Option<&str>, &str) + MaybeSend + 'static) {
+        self.state.error_callbacks.write().push(Arc::new(callback));
+    }
+
+    // stops reconnecting, handles keep their last values
+    pub fn close(&self) {
+        self.connection.close();
+        self.state.connected.store(false, Ordering::SeqCst);
+    }
+}
+
+impl ClientState {
+    // the messages to answer with
+    pub(crate) fn handle(&self, message: WSMessage) -> Vec<WSMessage> {
+        let mut replies = Vec::new();
+        if let Some(seq) = message.seq {
+            // retransmitted messages keep their original number
+            let mut last_seq = self.last_seq.lock();
+            *last_seq = Some(last_seq.map_or(seq, |last| last.max(seq)));
+            if message.ack {
+                let mut ack = WSMessage::new(WSMessageType::Ack, None, None);
+                ack.seq = Some(seq);
+                replies.push(ack);
+            }
+        }
+        if let (Some(key), Some(version)) = (&message.key, message.version) {
+            self.versions.lock().insert(key.clone(), version);
+        }
+        let data = message
+            .data
+            .as_deref()
+            .and_then(|data| serde_json::from_str::<Value>(data).ok());
+        let key = message.key.clone().unwrap_or_default();
+        match (message.message_type, data) {
+            (WSMessageType::Set, Some(value)) => {
+                self.values.write().insert(key.clone(), value);
+                self.changed(&key);
+            }
+            // the value comes as JSON inside a JSON string
+            (WSMessageType::Get, Some(Value::String(data))) => {
+                if let Ok(value) = serde_json::from_str(&data) {
+                    self.values.write().insert(key.clone(), value);
+                    self.changed(&key);
+                }
+            }
+            (WSMessageType::MergePatch, Some(Value::Object(fields))) => {
+                if let Some(Value::Object(object)) = self.values.write().get_mut(&key) {
+                    object.extend(fields);
+                }
+                self.changed(&key);
+            }
+            (WSMessageType::Patch, Some(ops)) => {
+                if let Ok(ops) = serde_json::from_value::<json_patch::Patch>(ops) {
+                    if let Some(value) = self.values.write().get_mut(&key) {
+                        json_patch::patch(value, &ops).ok();
+                    }
+                }
+                self.changed(&key);
+            }
+            (WSMessageType::Increment, Some(by)) => {
+                if let Some(value) = self.values.write().get_mut(&key) {
+                    *value = match (value.as_i64(), by.as_i64()) {
+                        (Some(value), Some(by)) => Value::from(value + by),
+                        _ => Value::from(
+                            value.as_f64().unwrap_or_default() + by.as_f64().unwrap_or_default(),
+                        ),
+                    };
+                }
+                self.changed(&key);
+            }
+            (WSMessageType::Snapshot, Some(Value::Object(values))) => {
+                let keys = values.keys().cloned().collect::<Vec<_>>();
+                self.values.write().extend(values);
+                for key in keys {
+                    self.changed(&key);
+                }
+            }
+            // values that drifted from the server's are fetched again
+            (WSMessageType::Checksums, Some(Value::Object(checksums))) => {
+                let values = self.values.read();
+                for (key, expected) in checksums {
+                    if values.get(&key).map(checksum).as_deref() != expected.as_str() {
+                        replies.push(WSMessage::new(WSMessageType::Get, Some(key), None));
+                    }
+                }
+            }
+            (WSMessageType::Remove, _) => {
+                self.values.write().remove(&key);
+                self.versions.lock().remove(&key);
+            }
+            (WSMessageType::Batch, _) => {
+                let batch = message
+                    .data
+                    .as_deref()
+                    .and_then(|data| serde_json::from_str::<Vec<WSMessage>>(data).ok());
+                for message in batch.unwrap_or_default() {
+                    replies.extend(self.handle(message));
+                }
+            }
+            (WSMessageType::Request, request) => {
+                // without holding the lock, the handler may add others
+                let handler = self.handlers.read().get(&key).cloned();
+                let result = match handler {
+                    Some(handler) => handler(request.unwrap_or_default()),
+                    None => Err(format!("Method {} does not exist", key)),
+                };
+                let mut reply = match result {
+                    Ok(response) => WSMessage::new(WSMessageType::Response, None, Some(response)),
+                    Err(reason) => WSMessage::new(WSMessageType::Error, None, Some(reason)),
+                };
+                reply.id = message.id;
+                replies.push(reply);
+            }
+            (WSMessageType::Error, _) => {
+                let reason = message.data.unwrap_or_default();
+                let callbacks = self.error_callbacks.read().clone();
+                for callback in callbacks {
+                    callback(message.key.as_deref(), &reason);
+                }
+            }
+            _ => {}
+        }
+        replies
+    }
+
+    // calls the callbacks of the key, without holding any lock
+    fn changed(&self, key: &str) {
+        let value = match self.values.read().get(key) {
+            Some(value) => value.clone(),
+            None => return,
+        };
+        let callbacks = self.callbacks.read().get(key).cloned().unwrap_or_default();
+        for (_, callback) in callbacks {
+            callback(&value);
+        }
+    }
+
+    pub(crate) fn resume_url(&self, url: &str) -> String {
+        match *self.last_seq.lock() {
+            Some(seq) if url.contains('?') => format!("{}&resume={}", url, seq),
+            Some(seq) => format!("{}?resume={}", url, seq),
+            None => url.to_string(),
+        }
+    }
+}
+
+// Servers accept connections on `/ws` by default, a URL without a path gets
+// it, e.g. `ws://host?resume=1` becomes `ws://host/ws?resume=1`.
+pub(crate) fn with_path(url: String) -> String {
+    let authority = url.find("://").map_or(0, |index| index + 3);
+    if url[authority..].contains('/') {
+        return url;
+    }
+    match url[authority..].find('?') {
+        Some(index) => format!(
+            "{}/ws{}",
+            &url[..authority + index],
+            &url[authority + index..]
+        ),
+        None => format!("{}/ws", url),
+    }
+}
@@ -0,0 +1,99 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    client::{CallbackId, ClientState, MaybeSend},
+    message::{WSMessage, WSMessageType},
+};
+
+// A key of the server, like `DataHandle` on the server side.
+pub struct ClientDataHandle<T> {
+    key: String,
+    state: Arc<ClientState>,
+    outgoing: mpsc::UnboundedSender<WSMessage>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for ClientDataHandle<T> {
+    fn clone(&self) -> Self {
+        ClientDataHandle {
+            key: self.key.clone(),
+            state: self.state.clone(),
+            outgoing: self.outgoing.clone(),
+            _type: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> ClientDataHandle<T> {
+    pub(crate) fn new(
+        key: String,
+        state: Arc<ClientState>,
+        outgoing: mpsc::UnboundedSender<WSMessage>,
+    ) -> Self {
+        ClientDataHandle {
+            key,
+            state,
+            outgoing,
+            _type: PhantomData,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    // None if the server has no such key, or it holds another type
+    pub fn get(&self) -> Option<T> {
+        let value = self.state.values.read().get(&self.key).cloned()?;
+        serde_json::from_value(value).ok()
+    }
+
+    // applied locally right away, the server may still reject it, see
+    // `PocaClient::on_error`
+    pub fn set(&self, value: T) -> Result<(), serde_json::Error> {
+        let data = serde_json::to_value(&value)?;
+        let mut message = WSMessage::new(
+            WSMessageType::Set,
+            Some(self.key.clone()),
+            Some(data.to_string()),
+        );
+        message.version = self.state.versions.lock().get(&self.key).copied();
+        self.state.values.write().insert(self.key.clone(), data);
+        self.outgoing.send(message).ok();
+        Ok(())
+    }
+
+    // called for changes pushed by the server, not for `set`
+    pub fn on_change(&self, callback: impl Fn(T) + MaybeSend + 'static) -> CallbackId {
+        let id = CallbackId::next();
+        self.state
+            .callbacks
+            .write()
+            .entry(self.key.clone())
+            .or_default()
+            .push((
+                id,
+                Arc::new(move |value| {
+                    if let Ok(value) = serde_json::from_value(value.clone()) {
+                        callback(value);
+                    }
+                }),
+            ));
+        id
+    }
+
+    // false if the callback was already removed
+    pub fn remove_on_change(&self, id: CallbackId) -> bool {
+        match self.state.callbacks.write().get_mut(&self.key) {
+            Some(callbacks) => {
+                let count = callbacks.len();
+                callbacks.retain(|(each, _)| *each != id);
+                callbacks.len() != count
+            }
+            None => false,
+        }
+    }
+}
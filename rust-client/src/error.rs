@@ -0,0 +1,31 @@
+use std::{error::Error, fmt::Display};
+
+#[derive(Debug)]
+pub enum ClientError {
+    #[cfg(not(target_arch = "wasm32"))]
+    Connect(tokio_tungstenite::tungstenite::Error),
+    // what the browser threw, as text
+    #[cfg(target_arch = "wasm32")]
+    Connect(String),
+    // before the server sent the initial snapshot
+    Closed,
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Connect(error) => write!(f, "Failed to connect: {}", error),
+            ClientError::Closed => write!(f, "Connection closed by the server"),
+        }
+    }
+}
+
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            ClientError::Connect(error) => Some(error),
+            _ => None,
+        }
+    }
+}
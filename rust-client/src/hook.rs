@@ -0,0 +1,26 @@
+use serde::{de::DeserializeOwned, Serialize};
+use yew::{hook, use_effect_with, use_state, UseStateHandle};
+
+use crate::data_handle::ClientDataHandle;
+
+// The value of the key as Yew state, so components in the browser re-render
+// with every change pushed by the server. Writes go through
+// `ClientDataHandle::set`. The callback is removed once the component unmounts.
+#[hook]
+pub fn use_synced<T>(handle: &ClientDataHandle<T>) -> UseStateHandle<Option<T>>
+where
+    T: Serialize + DeserializeOwned + 'static,
+{
+    let state = use_state(|| handle.get());
+    {
+        let state = state.clone();
+        let handle = handle.clone();
+        use_effect_with((), move |_| {
+            let id = handle.on_change(move |value| state.set(Some(value)));
+            move || {
+                handle.remove_on_change(id);
+            }
+        });
+    }
+    state
+}
@@ -0,0 +1,19 @@
+mod checksum;
+mod client;
+mod data_handle;
+mod error;
+#[cfg(all(feature = "yew", target_arch = "wasm32"))]
+mod hook;
+mod message;
+#[cfg(not(target_arch = "wasm32"))]
+mod native;
+#[cfg(feature = "leptos")]
+mod signal;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+pub use client::{CallbackId, MaybeSend, PocaClient};
+pub use data_handle::ClientDataHandle;
+pub use error::ClientError;
+#[cfg(all(feature = "yew", target_arch = "wasm32"))]
+pub use hook::use_synced;
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use serde_repr::*;
+
+// mirrors server/src/message.rs
+#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug, Clone)]
+#[repr(u8)]
+pub enum WSMessageType {
+    Set = 1,
+    Emit = 2,
+    Get = 3,
+    Error = 4,
+    Subscribe = 5,
+    Unsubscribe = 6,
+    Snapshot = 7,
+    MergePatch = 8,
+    Patch = 9,
+    Increment = 10,
+    TextOps = 11,
+    CompareAndSet = 12,
+    Batch = 13,
+    Remove = 14,
+    Keys = 15,
+    Event = 16,
+    Request = 17,
+    Response = 18,
+    Ack = 19,
+    Schema = 20,
+    Checksums = 21,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WSMessage {
+    pub message_type: WSMessageType,
+    pub key: Option<String>,
+    pub data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ack: bool,
+}
+
+impl WSMessage {
+    pub fn new(message_type: WSMessageType, key: Option<String>, data: Option<String>) -> Self {
+        WSMessage {
+            message_type,
+            key,
+            data,
+            version: None,
+            id: None,
+            seq: None,
+            ack: false,
+        }
+    }
+}
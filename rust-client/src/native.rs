@@ -0,0 +1,140 @@
+use std::sync::{atomic::Ordering, Arc};
+
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use tokio::{net::TcpStream, sync::mpsc, task::JoinHandle};
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+use crate::{
+    client::{with_path, ClientState, PocaClient, MAX_RECONNECT_DELAY, MIN_RECONNECT_DELAY},
+    error::ClientError,
+    message::{WSMessage, WSMessageType},
+};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// the task keeping the client connected
+pub(crate) struct Connection {
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Connection {
+    pub fn close(&self) {
+        if let Some(task) = self.task.lock().take() {
+            task.abort();
+        }
+    }
+}
+
+impl PocaClient {
+    // resolves once the server sent the current values
+    pub async fn connect(url: impl Into<String>) -> Result<Self, ClientError> {
+        let url = with_path(url.into());
+        let state = Arc::new(ClientState::default());
+        let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .map_err(ClientError::Connect)?;
+        loop {
+            let text = match socket.next().await {
+                Some(Ok(tungstenite::Message::Text(text))) => text,
+                Some(Ok(_)) => continue,
+                _ => return Err(ClientError::Closed),
+            };
+            if let Ok(message) = serde_json::from_str::<WSMessage>(&text) {
+                let snapshot = message.message_type == WSMessageType::Snapshot;
+                for reply in state.handle(message) {
+                    send(&mut socket, &reply).await.ok();
+                }
+                if snapshot {
+                    break;
+                }
+            }
+        }
+        state.connected.store(true, Ordering::SeqCst);
+
+        let (outgoing, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(run(state.clone(), url, socket, receiver));
+        Ok(PocaClient {
+            state,
+            outgoing,
+            connection: Arc::new(Connection {
+                task: Mutex::new(Some(task)),
+            }),
+        })
+    }
+}
+
+async fn run(
+    state: Arc<ClientState>,
+    url: String,
+    mut socket: Socket,
+    mut outgoing: mpsc::UnboundedReceiver<WSMessage>,
+) {
+    loop {
+        if !session(&state, &mut socket, &mut outgoing).await {
+            return;
+        }
+        state.connected.store(false, Ordering::SeqCst);
+        let mut delay = MIN_RECONNECT_DELAY;
+        socket = loop {
+            tokio::time::sleep(delay).await;
+            match tokio_tungstenite::connect_async(state.resume_url(&url)).await {
+                Ok((socket, _)) => break socket,
+                Err(_) => delay = (delay * 2).min(MAX_RECONNECT_DELAY),
+            }
+        };
+        state.connected.store(true, Ordering::SeqCst);
+        let subscriptions = state.subscriptions.lock().clone();
+        for key in subscriptions {
+            let message = WSMessage::new(WSMessageType::Subscribe, Some(key), None);
+            send(&mut socket, &message).await.ok();
+        }
+    }
+}
+
+// false once every handle of the client is dropped
+async fn session(
+    state: &ClientState,
+    socket: &mut Socket,
+    outgoing: &mut mpsc::UnboundedReceiver<WSMessage>,
+) -> bool {
+    loop {
+        tokio::select! {
+            message = outgoing.recv() => match message {
+                Some(message) => {
+                    if send(socket, &message).await.is_err() {
+                        return true;
+                    }
+                }
+                None => {
+                    socket.close(None).await.ok();
+                    return false;
+                }
+            },
+            frame = socket.next() => match frame {
+                Some(Ok(tungstenite::Message::Text(text))) => {
+                    let message = match serde_json::from_str::<WSMessage>(&text) {
+                        Ok(message) => message,
+                        Err(_) => continue,
+                    };
+                    for reply in state.handle(message) {
+                        if send(socket, &reply).await.is_err() {
+                            return true;
+                        }
+                    }
+                }
+                Some(Ok(_)) => {}
+                _ => return true,
+            },
+        }
+    }
+}
+
+// Messages only hold strings and numbers, so one that can't be encoded is
+// skipped rather than ending the connection.
+async fn send(socket: &mut Socket, message: &WSMessage) -> Result<(), tungstenite::Error> {
+    match serde_json::to_string(message) {
+        Ok(text) => socket.send(tungstenite::Message::Text(text)).await,
+        Err(_) => Ok(()),
+    }
+}
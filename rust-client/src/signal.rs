@@ -0,0 +1,32 @@
+use leptos::{create_effect, create_rw_signal, on_cleanup, RwSignal, SignalGet, SignalSet};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{client::MaybeSend, data_handle::ClientDataHandle};
+
+impl<T> ClientDataHandle<T>
+where
+    T: Serialize + DeserializeOwned + Clone + PartialEq + MaybeSend + 'static,
+{
+    // A Leptos signal following the key. Changes pushed by the server are
+    // set on it, values set on it are written to the server. It stops
+    // following the key once its owner is disposed.
+    pub fn signal(&self) -> RwSignal<Option<T>> {
+        let signal = create_rw_signal(self.get());
+        let id = self.on_change(move |value| signal.set(Some(value)));
+        let handle = self.clone();
+        on_cleanup(move || {
+            handle.remove_on_change(id);
+        });
+        let handle = self.clone();
+        create_effect(move |_| {
+            // pushed values are already known to the handle
+            if let Some(value) = signal.get() {
+                if handle.get().as_ref() != Some(&value) {
+                    // a value of the signal that can't be serialized stays local
+                    handle.set(value).ok();
+                }
+            }
+        });
+        signal
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::{atomic::Ordering, Arc},
+};
+
+use tokio::sync::{mpsc, oneshot};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{CloseEvent, MessageEvent, WebSocket};
+
+use crate::{
+    client::{with_path, ClientState, PocaClient, MAX_RECONNECT_DELAY, MIN_RECONNECT_DELAY},
+    error::ClientError,
+    message::{WSMessage, WSMessageType},
+};
+
+// The browser's WebSocket, replaced on every reconnection. Its handlers only
+// hold on to it weakly, so it is closed once the client is dropped.
+pub(crate) struct Connection(Rc<Socket>);
+
+struct Socket {
+    url: String,
+    state: Arc<ClientState>,
+    socket: RefCell<Option<WebSocket>>,
+    // kept alive as long as the socket calls them
+    handlers: RefCell<Option<Handlers>>,
+    // sent once the socket is open again
+    pending: RefCell<Vec<String>>,
+    // resolves `connect`, taken by the first snapshot
+    ready: RefCell<Option<oneshot::Sender<()>>>,
+    delay: Cell<i32>,
+    closed: Cell<bool>,
+}
+
+struct Handlers {
+    _open: Closure<dyn FnMut()>,
+    _message: Closure<dyn FnMut(MessageEvent)>,
+    _close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+impl Connection {
+    pub fn close(&self) {
+        self.0.closed.set(true);
+        if let Some(socket) = self.0.socket.borrow_mut().take() {
+            // the handlers are dropped below, the browser must not call them
+            socket.set_onopen(None);
+            socket.set_onmessage(None);
+            socket.set_onclose(None);
+            socket.close().ok();
+        }
+        self.0.handlers.borrow_mut().take();
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl PocaClient {
+    // resolves once the server sent the current values
+    pub async fn connect(url: impl Into<String>) -> Result<Self, ClientError> {
+        let (ready, connected) = oneshot::channel();
+        let socket = Rc::new(Socket {
+            url: with_path(url.into()),
+            state: Arc::new(ClientState::default()),
+            socket: RefCell::new(None),
+            handlers: RefCell::new(None),
+            pending: RefCell::new(Vec::new()),
+            ready: RefCell::new(Some(ready)),
+            delay: Cell::new(MIN_RECONNECT_DELAY.as_millis() as i32),
+            closed: Cell::new(false),
+        });
+        open(&socket).map_err(|error| ClientError::Connect(format!("{:?}", error)))?;
+        connected.await.map_err(|_| ClientError::Closed)?;
+
+        let (outgoing, mut receiver) = mpsc::unbounded_channel::<WSMessage>();
+        let sender = Rc::downgrade(&socket);
+        wasm_bindgen_futures::spawn_local(async move {
+            while let Some(message) = receiver.recv().await {
+                match sender.upgrade() {
+                    Some(sender) => sender.send_message(&message),
+                    None => return,
+                }
+            }
+        });
+        Ok(PocaClient {
+            state: socket.state.clone(),
+            outgoing,
+            connection: Arc::new(Connection(socket)),
+        })
+    }
+}
+
+impl Socket {
+    fn send(&self, text: String) {
+        match &*self.socket.borrow() {
+            Some(socket) if socket.ready_state() == WebSocket::OPEN => {
+                socket.send_with_str(&text).ok();
+            }
+            _ => self.pending.borrow_mut().push(text),
+        }
+    }
+
+    // Messages only hold strings and numbers, so one that can't be encoded
+    // is skipped rather than ending the connection.
+    fn send_message(&self, message: &WSMessage) {
+        if let Ok(text) = serde_json::to_string(message) {
+            self.send(text);
+        }
+    }
+}
+
+fn open(socket: &Rc<Socket>) -> Result<(), JsValue> {
+    let websocket = WebSocket::new(&socket.state.resume_url(&socket.url))?;
+
+    let opened = Rc::downgrade(socket);
+    let on_open = Closure::wrap(Box::new(move || {
+        let opened = match opened.upgrade() {
+            Some(opened) => opened,
+            None => return,
+        };
+        opened.state.connected.store(true, Ordering::SeqCst);
+        opened.delay.set(MIN_RECONNECT_DELAY.as_millis() as i32);
+        let subscriptions = opened.state.subscriptions.lock().clone();
+        for key in subscriptions {
+            let message = WSMessage::new(WSMessageType::Subscribe, Some(key), None);
+            opened.send_message(&message);
+        }
+        let pending = opened.pending.take();
+        for text in pending {
+            opened.send(text);
+        }
+    }) as Box<dyn FnMut()>);
+
+    let receiving = Rc::downgrade(socket);
+    let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+        let receiving = match receiving.upgrade() {
+            Some(receiving) => receiving,
+            None => return,
+        };
+        let message = match event.data().as_string() {
+            Some(text) => serde_json::from_str::<WSMessage>(&text).ok(),
+            None => None,
+        };
+        if let Some(message) = message {
+            let snapshot = message.message_type == WSMessageType::Snapshot;
+            for reply in receiving.state.handle(message) {
+                receiving.send_message(&reply);
+            }
+            if snapshot {
+                if let Some(ready) = receiving.ready.borrow_mut().take() {
+                    ready.send(()).ok();
+                }
+            }
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+
+    let closing = Rc::downgrade(socket);
+    let on_close = Closure::wrap(Box::new(move |_: CloseEvent| {
+        let closing = match closing.upgrade() {
+            Some(closing) => closing,
+            None => return,
+        };
+        closing.state.connected.store(false, Ordering::SeqCst);
+        // the first connection failing fails `connect` instead
+        if closing.closed.get() || closing.ready.borrow_mut().take().is_some() {
+            return;
+        }
+        let delay = closing.delay.get();
+        let max = MAX_RECONNECT_DELAY.as_millis() as i32;
+        closing.delay.set((delay * 2).min(max));
+        let reopening = Rc::downgrade(&closing);
+        let reopen = Closure::once_into_js(move || match reopening.upgrade() {
+            Some(reopening) if !reopening.closed.get() => {
+                open(&reopening).ok();
+            }
+            _ => {}
+        });
+        if let Some(window) = web_sys::window() {
+            window
+                .set_timeout_with_callback_and_timeout_and_arguments_0(
+                    reopen.unchecked_ref(),
+                    delay,
+                )
+                .ok();
+        }
+    }) as Box<dyn FnMut(CloseEvent)>);
+
+    websocket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+    websocket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    websocket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    *socket.socket.borrow_mut() = Some(websocket);
+    *socket.handlers.borrow_mut() = Some(Handlers {
+        _open: on_open,
+        _message: on_message,
+        _close: on_close,
+    });
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use poca::{ClientInfo, Flow, Middleware, Poca, RpcError, WSMessage, WSMessageType};
+use poca_client::PocaClient;
+use tokio::{sync::mpsc, time::timeout};
+
+#[tokio::test]
+async fn syncing_with_a_server() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}", poca.local_addr().unwrap());
+
+    let client = PocaClient::connect(url).await.unwrap();
+    let handle = client.data::<i32>("counter");
+    assert_eq!(handle.get(), Some(1));
+    assert_eq!(client.data::<String>("counter").get(), None);
+
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    handle.on_change(move |value| {
+        sender.send(value).ok();
+    });
+    counter.set(2);
+    assert_eq!(changes.recv().await, Some(2));
+
+    handle.set(3).unwrap();
+    for _ in 0..100 {
+        if counter.get() == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(counter.get(), 3);
+    client.close();
+    poca.stop();
+}
+
+#[tokio::test]
+async fn removing_callbacks() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}", poca.local_addr().unwrap());
+
+    let client = PocaClient::connect(url).await.unwrap();
+    let handle = client.data::<i32>("counter");
+    let (removed_sender, mut removed) = mpsc::unbounded_channel();
+    let id = handle.on_change(move |value| {
+        removed_sender.send(value).ok();
+    });
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    handle.on_change(move |value| {
+        sender.send(value).ok();
+    });
+    assert!(handle.remove_on_change(id));
+    assert!(!handle.remove_on_change(id));
+
+    counter.set(2);
+    assert_eq!(changes.recv().await, Some(2));
+    // the sender was dropped along with the callback
+    assert_eq!(removed.recv().await, None);
+    client.close();
+    poca.stop();
+}
+
+#[tokio::test]
+async fn reconnecting() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}", poca.local_addr().unwrap());
+
+    let client = PocaClient::connect(url).await.unwrap();
+    let handle = client.data::<i32>("counter");
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    handle.on_change(move |value| {
+        sender.send(value).ok();
+    });
+    let id = poca.clients()[0].id;
+    assert!(poca.disconnect(id, "Bye"));
+    while client.is_connected() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // missed while disconnected
+    counter.set(2);
+
+    let change = timeout(Duration::from_secs(5), changes.recv()).await;
+    assert_eq!(change.unwrap(), Some(2));
+    assert!(client.is_connected());
+    client.close();
+    poca.stop();
+}
+
+// loses the first change sent to the client
+struct Lossy {
+    dropped: AtomicBool,
+}
+
+impl Middleware for Lossy {
+    fn outbound(&self, _client: &ClientInfo, message: WSMessage) -> Flow {
+        if message.message_type == WSMessageType::Set && !self.dropped.swap(true, Ordering::SeqCst)
+        {
+            return Flow::Drop;
+        }
+        Flow::Continue(message)
+    }
+}
+
+#[tokio::test]
+async fn healing_drifted_values() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .anti_entropy_interval(Duration::from_millis(50))
+        .build();
+    let counter = poca.data("counter", 1.0);
+    poca.add_middleware(Lossy {
+        dropped: AtomicBool::new(false),
+    });
+    poca.start().await.unwrap();
+    let url = format!("ws://{}", poca.local_addr().unwrap());
+
+    let client = PocaClient::connect(url).await.unwrap();
+    let handle = client.data::<f64>("counter");
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    handle.on_change(move |value| {
+        sender.send(value).ok();
+    });
+    counter.set(2.0);
+
+    let change = timeout(Duration::from_secs(5), changes.recv()).await;
+    assert_eq!(change.unwrap(), Some(2.0));
+    client.close();
+    poca.stop();
+}
+
+#[tokio::test]
+async fn answering_calls_of_the_server() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}", poca.local_addr().unwrap());
+
+    let client = PocaClient::connect(url).await.unwrap();
+    client.handle("double", |number: i32| Ok(number * 2));
+    client.handle("fail", |_: ()| Err::<(), _>("Not today".to_string()));
+    let id = poca.clients()[0].id;
+
+    assert_eq!(poca.call::<_, i32>(id, "double", 21).await.unwrap(), 42);
+    match poca.call::<_, ()>(id, "fail", ()).await {
+        Err(RpcError::Remote(reason)) => assert_eq!(reason, "Not today"),
+        other => panic!("Expected the reason of the client, got {:?}", other),
+    }
+    match poca.call::<_, ()>(id, "missing", ()).await {
+        Err(RpcError::Remote(reason)) => assert_eq!(reason, "Method missing does not exist"),
+        other => panic!("Expected the reason of the client, got {:?}", other),
+    }
+    client.close();
+    poca.stop();
+}
+
+#[tokio::test]
+async fn rejected_writes() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}", poca.local_addr().unwrap());
+
+    let client = PocaClient::connect(url).await.unwrap();
+    let (sender, mut errors) = mpsc::unbounded_channel();
+    client.on_error(move |key, _| {
+        sender.send(key.map(str::to_string)).ok();
+    });
+    client
+        .data::<String>("counter")
+        .set("one".to_string())
+        .unwrap();
+
+    let error = timeout(Duration::from_secs(5), errors.recv()).await;
+    assert_eq!(error.unwrap(), Some(Some("counter".to_string())));
+    assert_eq!(counter.get(), 1);
+    client.close();
+    poca.stop();
+}
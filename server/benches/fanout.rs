@@ -0,0 +1,75 @@
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures_util::{future, StreamExt};
+use poca::Poca;
+use tokio::runtime::Runtime;
+
+// changes per iteration, queues are large enough that none of them is dropped
+const WRITES: usize = 1_000;
+const KEYS: usize = 64;
+
+// Every change is copied to every subscriber, like to connected clients.
+fn fanout(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("fanout");
+    group.sample_size(10);
+    for subscribers in [1, 50, 200, 500] {
+        let poca = Poca::builder().channel_size(WRITES).build();
+        let handles = (0..KEYS)
+            .map(|index| poca.data(&format!("key{}", index), 0u64))
+            .collect::<Vec<_>>();
+        let mut streams = runtime.block_on(async {
+            (0..subscribers)
+                .map(|_| poca.all_changes())
+                .collect::<Vec<_>>()
+        });
+        group.throughput(Throughput::Elements((WRITES * subscribers) as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(subscribers),
+            &subscribers,
+            |b, _| {
+                b.iter(|| {
+                    for write in 0..WRITES {
+                        handles[write % KEYS].set(write as u64);
+                    }
+                    runtime.block_on(future::join_all(
+                        streams
+                            .iter_mut()
+                            .map(|stream| stream.by_ref().take(WRITES).count()),
+                    ))
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// Writers on unrelated keys, which shouldn't wait for each other.
+fn concurrent_writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_writes");
+    for writers in [1, 4, 16] {
+        let poca = Poca::builder().build();
+        let handles = (0..writers)
+            .map(|index| poca.data(&format!("key{}", index), 0u64))
+            .collect::<Vec<_>>();
+        group.throughput(Throughput::Elements((WRITES * writers) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(writers), &writers, |b, _| {
+            b.iter(|| {
+                thread::scope(|scope| {
+                    for handle in &handles {
+                        scope.spawn(move || {
+                            for write in 0..WRITES {
+                                handle.set(write as u64);
+                            }
+                        });
+                    }
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fanout, concurrent_writes);
+criterion_main!(benches);
@@ -1,21 +1,15 @@
-#[macro_use]
-extern crate lazy_static;
-
 use poca::{include_app_dir, Poca};
 
-lazy_static! {
-    static ref POCA: Poca = Poca::new(
+#[tokio::main]
+async fn main() {
+    let poca = Poca::new(
         "localhost:1120",
         include_app_dir!("examples/resources/"),
-        None
+        None,
     );
-}
-
-#[tokio::main]
-async fn main() {
-    let _handle = POCA.data("entry1", 42);
+    let _handle = poca.data("entry1", 42);
     println!("Starting websocket server");
-    POCA.start().await;
+    poca.start().await.expect("Failed to start server");
 
     tokio::signal::ctrl_c()
         .await
@@ -0,0 +1,7 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Access {
+    // clients can read the value, but their writes are rejected
+    ReadOnly,
+    #[default]
+    ClientWritable,
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use crate::client::ClientId;
+
+struct PendingAck {
+    key: String,
+    sent: Instant,
+    attempts: u32,
+}
+
+// Changes of keys that require acknowledgement, by client and sequence number.
+// Clients acknowledge the sequence number of the latest message they applied,
+// which covers everything before it.
+#[derive(Clone, Default)]
+pub struct Acks {
+    pending: Arc<Mutex<HashMap<ClientId, BTreeMap<u64, PendingAck>>>>,
+}
+
+// what to do about changes that weren't acknowledged in time
+#[derive(Default)]
+pub struct Overdue {
+    // sequence numbers to send the current value of the key again with
+    pub retransmit: Vec<(u64, String)>,
+    // keys of changes that ran out of attempts
+    pub failed: Vec<String>,
+}
+
+impl Acks {
+    pub fn expect(&self, client: ClientId, seq: u64, key: &str) {
+        self.pending.lock().entry(client).or_default().insert(
+            seq,
+            PendingAck {
+                key: key.to_string(),
+                sent: Instant::now(),
+                attempts: 0,
+            },
+        );
+    }
+
+    pub fn acknowledge(&self, client: ClientId, seq: u64) {
+        if let Some(pending) = self.pending.lock().get_mut(&client) {
+            // nothing comes after the last sequence number
+            *pending = match seq.checked_add(1) {
+                Some(next) => pending.split_off(&next),
+                None => BTreeMap::new(),
+            };
+        }
+    }
+
+    // for a change that wasn't sent after all
+    pub fn cancel(&self, client: ClientId, seq: u64) {
+        if let Some(pending) = self.pending.lock().get_mut(&client) {
+            pending.remove(&seq);
+        }
+    }
+
+    pub fn forget(&self, client: ClientId) {
+        self.pending.lock().remove(&client);
+    }
+
+    // Changes sent more than `timeout` ago, retransmitted up to `retries` times.
+    // Only the latest change of a key is retransmitted, it carries the current value.
+    pub fn overdue(&self, client: ClientId, timeout: Duration, retries: u32) -> Overdue {
+        let mut overdue = Overdue::default();
+        let mut guard = self.pending.lock();
+        let pending = match guard.get_mut(&client) {
+            Some(pending) => pending,
+            None => return overdue,
+        };
+        let now = Instant::now();
+        let mut latest = HashMap::new();
+        for (seq, ack) in pending.iter() {
+            latest.insert(ack.key.clone(), *seq);
+        }
+        pending.retain(|seq, ack| {
+            if latest.get(&ack.key) != Some(seq) {
+                return false;
+            }
+            if now.duration_since(ack.sent) < timeout {
+                return true;
+            }
+            if ack.attempts >= retries {
+                overdue.failed.push(ack.key.clone());
+                return false;
+            }
+            ack.attempts += 1;
+            ack.sent = now;
+            overdue.retransmit.push((*seq, ack.key.clone()));
+            true
+        });
+        overdue
+    }
+
+    // clients with changes of the key they haven't acknowledged yet
+    pub fn unacknowledged(&self, key: &str) -> Vec<ClientId> {
+        let mut clients = self
+            .pending
+            .lock()
+            .iter()
+            .filter(|(_, pending)| pending.values().any(|ack| ack.key == key))
+            .map(|(client, _)| *client)
+            .collect::<Vec<_>>();
+        clients.sort();
+        clients
+    }
+}
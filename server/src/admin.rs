@@ -0,0 +1,160 @@
+use std::net::SocketAddr;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use warp::{
+    http::{HeaderMap, StatusCode},
+    reply::Response,
+    ws::{self, WebSocket},
+    Filter, Rejection, Reply,
+};
+
+use crate::{
+    client::ClientId,
+    error::PocaError,
+    listener,
+    poca::{Poca, WeakPoca},
+};
+
+// what operators can do over the admin channel, one JSON object per frame
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+    ListClients,
+    Kick {
+        client: ClientId,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    DumpStore,
+    // saves the store to the persistence backend right away
+    Snapshot,
+    Verbose {
+        enabled: bool,
+    },
+}
+
+// A WebSocket at `/admin` for operating the server while it runs, see
+// `PocaBuilder::admin_token`. The token goes into the `Authorization` header
+// as a bearer token or into the `token` query parameter. Every command is
+// answered with a JSON object, which has an `error` if it failed.
+pub(crate) fn routes(
+    token: String,
+    poca: WeakPoca,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone + Send + Sync + 'static {
+    warp::path!("admin")
+        .and(warp::ws())
+        .and(listener::remote_addr())
+        .and(warp::header::headers_cloned())
+        .and(
+            warp::query::raw()
+                .map(Some)
+                .or(warp::any().map(|| None::<String>))
+                .unify(),
+        )
+        .map(
+            move |websocket: ws::Ws,
+                  address: Option<SocketAddr>,
+                  headers: HeaderMap,
+                  query: Option<String>| {
+                if !authorized(&token, &headers, query.as_deref()) {
+                    if let Some(poca) = poca.upgrade() {
+                        poca.report(PocaError::Auth {
+                            address,
+                            reason: "Invalid admin token".to_string(),
+                        });
+                    }
+                    return warp::reply::with_status(
+                        "Invalid admin token",
+                        StatusCode::UNAUTHORIZED,
+                    )
+                    .into_response();
+                }
+                let poca = poca.clone();
+                websocket
+                    .on_upgrade(move |websocket| session(websocket, poca))
+                    .into_response()
+            },
+        )
+}
+
+fn authorized(token: &str, headers: &HeaderMap, query: Option<&str>) -> bool {
+    let bearer = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let queried = query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("token="));
+    bearer
+        .or(queried)
+        .is_some_and(|given| same_secret(given.as_bytes(), token.as_bytes()))
+}
+
+// compares every byte, so the time taken doesn't tell how much of it matched
+fn same_secret(given: &[u8], secret: &[u8]) -> bool {
+    given.len() == secret.len()
+        && given
+            .iter()
+            .zip(secret)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+async fn session(websocket: WebSocket, poca: WeakPoca) {
+    let (mut sender, mut receiver) = websocket.split();
+    while let Some(Ok(frame)) = receiver.next().await {
+        let text = match frame.to_str() {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let reply = match (poca.upgrade(), serde_json::from_str(text)) {
+            (Some(poca), Ok(command)) => execute(&poca, command).await,
+            (Some(_), Err(error)) => json!({ "error": error.to_string() }),
+            // the server went away
+            (None, _) => break,
+        };
+        if sender
+            .send(ws::Message::text(reply.to_string()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn execute(poca: &Poca, command: Command) -> Value {
+    match command {
+        Command::ListClients => {
+            let clients = poca
+                .clients()
+                .iter()
+                .map(|client| {
+                    json!({
+                        "id": client.id,
+                        "address": client.address,
+                        "metadata": client.summary().metadata,
+                    })
+                })
+                .collect::<Vec<_>>();
+            json!({ "clients": clients })
+        }
+        Command::Kick { client, reason } => {
+            let reason = reason.as_deref().unwrap_or("Kicked by an administrator");
+            json!({ "kicked": poca.disconnect(client, reason) })
+        }
+        Command::DumpStore => json!({ "values": poca.dump() }),
+        // saved on a blocking thread, not the one of the session
+        Command::Snapshot => match poca.flush().await {
+            Ok(()) => json!({ "saved": true }),
+            Err(error) => json!({ "error": error.to_string() }),
+        },
+        Command::Verbose { enabled } => {
+            poca.set_verbose(enabled);
+            json!({ "verbose": enabled })
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::RwLock;
+use tokio::sync::Notify;
+use warp::{
+    http::{HeaderMap, StatusCode},
+    reply::Response,
+    Reply,
+};
+
+use crate::{
+    auth::{AuthRequest, Authenticator},
+    client::{ClientId, ClientInfo},
+    deny_list::DenyList,
+    error::PocaError,
+    poca::Stores,
+    ws_handler::{report, HandlerContext},
+};
+
+// Decides whether a client may connect, the same way for every transport.
+#[derive(Clone)]
+pub(crate) struct Admission {
+    pub context: HandlerContext,
+    pub stores: Stores,
+    pub connections: Arc<AtomicUsize>,
+    pub connection_closed: Arc<Notify>,
+    pub next_client_id: Arc<AtomicU64>,
+    pub authenticator: Option<Arc<dyn Authenticator>>,
+    pub deny_list: Arc<RwLock<DenyList>>,
+    pub max_connections: Option<usize>,
+}
+
+pub(crate) struct Admitted {
+    pub context: HandlerContext,
+    pub client: ClientInfo,
+    pub slot: ConnectionSlot,
+}
+
+impl Admission {
+    // the response to send instead if the client is turned away
+    pub fn admit(
+        &self,
+        path: &str,
+        address: Option<SocketAddr>,
+        headers: HeaderMap,
+        query: Option<String>,
+    ) -> Result<Admitted, Box<Response>> {
+        let client_id = ClientId::new(self.next_client_id.fetch_add(1, Ordering::SeqCst));
+        let client = ClientInfo::new(client_id, address);
+        if let Some(authenticator) = &self.authenticator {
+            let request = AuthRequest {
+                headers,
+                query,
+                address,
+            };
+            if let Err(reason) = authenticator.authenticate(&request, &client) {
+                let error = PocaError::Auth {
+                    address,
+                    reason: reason.clone(),
+                };
+                report(&self.context.error_hook, &self.context.panic_hook, error);
+                return Err(Box::new(
+                    warp::reply::with_status(reason, StatusCode::UNAUTHORIZED).into_response(),
+                ));
+            }
+        }
+        if self.deny_list.read().is_denied(&client) {
+            return Err(Box::new(
+                warp::reply::with_status("Access denied".to_string(), StatusCode::FORBIDDEN)
+                    .into_response(),
+            ));
+        }
+        let context = match self.stores.read().get(path) {
+            Some(store) => store.handler_context(),
+            None => self.context.clone(),
+        };
+        // taken before upgrading, so a burst can't overshoot the limit
+        let previous = self.connections.fetch_add(1, Ordering::SeqCst);
+        let slot = ConnectionSlot {
+            connections: self.connections.clone(),
+            closed: self.connection_closed.clone(),
+        };
+        if self.max_connections.is_some_and(|max| previous >= max) {
+            return Err(Box::new(
+                warp::reply::with_status(
+                    "Too many connections".to_string(),
+                    StatusCode::SERVICE_UNAVAILABLE,
+                )
+                .into_response(),
+            ));
+        }
+        Ok(Admitted {
+            context,
+            client,
+            slot,
+        })
+    }
+}
+
+// counts a connection until dropped, even if the upgrade never happens
+pub(crate) struct ConnectionSlot {
+    connections: Arc<AtomicUsize>,
+    closed: Arc<Notify>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+        self.closed.notify_waiters();
+    }
+}
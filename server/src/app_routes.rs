@@ -25,6 +25,7 @@ pub fn generate_app_routes(routes: RouteNode) -> AppRoutes {
 }
 
 impl<'a> AppRoutes<'a> {
+    // None for paths without a file, `initial` for the root of the app
     pub fn get_route(&self, path: &[&str], initial: bool) -> Option<&'a [u8]> {
         if path.is_empty() {
             return None;
@@ -32,17 +33,22 @@ impl<'a> AppRoutes<'a> {
         if path[0].is_empty() {
             return Some(self.content);
         }
-        if path[0] == self.root || initial {
-            if path.len() == 1 {
-                Some(self.content)
-            } else {
-                let next_path = if initial { path } else { &path[1..] };
-                self.routes
-                    .iter()
-                    .find_map(|route| route.get_route(next_path, false))
-            }
-        } else {
-            None
+        if initial {
+            return self
+                .routes
+                .iter()
+                .find_map(|route| route.get_route(path, false));
+        }
+        if path[0] != self.root {
+            return None;
+        }
+        // with or without a trailing slash
+        match &path[1..] {
+            [] | [""] => Some(self.content),
+            next_path => self
+                .routes
+                .iter()
+                .find_map(|route| route.get_route(next_path, false)),
         }
     }
 }
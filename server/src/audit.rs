@@ -0,0 +1,119 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{client::ClientId, journal::Change, message::Message, poca::Store, trace::warn};
+
+// who changed a key and when, see `Poca::audit`
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub key: String,
+    // FNV-1a of the value before as JSON, None for a key that didn't exist
+    pub old_hash: Option<String>,
+    // None once removed
+    pub new_value: Option<Value>,
+    // None for changes made on the server
+    pub client: Option<ClientId>,
+    // milliseconds since the epoch
+    pub timestamp: u64,
+}
+
+// Where audit entries go, like a file or a database. Called while the change
+// is routed and the value is locked, so it should be quick and not access
+// the key.
+pub trait AuditSink: Send + Sync + 'static {
+    fn record(&self, entry: &AuditEntry);
+}
+
+impl<F: Fn(&AuditEntry) + Send + Sync + 'static> AuditSink for F {
+    fn record(&self, entry: &AuditEntry) {
+        self(entry)
+    }
+}
+
+// one entry per line, as JSON
+pub struct FileAuditSink {
+    file: Mutex<File>,
+}
+
+impl FileAuditSink {
+    // appended to if it exists
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileAuditSink {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    // failures are only logged with the `tracing` feature
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn record(&self, entry: &AuditEntry) {
+        let mut line = serde_json::to_string(entry).unwrap();
+        line.push('\n');
+        if let Err(error) = self.file.lock().write_all(line.as_bytes()) {
+            warn!(%error, "Failed to write to the audit log");
+        }
+    }
+}
+
+// The values the changes lead to, like the journal keeps them, so the value
+// before each change is known without looking at the store.
+pub struct Audit {
+    sink: Box<dyn AuditSink>,
+    values: Mutex<Map<String, Value>>,
+}
+
+impl Audit {
+    pub fn new(sink: impl AuditSink, store: &Store) -> Audit {
+        let values = store
+            .elements()
+            .into_iter()
+            .map(|(key, element)| {
+                let data = element.read().data.serialize();
+                (key, serde_json::from_str(&data).unwrap())
+            })
+            .collect();
+        Audit {
+            sink: Box::new(sink),
+            values: Mutex::new(values),
+        }
+    }
+
+    pub fn routed(&self, message: &Message) {
+        let mut values = self.values.lock();
+        for change in Change::from_message(message) {
+            let old_hash = values.get(&change.key).map(hash);
+            let (key, client, timestamp) = (change.key.clone(), change.client, change.timestamp);
+            change.apply(&mut values);
+            // the value a key is registered with isn't a change
+            if message.registers_key() {
+                continue;
+            }
+            self.sink.record(&AuditEntry {
+                new_value: values.get(&key).cloned(),
+                key,
+                old_hash,
+                client,
+                timestamp,
+            });
+        }
+    }
+}
+
+fn hash(value: &Value) -> String {
+    let hash = value
+        .to_string()
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,40 @@
+use std::net::SocketAddr;
+
+use warp::http::HeaderMap;
+
+use crate::client::ClientInfo;
+
+#[derive(Debug, Clone)]
+pub struct AuthRequest {
+    pub headers: HeaderMap,
+    pub query: Option<String>,
+    pub address: Option<SocketAddr>,
+}
+
+impl AuthRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.as_deref()?.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key == name).then_some(value)
+        })
+    }
+}
+
+// Err carries the reason sent back with the 401 response.
+// The client info can be used to attach the authenticated identity as metadata.
+pub trait Authenticator: Send + Sync + 'static {
+    fn authenticate(&self, request: &AuthRequest, client: &ClientInfo) -> Result<(), String>;
+}
+
+impl<F> Authenticator for F
+where
+    F: Fn(&AuthRequest, &ClientInfo) -> Result<(), String> + Send + Sync + 'static,
+{
+    fn authenticate(&self, request: &AuthRequest, client: &ClientInfo) -> Result<(), String> {
+        self(request, client)
+    }
+}
@@ -0,0 +1,458 @@
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    app_routes::AppRoutes,
+    cluster::ClusterBackend,
+    error::PocaError,
+    poca::{Poca, WindowOptions},
+    rate_limit::RateLimit,
+    router::Overflow,
+    storage::StorageBackend,
+};
+
+const DEFAULT_CHANNEL_SIZE: usize = 256;
+const DEFAULT_REPLAY_SIZE: usize = 256;
+const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_ACK_RETRIES: u32 = 3;
+const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_MISSED_PONGS: u32 = 3;
+const DEFAULT_BACKLOG: u32 = 1024;
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_COMPACT_AFTER: usize = 10_000;
+const DEFAULT_WS_PATH: &str = "/ws";
+
+// what happens when a client falls so far behind that changes are lost
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LagPolicy {
+    // the client gets a snapshot of all values
+    #[default]
+    Resync,
+    Disconnect,
+    // changes are queued without limit, so only memory bounds the lag
+    Grow,
+}
+
+// when changes appended to the log are synced to the disk, see
+// `PocaBuilder::write_ahead_log`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Durability {
+    // left to the operating system, changes may be lost when it crashes
+    Os,
+    // once for the changes that queued up while the last ones were written
+    #[default]
+    Batch,
+    // after every change
+    Always,
+}
+
+#[derive(Clone, Debug)]
+pub struct PocaConfig {
+    // messages queued for a client before it misses some, see `overflow`
+    pub channel_size: usize,
+    // what happens to messages for a client whose queue is full
+    pub overflow: Overflow,
+    pub max_connections: Option<usize>,
+    // metadata keys clients see of each other in `CLIENTS_KEY`, none by default
+    pub public_metadata: Vec<String>,
+    // Upgrades are only accepted on this path, `/ws` by default, stores are
+    // found below it. With `/` or without one, clients can connect on any path.
+    pub ws_path: Option<String>,
+    // served before the app routes, like a frontend bundle built next to the server
+    pub static_dir: Option<PathBuf>,
+    // read-only `/api/keys` and `/api/data/{key}` endpoints, see `rest::routes`
+    pub rest_api: bool,
+    // a `/metrics` endpoint in the Prometheus text format, see `Poca::metrics`
+    pub metrics: bool,
+    // enables the `/admin` WebSocket for clients presenting it, see `admin::routes`
+    pub admin_token: Option<String>,
+    // `/sse` streams changes as server-sent events, writes are posted back
+    pub sse: bool,
+    // socket options of accepted connections
+    pub nodelay: bool,
+    pub tcp_keepalive: Option<Duration>,
+    // pending connections the OS queues before they are accepted
+    pub backlog: u32,
+    // larger messages and frames close the connection with a protocol error
+    pub max_message_size: Option<usize>,
+    pub max_frame_size: Option<usize>,
+    pub ping_interval: Option<Duration>,
+    // connections that answer none of this many pings in a row are closed
+    pub max_missed_pongs: u32,
+    // connections without messages in either direction are closed, pings don't count
+    pub idle_timeout: Option<Duration>,
+    pub shutdown_timeout: Duration,
+    // frames at least this large are compressed, None disables compression
+    // only for clients of the poca.json+deflate subprotocol, not permessage-deflate
+    pub compression_threshold: Option<usize>,
+    pub rate_limit: Option<RateLimit>,
+    pub lag_policy: LagPolicy,
+    // changes routed within it are sent to a client as one frame, see `coalesce`
+    pub flush_interval: Option<Duration>,
+    // changes kept for reconnecting clients, 0 always sends them a snapshot
+    pub replay_size: usize,
+    // for keys that require acknowledgement, see `DataHandle::set_require_ack`
+    pub ack_timeout: Duration,
+    pub ack_retries: u32,
+    // how long `Poca::call` waits for the client to answer
+    pub call_timeout: Duration,
+    // how often clients get checksums of their values to find ones that
+    // drifted, see `checksum::checksums`
+    pub anti_entropy_interval: Option<Duration>,
+    // how often the store is saved, see `Poca::persist_to`
+    pub persist_interval: Duration,
+    // every change is appended to a log next to the persisted file, see `Journal`
+    pub write_ahead_log: bool,
+    // changes in the log before it is folded into the file
+    pub compact_after: usize,
+    // when the log is synced, see `Durability`
+    pub durability: Durability,
+}
+
+impl PocaConfig {
+    // settings that can't be used, which `try_build` refuses
+    fn validate(&self) -> Result<(), PocaError> {
+        if self.channel_size == 0 {
+            return Err(PocaError::Config(
+                "channel_size must not be zero".to_string(),
+            ));
+        }
+        if self.ack_timeout.is_zero() {
+            return Err(PocaError::Config(
+                "ack_timeout must not be zero".to_string(),
+            ));
+        }
+        if self.max_missed_pongs == 0 {
+            return Err(PocaError::Config(
+                "max_missed_pongs must not be zero".to_string(),
+            ));
+        }
+        if self.persist_interval.is_zero() {
+            return Err(PocaError::Config(
+                "persist_interval must not be zero".to_string(),
+            ));
+        }
+        if self.ping_interval == Some(Duration::ZERO) {
+            return Err(PocaError::Config(
+                "ping_interval must not be zero".to_string(),
+            ));
+        }
+        if self.anti_entropy_interval == Some(Duration::ZERO) {
+            return Err(PocaError::Config(
+                "anti_entropy_interval must not be zero".to_string(),
+            ));
+        }
+        if let Some(limit) = &self.rate_limit {
+            if limit.messages_per_second == Some(0) || limit.bytes_per_second == Some(0) {
+                return Err(PocaError::Config(
+                    "rate_limit rates must not be zero".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for PocaConfig {
+    fn default() -> Self {
+        PocaConfig {
+            channel_size: DEFAULT_CHANNEL_SIZE,
+            overflow: Overflow::default(),
+            max_connections: None,
+            public_metadata: Vec::new(),
+            ws_path: Some(DEFAULT_WS_PATH.to_string()),
+            static_dir: None,
+            rest_api: false,
+            metrics: false,
+            admin_token: None,
+            sse: false,
+            nodelay: true,
+            tcp_keepalive: None,
+            backlog: DEFAULT_BACKLOG,
+            max_message_size: None,
+            max_frame_size: None,
+            ping_interval: None,
+            max_missed_pongs: DEFAULT_MAX_MISSED_PONGS,
+            idle_timeout: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            compression_threshold: None,
+            rate_limit: None,
+            lag_policy: LagPolicy::default(),
+            replay_size: DEFAULT_REPLAY_SIZE,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            ack_retries: DEFAULT_ACK_RETRIES,
+            call_timeout: DEFAULT_CALL_TIMEOUT,
+            anti_entropy_interval: None,
+            flush_interval: None,
+            persist_interval: DEFAULT_PERSIST_INTERVAL,
+            write_ahead_log: false,
+            compact_after: DEFAULT_COMPACT_AFTER,
+            durability: Durability::default(),
+        }
+    }
+}
+
+pub struct PocaBuilder {
+    addresses: Vec<SocketAddr>,
+    app_routes: AppRoutes<'static>,
+    window_options: WindowOptions,
+    config: PocaConfig,
+    storage: Option<Arc<dyn StorageBackend>>,
+    cluster: Option<Arc<dyn ClusterBackend>>,
+}
+
+impl Default for PocaBuilder {
+    fn default() -> Self {
+        PocaBuilder {
+            addresses: Vec::new(),
+            app_routes: AppRoutes {
+                root: "",
+                routes: Vec::new(),
+                content: &[],
+            },
+            window_options: WindowOptions::default(),
+            config: PocaConfig::default(),
+            storage: None,
+            cluster: None,
+        }
+    }
+}
+
+impl PocaBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The server listens on every address the name resolves to, like both
+    // IPv4 and IPv6 for "localhost". An unresolvable address is reported by `Poca::start`.
+    // With port 0 every address is bound to a port of its own, see `Poca::local_addrs`.
+    pub fn address(mut self, address: impl ToSocketAddrs) -> Self {
+        self.addresses.clear();
+        for address in address.to_socket_addrs().into_iter().flatten() {
+            if !self.addresses.contains(&address) {
+                self.addresses.push(address);
+            }
+        }
+        self
+    }
+
+    pub fn app_routes(mut self, app_routes: AppRoutes<'static>) -> Self {
+        self.app_routes = app_routes;
+        self
+    }
+
+    pub fn window_options(mut self, window_options: WindowOptions) -> Self {
+        self.window_options = window_options;
+        self
+    }
+
+    // must not be zero
+    pub fn channel_size(mut self, channel_size: usize) -> Self {
+        self.config.channel_size = channel_size;
+        self
+    }
+
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.config.overflow = overflow;
+        self
+    }
+
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_connections = Some(max_connections);
+        self
+    }
+
+    // adds to the keys published so far
+    pub fn public_metadata(mut self, key: impl Into<String>) -> Self {
+        self.config.public_metadata.push(key.into());
+        self
+    }
+
+    // "/" accepts upgrades on any path again, see `PocaConfig::ws_path`
+    pub fn ws_path(mut self, ws_path: impl Into<String>) -> Self {
+        self.config.ws_path = Some(ws_path.into());
+        self
+    }
+
+    pub fn static_dir(mut self, static_dir: impl Into<PathBuf>) -> Self {
+        self.config.static_dir = Some(static_dir.into());
+        self
+    }
+
+    pub fn rest_api(mut self, rest_api: bool) -> Self {
+        self.config.rest_api = rest_api;
+        self
+    }
+
+    pub fn metrics(mut self, metrics: bool) -> Self {
+        self.config.metrics = metrics;
+        self
+    }
+
+    pub fn admin_token(mut self, admin_token: impl Into<String>) -> Self {
+        self.config.admin_token = Some(admin_token.into());
+        self
+    }
+
+    pub fn sse(mut self, sse: bool) -> Self {
+        self.config.sse = sse;
+        self
+    }
+
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.config.nodelay = nodelay;
+        self
+    }
+
+    pub fn tcp_keepalive(mut self, tcp_keepalive: Duration) -> Self {
+        self.config.tcp_keepalive = Some(tcp_keepalive);
+        self
+    }
+
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.config.backlog = backlog;
+        self
+    }
+
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.config.max_message_size = Some(max_message_size);
+        self
+    }
+
+    pub fn max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.config.max_frame_size = Some(max_frame_size);
+        self
+    }
+
+    // must not be zero
+    pub fn ping_interval(mut self, ping_interval: Duration) -> Self {
+        self.config.ping_interval = Some(ping_interval);
+        self
+    }
+
+    // must not be zero
+    pub fn max_missed_pongs(mut self, max_missed_pongs: u32) -> Self {
+        self.config.max_missed_pongs = max_missed_pongs;
+        self
+    }
+
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.config.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    pub fn shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.config.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    pub fn compression_threshold(mut self, compression_threshold: usize) -> Self {
+        self.config.compression_threshold = Some(compression_threshold);
+        self
+    }
+
+    // the rates must not be zero
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.config.rate_limit = Some(rate_limit);
+        self
+    }
+
+    pub fn lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.config.lag_policy = lag_policy;
+        self
+    }
+
+    // e.g. 16ms for values changing with every frame, like sliders or cursors
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.config.flush_interval = Some(flush_interval);
+        self
+    }
+
+    pub fn replay_size(mut self, replay_size: usize) -> Self {
+        self.config.replay_size = replay_size;
+        self
+    }
+
+    // must not be zero
+    pub fn ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.config.ack_timeout = ack_timeout;
+        self
+    }
+
+    pub fn ack_retries(mut self, ack_retries: u32) -> Self {
+        self.config.ack_retries = ack_retries;
+        self
+    }
+
+    pub fn call_timeout(mut self, call_timeout: Duration) -> Self {
+        self.config.call_timeout = call_timeout;
+        self
+    }
+
+    // must not be zero
+    pub fn anti_entropy_interval(mut self, anti_entropy_interval: Duration) -> Self {
+        self.config.anti_entropy_interval = Some(anti_entropy_interval);
+        self
+    }
+
+    // must not be zero
+    pub fn persist_interval(mut self, persist_interval: Duration) -> Self {
+        self.config.persist_interval = persist_interval;
+        self
+    }
+
+    pub fn write_ahead_log(mut self, write_ahead_log: bool) -> Self {
+        self.config.write_ahead_log = write_ahead_log;
+        self
+    }
+
+    pub fn compact_after(mut self, compact_after: usize) -> Self {
+        self.config.compact_after = compact_after;
+        self
+    }
+
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.config.durability = durability;
+        self
+    }
+
+    // persisted like with `Poca::persist_with`, restored when built
+    pub fn storage(mut self, storage: impl StorageBackend) -> Self {
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    // joined like with `Poca::join_cluster`
+    pub fn cluster(mut self, cluster: impl ClusterBackend) -> Self {
+        self.cluster = Some(Arc::new(cluster));
+        self
+    }
+
+    // panics if the configuration is invalid or the storage can't be restored,
+    // see `try_build`
+    pub fn build(self) -> Poca {
+        self.try_build().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_build(self) -> Result<Poca, PocaError> {
+        self.config.validate()?;
+        let poca = Poca::from_parts(
+            self.addresses,
+            self.app_routes,
+            self.window_options,
+            self.config,
+        );
+        if let Some(storage) = self.storage {
+            poca.restore(storage)?;
+        }
+        if let Some(cluster) = self.cluster {
+            poca.connect_cluster(cluster);
+        }
+        Ok(poca)
+    }
+}
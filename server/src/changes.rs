@@ -0,0 +1,33 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::event_handler::CallbackGuard;
+
+// every new value of a key, in order, see `DataHandle::changes`
+// the handler feeding the stream is removed once it is dropped
+pub struct Changes<T> {
+    receiver: UnboundedReceiverStream<T>,
+    _guard: CallbackGuard,
+}
+
+impl<T> Changes<T> {
+    pub(crate) fn new(receiver: UnboundedReceiverStream<T>, guard: CallbackGuard) -> Self {
+        Self {
+            receiver,
+            _guard: guard,
+        }
+    }
+}
+
+impl<T> Stream for Changes<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ClientId(u64);
+
+impl ClientId {
+    pub(crate) fn new(id: u64) -> Self {
+        ClientId(id)
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for ClientId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// where a change came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Origin {
+    Server,
+    Client(ClientId),
+}
+
+impl Origin {
+    pub fn client(&self) -> Option<ClientId> {
+        match self {
+            Origin::Server => None,
+            Origin::Client(id) => Some(*id),
+        }
+    }
+}
+
+// cloned into every hook, metadata is shared between the clones
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: ClientId,
+    pub address: Option<SocketAddr>,
+    pub connected_at: SystemTime,
+    metadata: Arc<RwLock<HashMap<String, String>>>,
+    latency: Arc<RwLock<Option<Duration>>>,
+}
+
+impl ClientInfo {
+    pub fn new(id: ClientId, address: Option<SocketAddr>) -> Self {
+        ClientInfo {
+            id,
+            address,
+            connected_at: SystemTime::now(),
+            metadata: Arc::new(RwLock::new(HashMap::new())),
+            latency: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    pub fn set_metadata(&self, key: &str, value: impl Into<String>) {
+        self.metadata.write().insert(key.to_string(), value.into());
+    }
+
+    pub fn get_metadata(&self, key: &str) -> Option<String> {
+        self.metadata.read().get(key).cloned()
+    }
+
+    pub fn remove_metadata(&self, key: &str) -> Option<String> {
+        self.metadata.write().remove(key)
+    }
+
+    // round trip of the latest answered ping, see `PocaBuilder::ping_interval`
+    pub fn latency(&self) -> Option<Duration> {
+        *self.latency.read()
+    }
+
+    pub(crate) fn set_latency(&self, latency: Duration) {
+        *self.latency.write() = Some(latency);
+    }
+
+    pub fn summary(&self) -> ClientSummary {
+        ClientSummary {
+            id: self.id,
+            metadata: self.metadata.read().clone(),
+        }
+    }
+}
+
+// What every client sees of the connected clients, with the metadata
+// of `PocaConfig::public_metadata`. See `Poca::clients` for the server side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientSummary {
+    pub id: ClientId,
+    pub metadata: HashMap<String, String>,
+}
@@ -0,0 +1,198 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    error::Error,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{self, mpsc};
+
+use crate::{
+    client::Origin,
+    journal::{Change, Op},
+    message::Message,
+    poca::Store,
+    router::Router,
+    trace::warn,
+};
+
+pub type ClusterError = Box<dyn Error + Send + Sync>;
+
+// How the servers of a cluster reach each other, see `Poca::join_cluster`.
+// Every payload published by one server is received by all the others,
+// receiving the own ones as well is fine.
+pub trait ClusterBackend: Send + Sync + 'static {
+    fn publish(&self, payload: String) -> Result<(), ClusterError>;
+
+    // called once, when joining
+    fn subscribe(&self) -> mpsc::UnboundedReceiver<String>;
+}
+
+// Servers in the same process, clones of it share the same cluster.
+#[derive(Clone, Default)]
+pub struct MemoryCluster {
+    subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<String>>>>,
+}
+
+impl MemoryCluster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ClusterBackend for MemoryCluster {
+    fn publish(&self, payload: String) -> Result<(), ClusterError> {
+        self.subscribers
+            .lock()
+            .retain(|subscriber| subscriber.send(payload.clone()).is_ok());
+        Ok(())
+    }
+
+    fn subscribe(&self) -> mpsc::UnboundedReceiver<String> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscribers.lock().push(sender);
+        receiver
+    }
+}
+
+thread_local! {
+    // set while a change of another server is routed, so it isn't published again
+    static REMOTE: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    node: u64,
+    // of the value on the publishing server, after the change
+    version: u64,
+    change: Change,
+}
+
+// Concurrent sets of a key are ordered by the version of the value, then by
+// the server that made them, so every server keeps the same one. Other
+// changes, like increments, are applied in any order.
+type Writers = Arc<Mutex<HashMap<String, (u64, u64)>>>;
+
+pub struct Cluster {
+    backend: Arc<dyn ClusterBackend>,
+    node: u64,
+    // the version of the latest set of each key and the server that made it
+    writers: Writers,
+    // changes routed here, until the task publishes them
+    outgoing: sync::Mutex<mpsc::UnboundedReceiver<String>>,
+    incoming: sync::Mutex<mpsc::UnboundedReceiver<String>>,
+}
+
+impl Cluster {
+    // publishes every change routed from now on
+    pub fn join(backend: Arc<dyn ClusterBackend>, router: &Router) -> Cluster {
+        let node = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64)
+            ^ ((std::process::id() as u64) << 32);
+        let (sender, outgoing) = mpsc::unbounded_channel();
+        let writers = Writers::default();
+        let written = writers.clone();
+        router.tap(move |message| {
+            // registering a key doesn't overwrite the value the others have
+            if REMOTE.with(Cell::get) || message.registers_key() {
+                return;
+            }
+            for (version, change) in versioned(message) {
+                if let Op::Set(_) = change.op {
+                    written.lock().insert(change.key.clone(), (version, node));
+                }
+                let envelope = Envelope {
+                    node,
+                    version,
+                    change,
+                };
+                sender.send(serde_json::to_string(&envelope).unwrap()).ok();
+            }
+        });
+        Cluster {
+            incoming: sync::Mutex::new(backend.subscribe()),
+            backend,
+            node,
+            writers,
+            outgoing: sync::Mutex::new(outgoing),
+        }
+    }
+
+    // Changes of other servers are applied like changes made on this one,
+    // keys that aren't registered here are skipped.
+    fn receive(&self, payload: &str, store: &Store, router: &Router) {
+        let Envelope {
+            node,
+            version,
+            change,
+        } = match serde_json::from_str(payload) {
+            Ok(envelope) => envelope,
+            Err(_) => return,
+        };
+        if node == self.node {
+            return;
+        }
+        let applied = if matches!(change.op, Op::Set(_)) {
+            let key = change.key.clone();
+            change.apply_if(store, Origin::Server, |current| {
+                let mut writers = self.writers.lock();
+                let latest = writers.get(&key).copied().unwrap_or((current, self.node));
+                // the version of the latest set, or of changes applied since
+                let latest = (latest.0.max(current), latest.1);
+                ((version, node) > latest).then(|| {
+                    writers.insert(key, (version, node));
+                    version
+                })
+            })
+        } else {
+            change.apply_to(store, Origin::Server)
+        };
+        let message = match applied {
+            Some(message) => message,
+            None => return,
+        };
+        REMOTE.with(|remote| remote.set(true));
+        router.send(message);
+        REMOTE.with(|remote| remote.set(false));
+    }
+}
+
+// the changes a routed message consists of, with the version of their value
+fn versioned(message: &Message) -> Vec<(u64, Change)> {
+    match message {
+        Message::Batch(messages) => messages.iter().flat_map(versioned).collect(),
+        message => {
+            let version = message.version().unwrap_or_default();
+            Change::from_message(message)
+                .into_iter()
+                .map(|change| (version, change))
+                .collect()
+        }
+    }
+}
+
+// Publishes the changes made here and applies the ones of other servers,
+// while the server is running. Failures to publish are only logged with the
+// `tracing` feature.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub async fn run(cluster: Arc<Cluster>, store: Store, router: Router) {
+    let mut outgoing = cluster.outgoing.lock().await;
+    let mut incoming = cluster.incoming.lock().await;
+    loop {
+        tokio::select! {
+            Some(payload) = outgoing.recv() => {
+                let backend = cluster.backend.clone();
+                let published = tokio::task::spawn_blocking(move || backend.publish(payload)).await;
+                if let Ok(Err(error)) = published {
+                    warn!(%error, "Failed to publish a change");
+                }
+            }
+            Some(payload) = incoming.recv() => cluster.receive(&payload, &store, &router),
+            else => break,
+        }
+    }
+}
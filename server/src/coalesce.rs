@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use futures_util::{stream, Stream, StreamExt};
+use tokio::time::{sleep_until, Instant};
+
+use crate::message::Message;
+
+// as routed to a connection, see `router::Subscription`
+type Queued = Result<(u64, Message), u64>;
+
+// Packs the messages routed within `interval` of the first one into a single
+// batch, numbered like the last of them, see `PocaBuilder::flush_interval`.
+// A new value of a key replaces the changes of the key before it in the batch.
+// Close frames and lag notices are passed on as they are, after the batch.
+pub fn coalesce(
+    queue: impl Stream<Item = Queued> + Unpin,
+    interval: Duration,
+) -> impl Stream<Item = Queued> {
+    stream::unfold((queue.fuse(), None), move |(mut queue, held)| async move {
+        let queued = match held {
+            Some(queued) => queued,
+            None => queue.next().await?,
+        };
+        let (mut seq, first) = match queued {
+            Ok((seq, message)) if batchable(&message) => (seq, message),
+            other => return Some((other, (queue, None))),
+        };
+        let mut batch = Vec::new();
+        push(&mut batch, first);
+        let deadline = Instant::now() + interval;
+        let mut held = None;
+        loop {
+            tokio::select! {
+                _ = sleep_until(deadline) => break,
+                queued = queue.next() => match queued {
+                    Some(Ok((next, message))) if batchable(&message) => {
+                        seq = next;
+                        push(&mut batch, message);
+                    }
+                    other => {
+                        held = other;
+                        break;
+                    }
+                },
+            }
+        }
+        let message = match batch.len() {
+            1 => batch.pop().unwrap(),
+            _ => Message::Batch(batch),
+        };
+        Some((Ok((seq, message)), (queue, held)))
+    })
+}
+
+fn batchable(message: &Message) -> bool {
+    !matches!(message, Message::Close { .. })
+}
+
+fn push(batch: &mut Vec<Message>, message: Message) {
+    match message {
+        Message::Batch(messages) => {
+            for message in messages {
+                push(batch, message);
+            }
+        }
+        message => {
+            if let Message::Set { key, .. } | Message::Remove { key } = &message {
+                batch.retain(|each| !matches!(each.change(), Some((each, _)) if each == key));
+            }
+            batch.push(message);
+        }
+    }
+}
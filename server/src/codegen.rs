@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::poca::Store;
+
+const HEADER: &str = "// Generated from the keys registered on the server, do not edit.
+import {Poca} from \"poca-client\";
+";
+
+// the schema of every key in the rooms the filter accepts, if it has one
+pub(crate) fn schemas(
+    store: &Store,
+    filter: impl Fn(Option<&str>) -> bool,
+) -> BTreeMap<String, Option<Value>> {
+    store
+        .elements()
+        .into_iter()
+        .filter_map(|(key, element)| {
+            let guard = element.read();
+            filter(guard.room.as_deref()).then(|| (key, guard.schema.clone()))
+        })
+        .collect()
+}
+
+// One JSON Schema for all keys, with the definitions of the separate schemas
+// merged, see `Poca::schema`. Keys without a schema accept any value.
+pub(crate) fn schema(keys: &BTreeMap<String, Option<Value>>) -> Value {
+    let properties = keys
+        .iter()
+        .map(|(key, schema)| {
+            let schema = match schema {
+                Some(Value::Object(schema)) => {
+                    let mut schema = schema.clone();
+                    schema.remove("definitions");
+                    schema.remove("$schema");
+                    Value::Object(schema)
+                }
+                Some(schema) => schema.clone(),
+                None => Value::Bool(true),
+            };
+            (key.clone(), schema)
+        })
+        .collect::<Map<_, _>>();
+    let definitions = definitions(keys)
+        .into_iter()
+        .map(|(name, schema)| (name, schema.clone()))
+        .collect::<Map<_, _>>();
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "object",
+        "properties": properties,
+        "required": keys.keys().collect::<Vec<_>>(),
+        "definitions": definitions,
+    })
+}
+
+// TypeScript for the keys and the JSON Schemas of their types, see
+// `Poca::typescript`. Definitions shared by the schemas become interfaces,
+// every key gets typed accessors in `keys`.
+pub(crate) fn typescript(keys: &BTreeMap<String, Option<Value>>) -> String {
+    let definitions = definitions(keys);
+
+    let mut output = HEADER.to_string();
+    for (name, schema) in definitions {
+        output.push('\n');
+        match schema.get("properties") {
+            Some(Value::Object(properties)) => {
+                output += &format!("export interface {} {{\n", name);
+                for line in property_lines(properties, schema) {
+                    output += &format!("  {};\n", line);
+                }
+                output += "}\n";
+            }
+            _ => output += &format!("export type {} = {};\n", name, ts_type(schema)),
+        }
+    }
+
+    output += "\nexport interface Keys {\n";
+    for (key, schema) in keys {
+        let ty = schema.as_ref().map_or("unknown".to_string(), ts_type);
+        output += &format!("  {}: {};\n", property_name(key), ty);
+    }
+    output += "}\n\nexport const keys = {\n";
+    for key in keys.keys() {
+        let literal = Value::from(key.as_str()).to_string();
+        let ty = format!("Keys[{}]", literal);
+        output += &format!("  {}: {{\n", literal);
+        output += &format!(
+            // `reactive` only takes objects
+            "    reactive: (poca: Poca) => poca.reactive<{} & Object>({}),\n",
+            ty, literal
+        );
+        output += &format!(
+            "    compare_and_set: (poca: Poca, expected: {}, value: {}) =>\n      poca.compare_and_set<{}>({}, expected, value),\n",
+            ty, ty, ty, literal
+        );
+        output += "  },\n";
+    }
+    output += "};\n";
+    output
+}
+
+fn definitions(keys: &BTreeMap<String, Option<Value>>) -> BTreeMap<String, &Value> {
+    let mut definitions = BTreeMap::new();
+    for schema in keys.values().flatten() {
+        if let Some(Value::Object(shared)) = schema.get("definitions") {
+            definitions.extend(shared.iter().map(|(name, schema)| (name.clone(), schema)));
+        }
+    }
+    definitions
+}
+
+fn ts_type(schema: &Value) -> String {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        Value::Bool(false) => return "never".to_string(),
+        _ => return "unknown".to_string(),
+    };
+    if let Some(Value::String(reference)) = schema.get("$ref") {
+        // like `#/definitions/Point`
+        return reference.rsplit('/').next().unwrap_or_default().to_string();
+    }
+    if let Some(value) = schema.get("const") {
+        return value.to_string();
+    }
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        return union(values.iter().map(Value::to_string));
+    }
+    for combinator in ["anyOf", "oneOf"] {
+        if let Some(Value::Array(schemas)) = schema.get(combinator) {
+            return union(schemas.iter().map(ts_type));
+        }
+    }
+    if let Some(Value::Array(schemas)) = schema.get("allOf") {
+        let types = schemas.iter().map(ts_type).collect::<Vec<_>>();
+        return types.join(" & ");
+    }
+    let types = match schema.get("type") {
+        Some(Value::String(ty)) => vec![ty.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => return "unknown".to_string(),
+    };
+    union(types.into_iter().map(|ty| match ty {
+        "null" => "null".to_string(),
+        "boolean" => "boolean".to_string(),
+        "integer" | "number" => "number".to_string(),
+        "string" => "string".to_string(),
+        "array" => array_type(schema),
+        "object" => object_type(schema),
+        _ => "unknown".to_string(),
+    }))
+}
+
+fn array_type(schema: &Map<String, Value>) -> String {
+    match schema.get("items") {
+        // tuples
+        Some(Value::Array(items)) => {
+            let items = items.iter().map(ts_type).collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Some(items) => {
+            let item = ts_type(items);
+            if item.contains(' ') {
+                format!("({})[]", item)
+            } else {
+                format!("{}[]", item)
+            }
+        }
+        None => "unknown[]".to_string(),
+    }
+}
+
+fn object_type(schema: &Map<String, Value>) -> String {
+    if let Some(Value::Object(properties)) = schema.get("properties") {
+        let schema = Value::Object(schema.clone());
+        return format!("{{{}}}", property_lines(properties, &schema).join("; "));
+    }
+    match schema.get("additionalProperties") {
+        Some(Value::Bool(false)) => "{}".to_string(),
+        Some(values) => format!("Record<string, {}>", ts_type(values)),
+        None => "Record<string, unknown>".to_string(),
+    }
+}
+
+fn property_lines(properties: &Map<String, Value>, schema: &Value) -> Vec<String> {
+    let required = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| {
+            required
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    properties
+        .iter()
+        .map(|(name, property)| {
+            let optional = if required.contains(&name.as_str()) {
+                ""
+            } else {
+                "?"
+            };
+            format!("{}{}: {}", property_name(name), optional, ts_type(property))
+        })
+        .collect()
+}
+
+// quoted unless it is a valid identifier
+fn property_name(name: &str) -> String {
+    let identifier = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if identifier {
+        name.to_string()
+    } else {
+        Value::from(name).to_string()
+    }
+}
+
+fn union(types: impl Iterator<Item = String>) -> String {
+    let mut unique = Vec::new();
+    for ty in types {
+        if !unique.contains(&ty) {
+            unique.push(ty);
+        }
+    }
+    match unique.len() {
+        0 => "never".to_string(),
+        _ => unique.join(" | "),
+    }
+}
@@ -0,0 +1,15 @@
+use crate::synchronizable::Synchronizable;
+
+// How a client write based on an outdated version of a value is handled.
+// A merge handler set with `DataHandle::on_conflict` takes precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    #[default]
+    LastWriteWins,
+    RejectStale,
+}
+
+// called with the current value and the stale incoming one
+pub type MergeHandler = Box<
+    dyn Fn(&dyn Synchronizable, Box<dyn Synchronizable>) -> Box<dyn Synchronizable> + Send + Sync,
+>;
@@ -0,0 +1,145 @@
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::{Mutex, ReentrantMutex};
+use tokio::sync::mpsc;
+
+use crate::{
+    client::{ClientId, ClientInfo, ClientSummary},
+    data_handle::DataHandle,
+    message::Message,
+};
+
+// built-in, read-only key listing the connected clients
+pub const CLIENTS_KEY: &str = "$clients";
+
+// a channel to every connected client, next to its queue in the router
+// for messages that only some clients should get
+#[derive(Clone)]
+pub struct Connections {
+    clients: Arc<Mutex<HashMap<ClientId, Connection>>>,
+    online: Arc<Mutex<Option<DataHandle<Vec<ClientSummary>>>>>,
+    // Held from listing the clients until the list is set, so the latest
+    // list is set last. Handlers of the key may publish again.
+    publishing: Arc<ReentrantMutex<()>>,
+    // messages queued for a client before further ones are dropped
+    capacity: usize,
+    // the metadata keys that are published
+    public: Arc<Vec<String>>,
+}
+
+struct Connection {
+    info: ClientInfo,
+    messages: mpsc::Sender<Message>,
+    // close frames can't be held back by a full queue
+    close: mpsc::UnboundedSender<Message>,
+}
+
+// the receiving ends of a client's channels
+pub struct Direct {
+    pub messages: mpsc::Receiver<Message>,
+    pub close: mpsc::UnboundedReceiver<Message>,
+}
+
+impl Connections {
+    pub fn new(capacity: usize, public: Vec<String>) -> Self {
+        Connections {
+            clients: Arc::default(),
+            online: Arc::default(),
+            publishing: Arc::default(),
+            capacity,
+            public: Arc::new(public),
+        }
+    }
+
+    // the key `publish` keeps up to date
+    pub fn set_online(&self, handle: DataHandle<Vec<ClientSummary>>) {
+        *self.online.lock() = Some(handle);
+    }
+
+    pub fn connect(&self, client: &ClientInfo) -> Direct {
+        let (messages, message_receiver) = mpsc::channel(self.capacity);
+        let (close, close_receiver) = mpsc::unbounded_channel();
+        let connection = Connection {
+            info: client.clone(),
+            messages,
+            close,
+        };
+        self.clients.lock().insert(client.id, connection);
+        self.publish();
+        Direct {
+            messages: message_receiver,
+            close: close_receiver,
+        }
+    }
+
+    pub fn disconnect(&self, client: ClientId) {
+        self.clients.lock().remove(&client);
+        self.publish();
+    }
+
+    pub fn is_connected(&self, client: ClientId) -> bool {
+        self.clients.lock().contains_key(&client)
+    }
+
+    pub fn get(&self, client: ClientId) -> Option<ClientInfo> {
+        self.clients
+            .lock()
+            .get(&client)
+            .map(|connection| connection.info.clone())
+    }
+
+    // ordered by id
+    pub fn clients(&self) -> Vec<ClientInfo> {
+        let mut clients = self
+            .clients
+            .lock()
+            .values()
+            .map(|connection| connection.info.clone())
+            .collect::<Vec<_>>();
+        clients.sort_by_key(|info| info.id);
+        clients
+    }
+
+    // updates the built-in key, with the public metadata only
+    pub fn publish(&self) {
+        let _publishing = self.publishing.lock();
+        let summaries = self
+            .clients()
+            .iter()
+            .map(|info| {
+                let mut summary = info.summary();
+                summary.metadata.retain(|key, _| self.public.contains(key));
+                summary
+            })
+            .collect();
+        let online = self.online.lock().clone();
+        if let Some(online) = online {
+            online.set(summaries);
+        }
+    }
+
+    // false if the client is not connected or its queue is full
+    pub fn send(&self, client: ClientId, message: Message) -> bool {
+        match self.clients.lock().get(&client) {
+            Some(connection) => connection.send(message),
+            None => false,
+        }
+    }
+
+    pub fn send_except(&self, client: ClientId, message: Message) {
+        for (id, connection) in self.clients.lock().iter() {
+            if *id != client {
+                connection.send(message.clone());
+            }
+        }
+    }
+}
+
+impl Connection {
+    fn send(&self, message: Message) -> bool {
+        match message {
+            Message::Close { .. } => self.close.send(message).is_ok(),
+            message => self.messages.try_send(message).is_ok(),
+        }
+    }
+}
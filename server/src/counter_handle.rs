@@ -0,0 +1,42 @@
+use std::ops::Deref;
+
+use crate::{client::Origin, data_handle::DataHandle, message::Message};
+
+// Increments are broadcast as operations rather than values, so concurrent
+// increments from the server and from clients don't overwrite each other.
+#[derive(Clone)]
+pub struct CounterHandle {
+    handle: DataHandle<i64>,
+}
+
+impl Deref for CounterHandle {
+    type Target = DataHandle<i64>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl CounterHandle {
+    pub fn new(handle: DataHandle<i64>) -> Self {
+        Self { handle }
+    }
+
+    // returns the new value
+    pub fn increment(&self, by: i64) -> i64 {
+        self.handle.update_with_message(|value, version| {
+            *value = value.wrapping_add(by);
+            let message = Message::Increment {
+                key: self.handle.get_key().to_owned(),
+                by,
+                origin: Origin::Server,
+                version,
+            };
+            (*value, Some(message))
+        })
+    }
+
+    pub fn decrement(&self, by: i64) -> i64 {
+        self.increment(by.wrapping_neg())
+    }
+}
@@ -1,35 +1,66 @@
 use crate::{
-    event_handler::{EventHandler, OnChangeEventHandlerStore},
-    message::Message,
-    poca::DataElement,
+    access::Access,
+    changes::Changes,
+    client::Origin,
+    conflict::{ConflictPolicy, MergeHandler},
+    error::{KeyError, PatchError},
+    event_handler::{notify_change, remove_handler, CallbackGuard, CallbackId, OnChangeHandler},
+    message::{payload, Message},
+    patch::{apply_patch, changed_fields},
+    poca::{DataElement, Store},
+    router::{Priority, Router},
     synchronizable::Synchronizable,
+    trace::warn,
+    transform::Transform,
+    validation::Validator,
 };
-use parking_lot::RwLock;
-use std::{marker::PhantomData, ops::Deref, sync::Arc};
-use tokio::sync::broadcast;
+use futures_util::Stream;
+use parking_lot::{MappedRwLockReadGuard, RwLockReadGuard};
+use serde_json::Value;
+use std::{future::Future, marker::PhantomData, sync::Arc, time::Duration};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, watch},
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
 
 pub struct DataHandle<T>
 where
     T: Synchronizable + 'static,
 {
     key: String,
-    sender: broadcast::Sender<Message>,
+    sender: Router,
     data_type: PhantomData<T>,
     data_element: DataElement,
-    on_change: OnChangeEventHandlerStore<T>,
+    store: Store,
+}
+
+impl<T> Clone for DataHandle<T>
+where
+    T: Synchronizable + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key.clone(),
+            sender: self.sender.clone(),
+            data_type: PhantomData,
+            data_element: self.data_element.clone(),
+            store: self.store.clone(),
+        }
+    }
 }
 
 impl<T> DataHandle<T>
 where
     T: Synchronizable + 'static,
 {
-    pub fn new(key: String, sender: broadcast::Sender<Message>, data_element: DataElement) -> Self {
+    pub fn new(key: String, sender: Router, data_element: DataElement, store: Store) -> Self {
         Self {
             key,
             sender,
             data_type: PhantomData,
             data_element,
-            on_change: Arc::new(RwLock::new(Vec::new())),
+            store,
         }
     }
 
@@ -37,43 +68,404 @@ where
         &self.key
     }
 
+    // removes the key from the store, unless it was registered again since
+    pub fn delete(self) -> Result<(), KeyError> {
+        {
+            let mut store_lock = self.store.shard(&self.key);
+            match store_lock.get(&self.key) {
+                Some(element) if Arc::ptr_eq(element, &self.data_element) => {
+                    store_lock.remove(&self.key);
+                }
+                _ => return Err(KeyError::NotFound(self.key)),
+            }
+        }
+        self.sender.send(Message::Remove { key: self.key });
+        Ok(())
+    }
+
+    pub fn get_access(&self) -> Access {
+        self.data_element.read().access
+    }
+
+    pub fn set_access(&self, access: Access) {
+        self.data_element.write().access = access;
+    }
+
+    pub fn get_room(&self) -> Option<String> {
+        self.data_element.read().room.clone()
+    }
+
+    // Only members of the room receive the key, see `Poca::join`.
+    // Clients that can no longer see it keep their last value.
+    pub fn set_room(&self, room: Option<&str>) {
+        self.data_element.write().room = room.map(str::to_string);
+    }
+
+    pub fn get_schema(&self) -> Option<Value> {
+        self.data_element.read().schema.clone()
+    }
+
+    // Describes the type for generated clients, see `Poca::typescript`.
+    // Usually derived with `with_schema`.
+    pub fn set_schema(&self, schema: Value) {
+        self.data_element.write().schema = Some(schema);
+    }
+
+    #[cfg(feature = "schema")]
+    pub fn with_schema(self) -> Self
+    where
+        T: schemars::JsonSchema,
+    {
+        let schema = schemars::schema_for!(T);
+        self.set_schema(serde_json::to_value(schema).unwrap());
+        self
+    }
+
+    pub fn get_require_ack(&self) -> bool {
+        self.data_element.read().require_ack
+    }
+
+    // Clients have to acknowledge every change of the key. Unacknowledged
+    // changes are sent again, see `PocaBuilder::ack_timeout`.
+    pub fn set_require_ack(&self, require_ack: bool) {
+        self.data_element.write().require_ack = require_ack;
+    }
+
+    pub fn get_conflict_policy(&self) -> ConflictPolicy {
+        self.data_element.read().conflict_policy
+    }
+
+    pub fn set_conflict_policy(&self, policy: ConflictPolicy) {
+        self.data_element.write().conflict_policy = policy;
+    }
+
+    // resolves stale client writes from the current and the incoming value,
+    // the patched one for patches, regardless of the conflict policy
+    pub fn on_conflict(&self, handler: impl Fn(&T, T) -> T + Send + Sync + 'static) {
+        let handler: MergeHandler = Box::new(move |current, incoming| {
+            let current = current.as_any_ref().downcast_ref::<T>().unwrap();
+            let incoming = *incoming.clone_any_box().downcast::<T>().unwrap();
+            Box::new(handler(current, incoming))
+        });
+        self.data_element.write().merge_handler = Some(handler);
+    }
+
+    // Client writes a validator rejects are not applied, the reason is sent
+    // back to the client. Changes made on the server are not validated.
+    pub fn validate(&self, validator: impl Fn(&T) -> Result<(), String> + Send + Sync + 'static) {
+        let validator: Validator =
+            Box::new(move |value| validator(value.as_any_ref().downcast_ref::<T>().unwrap()));
+        self.data_element.write().validators.push(validator);
+    }
+
+    // Client writes are passed through the transforms before validation, e.g.
+    // to normalize strings. Changes made on the server are not transformed.
+    pub fn transform(&self, transform: impl Fn(T) -> T + Send + Sync + 'static) {
+        let transform: Transform = Box::new(move |value| {
+            let value = *value.clone_any_box().downcast::<T>().unwrap();
+            Box::new(transform(value))
+        });
+        self.data_element.write().transforms.push(transform);
+    }
+
+    // Changes are broadcast at most once per interval, the ones in between
+    // are only sent as part of the value at the end of it. Best set before
+    // the key changes, it applies until the key is removed.
+    pub fn max_update_rate(&self, interval: Duration) {
+        let value = serde_json::from_str(&self.data_element.read().data.serialize()).unwrap();
+        self.sender.throttle(&self.key, interval, value);
+    }
+
+    // When a connection falls behind, changes of the key overtake queued
+    // messages of lower priority. Applies until the key is removed.
+    pub fn set_priority(&self, priority: Priority) {
+        self.sender.prioritize(&self.key, priority);
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.sender.priority_of(&self.key)
+    }
+
+    pub fn version(&self) -> u64 {
+        self.data_element.read().version
+    }
+
     pub fn set(&self, value: T) {
+        self.update(move |data| *data = value);
+    }
+
+    // Changes are sent while the value is locked, so they are broadcast in the
+    // order of their versions, and before the ones handlers make.
+    pub fn update(&self, updater: impl FnOnce(&mut T)) {
+        let old;
         {
             let mut guard = self.data_element.write();
-            guard.data = value.clone_synchronizable();
+            guard.version += 1;
+            let version = guard.version;
+            old = guard.data.clone();
+            guard.remember(old.as_ref());
+            updater(guard.data.as_any_mut().downcast_mut().unwrap());
+            let data = payload(guard.data.as_ref());
+            let fields = match (
+                serde_json::from_str::<Value>(&old.serialize()),
+                serde_json::from_slice::<Value>(&data),
+            ) {
+                (Ok(old), Ok(new)) => changed_fields(&old, &new),
+                _ => None,
+            };
+            let request = match fields {
+                Some(fields) => Message::MergePatch {
+                    key: self.key.to_owned(),
+                    fields,
+                    origin: Origin::Server,
+                    version,
+                },
+                None => Message::Set {
+                    key: self.key.to_owned(),
+                    data,
+                    origin: Origin::Server,
+                    version,
+                },
+            };
+            self.sender.send(request);
         }
+        self.notify_change(old);
+    }
+
+    // on mismatch the current value is returned and nothing changes
+    pub fn compare_and_set(&self, expected: &T, new: T) -> Result<(), T>
+    where
+        T: PartialEq,
+    {
+        let old;
         {
-            let handle = self.data_element.read();
-            for each in &handle.on_change {
-                let handler = each.deref();
-                handler.execute();
+            let mut guard = self.data_element.write();
+            if guard.data.as_any_ref().downcast_ref::<T>().unwrap() != expected {
+                return Err(*guard.data.clone_any_box().downcast().unwrap());
             }
+            old = std::mem::replace(&mut guard.data, Box::new(new) as Box<dyn Synchronizable>);
+            guard.remember(old.as_ref());
+            guard.version += 1;
+            self.sender.send(Message::Set {
+                key: self.key.to_owned(),
+                data: payload(guard.data.as_ref()),
+                origin: Origin::Server,
+                version: guard.version,
+            });
         }
-        let request = Message::Set {
-            key: self.key.to_owned(),
-            data: Box::new(value),
+        self.notify_change(old);
+        Ok(())
+    }
+
+    pub fn patch(&self, ops: json_patch::Patch) -> Result<(), PatchError> {
+        let old = {
+            let mut guard = self.data_element.write();
+            let old = guard.data.clone();
+            apply_patch(&mut guard, &ops)?;
+            guard.remember(old.as_ref());
+            guard.version += 1;
+            self.sender.send(Message::Patch {
+                key: self.key.to_owned(),
+                ops,
+                origin: Origin::Server,
+                version: guard.version,
+            });
+            old
+        };
+        self.notify_change(old);
+        Ok(())
+    }
+
+    // for specialized handles that know the delta of their own operations
+    pub(crate) fn update_with_patch<R>(
+        &self,
+        updater: impl FnOnce(&mut T) -> (R, json_patch::Patch),
+    ) -> R {
+        self.update_with_message(|data, version| {
+            let (result, ops) = updater(data);
+            let message = (!ops.0.is_empty()).then(|| Message::Patch {
+                key: self.key.to_owned(),
+                ops,
+                origin: Origin::Server,
+                version,
+            });
+            (result, message)
+        })
+    }
+
+    // The updater gets the version the value will have after the change.
+    // Nothing is broadcast and no handler is called if there is no message.
+    pub(crate) fn update_with_message<R>(
+        &self,
+        updater: impl FnOnce(&mut T, u64) -> (R, Option<Message>),
+    ) -> R {
+        let (result, changed, old) = {
+            let mut guard = self.data_element.write();
+            let version = guard.version + 1;
+            let old = guard.data.clone();
+            let (result, message) =
+                updater(guard.data.as_any_mut().downcast_mut().unwrap(), version);
+            let changed = message.is_some();
+            if let Some(message) = message {
+                guard.remember(old.as_ref());
+                guard.version = version;
+                self.sender.send(message);
+            }
+            (result, changed, old)
         };
-        self.sender.send(request).unwrap();
+        if changed {
+            self.notify_change(old);
+        }
+        result
     }
 
-    pub fn get(&self) -> Box<T> {
+    // the last `n` previous values, the most recent first
+    pub fn history(&self, n: usize) -> Vec<T> {
         let guard = self.data_element.read();
-        guard.data.clone_any_box().downcast().unwrap()
-    }
-
-    pub fn on_change(&'static self, handler: impl Fn(T) + Send + Sync + 'static) {
-        let boxed_handler = Box::new(handler);
-        let mut lock = self.on_change.write();
-        let current_index = lock.len();
-        lock.push(boxed_handler);
-        let new_handler = move || {
-            let mut guard = self.on_change.write();
-            let handler = guard.get_mut(current_index).unwrap();
-            let value = self.get();
-            (*handler)(*value);
+        guard
+            .history
+            .iter()
+            .rev()
+            .take(n)
+            .map(|value| *value.clone_any_box().downcast().unwrap())
+            .collect()
+    }
+
+    // How many previous values are kept for `undo`, 16 by default.
+    // 0 disables the history.
+    pub fn set_history_limit(&self, limit: usize) {
+        let mut guard = self.data_element.write();
+        guard.history_limit = limit;
+        let excess = guard.history.len().saturating_sub(limit);
+        guard.history.drain(..excess);
+    }
+
+    // Reverts to the previous value and broadcasts it, false if there is none.
+    // Undoing is not recorded, so repeated calls step further back.
+    pub fn undo(&self) -> bool {
+        let old = {
+            let mut guard = self.data_element.write();
+            let previous = match guard.history.pop_back() {
+                Some(previous) => previous,
+                None => return false,
+            };
+            let old = std::mem::replace(&mut guard.data, previous);
+            guard.version += 1;
+            self.sender.send(Message::Set {
+                key: self.key.to_owned(),
+                data: payload(guard.data.as_ref()),
+                origin: Origin::Server,
+                version: guard.version,
+            });
+            old
         };
-        let dyn_handler = Box::new(new_handler) as Box<dyn Fn() + Send + Sync>;
+        notify_change(&self.data_element, old, Origin::Server);
+        true
+    }
+
+    fn notify_change(&self, old: Box<dyn Synchronizable>) {
+        notify_change(&self.data_element, old, Origin::Server);
+    }
+
+    pub fn get(&self) -> T {
+        let guard = self.data_element.read();
+        *guard.data.clone_any_box().downcast().unwrap()
+    }
+
+    pub fn read(&self) -> MappedRwLockReadGuard<'_, T> {
+        RwLockReadGuard::map(self.data_element.read(), |inner| {
+            inner.data.as_any_ref().downcast_ref().unwrap()
+        })
+    }
+
+    pub fn on_change(&self, handler: impl Fn(T) + Send + Sync + 'static) -> CallbackId {
+        let dyn_handler = Arc::new(
+            move |_: &dyn Synchronizable, data: &dyn Synchronizable, _| {
+                handler(*data.clone_any_box().downcast::<T>().unwrap());
+            },
+        ) as OnChangeHandler;
+        self.add_handler(dyn_handler)
+    }
+
+    // The returned future is spawned on the runtime the change happens on,
+    // or else on the one the handler was registered on. Without either, the
+    // change is only logged with the `tracing` feature.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub fn on_change_async<F, Fut>(&self, handler: F) -> CallbackId
+    where
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let registered_on = Handle::try_current().ok();
+        let key = self.key.clone();
+        self.on_change(move |value| {
+            if let Some(runtime) = Handle::try_current().ok().or_else(|| registered_on.clone()) {
+                runtime.spawn(handler(value));
+            } else {
+                warn!(%key, "No runtime to run an on_change handler on");
+            }
+        })
+    }
+
+    // yields every new value from now on, the handler goes away with the stream
+    pub fn changes(&self) -> impl Stream<Item = T> + Unpin {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let id = self.on_change(move |value| {
+            sender.send(value).ok();
+        });
+        Changes::new(UnboundedReceiverStream::new(receiver), self.guard(id))
+    }
+
+    // always holds the latest value, the handler goes away with the last receiver
+    pub fn watch(&self) -> watch::Receiver<T> {
         let mut guard = self.data_element.write();
-        guard.on_change.push(dyn_handler);
+        let (sender, receiver) =
+            watch::channel(*guard.data.clone_any_box().downcast::<T>().unwrap());
+        let id = CallbackId::next();
+        let element = Arc::downgrade(&self.data_element);
+        let handler = Arc::new(
+            move |_: &dyn Synchronizable, data: &dyn Synchronizable, _| {
+                let value = *data.clone_any_box().downcast::<T>().unwrap();
+                if sender.send(value).is_err() {
+                    if let Some(element) = element.upgrade() {
+                        remove_handler(&element, id);
+                    }
+                }
+            },
+        ) as OnChangeHandler;
+        guard.on_change.push((id, handler));
+        receiver
+    }
+
+    pub fn on_change_with_old(
+        &self,
+        handler: impl Fn(&T, &T, Origin) + Send + Sync + 'static,
+    ) -> CallbackId {
+        let dyn_handler = Arc::new(
+            move |old: &dyn Synchronizable, new: &dyn Synchronizable, origin| {
+                handler(
+                    old.as_any_ref().downcast_ref().unwrap(),
+                    new.as_any_ref().downcast_ref().unwrap(),
+                    origin,
+                );
+            },
+        ) as OnChangeHandler;
+        self.add_handler(dyn_handler)
+    }
+
+    fn add_handler(&self, handler: OnChangeHandler) -> CallbackId {
+        let id = CallbackId::next();
+        self.data_element.write().on_change.push((id, handler));
+        id
+    }
+
+    // false if the handler was already removed
+    pub fn remove_on_change(&self, id: CallbackId) -> bool {
+        remove_handler(&self.data_element, id)
+    }
+
+    // ties the handler to the lifetime of the returned guard
+    pub fn guard(&self, id: CallbackId) -> CallbackGuard {
+        CallbackGuard::new(id, &self.data_element)
     }
 }
@@ -0,0 +1,25 @@
+use std::{collections::HashSet, net::IpAddr};
+
+use crate::client::ClientInfo;
+
+// the metadata key an authenticator stores the identity of a client under
+pub const IDENTITY_METADATA: &str = "identity";
+
+// consulted when a client connects, after authentication
+#[derive(Debug, Default)]
+pub struct DenyList {
+    pub addresses: HashSet<IpAddr>,
+    pub identities: HashSet<String>,
+}
+
+impl DenyList {
+    pub fn is_denied(&self, client: &ClientInfo) -> bool {
+        let address = client
+            .address
+            .is_some_and(|address| self.addresses.contains(&address.ip()));
+        let identity = client
+            .get_metadata(IDENTITY_METADATA)
+            .is_some_and(|identity| self.identities.contains(&identity));
+        address || identity
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use warp::ws;
+
+use crate::message::WSMessage;
+
+pub const JSON_SUBPROTOCOL: &str = "poca.json";
+#[cfg(feature = "msgpack")]
+pub const MSGPACK_SUBPROTOCOL: &str = "poca.msgpack";
+#[cfg(feature = "deflate")]
+pub const DEFLATE_SUBPROTOCOL: &str = "poca.json+deflate";
+
+pub trait Encoding: Send + Sync + 'static {
+    fn encode(&self, message: &WSMessage) -> ws::Message;
+    fn decode(&self, message: &ws::Message) -> Result<WSMessage, String>;
+
+    // Encodings of the same name must encode a message into the same frame.
+    // Broadcast messages are then only encoded once for all of their clients.
+    fn name(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+pub struct JsonEncoding;
+
+impl Encoding for JsonEncoding {
+    fn encode(&self, message: &WSMessage) -> ws::Message {
+        ws::Message::text(serde_json::to_string(message).unwrap())
+    }
+
+    fn decode(&self, message: &ws::Message) -> Result<WSMessage, String> {
+        let text = message
+            .to_str()
+            .map_err(|_| "Expected a text frame".to_string())?;
+        serde_json::from_str(text).map_err(|error| error.to_string())
+    }
+
+    fn name(&self) -> Option<&'static str> {
+        Some(JSON_SUBPROTOCOL)
+    }
+}
+
+// Values travel as native MessagePack instead of nested JSON strings,
+// so they are transcoded at the edge while the rest of the pipeline stays JSON.
+#[cfg(feature = "msgpack")]
+pub struct MessagePackEncoding;
+
+#[cfg(feature = "msgpack")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BinaryMessage {
+    message_type: crate::message::WSMessageType,
+    key: Option<String>,
+    data: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seq: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    ack: bool,
+}
+
+#[cfg(feature = "msgpack")]
+impl Encoding for MessagePackEncoding {
+    fn encode(&self, message: &WSMessage) -> ws::Message {
+        let data = message.data.as_ref().map(|data| {
+            serde_json::from_str(data).unwrap_or_else(|_| serde_json::Value::String(data.clone()))
+        });
+        ws::Message::binary(
+            rmp_serde::to_vec_named(&BinaryMessage {
+                message_type: message.message_type.clone(),
+                key: message.key.clone(),
+                data,
+                version: message.version,
+                id: message.id,
+                seq: message.seq,
+                ack: message.ack,
+            })
+            .unwrap(),
+        )
+    }
+
+    fn decode(&self, message: &ws::Message) -> Result<WSMessage, String> {
+        let message: BinaryMessage =
+            rmp_serde::from_slice(message.as_bytes()).map_err(|error| error.to_string())?;
+        Ok(WSMessage {
+            message_type: message.message_type,
+            key: message.key,
+            data: message.data.map(|data| data.to_string()),
+            version: message.version,
+            id: message.id,
+            seq: message.seq,
+            ack: message.ack,
+        })
+    }
+
+    fn name(&self) -> Option<&'static str> {
+        Some(MSGPACK_SUBPROTOCOL)
+    }
+}
+
+// warp's tungstenite does not implement the permessage-deflate extension,
+// so compression is negotiated as a subprotocol instead: JSON frames above
+// the threshold are sent as binary frames holding the raw DEFLATE stream.
+#[cfg(feature = "deflate")]
+pub struct DeflateEncoding {
+    pub threshold: usize,
+}
+
+#[cfg(feature = "deflate")]
+impl Encoding for DeflateEncoding {
+    fn encode(&self, message: &WSMessage) -> ws::Message {
+        use std::io::Write;
+
+        let text = serde_json::to_string(message).unwrap();
+        if text.len() < self.threshold {
+            return ws::Message::text(text);
+        }
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        ws::Message::binary(encoder.finish().unwrap())
+    }
+
+    fn decode(&self, message: &ws::Message) -> Result<WSMessage, String> {
+        use std::io::Read;
+
+        if !message.is_binary() {
+            return JsonEncoding.decode(message);
+        }
+        let mut text = String::new();
+        flate2::read::DeflateDecoder::new(message.as_bytes())
+            .read_to_string(&mut text)
+            .map_err(|error| error.to_string())?;
+        serde_json::from_str(&text).map_err(|error| error.to_string())
+    }
+
+    // the threshold is the same for every client of a server
+    fn name(&self) -> Option<&'static str> {
+        Some(DEFLATE_SUBPROTOCOL)
+    }
+}
+
+// Picks the first supported entry of the Sec-WebSocket-Protocol header.
+// Returns the protocol to echo back, none if the client did not ask for one.
+// Compression is only offered when the server has a threshold configured.
+#[cfg_attr(not(feature = "deflate"), allow(unused_variables))]
+pub fn negotiate(
+    requested: Option<&str>,
+    compression_threshold: Option<usize>,
+) -> (Arc<dyn Encoding>, Option<&'static str>) {
+    for protocol in requested.unwrap_or_default().split(',').map(str::trim) {
+        match protocol {
+            JSON_SUBPROTOCOL => return (Arc::new(JsonEncoding), Some(JSON_SUBPROTOCOL)),
+            #[cfg(feature = "msgpack")]
+            MSGPACK_SUBPROTOCOL => {
+                return (Arc::new(MessagePackEncoding), Some(MSGPACK_SUBPROTOCOL))
+            }
+            #[cfg(feature = "deflate")]
+            DEFLATE_SUBPROTOCOL => {
+                if let Some(threshold) = compression_threshold {
+                    return (
+                        Arc::new(DeflateEncoding { threshold }),
+                        Some(DEFLATE_SUBPROTOCOL),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    (Arc::new(JsonEncoding), None)
+}
@@ -0,0 +1,218 @@
+use std::{error::Error, fmt::Display, net::SocketAddr, path::PathBuf};
+
+use crate::client::ClientId;
+
+#[derive(Debug)]
+pub enum PocaError {
+    Bind {
+        address: SocketAddr,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    BindUnix {
+        path: PathBuf,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    AlreadyRunning,
+    InvalidAddress,
+    Tls(String),
+    // a setting of the builder can't be used
+    Config(String),
+    // the storage the store is persisted to can't be read
+    Persistence(Box<dyn Error + Send + Sync>),
+    // the client sent something that isn't a valid message
+    Protocol {
+        client: ClientId,
+        reason: String,
+    },
+    // a value sent by a client doesn't fit the type of its key
+    Serialization {
+        key: String,
+        type_name: &'static str,
+        reason: String,
+    },
+    // the authenticator turned a connection away
+    Auth {
+        address: Option<SocketAddr>,
+        reason: String,
+    },
+    // the connection of the client broke down before it was closed
+    ChannelClosed(ClientId),
+}
+
+impl Display for PocaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PocaError::Bind { address, source } => {
+                write!(f, "Failed to bind to {}: {}", address, source)
+            }
+            PocaError::BindUnix { path, source } => {
+                write!(f, "Failed to bind to {}: {}", path.display(), source)
+            }
+            PocaError::AlreadyRunning => write!(f, "Server is already running"),
+            PocaError::InvalidAddress => write!(f, "Server address cannot be resolved"),
+            PocaError::Tls(reason) => write!(f, "Invalid TLS configuration: {}", reason),
+            PocaError::Config(reason) => write!(f, "Invalid configuration: {}", reason),
+            PocaError::Persistence(source) => write!(f, "Failed to restore the store: {}", source),
+            PocaError::Protocol { client, reason } => {
+                write!(f, "Protocol error of client {}: {}", client, reason)
+            }
+            PocaError::Serialization {
+                key,
+                type_name,
+                reason,
+            } => write!(
+                f,
+                "Invalid value for key {} of type {}: {}",
+                key, type_name, reason
+            ),
+            PocaError::Auth {
+                address: Some(address),
+                reason,
+            } => write!(f, "Authentication of {} failed: {}", address, reason),
+            PocaError::Auth {
+                address: None,
+                reason,
+            } => write!(f, "Authentication failed: {}", reason),
+            PocaError::ChannelClosed(client) => {
+                write!(f, "Connection of client {} broke down", client)
+            }
+        }
+    }
+}
+
+impl Error for PocaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PocaError::Bind { source, .. }
+            | PocaError::BindUnix { source, .. }
+            | PocaError::Persistence(source) => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyError {
+    AlreadyExists(String),
+    NotFound(String),
+    TypeMismatch(String),
+}
+
+impl Display for KeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyError::AlreadyExists(key) => write!(f, "Key {} already exists", key),
+            KeyError::NotFound(key) => write!(f, "Key {} does not exist", key),
+            KeyError::TypeMismatch(key) => write!(f, "Key {} holds a value of another type", key),
+        }
+    }
+}
+
+impl Error for KeyError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeError {
+    NotFound(String),
+    Mismatch {
+        key: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeError::NotFound(key) => write!(f, "Key {} does not exist", key),
+            TypeError::Mismatch {
+                key,
+                expected,
+                found,
+            } => write!(f, "Key {} holds a {}, not a {}", key, found, expected),
+        }
+    }
+}
+
+impl Error for TypeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    Apply(String),
+    Type(String),
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::Apply(reason) => write!(f, "Failed to apply patch: {}", reason),
+            PatchError::Type(reason) => write!(f, "Patched value has the wrong shape: {}", reason),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+// an element of a list that doesn't exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexError {
+    pub index: usize,
+    pub len: usize,
+}
+
+impl Display for IndexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Index {} is out of bounds for length {}",
+            self.index, self.len
+        )
+    }
+}
+
+impl Error for IndexError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcError {
+    Disconnected(ClientId),
+    // the client didn't answer within the call timeout
+    Timeout(ClientId),
+    // the reason the client gave
+    Remote(String),
+    InvalidRequest(String),
+    InvalidResponse(String),
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::Disconnected(client) => write!(f, "Client {} is not connected", client),
+            RpcError::Timeout(client) => write!(f, "Client {} did not answer in time", client),
+            RpcError::Remote(reason) => write!(f, "Client failed to answer: {}", reason),
+            RpcError::InvalidRequest(reason) => {
+                write!(f, "Request can't be serialized: {}", reason)
+            }
+            RpcError::InvalidResponse(reason) => {
+                write!(f, "Response has the wrong shape: {}", reason)
+            }
+        }
+    }
+}
+
+impl Error for RpcError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    AlreadyExists(String),
+    NotFound(String),
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::AlreadyExists(path) => write!(f, "Store at /{} already exists", path),
+            StoreError::NotFound(path) => write!(f, "No store at /{}", path),
+        }
+    }
+}
+
+impl Error for StoreError {}
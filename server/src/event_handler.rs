@@ -1,10 +1,174 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+};
 
 use parking_lot::RwLock;
 
-pub type OnChangeEventHandlerStore<T> = Arc<RwLock<Vec<Box<dyn FnMut(T) + Send + Sync + 'static>>>>;
-pub type EventHandlerStore =
-    Arc<RwLock<HashMap<String, Vec<Box<dyn Fn() + Send + Sync + 'static>>>>>;
+use crate::{
+    client::{ClientInfo, Origin},
+    error::PocaError,
+    poca::{DataElement, DataElementInner},
+    synchronizable::Synchronizable,
+    trace::warn,
+};
+
+// called with the old and the new value, and the client that made the change
+pub type OnChangeHandler =
+    Arc<dyn Fn(&dyn Synchronizable, &dyn Synchronizable, Origin) + Send + Sync + 'static>;
+// called with the key, the new value and the client that made the change
+pub type AnyChangeHandler = Arc<dyn Fn(&str, &dyn Synchronizable, Origin) + Send + Sync + 'static>;
+// shared by all keys of a Poca, see `Poca::on_any_change`
+pub type AnyChangeHandlers = Arc<RwLock<Vec<(CallbackId, AnyChangeHandler)>>>;
+// a user callback that panicked, see `Poca::on_panic`
+#[derive(Debug, Clone)]
+pub struct CallbackPanic {
+    // the key or event the callback was registered for
+    pub key: Option<String>,
+    pub message: String,
+}
+
+pub type PanicHook = Arc<RwLock<Option<Box<dyn Fn(&CallbackPanic) + Send + Sync + 'static>>>>;
+// called with the number of changes a client missed, see `LagPolicy`
+pub type LagHook = Arc<RwLock<Option<Box<dyn Fn(&ClientInfo, u64) + Send + Sync + 'static>>>>;
+// called with the problems of connections, see `Poca::on_error`
+pub type ErrorHook = Arc<RwLock<Option<Box<dyn Fn(&PocaError) + Send + Sync + 'static>>>>;
+// called with the key of a change the client didn't acknowledge
+pub type UnacknowledgedHook =
+    Arc<RwLock<Option<Box<dyn Fn(&ClientInfo, &str) + Send + Sync + 'static>>>>;
+
+// runs a user callback, reporting a panic instead of unwinding into the caller
+pub fn catch_panic(hook: &PanicHook, key: Option<&str>, callback: impl FnOnce()) {
+    if let Err(payload) = catch_unwind(AssertUnwindSafe(callback)) {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let panic = CallbackPanic {
+            key: key.map(str::to_string),
+            message,
+        };
+        warn!(key = ?panic.key, message = %panic.message, "Callback panicked");
+        if let Some(hook) = hook.read().deref() {
+            hook(&panic);
+        }
+    }
+}
+
+pub type PendingChange = (Box<dyn Synchronizable>, Box<dyn Synchronizable>, Origin);
+// gets the serialized payload the client sent, if any,
+// and fails if it doesn't match what the handler expects
+pub type EventCallback =
+    Box<dyn Fn(&ClientInfo, Option<&str>) -> Result<(), String> + Send + Sync + 'static>;
+pub type EventHandlerStore = Arc<RwLock<HashMap<String, Vec<EventCallback>>>>;
+pub type ConnectionHandlerStore = Arc<RwLock<Vec<Box<dyn Fn(ClientInfo) + Send + Sync + 'static>>>>;
+
+// identifies an on_change handler so it can be removed again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallbackId(u64);
+
+impl CallbackId {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        CallbackId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+// removes the handler when dropped
+pub struct CallbackGuard {
+    id: CallbackId,
+    element: Weak<RwLock<DataElementInner>>,
+}
+
+impl CallbackGuard {
+    pub(crate) fn new(id: CallbackId, element: &DataElement) -> Self {
+        Self {
+            id,
+            element: Arc::downgrade(element),
+        }
+    }
+}
+
+impl Drop for CallbackGuard {
+    fn drop(&mut self) {
+        if let Some(element) = self.element.upgrade() {
+            remove_handler(&element, self.id);
+        }
+    }
+}
+
+pub fn remove_handler(element: &DataElement, id: CallbackId) -> bool {
+    let mut guard = element.write();
+    let count = guard.on_change.len();
+    guard.on_change.retain(|(each, _)| *each != id);
+    guard.on_change.len() != count
+}
+
+// Handlers are called without holding any lock, so they can read and change
+// values, including their own. Handlers of a key never run re-entrantly or
+// concurrently: changes made meanwhile, by a handler or by another thread,
+// are queued and handed to the handlers in order once they have returned.
+// A handler that always changes its own key therefore loops forever.
+// Handlers for any key run after the ones of the key, with the same guarantees.
+// The old value is recorded in the history with the change, see
+// `DataElementInner::remember`.
+pub fn notify_change(element: &DataElement, old: Box<dyn Synchronizable>, origin: Origin) {
+    let (key, hook, any_change) = {
+        let mut guard = element.write();
+        if guard.on_change.is_empty() && guard.any_change.read().is_empty() {
+            return;
+        }
+        let new = guard.data.clone();
+        guard.pending_changes.push_back((old, new, origin));
+        if guard.notifying {
+            return;
+        }
+        guard.notifying = true;
+        (
+            guard.key.clone(),
+            guard.panic_hook.clone(),
+            guard.any_change.clone(),
+        )
+    };
+    loop {
+        let (handlers, (old, new, origin)) = {
+            let mut guard = element.write();
+            match guard.pending_changes.pop_front() {
+                Some(change) => (
+                    guard
+                        .on_change
+                        .iter()
+                        .map(|(_, handler)| handler.clone())
+                        .collect::<Vec<_>>(),
+                    change,
+                ),
+                None => {
+                    guard.notifying = false;
+                    return;
+                }
+            }
+        };
+        for handler in handlers {
+            catch_panic(&hook, Some(&key), || {
+                handler(old.deref(), new.deref(), origin)
+            });
+        }
+        let any_handlers = any_change
+            .read()
+            .iter()
+            .map(|(_, handler)| handler.clone())
+            .collect::<Vec<_>>();
+        for handler in any_handlers {
+            catch_panic(&hook, Some(&key), || handler(&key, new.deref(), origin));
+        }
+    }
+}
 
 pub trait EventHandler: Send + Sync + 'static {
     fn execute(&self);
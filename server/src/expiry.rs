@@ -0,0 +1,84 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+use tokio::{
+    sync::Notify,
+    time::{sleep_until, Instant},
+};
+
+use crate::{
+    message::Message,
+    poca::{DataElement, Store},
+    router::Router,
+};
+
+// Keys registered with a time to live. They are removed by `run`,
+// which is spawned while the server is running.
+#[derive(Clone, Default)]
+pub struct Expirations {
+    deadlines: Arc<Mutex<HashMap<String, (Instant, DataElement)>>>,
+    changed: Arc<Notify>,
+}
+
+impl Expirations {
+    pub fn insert(&self, key: &str, ttl: Duration, element: DataElement) {
+        self.deadlines
+            .lock()
+            .insert(key.to_string(), (Instant::now() + ttl, element));
+        // stores a permit if `run` isn't waiting right now
+        self.changed.notify_one();
+    }
+
+    pub async fn run(self, store: Store, sender: Router) {
+        loop {
+            let next = self
+                .deadlines
+                .lock()
+                .values()
+                .map(|(deadline, _)| *deadline)
+                .min();
+            match next {
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = sleep_until(deadline) => {},
+                        _ = self.changed.notified() => continue,
+                    }
+                }
+                None => {
+                    self.changed.notified().await;
+                    continue;
+                }
+            }
+            for (key, element) in self.take_expired() {
+                let removed = {
+                    let mut store_lock = store.shard(&key);
+                    match store_lock.get(&key) {
+                        // the key may have been removed and registered again
+                        Some(current) if Arc::ptr_eq(current, &element) => {
+                            store_lock.remove(&key);
+                            true
+                        }
+                        _ => false,
+                    }
+                };
+                if removed {
+                    sender.send(Message::Remove { key });
+                }
+            }
+        }
+    }
+
+    fn take_expired(&self) -> Vec<(String, DataElement)> {
+        let now = Instant::now();
+        let mut deadlines = self.deadlines.lock();
+        let expired = deadlines
+            .iter()
+            .filter(|(_, (deadline, _))| *deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        expired
+            .into_iter()
+            .filter_map(|key| deadlines.remove(&key).map(|(_, element)| (key, element)))
+            .collect()
+    }
+}
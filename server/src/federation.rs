@@ -0,0 +1,157 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Map, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+use crate::{
+    client::{ClientId, Origin},
+    journal::{Change, Op},
+    message::{WSMessage, WSMessageType},
+    poca::Store,
+    router::Router,
+};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+// Another server this one is connected to as a client, see `Poca::add_peer`.
+// Changes of the peer are applied here as made by `link`, so they are told
+// apart from the changes made here and never sent back.
+pub struct Peer {
+    pub url: String,
+    pub keys: HashSet<String>,
+    pub link: ClientId,
+}
+
+// Keeps the keys in sync with the peer while the server is running,
+// reconnecting whenever the connection is lost.
+pub async fn run(peer: Arc<Peer>, store: Store, router: Router) {
+    let mut delay = MIN_RECONNECT_DELAY;
+    loop {
+        if let Ok((socket, _)) = tokio_tungstenite::connect_async(peer.url.as_str()).await {
+            delay = MIN_RECONNECT_DELAY;
+            session(&peer, socket, &store, &router).await;
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+async fn session(peer: &Peer, socket: Socket, store: &Store, router: &Router) {
+    let (mut sender, mut receiver) = socket.split();
+    // subscribed before the peer's snapshot arrives, so no change in between is lost
+    let mut queue = router.subscribe(Some(peer.link), false, None);
+    for key in &peer.keys {
+        let subscribe = ws_message(WSMessageType::Subscribe, Some(key.clone()), None);
+        if sender.send(encode(&subscribe)).await.is_err() {
+            return;
+        }
+    }
+    loop {
+        tokio::select! {
+            routed = queue.next() => {
+                let message = match routed {
+                    Some(Ok((_, message))) => message,
+                    Some(Err(_)) => continue,
+                    None => return,
+                };
+                for change in Change::from_message(&message) {
+                    if change.client == Some(peer.link) || !peer.keys.contains(&change.key) {
+                        continue;
+                    }
+                    if let Some(message) = outgoing(change) {
+                        if sender.send(encode(&message)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+            frame = receiver.next() => {
+                let text = match frame {
+                    Some(Ok(tungstenite::Message::Text(text))) => text,
+                    Some(Ok(_)) => continue,
+                    _ => return,
+                };
+                let message = match serde_json::from_str::<WSMessage>(&text) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                };
+                if let (true, Some(seq)) = (message.ack, message.seq) {
+                    let mut ack = ws_message(WSMessageType::Ack, None, None);
+                    ack.seq = Some(seq);
+                    sender.send(encode(&ack)).await.ok();
+                }
+                for change in incoming(message) {
+                    if !peer.keys.contains(&change.key) {
+                        continue;
+                    }
+                    if let Some(message) = change.apply_to(store, Origin::Client(peer.link)) {
+                        router.send(message);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Without a version, the peer applies it to whatever value it has.
+// Removing a key isn't mirrored, both servers register their keys themselves.
+fn outgoing(change: Change) -> Option<WSMessage> {
+    let (message_type, data) = match change.op {
+        Op::Set(value) => (WSMessageType::Set, value.to_string()),
+        Op::MergePatch(fields) => (WSMessageType::MergePatch, Value::Object(fields).to_string()),
+        Op::Patch(ops) => (WSMessageType::Patch, serde_json::to_string(&ops).unwrap()),
+        Op::Increment(by) => (WSMessageType::Increment, by.to_string()),
+        Op::TextOps(ops) => (WSMessageType::TextOps, serde_json::to_string(&ops).unwrap()),
+        Op::Remove => return None,
+    };
+    Some(ws_message(message_type, Some(change.key), Some(data)))
+}
+
+// the changes a message of the peer consists of, its snapshot sets every key
+fn incoming(message: WSMessage) -> Vec<Change> {
+    let data = message.data.unwrap_or_default();
+    let op = match message.message_type {
+        WSMessageType::Set => serde_json::from_str(&data).map(Op::Set).ok(),
+        WSMessageType::MergePatch => serde_json::from_str(&data).map(Op::MergePatch).ok(),
+        WSMessageType::Patch => serde_json::from_str(&data).map(Op::Patch).ok(),
+        WSMessageType::Increment => data.parse().map(Op::Increment).ok(),
+        WSMessageType::TextOps => serde_json::from_str(&data).map(Op::TextOps).ok(),
+        WSMessageType::Batch => {
+            let messages: Vec<WSMessage> = serde_json::from_str(&data).unwrap_or_default();
+            return messages.into_iter().flat_map(incoming).collect();
+        }
+        WSMessageType::Snapshot => {
+            let values: Map<_, _> = serde_json::from_str(&data).unwrap_or_default();
+            return values
+                .into_iter()
+                .map(|(key, value)| Change::new(key, Op::Set(value), None))
+                .collect();
+        }
+        _ => None,
+    };
+    match (message.key, op) {
+        (Some(key), Some(op)) => vec![Change::new(key, op, None)],
+        _ => Vec::new(),
+    }
+}
+
+fn ws_message(message_type: WSMessageType, key: Option<String>, data: Option<String>) -> WSMessage {
+    WSMessage {
+        message_type,
+        key,
+        data,
+        version: None,
+        id: None,
+        seq: None,
+        ack: false,
+    }
+}
+
+fn encode(message: &WSMessage) -> tungstenite::Message {
+    tungstenite::Message::Text(serde_json::to_string(message).unwrap())
+}
@@ -0,0 +1,56 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use warp::ws;
+
+// how many frames are kept, enough for connections that fall a bit behind
+const KEPT: usize = 1024;
+
+type FrameKey = (u64, &'static str, bool);
+
+// Broadcast messages as encoded for the first connection that sent them, so
+// every other connection with the same encoding sends a copy of that frame
+// instead of serializing the message again, see `Encoding::name`. The frame
+// is still copied once per connection, as a `ws::Message` owns its contents.
+// Frames are looked up by sequence number and whether an acknowledgement is
+// expected. They are encoded outside of the lock, so two connections may
+// encode the same message at once, the first one is kept.
+#[derive(Clone, Default)]
+pub struct Frames(Arc<Mutex<FramesInner>>);
+
+#[derive(Default)]
+struct FramesInner {
+    frames: HashMap<FrameKey, ws::Message>,
+    // oldest first
+    order: VecDeque<FrameKey>,
+}
+
+impl Frames {
+    pub fn get_or_encode(
+        &self,
+        seq: u64,
+        encoding: &'static str,
+        ack: bool,
+        encode: impl FnOnce() -> Option<ws::Message>,
+    ) -> Option<ws::Message> {
+        let key = (seq, encoding, ack);
+        if let Some(frame) = self.0.lock().frames.get(&key) {
+            return Some(frame.clone());
+        }
+        let frame = encode()?;
+        let mut inner = self.0.lock();
+        if !inner.frames.contains_key(&key) {
+            if inner.order.len() == KEPT {
+                if let Some(evicted) = inner.order.pop_front() {
+                    inner.frames.remove(&evicted);
+                }
+            }
+            inner.order.push_back(key);
+            inner.frames.insert(key, frame.clone());
+        }
+        Some(frame)
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    access::Access,
+    builder::Durability,
+    client::{ClientId, Origin},
+    connections::CLIENTS_KEY,
+    event_handler::notify_change,
+    message::{payload, Message},
+    poca::Store,
+    storage::{StorageBackend, StorageError},
+    text::{Text, TextOp},
+    trace::warn,
+    transform::transform,
+    validation::validate,
+};
+
+// a single accepted change, one per line of the log
+#[derive(Serialize, Deserialize)]
+pub struct Change {
+    pub key: String,
+    #[serde(flatten)]
+    pub op: Op,
+    // None for changes made on the server
+    pub client: Option<ClientId>,
+    // milliseconds since the epoch
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", content = "data", rename_all = "snake_case")]
+pub enum Op {
+    Set(Value),
+    MergePatch(Map<String, Value>),
+    Patch(json_patch::Patch),
+    Increment(i64),
+    TextOps(Vec<TextOp>),
+    Remove,
+}
+
+impl Change {
+    // the changes a routed message consists of
+    pub fn from_message(message: &Message) -> Vec<Change> {
+        let (key, op, origin) = match message {
+            Message::Set {
+                key, data, origin, ..
+            } => {
+                let value = serde_json::from_slice(data).unwrap();
+                (key, Op::Set(value), origin.client())
+            }
+            Message::Register { key, data } => {
+                let value = serde_json::from_slice(data).unwrap();
+                (key, Op::Set(value), None)
+            }
+            Message::MergePatch {
+                key,
+                fields,
+                origin,
+                ..
+            } => (key, Op::MergePatch(fields.clone()), origin.client()),
+            Message::Patch {
+                key, ops, origin, ..
+            } => (key, Op::Patch(ops.clone()), origin.client()),
+            Message::Increment {
+                key, by, origin, ..
+            } => (key, Op::Increment(*by), origin.client()),
+            Message::TextOps {
+                key, ops, origin, ..
+            } => (key, Op::TextOps(ops.clone()), origin.client()),
+            Message::Remove { key } => (key, Op::Remove, None),
+            Message::Batch(messages) => {
+                return messages.iter().flat_map(Change::from_message).collect()
+            }
+            _ => return Vec::new(),
+        };
+        if key == CLIENTS_KEY {
+            return Vec::new();
+        }
+        vec![Change::new(key.clone(), op, origin)]
+    }
+
+    // made now
+    pub fn new(key: String, op: Op, client: Option<ClientId>) -> Change {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        Change {
+            key,
+            op,
+            client,
+            timestamp,
+        }
+    }
+
+    // Changes that don't apply to the value anymore are skipped, they were
+    // rejected when they were made as well.
+    pub fn apply(self, values: &mut Map<String, Value>) {
+        match (self.op, values.get_mut(&self.key)) {
+            (Op::Set(new), Some(value)) => *value = new,
+            (Op::Set(new), None) => {
+                values.insert(self.key, new);
+            }
+            (Op::Remove, _) => {
+                values.remove(&self.key);
+            }
+            (Op::MergePatch(fields), Some(Value::Object(object))) => object.extend(fields),
+            (Op::Patch(ops), Some(value)) => {
+                let mut patched = value.clone();
+                if json_patch::patch(&mut patched, &ops).is_ok() {
+                    *value = patched;
+                }
+            }
+            (Op::Increment(by), Some(value)) => {
+                if let Some(current) = value.as_i64() {
+                    *value = current.wrapping_add(by).into();
+                }
+            }
+            (Op::TextOps(ops), Some(value)) => {
+                if let Ok(mut text) = serde_json::from_value::<Text>(value.clone()) {
+                    if ops.iter().try_for_each(|op| text.apply(op)).is_ok() {
+                        *value = serde_json::to_value(text).unwrap();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Applied to the value in the store like a change made there, for changes
+    // of other servers. The message to route is None if the key isn't
+    // registered or the change doesn't apply.
+    pub fn apply_to(self, store: &Store, origin: Origin) -> Option<Message> {
+        self.apply_if(store, origin, |version| Some(version + 1))
+    }
+
+    // Like `apply_to`, for changes made outside of the server, like the ones
+    // of bridges. They are checked like the changes of clients: keys in rooms
+    // don't exist for them, read-only keys are refused and the validators and
+    // transforms of the key apply. Returns the reason if it was refused.
+    pub fn apply_checked(self, store: &Store, origin: Origin) -> Result<Message, String> {
+        let key = self.key.clone();
+        let element = store
+            .get(&key)
+            .filter(|element| element.read().room.is_none())
+            .ok_or_else(|| format!("Key {} does not exist", key))?;
+        let (old, data, version, routed) = {
+            let mut guard = element.write();
+            if guard.access == Access::ReadOnly {
+                return Err(format!("Key {} is read-only", key));
+            }
+            let mut values = Map::new();
+            let value = serde_json::from_str(&guard.data.serialize()).unwrap();
+            values.insert(key.clone(), value);
+            self.apply(&mut values);
+            let value = values
+                .get(&key)
+                .ok_or_else(|| format!("Change does not apply to key {}", key))?;
+            let mut data = guard
+                .data
+                .try_deserialize(&value.to_string())
+                .map_err(|reason| format!("Invalid value for key {}: {}", key, reason))?;
+            let mut routed = origin;
+            if let Some(transformed) = transform(&guard, data.as_ref()) {
+                data = transformed;
+                // the sender doesn't hold the transformed value yet
+                routed = Origin::Server;
+            }
+            validate(&guard, &key, data.as_ref())?;
+            let old = std::mem::replace(&mut guard.data, data);
+            guard.remember(old.as_ref());
+            guard.version += 1;
+            (old, payload(guard.data.as_ref()), guard.version, routed)
+        };
+        notify_change(&element, old, origin);
+        Ok(Message::Set {
+            key,
+            data,
+            origin: routed,
+            version,
+        })
+    }
+
+    // Like `apply_to`, `next` gets the current version of the value and
+    // returns the version after the change, or None to skip it.
+    pub fn apply_if(
+        self,
+        store: &Store,
+        origin: Origin,
+        next: impl FnOnce(u64) -> Option<u64>,
+    ) -> Option<Message> {
+        let element = store.get(&self.key)?;
+        let key = self.key.clone();
+        let (old, data, version) = {
+            let mut guard = element.write();
+            let version = next(guard.version)?;
+            let mut values = Map::new();
+            let value = serde_json::from_str(&guard.data.serialize()).unwrap();
+            values.insert(key.clone(), value);
+            self.apply(&mut values);
+            let value = values.get(&key)?;
+            let data = guard.data.try_deserialize(&value.to_string()).ok()?;
+            let old = std::mem::replace(&mut guard.data, data);
+            guard.remember(old.as_ref());
+            guard.version = version;
+            (old, payload(guard.data.as_ref()), guard.version)
+        };
+        notify_change(&element, old, origin);
+        Some(Message::Set {
+            key,
+            data,
+            origin,
+            version,
+        })
+    }
+}
+
+// Accepted changes, appended to the storage as they are routed, see
+// `PocaBuilder::write_ahead_log`. The values they lead to are kept as well,
+// so compacting saves them and starts a new log without looking at the store.
+pub struct Journal {
+    backend: Arc<dyn StorageBackend>,
+    values: Map<String, Value>,
+    entries: usize,
+    compact_after: usize,
+    durability: Durability,
+    // changes appended since the log was synced
+    unsynced: bool,
+}
+
+impl Journal {
+    // the saved values with the log replayed on top, compacted right away
+    pub fn open(
+        backend: Arc<dyn StorageBackend>,
+        compact_after: usize,
+        durability: Durability,
+    ) -> Result<Journal, StorageError> {
+        let mut journal = Journal {
+            values: backend.load()?,
+            backend,
+            entries: 0,
+            compact_after,
+            durability,
+            unsynced: false,
+        };
+        journal.compact()?;
+        Ok(journal)
+    }
+
+    pub fn values(&self) -> &Map<String, Value> {
+        &self.values
+    }
+
+    // failures are only logged with the `tracing` feature
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub fn append(&mut self, message: &Message) {
+        for change in Change::from_message(message) {
+            if let Err(error) = self.backend.append_change(&change) {
+                warn!(%error, "Failed to append a change");
+            }
+            change.apply(&mut self.values);
+            self.entries += 1;
+            self.unsynced = true;
+            if self.durability == Durability::Always {
+                self.sync();
+            }
+        }
+        if self.entries >= self.compact_after {
+            if let Err(error) = self.compact() {
+                warn!(%error, "Failed to compact the log");
+            }
+        }
+    }
+
+    // the values are saved before the log is emptied
+    pub fn compact(&mut self) -> Result<(), StorageError> {
+        self.backend.save(&self.values)?;
+        self.entries = 0;
+        self.unsynced = false;
+        Ok(())
+    }
+
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn sync(&mut self) {
+        if !self.unsynced {
+            return;
+        }
+        if let Err(error) = self.backend.sync() {
+            warn!(%error, "Failed to sync the log");
+        }
+        self.unsynced = false;
+    }
+}
+
+enum Entry {
+    Append(Message),
+    Compact(mpsc::Sender<Result<(), StorageError>>),
+}
+
+// The journal on a thread of its own, so routing never waits for the storage.
+// Entries are handled in the order they were sent, the thread ends once every
+// writer is dropped.
+#[derive(Clone)]
+pub struct JournalWriter {
+    sender: mpsc::Sender<Entry>,
+}
+
+impl JournalWriter {
+    pub fn spawn(journal: Journal) -> JournalWriter {
+        let (sender, entries) = mpsc::channel();
+        thread::Builder::new()
+            .name("poca-journal".to_string())
+            .spawn(move || write(journal, entries))
+            .expect("Failed to start the journal");
+        JournalWriter { sender }
+    }
+
+    pub fn append(&self, message: &Message) {
+        self.sender.send(Entry::Append(message.clone())).ok();
+    }
+
+    // Blocks until the changes appended before are saved with the values.
+    pub fn compact(&self) -> Result<(), StorageError> {
+        let (reply, result) = mpsc::channel();
+        if self.sender.send(Entry::Compact(reply)).is_err() {
+            return Ok(());
+        }
+        result.recv().unwrap_or(Ok(()))
+    }
+}
+
+// With `Durability::Batch`, the log is synced once for all changes that
+// queued up while the last ones were written.
+fn write(mut journal: Journal, entries: mpsc::Receiver<Entry>) {
+    while let Ok(entry) = entries.recv() {
+        let mut next = Some(entry);
+        while let Some(entry) = next {
+            match entry {
+                Entry::Append(message) => journal.append(&message),
+                Entry::Compact(reply) => {
+                    reply.send(journal.compact()).ok();
+                }
+            }
+            next = entries.try_recv().ok();
+        }
+        if journal.durability == Durability::Batch {
+            journal.sync();
+        }
+    }
+}
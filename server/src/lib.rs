@@ -1,23 +1,129 @@
+mod access;
+mod admin;
+mod ack;
+mod admission;
 mod app_routes;
+mod audit;
+mod auth;
+mod builder;
+mod changes;
+mod checksum;
+mod client;
+mod cluster;
+mod coalesce;
+mod codegen;
+mod conflict;
+mod connections;
+mod counter_handle;
 mod data_handle;
+mod deny_list;
+mod encoding;
+mod error;
 mod event_handler;
+mod expiry;
+#[cfg(feature = "federation")]
+mod federation;
+mod frames;
+mod journal;
+mod list_handle;
+mod listener;
+mod map_handle;
 mod message;
+mod metrics;
+mod middleware;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+#[cfg(feature = "nats")]
+mod nats;
+mod patch;
+mod persistence;
 mod poca;
+mod rate_limit;
+#[cfg(feature = "redis")]
+mod redis_cluster;
+mod replay;
+mod rest;
+mod rooms;
+mod router;
+mod rpc;
+#[cfg(feature = "sled")]
+mod sled_storage;
+#[cfg(feature = "sqlite")]
+mod sqlite_storage;
+mod sse;
+mod storage;
+mod store;
+mod subscription;
 mod synchronizable;
+mod text;
+mod text_handle;
+#[cfg(feature = "tls")]
+mod tls;
+mod throttle;
+mod trace;
+mod transaction;
+mod transform;
+mod transport;
+#[cfg(all(unix, feature = "unix"))]
+mod unix;
+mod validation;
+#[cfg(feature = "webtransport")]
+mod webtransport;
 mod ws_handler;
 
+pub use access::Access;
 pub use app_routes::AppRoutes as _AppRoutes;
+pub use audit::{AuditEntry, AuditSink, FileAuditSink};
+pub use auth::{AuthRequest, Authenticator};
+pub use builder::{Durability, LagPolicy, PocaBuilder, PocaConfig};
+pub use client::{ClientId, ClientInfo, ClientSummary, Origin};
+pub use cluster::{ClusterBackend, ClusterError, MemoryCluster};
+pub use conflict::ConflictPolicy;
+pub use connections::CLIENTS_KEY;
+pub use counter_handle::CounterHandle;
 pub use data_handle::DataHandle;
+pub use deny_list::IDENTITY_METADATA;
+pub use encoding::{Encoding, JsonEncoding};
+pub use error::{IndexError, KeyError, PatchError, PocaError, RpcError, StoreError, TypeError};
+pub use event_handler::{CallbackGuard, CallbackId, CallbackPanic};
+pub use journal::{Change, Op};
+pub use json_patch;
+pub use list_handle::ListHandle;
+pub use map_handle::MapHandle;
+pub use message::{WSMessage, WSMessageType};
+pub use middleware::{Flow, Middleware};
+#[cfg(feature = "mqtt")]
+pub use mqtt::MqttBridge;
+#[cfg(feature = "nats")]
+pub use nats::NatsBridge;
 pub use poca::{Poca, WindowOptions};
+pub use rate_limit::{RateLimit, RateLimitPolicy};
+#[cfg(feature = "redis")]
+pub use redis_cluster::RedisCluster;
+pub use router::{Overflow, Priority, QueueStats};
+#[cfg(feature = "schema")]
+pub use schemars;
+#[cfg(feature = "sled")]
+pub use sled_storage::SledStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite_storage::SqliteStorage;
+pub use storage::{FileStorage, MemoryStorage, StorageBackend, StorageError};
+pub use synchronizable::Synchronizable;
+pub use text::{CharId, Text, TextOp};
+pub use text_handle::TextHandle;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+pub use transaction::Transaction;
 
 // macro-related functions
 // should not be documented
 pub use app_routes::generate_app_routes as _g_a_r;
 pub use app_routes::AppRoutes as _AR;
 pub use app_routes::RouteNode as _N;
+pub use synchronizable::DeriveSynchronizable as _DeriveSynchronizable;
 
 // probably should be in a common module
 // not actually needed
 pub use message::{WSMessage as _WSMessage, WSMessageType as _WSMessageType};
 
-pub use poca_macro::include_app_dir;
+pub use poca_macro::{include_app_dir, Synchronizable};
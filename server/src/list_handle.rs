@@ -0,0 +1,125 @@
+use std::{fmt::Debug, ops::Deref};
+
+use json_patch::Patch;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    data_handle::DataHandle,
+    error::IndexError,
+    patch::{add_op, remove_op, replace_op},
+};
+
+// Element operations are broadcast as JSON Patch operations
+// instead of retransmitting the whole list.
+pub struct ListHandle<T>
+where
+    T: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+{
+    handle: DataHandle<Vec<T>>,
+}
+
+impl<T> Clone for ListHandle<T>
+where
+    T: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+impl<T> Deref for ListHandle<T>
+where
+    T: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+{
+    type Target = DataHandle<Vec<T>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl<T> ListHandle<T>
+where
+    T: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+{
+    pub fn new(handle: DataHandle<Vec<T>>) -> Self {
+        Self { handle }
+    }
+
+    pub fn len(&self) -> usize {
+        self.handle.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handle.read().is_empty()
+    }
+
+    pub fn get_at(&self, index: usize) -> Option<T> {
+        self.handle.read().get(index).cloned()
+    }
+
+    pub fn push(&self, value: T) {
+        self.handle.update_with_patch(|list| {
+            let ops = Patch(vec![add_op("/-".to_string(), &value)]);
+            list.push(value);
+            ((), ops)
+        })
+    }
+
+    // the index may be the length, to append
+    pub fn insert(&self, index: usize, value: T) -> Result<(), IndexError> {
+        self.handle.update_with_patch(|list| {
+            if index > list.len() {
+                return (Err(out_of_bounds(index, list)), Patch(Vec::new()));
+            }
+            let ops = Patch(vec![add_op(format!("/{}", index), &value)]);
+            list.insert(index, value);
+            (Ok(()), ops)
+        })
+    }
+
+    pub fn remove(&self, index: usize) -> Option<T> {
+        self.handle.update_with_patch(|list| {
+            if index >= list.len() {
+                return (None, Patch(Vec::new()));
+            }
+            let value = list.remove(index);
+            let ops = Patch(vec![remove_op(format!("/{}", index))]);
+            (Some(value), ops)
+        })
+    }
+
+    pub fn swap(&self, a: usize, b: usize) -> Result<(), IndexError> {
+        self.handle.update_with_patch(|list| {
+            if let Some(index) = [a, b].into_iter().find(|index| *index >= list.len()) {
+                return (Err(out_of_bounds(index, list)), Patch(Vec::new()));
+            }
+            list.swap(a, b);
+            let ops = Patch(vec![
+                replace_op(format!("/{}", a), &list[a]),
+                replace_op(format!("/{}", b), &list[b]),
+            ]);
+            (Ok(()), ops)
+        })
+    }
+
+    pub fn set_at(&self, index: usize, value: T) -> Result<(), IndexError> {
+        self.handle.update_with_patch(|list| {
+            if index >= list.len() {
+                return (Err(out_of_bounds(index, list)), Patch(Vec::new()));
+            }
+            let ops = Patch(vec![replace_op(format!("/{}", index), &value)]);
+            list[index] = value;
+            (Ok(()), ops)
+        })
+    }
+}
+
+fn out_of_bounds<T>(index: usize, list: &[T]) -> IndexError {
+    IndexError {
+        index,
+        len: list.len(),
+    }
+}
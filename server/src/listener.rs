@@ -0,0 +1,79 @@
+use std::{convert::Infallible, future::Future, net::SocketAddr};
+
+use hyper::{
+    server::conn::{AddrIncoming, AddrStream},
+    service::{make_service_fn, service_fn, Service},
+};
+use tokio::net::TcpSocket;
+use warp::{Filter, Rejection, Reply};
+
+use crate::{builder::PocaConfig, error::PocaError, trace::warn};
+
+// warp only knows the remote address for listeners it binds itself,
+// so it is handed to the filters as an extension of the request
+#[derive(Clone, Copy)]
+struct RemoteAddr(SocketAddr);
+
+// a listener with the socket options of the config
+pub(crate) fn bind(address: SocketAddr, config: &PocaConfig) -> Result<AddrIncoming, PocaError> {
+    let error = |source: std::io::Error| PocaError::Bind {
+        address,
+        source: source.into(),
+    };
+    let socket = match address {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }
+    .map_err(error)?;
+    // like std, so a restarted server doesn't wait for old connections to time out
+    #[cfg(unix)]
+    socket.set_reuseaddr(true).map_err(error)?;
+    socket.bind(address).map_err(error)?;
+    let listener = socket.listen(config.backlog).map_err(error)?;
+    let mut incoming = AddrIncoming::from_listener(listener).map_err(|source| PocaError::Bind {
+        address,
+        source: source.into(),
+    })?;
+    incoming.set_nodelay(config.nodelay);
+    incoming.set_keepalive(config.tcp_keepalive);
+    Ok(incoming)
+}
+
+// the error of the server is only logged with the `tracing` feature
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn serve<F, R>(
+    filter: F,
+    incoming: AddrIncoming,
+    signal: impl Future<Output = ()>,
+) -> impl Future<Output = ()>
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let service = warp::service(filter);
+    let make_service = make_service_fn(move |stream: &AddrStream| {
+        let remote_addr = RemoteAddr(stream.remote_addr());
+        let mut service = service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut request| {
+                request.extensions_mut().insert(remote_addr);
+                service.call(request)
+            }))
+        }
+    });
+    let server = hyper::Server::builder(incoming)
+        .serve(make_service)
+        .with_graceful_shutdown(signal);
+    async move {
+        if let Err(error) = server.await {
+            warn!(%error, "Server error");
+        }
+    }
+}
+
+// of connections accepted by `serve`
+pub(crate) fn remote_addr(
+) -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Copy {
+    warp::ext::optional::<RemoteAddr>()
+        .map(|address: Option<RemoteAddr>| address.map(|RemoteAddr(address)| address))
+}
@@ -0,0 +1,95 @@
+use std::{collections::HashMap, fmt::Debug, hash::Hash, ops::Deref};
+
+use json_patch::Patch;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    data_handle::DataHandle,
+    patch::{add_op, pointer, remove_op},
+};
+
+// Only the affected entry is broadcast, as a JSON Patch operation on its key.
+pub struct MapHandle<K, V>
+where
+    K: Serialize + DeserializeOwned + ToString + Eq + Hash + Clone + Debug + Send + Sync + 'static,
+    V: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+{
+    handle: DataHandle<HashMap<K, V>>,
+}
+
+impl<K, V> Clone for MapHandle<K, V>
+where
+    K: Serialize + DeserializeOwned + ToString + Eq + Hash + Clone + Debug + Send + Sync + 'static,
+    V: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+impl<K, V> Deref for MapHandle<K, V>
+where
+    K: Serialize + DeserializeOwned + ToString + Eq + Hash + Clone + Debug + Send + Sync + 'static,
+    V: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+{
+    type Target = DataHandle<HashMap<K, V>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl<K, V> MapHandle<K, V>
+where
+    K: Serialize + DeserializeOwned + ToString + Eq + Hash + Clone + Debug + Send + Sync + 'static,
+    V: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+{
+    pub fn new(handle: DataHandle<HashMap<K, V>>) -> Self {
+        Self { handle }
+    }
+
+    pub fn len(&self) -> usize {
+        self.handle.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handle.read().is_empty()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.handle.read().contains_key(key)
+    }
+
+    pub fn get_entry(&self, key: &K) -> Option<V> {
+        self.handle.read().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.handle.update_with_patch(|map| {
+            let ops = Patch(vec![add_op(pointer(&key.to_string()), &value)]);
+            (map.insert(key, value), ops)
+        })
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.handle.update_with_patch(|map| match map.remove(key) {
+            Some(value) => (
+                Some(value),
+                Patch(vec![remove_op(pointer(&key.to_string()))]),
+            ),
+            None => (None, Patch(vec![])),
+        })
+    }
+
+    // mutates the entry in place, inserting `default()` first if it is missing
+    pub fn entry(&self, key: K, default: impl FnOnce() -> V, updater: impl FnOnce(&mut V)) {
+        self.handle.update_with_patch(|map| {
+            let path = pointer(&key.to_string());
+            let value = map.entry(key).or_insert_with(default);
+            updater(value);
+            ((), Patch(vec![add_op(path, &*value)]))
+        })
+    }
+}
@@ -1,19 +1,185 @@
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use serde_repr::*;
 
-use crate::synchronizable::Synchronizable;
+use crate::{
+    client::{ClientId, Origin},
+    synchronizable::Synchronizable,
+    text::TextOp,
+};
+
+// close code for clients that were removed or misbehaved
+pub const POLICY_VIOLATION: u16 = 1008;
+
+// A value serialized as JSON. Copies of a message share it, so a value is
+// serialized at most once however many clients it goes to. A value a client
+// sent is relayed as it was sent if that is how it is stored.
+pub type Payload = Bytes;
+
+pub fn payload(data: &dyn Synchronizable) -> Payload {
+    Payload::from(data.serialize())
+}
+
+// always valid UTF-8, as it holds JSON
+pub fn text(payload: &Payload) -> String {
+    String::from_utf8_lossy(payload).into_owned()
+}
 
 #[derive(Debug, Clone)]
 pub enum Message {
     Set {
         key: String,
-        data: Box<dyn Synchronizable>,
+        data: Payload,
+        origin: Origin,
+        version: u64,
+    },
+    // A key registered with its initial value, recorded for clients catching
+    // up after reconnecting. It isn't a change of the value, see `Poca::data`.
+    Register {
+        key: String,
+        data: Payload,
+    },
+    // only the top-level fields that changed, see `patch::changed_fields`
+    MergePatch {
+        key: String,
+        fields: serde_json::Map<String, serde_json::Value>,
+        origin: Origin,
+        version: u64,
+    },
+    Patch {
+        key: String,
+        ops: json_patch::Patch,
+        origin: Origin,
+        version: u64,
+    },
+    // applied on top of the current value, so concurrent increments compose
+    Increment {
+        key: String,
+        by: i64,
+        origin: Origin,
+        version: u64,
+    },
+    TextOps {
+        key: String,
+        ops: Vec<TextOp>,
+        origin: Origin,
+        version: u64,
     },
     Get {
-        //TODO: use client-id to only reply to the client that requested the data
         key: String,
-        data: Box<dyn Synchronizable>,
+        data: Payload,
+        client: ClientId,
+        version: u64,
     },
+    Remove {
+        key: String,
+    },
+    // registered keys and their type names
+    Keys {
+        keys: serde_json::Map<String, serde_json::Value>,
+        client: ClientId,
+    },
+    // the JSON Schema of all keys, see `Poca::schema`
+    Schema {
+        schema: serde_json::Value,
+        client: ClientId,
+    },
+    // the values of many keys at once
+    Snapshot {
+        values: serde_json::Map<String, serde_json::Value>,
+    },
+    // transient, unlike data it is not kept in the store
+    Event {
+        name: String,
+        // serialized json
+        payload: String,
+    },
+    // a call to a client, see `Poca::call`
+    Request {
+        id: u64,
+        method: String,
+        payload: String,
+        client: ClientId,
+    },
+    // answers a request of a client
+    Response {
+        id: u64,
+        result: Result<String, String>,
+        client: ClientId,
+    },
+    // changes applied together, see `Poca::transaction`
+    Batch(Vec<Message>),
+    Close {
+        code: u16,
+        reason: String,
+    },
+    Error {
+        key: Option<String>,
+        reason: String,
+        client: ClientId,
+    },
+}
+
+impl Message {
+    pub fn event<E: Serialize>(name: &str, payload: &E) -> Result<Self, serde_json::Error> {
+        Ok(Message::Event {
+            name: name.to_string(),
+            payload: serde_json::to_string(payload)?,
+        })
+    }
+
+    // key and origin of messages that change a value
+    pub fn change(&self) -> Option<(&str, Origin)> {
+        match self {
+            Message::Set { key, origin, .. }
+            | Message::MergePatch { key, origin, .. }
+            | Message::Patch { key, origin, .. }
+            | Message::Increment { key, origin, .. }
+            | Message::TextOps { key, origin, .. } => Some((key, *origin)),
+            Message::Remove { key } | Message::Register { key, .. } => Some((key, Origin::Server)),
+            _ => None,
+        }
+    }
+
+    // the version of the value after the change
+    pub fn version(&self) -> Option<u64> {
+        match self {
+            Message::Set { version, .. }
+            | Message::MergePatch { version, .. }
+            | Message::Patch { version, .. }
+            | Message::Increment { version, .. }
+            | Message::TextOps { version, .. } => Some(*version),
+            Message::Register { .. } => Some(0),
+            _ => None,
+        }
+    }
+
+    // Keys of the values the message changes, including the ones of a batch.
+    // Registering a key isn't a change.
+    pub fn changed_keys(&self) -> Vec<&str> {
+        match self {
+            Message::Batch(messages) => messages.iter().flat_map(Message::changed_keys).collect(),
+            Message::Register { .. } => Vec::new(),
+            message => message.change().map(|(key, _)| key).into_iter().collect(),
+        }
+    }
+
+    pub fn registers_key(&self) -> bool {
+        matches!(self, Message::Register { .. })
+    }
+
+    // the only client that should receive the message, if any
+    pub fn recipient(&self) -> Option<ClientId> {
+        match self {
+            Message::Get { client, .. }
+            | Message::Error { client, .. }
+            | Message::Keys { client, .. }
+            | Message::Schema { client, .. }
+            | Message::Request { client, .. }
+            | Message::Response { client, .. } => Some(*client),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug, Clone)]
@@ -23,11 +189,41 @@ pub enum WSMessageType {
     Emit = 2,
     Get = 3,
     Error = 4,
+    Subscribe = 5,
+    Unsubscribe = 6,
+    Snapshot = 7,
+    MergePatch = 8,
+    Patch = 9,
+    Increment = 10,
+    TextOps = 11,
+    CompareAndSet = 12,
+    Batch = 13,
+    Remove = 14,
+    Keys = 15,
+    Event = 16,
+    Request = 17,
+    Response = 18,
+    Ack = 19,
+    Schema = 20,
+    // checksums of the values the client holds, see `checksum::checksums`
+    Checksums = 21,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WSMessage {
     pub message_type: WSMessageType,
     pub key: Option<String>,
     pub data: Option<String>,
+    // version of the value the message carries, or a client write is based on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    // correlates a request with its response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    // position in the sequence of messages a client received, see `Router`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    // the client is expected to acknowledge the sequence number, see `Acks`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ack: bool,
 }
@@ -0,0 +1,130 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::Mutex;
+
+use crate::{connections::CLIENTS_KEY, message::Message, poca::Store};
+
+// Counters of the whole server, rendered in the Prometheus text format by
+// `Poca::metrics` and served at `/metrics`, see `PocaBuilder::metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    received: AtomicU64,
+    sent: AtomicU64,
+    lag_events: AtomicU64,
+    // accepted changes by key, made by clients or on the server
+    writes: Mutex<BTreeMap<String, u64>>,
+}
+
+pub type SharedMetrics = Arc<Metrics>;
+
+impl Metrics {
+    pub fn received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn lagged(&self) {
+        self.lag_events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Called for every routed message, see `Router::tap`. Only the keys are
+    // looked at, the payloads aren't decoded.
+    pub fn routed(&self, message: &Message) {
+        let keys = message.changed_keys();
+        if keys.is_empty() {
+            return;
+        }
+        let mut writes = self.writes.lock();
+        for key in keys.into_iter().filter(|key| *key != CLIENTS_KEY) {
+            match writes.get_mut(key) {
+                Some(count) => *count += 1,
+                None => {
+                    writes.insert(key.to_string(), 1);
+                }
+            }
+        }
+    }
+
+    pub fn render(&self, store: &Store, connections: &AtomicUsize) -> String {
+        let (keys, bytes) = {
+            let elements = store.elements();
+            let bytes = elements
+                .iter()
+                .map(|(_, element)| element.read().data.serialize().len())
+                .sum::<usize>();
+            (elements.len(), bytes)
+        };
+        let mut output = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            writeln!(output, "# HELP {} {}", name, help).unwrap();
+            writeln!(output, "# TYPE {} {}", name, kind).unwrap();
+            writeln!(output, "{} {}", name, value).unwrap();
+        };
+        metric(
+            "poca_connected_clients",
+            "gauge",
+            "Clients connected right now.",
+            connections.load(Ordering::SeqCst) as u64,
+        );
+        metric(
+            "poca_messages_received_total",
+            "counter",
+            "Messages received from clients.",
+            self.received.load(Ordering::Relaxed),
+        );
+        metric(
+            "poca_messages_sent_total",
+            "counter",
+            "Messages sent to clients, pings aside.",
+            self.sent.load(Ordering::Relaxed),
+        );
+        metric(
+            "poca_lag_events_total",
+            "counter",
+            "Times a client fell behind and missed changes.",
+            self.lag_events.load(Ordering::Relaxed),
+        );
+        metric(
+            "poca_store_keys",
+            "gauge",
+            "Keys in the store.",
+            keys as u64,
+        );
+        metric(
+            "poca_store_bytes",
+            "gauge",
+            "Size of all values serialized as JSON.",
+            bytes as u64,
+        );
+        output += "# HELP poca_writes_total Changes accepted per key.\n";
+        output += "# TYPE poca_writes_total counter\n";
+        for (key, writes) in self.writes.lock().iter() {
+            writeln!(
+                output,
+                "poca_writes_total{{key=\"{}\"}} {}",
+                escape(key),
+                writes
+            )
+            .unwrap();
+        }
+        output
+    }
+}
+
+// label values are quoted
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
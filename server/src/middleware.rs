@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::{
+    client::ClientInfo,
+    message::{WSMessage, WSMessageType},
+};
+
+// what happens to a message after a middleware saw it
+pub enum Flow {
+    // passed on to the next middleware, then handled or sent
+    Continue(WSMessage),
+    Drop,
+    // dropped, the client gets an error with the reason instead
+    Reject(String),
+}
+
+// Sees every message a client sends before it is handled, and every message
+// before it is sent to a client, see `Poca::add_middleware`. Middlewares run
+// in the order they were added, the first one not continuing ends the chain.
+pub trait Middleware: Send + Sync + 'static {
+    fn inbound(&self, _client: &ClientInfo, message: WSMessage) -> Flow {
+        Flow::Continue(message)
+    }
+
+    fn outbound(&self, _client: &ClientInfo, message: WSMessage) -> Flow {
+        Flow::Continue(message)
+    }
+}
+
+pub type Middlewares = Arc<RwLock<Vec<Arc<dyn Middleware>>>>;
+
+pub fn inbound(middlewares: &Middlewares, client: &ClientInfo, message: WSMessage) -> Flow {
+    run(middlewares, message, |middleware, message| {
+        middleware.inbound(client, message)
+    })
+}
+
+// the message to send in place of the original one, if any
+pub fn outbound(
+    middlewares: &Middlewares,
+    client: &ClientInfo,
+    message: WSMessage,
+) -> Option<WSMessage> {
+    let key = message.key.clone();
+    match run(middlewares, message, |middleware, message| {
+        middleware.outbound(client, message)
+    }) {
+        Flow::Continue(message) => Some(message),
+        Flow::Drop => None,
+        Flow::Reject(reason) => Some(WSMessage {
+            message_type: WSMessageType::Error,
+            key,
+            data: Some(reason),
+            version: None,
+            id: None,
+            seq: None,
+            ack: false,
+        }),
+    }
+}
+
+fn run(
+    middlewares: &Middlewares,
+    message: WSMessage,
+    each: impl Fn(&dyn Middleware, WSMessage) -> Flow,
+) -> Flow {
+    // cloned, so middlewares can be added while a message passes through
+    let middlewares = middlewares.read().clone();
+    middlewares
+        .iter()
+        .try_fold(message, |message, middleware| {
+            match each(middleware.as_ref(), message) {
+                Flow::Continue(message) => Ok(message),
+                flow => Err(flow),
+            }
+        })
+        .map_or_else(|flow| flow, Flow::Continue)
+}
@@ -0,0 +1,127 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use serde_json::Value;
+
+use crate::{
+    client::{ClientId, Origin},
+    journal::{Change, Op},
+    poca::Store,
+    router::Router,
+    trace::{debug, warn},
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// Keys mapped to MQTT topics, see `Poca::add_mqtt_bridge`. Changes of a key
+// are published as its whole value in JSON, messages on its topic set it.
+// Payloads that aren't JSON are taken as strings.
+pub struct MqttBridge {
+    options: MqttOptions,
+    // topic by key
+    topics: HashMap<String, String>,
+    retain: bool,
+}
+
+impl MqttBridge {
+    pub fn new(client_id: &str, host: &str, port: u16) -> Self {
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        MqttBridge {
+            options,
+            topics: HashMap::new(),
+            retain: false,
+        }
+    }
+
+    pub fn key(mut self, key: &str, topic: &str) -> Self {
+        self.topics.insert(key.to_string(), topic.to_string());
+        self
+    }
+
+    // whether the broker keeps the latest value for devices subscribing later
+    pub fn retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+}
+
+// Messages on the topics are applied as made by `link`, so they aren't
+// published again, and checked like the values clients set. Keys in rooms
+// aren't bridged. The broker reconnects by itself when polled again. Errors
+// and refused values are only logged with the `tracing` feature.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub async fn run(bridge: Arc<MqttBridge>, link: ClientId, store: Store, router: Router) {
+    let (client, mut eventloop) = AsyncClient::new(bridge.options.clone(), 64);
+    let keys = bridge
+        .topics
+        .iter()
+        .map(|(key, topic)| (topic.clone(), key.clone()))
+        .collect::<HashMap<_, _>>();
+    let mut queue = router.subscribe(Some(link), false, None);
+    // the broker sends what was published back to the subscription
+    let mut published = HashMap::<String, Vec<u8>>::new();
+    loop {
+        tokio::select! {
+            routed = queue.next() => {
+                let message = match routed {
+                    Some(Ok((_, message))) => message,
+                    Some(Err(_)) => continue,
+                    None => return,
+                };
+                for change in Change::from_message(&message) {
+                    let topic = match bridge.topics.get(&change.key) {
+                        Some(topic) if change.client != Some(link) => topic,
+                        _ => continue,
+                    };
+                    // devices don't know about patches
+                    let payload = match store.get(&change.key) {
+                        Some(element) if element.read().room.is_none() => {
+                            element.read().data.serialize().into_bytes()
+                        }
+                        _ => continue,
+                    };
+                    published.insert(topic.clone(), payload.clone());
+                    if let Err(error) = client
+                        .publish(topic, QoS::AtLeastOnce, bridge.retain, payload)
+                        .await
+                    {
+                        warn!(%error, %topic, "Failed to publish to MQTT");
+                    }
+                }
+            }
+            event = eventloop.poll() => match event {
+                // subscriptions don't survive a reconnect with a clean session
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    for topic in keys.keys() {
+                        client.subscribe(topic, QoS::AtLeastOnce).await.ok();
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let key = match keys.get(&publish.topic) {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    let echo = published.get(&publish.topic).map(Vec::as_slice);
+                    if echo == Some(&publish.payload[..]) {
+                        continue;
+                    }
+                    let value = serde_json::from_slice(&publish.payload).unwrap_or_else(|_| {
+                        Value::String(String::from_utf8_lossy(&publish.payload).into_owned())
+                    });
+                    let change = Change::new(key.clone(), Op::Set(value), None);
+                    match change.apply_checked(&store, Origin::Client(link)) {
+                        Ok(message) => router.send(message),
+                        Err(reason) => debug!(%reason, "Refused a value from MQTT"),
+                    }
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    warn!(%error, "Lost the connection to the MQTT broker");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+
+use crate::{
+    client::Origin,
+    journal::{Change, Op},
+    message::Message,
+    poca::Store,
+    router::Router,
+    trace::{debug, warn},
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// Republishes changes and events to NATS subjects and applies changes
+// published by other services, see `Poca::add_nats_bridge`. With the prefix
+// `poca`, a change of `score` is published to `poca.changes.score` and an
+// event to `poca.events.<name>`. Changes published to `poca.apply.score`
+// like `{"op": "increment", "data": 1}` are applied to `score`, checked like
+// the changes clients make. Keys in rooms aren't bridged.
+pub struct NatsBridge {
+    url: String,
+    prefix: String,
+}
+
+impl NatsBridge {
+    // like `nats://127.0.0.1:4222`
+    pub fn new(url: &str) -> Self {
+        NatsBridge {
+            url: url.to_string(),
+            prefix: "poca".to_string(),
+        }
+    }
+
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+}
+
+// the error is only logged with the `tracing` feature
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub async fn run(bridge: Arc<NatsBridge>, store: Store, router: Router) {
+    loop {
+        match async_nats::connect(bridge.url.as_str()).await {
+            Ok(connection) => session(&bridge, connection, &store, &router).await,
+            Err(error) => warn!(%error, url = %bridge.url, "Failed to connect to NATS"),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+// refused changes are only logged with the `tracing` feature
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+async fn session(
+    bridge: &NatsBridge,
+    connection: async_nats::Connection,
+    store: &Store,
+    router: &Router,
+) {
+    let apply = format!("{}.apply.", bridge.prefix);
+    let mut inbound = match connection.subscribe(&format!("{}>", apply)).await {
+        Ok(subscription) => subscription,
+        Err(_) => return,
+    };
+    let mut queue = router.subscribe(None, false, None);
+    loop {
+        tokio::select! {
+            routed = queue.next() => {
+                let message = match routed {
+                    Some(Ok((_, message))) => message,
+                    Some(Err(_)) => continue,
+                    None => return,
+                };
+                for (subject, payload) in outbound(&bridge.prefix, &message, store) {
+                    if connection.publish(&subject, payload).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            received = inbound.next() => {
+                let received = match received {
+                    Some(received) => received,
+                    None => return,
+                };
+                let key = received.subject.trim_start_matches(apply.as_str()).to_string();
+                let op = match serde_json::from_slice::<Op>(&received.data) {
+                    Ok(op) => op,
+                    Err(_) => continue,
+                };
+                match Change::new(key, op, None).apply_checked(store, Origin::Server) {
+                    Ok(message) => router.send(message),
+                    Err(reason) => debug!(%reason, "Refused a change from NATS"),
+                }
+            }
+        }
+    }
+}
+
+// the subjects and payloads a routed message is published as
+fn outbound(prefix: &str, message: &Message, store: &Store) -> Vec<(String, Vec<u8>)> {
+    if let Message::Event { name, payload } = message {
+        let subject = format!("{}.events.{}", prefix, name);
+        return vec![(subject, payload.clone().into_bytes())];
+    }
+    Change::from_message(message)
+        .into_iter()
+        .filter(|change| {
+            store
+                .get(&change.key)
+                .map_or(true, |element| element.read().room.is_none())
+        })
+        .map(|change| {
+            let subject = format!("{}.changes.{}", prefix, change.key);
+            (subject, serde_json::to_vec(&change).unwrap())
+        })
+        .collect()
+}
@@ -0,0 +1,65 @@
+use json_patch::{AddOperation, PatchOperation, RemoveOperation, ReplaceOperation};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{error::PatchError, poca::DataElementInner};
+
+// applied to the JSON form of the value, which is then parsed back into its type
+pub fn apply_patch(
+    element: &mut DataElementInner,
+    ops: &json_patch::Patch,
+) -> Result<(), PatchError> {
+    let mut value: Value = serde_json::from_str(&element.data.serialize())
+        .map_err(|error| PatchError::Type(error.to_string()))?;
+    json_patch::patch(&mut value, ops).map_err(|error| PatchError::Apply(error.to_string()))?;
+    element.data = element
+        .data
+        .try_deserialize(&value.to_string())
+        .map_err(PatchError::Type)?;
+    Ok(())
+}
+
+// a single reference token of a JSON Pointer, escaped as per RFC 6901
+pub fn pointer(token: &str) -> String {
+    format!("/{}", token.replace('~', "~0").replace('/', "~1"))
+}
+
+pub fn add_op(path: String, value: impl Serialize) -> PatchOperation {
+    PatchOperation::Add(AddOperation {
+        path,
+        value: serde_json::to_value(value).unwrap(),
+    })
+}
+
+pub fn replace_op(path: String, value: impl Serialize) -> PatchOperation {
+    PatchOperation::Replace(ReplaceOperation {
+        path,
+        value: serde_json::to_value(value).unwrap(),
+    })
+}
+
+pub fn remove_op(path: String) -> PatchOperation {
+    PatchOperation::Remove(RemoveOperation { path })
+}
+
+// Top-level fields of `new` that differ from `old`, if both are objects
+// with the same set of fields and only some of them changed.
+pub fn changed_fields(old: &Value, new: &Value) -> Option<Map<String, Value>> {
+    let (old, new) = match (old, new) {
+        (Value::Object(old), Value::Object(new)) => (old, new),
+        _ => return None,
+    };
+    if old.len() != new.len() || !new.keys().all(|key| old.contains_key(key)) {
+        return None;
+    }
+    let changed = new
+        .iter()
+        .filter(|(key, value)| old.get(key.as_str()) != Some(value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect::<Map<String, Value>>();
+    if changed.is_empty() || changed.len() == new.len() {
+        None
+    } else {
+        Some(changed)
+    }
+}
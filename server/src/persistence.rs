@@ -0,0 +1,88 @@
+use std::{sync::Arc, time::Duration};
+
+use serde_json::{Map, Value};
+use tokio::time::{interval_at, Instant};
+
+use crate::{
+    connections::CLIENTS_KEY,
+    journal::JournalWriter,
+    poca::Store,
+    storage::{StorageBackend, StorageError},
+    trace::warn,
+};
+
+// Where the store is saved, see `Poca::persist_with`. Values loaded from it
+// wait here until their keys are registered again.
+pub struct Persistence {
+    pub backend: Arc<dyn StorageBackend>,
+    pub restored: Map<String, Value>,
+    pub journal: Option<JournalWriter>,
+}
+
+impl Persistence {
+    // With a journal, its log is compacted instead.
+    pub fn save(&self, store: &Store) -> Result<(), StorageError> {
+        self.saver(store)()
+    }
+
+    // saves the store when called, so it can be moved to a blocking thread
+    pub fn saver(
+        &self,
+        store: &Store,
+    ) -> impl FnOnce() -> Result<(), StorageError> + Send + 'static {
+        let (backend, journal, store) = (self.backend.clone(), self.journal.clone(), store.clone());
+        move || match journal {
+            Some(journal) => journal.compact(),
+            None => backend.save(&snapshot(&store)),
+        }
+    }
+}
+
+// the values of all keys
+pub fn snapshot(store: &Store) -> Map<String, Value> {
+    store
+        .elements()
+        .into_iter()
+        // about connections, which don't survive a restart
+        .filter(|(key, _)| key.as_str() != CLIENTS_KEY)
+        .map(|(key, element)| {
+            let data = element.read().data.serialize();
+            (key, serde_json::from_str(&data).unwrap())
+        })
+        .collect()
+}
+
+// Saves the store periodically while the server is running, if it changed.
+// With a journal, its log is compacted instead. Failures are only logged with
+// the `tracing` feature.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub async fn run(
+    backend: Arc<dyn StorageBackend>,
+    period: Duration,
+    store: Store,
+    journal: Option<JournalWriter>,
+) {
+    let mut interval = interval_at(Instant::now() + period, period);
+    let mut saved = None;
+    loop {
+        interval.tick().await;
+        if let Some(journal) = &journal {
+            let journal = journal.clone();
+            let compacted = tokio::task::spawn_blocking(move || journal.compact()).await;
+            if let Ok(Err(error)) = compacted {
+                warn!(%error, "Failed to compact the log");
+            }
+            continue;
+        }
+        let snapshot = snapshot(&store);
+        if saved.as_ref() == Some(&snapshot) {
+            continue;
+        }
+        let (backend, written) = (backend.clone(), snapshot.clone());
+        match tokio::task::spawn_blocking(move || backend.save(&written)).await {
+            Ok(Ok(())) => saved = Some(snapshot),
+            Ok(Err(error)) => warn!(%error, "Failed to save the store"),
+            Err(_) => {}
+        }
+    }
+}
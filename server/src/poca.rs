@@ -1,28 +1,144 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
     fmt::Debug,
-    net::{SocketAddr, ToSocketAddrs},
-    sync::Arc,
+    future::Future,
+    hash::Hash,
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
 };
 
+use futures_util::{future, stream, FutureExt, Stream, StreamExt};
+use hyper::{service::Service, Body, Request, Response};
 use parking_lot::{Mutex, RwLock};
+use serde::{de::DeserializeOwned, Serialize};
 use tokio::{
-    sync::{broadcast, oneshot},
+    sync::{oneshot, Notify},
     task::JoinHandle,
 };
-use warp::{path::FullPath, Filter};
+use warp::{
+    http::{HeaderMap, HeaderValue, StatusCode},
+    path::FullPath,
+    Filter, Rejection, Reply,
+};
 use web_view::Handle;
 
 use crate::{
-    app_routes::AppRoutes, data_handle::DataHandle, event_handler::EventHandlerStore,
-    message::Message, synchronizable::Synchronizable, ws_handler::websocket_handler,
+    access::Access,
+    ack::Acks,
+    admin,
+    admission::{Admission, Admitted},
+    app_routes::AppRoutes,
+    audit::{Audit, AuditSink},
+    auth::Authenticator,
+    builder::{PocaBuilder, PocaConfig},
+    client::{ClientId, ClientInfo, ClientSummary, Origin},
+    cluster::{self, Cluster, ClusterBackend},
+    codegen,
+    conflict::{ConflictPolicy, MergeHandler},
+    connections::{Connections, CLIENTS_KEY},
+    counter_handle::CounterHandle,
+    data_handle::DataHandle,
+    deny_list::DenyList,
+    encoding,
+    error::{KeyError, PocaError, RpcError, StoreError, TypeError},
+    event_handler::{
+        AnyChangeHandler, AnyChangeHandlers, CallbackId, CallbackPanic, ConnectionHandlerStore,
+        ErrorHook, EventCallback, EventHandlerStore, LagHook, OnChangeHandler, PanicHook,
+        PendingChange, UnacknowledgedHook,
+    },
+    expiry::Expirations,
+    frames::Frames,
+    journal::{Journal, JournalWriter},
+    list_handle::ListHandle,
+    listener,
+    map_handle::MapHandle,
+    message::{payload, Message, POLICY_VIOLATION},
+    metrics::{Metrics, SharedMetrics},
+    middleware::{Middleware, Middlewares},
+    persistence::{self, Persistence},
+    replay::{self, Recorder, SharedRecorder},
+    rest,
+    rooms::Rooms,
+    router::{QueueStats, Router},
+    rpc::{PendingCalls, RpcFuture, RpcHandler, RpcHandlerStore},
+    sse::{self, Sessions},
+    storage::{FileStorage, StorageBackend, StorageError},
+    store::ShardedStore,
+    synchronizable::Synchronizable,
+    text::Text,
+    text_handle::TextHandle,
+    trace::{info, info_span, warn},
+    transaction::Transaction,
+    transform::Transform,
+    validation::Validator,
+    ws_handler::{connection_handler, report, snapshot, HandlerContext},
 };
 
-const CHANNEL_SIZE: usize = 32;
+#[cfg(feature = "federation")]
+use crate::federation::{self, Peer};
+#[cfg(feature = "mqtt")]
+use crate::mqtt::{self, MqttBridge};
+#[cfg(feature = "nats")]
+use crate::nats::{self, NatsBridge};
+#[cfg(feature = "tls")]
+use crate::tls::{self, TlsConfig};
+#[cfg(all(unix, feature = "unix"))]
+use crate::unix::{self, SocketFile};
+#[cfg(feature = "webtransport")]
+use crate::webtransport;
+
+// previous values kept per key, see `DataHandle::set_history_limit`
+const DEFAULT_HISTORY_LIMIT: usize = 16;
 
 pub struct DataElementInner {
+    pub key: String,
     pub data: Box<dyn Synchronizable>,
-    pub on_change: Vec<Box<dyn Fn() + Send + Sync>>,
+    // as declared when the key was registered, for reflection
+    pub type_name: &'static str,
+    pub on_change: Vec<(CallbackId, OnChangeHandler)>,
+    // see `event_handler::notify_change`
+    pub pending_changes: VecDeque<PendingChange>,
+    pub notifying: bool,
+    pub panic_hook: PanicHook,
+    pub any_change: AnyChangeHandlers,
+    pub access: Access,
+    // only members of the room see the key, see `Rooms`
+    pub room: Option<String>,
+    // changes are retransmitted until clients acknowledge them, see `Acks`
+    pub require_ack: bool,
+    // incremented on every change
+    pub version: u64,
+    pub conflict_policy: ConflictPolicy,
+    pub merge_handler: Option<MergeHandler>,
+    // run on client writes before they are applied
+    pub validators: Vec<Validator>,
+    // applied to client writes in order, before the validators
+    pub transforms: Vec<Transform>,
+    // JSON Schema of the type, see `DataHandle::set_schema`
+    pub schema: Option<serde_json::Value>,
+    // previous values, oldest first, see `DataHandle::undo`
+    pub history: VecDeque<Box<dyn Synchronizable>>,
+    pub history_limit: usize,
+}
+
+impl DataElementInner {
+    // Keeps the value a change replaced, for `DataHandle::undo`. Called while
+    // the value is still locked, so the history is in the order of the changes.
+    pub fn remember(&mut self, old: &dyn Synchronizable) {
+        if self.history_limit > 0 {
+            self.history.push_back(old.clone_synchronizable());
+            while self.history.len() > self.history_limit {
+                self.history.pop_front();
+            }
+        }
+    }
 }
 
 impl Debug for DataElementInner {
@@ -32,25 +148,87 @@ impl Debug for DataElementInner {
 }
 
 pub type DataElement = Arc<RwLock<DataElementInner>>;
-pub type Store = Arc<Mutex<HashMap<String, DataElement>>>;
-
-pub type BroadcastSender = broadcast::Sender<Message>;
-pub type BroadcastReceiver = broadcast::Receiver<Message>;
+pub type Store = Arc<ShardedStore>;
 
+#[derive(Clone)]
 pub struct Poca {
+    inner: Arc<PocaInner>,
+}
+
+// for what the server holds on to itself, like its routes
+#[derive(Clone)]
+pub(crate) struct WeakPoca(Weak<PocaInner>);
+
+impl WeakPoca {
+    pub fn upgrade(&self) -> Option<Poca> {
+        self.0.upgrade().map(|inner| Poca { inner })
+    }
+}
+
+struct PocaInner {
     state: Mutex<ServerState>,
-    address: SocketAddr,
+    addresses: Vec<SocketAddr>,
+    // as bound, while the server is running
+    local_addrs: Mutex<Vec<SocketAddr>>,
     shutdown: Mutex<Option<oneshot::Sender<()>>>,
     store: Store,
     event_handler_store: EventHandlerStore,
-    broadcast: (BroadcastSender, BroadcastReceiver),
+    on_connect: ConnectionHandlerStore,
+    on_disconnect: ConnectionHandlerStore,
+    panic_hook: PanicHook,
+    lag_hook: LagHook,
+    error_hook: ErrorHook,
+    unacknowledged_hook: UnacknowledgedHook,
+    acks: Acks,
+    any_change: AnyChangeHandlers,
+    clients: Connections,
+    rooms: Rooms,
+    rpc_handlers: RpcHandlerStore,
+    pending_calls: PendingCalls,
+    authenticator: RwLock<Option<Arc<dyn Authenticator>>>,
+    router: Router,
     server: Mutex<Option<JoinHandle<()>>>,
-    app_routes: AppRoutes<'static>,
+    #[cfg(feature = "webtransport")]
+    webtransport: Mutex<Option<JoinHandle<()>>>,
+    app_routes: Arc<AppRoutes<'static>>,
     window_options: WindowOptions,
     //@TODO: support multiple windows
     window_handler: Mutex<Option<Handle<()>>>,
+    config: PocaConfig,
+    connections: Arc<AtomicUsize>,
+    connection_closed: Arc<Notify>,
+    next_client_id: Arc<AtomicU64>,
+    expirations: Expirations,
+    expiry_task: Mutex<Option<JoinHandle<()>>>,
+    stores: Stores,
+    deny_list: Arc<RwLock<DenyList>>,
+    sessions: Sessions,
+    middlewares: Middlewares,
+    frames: Frames,
+    metrics: SharedMetrics,
+    recorder: SharedRecorder,
+    verbose: Arc<AtomicBool>,
+    persistence: Mutex<Option<Persistence>>,
+    persist_task: Mutex<Option<JoinHandle<()>>>,
+    cluster: Mutex<Option<Arc<Cluster>>>,
+    cluster_task: Mutex<Option<JoinHandle<()>>>,
+    #[cfg(feature = "federation")]
+    peers: Mutex<Vec<Arc<Peer>>>,
+    #[cfg(feature = "federation")]
+    peer_tasks: Mutex<Vec<JoinHandle<()>>>,
+    #[cfg(feature = "mqtt")]
+    mqtt_bridges: Mutex<Vec<(Arc<MqttBridge>, ClientId)>>,
+    #[cfg(feature = "mqtt")]
+    mqtt_tasks: Mutex<Vec<JoinHandle<()>>>,
+    #[cfg(feature = "nats")]
+    nats_bridges: Mutex<Vec<Arc<NatsBridge>>>,
+    #[cfg(feature = "nats")]
+    nats_tasks: Mutex<Vec<JoinHandle<()>>>,
 }
 
+// stores hosted by the same listener, by the path clients connect to
+pub(crate) type Stores = Arc<RwLock<HashMap<String, Poca>>>;
+
 pub struct WindowOptions {
     title: String,
     size: (u32, u32),
@@ -89,69 +267,929 @@ impl Poca {
         app_routes: AppRoutes<'static>,
         window_options: impl Into<Option<WindowOptions>>,
     ) -> Poca {
-        let channel = broadcast::channel(CHANNEL_SIZE);
-        Poca {
-            state: Mutex::new(ServerState::Down),
-            address: address.to_socket_addrs().unwrap().next().unwrap(),
-            shutdown: Mutex::new(None),
-            store: Arc::new(Mutex::new(HashMap::new())),
-            event_handler_store: Arc::new(RwLock::new(HashMap::new())),
-            broadcast: channel,
-            server: Mutex::new(None),
-            app_routes,
-            window_options: window_options.into().unwrap_or(WindowOptions::default()),
-            window_handler: Mutex::new(None),
+        let builder = Poca::builder().address(address).app_routes(app_routes);
+        match window_options.into() {
+            Some(window_options) => builder.window_options(window_options),
+            None => builder,
         }
+        .build()
     }
 
-    pub fn data<T: Synchronizable>(&'static self, key: &str, data: T) -> DataHandle<T> {
-        let mut guard = self.store.lock();
+    pub fn builder() -> PocaBuilder {
+        PocaBuilder::new()
+    }
+
+    pub(crate) fn from_parts(
+        addresses: Vec<SocketAddr>,
+        app_routes: AppRoutes<'static>,
+        window_options: WindowOptions,
+        config: PocaConfig,
+    ) -> Poca {
+        let poca = Poca {
+            inner: Arc::new(PocaInner {
+                state: Mutex::new(ServerState::Down),
+                addresses,
+                local_addrs: Mutex::new(Vec::new()),
+                shutdown: Mutex::new(None),
+                store: Arc::new(ShardedStore::default()),
+                event_handler_store: Arc::new(RwLock::new(HashMap::new())),
+                on_connect: Arc::new(RwLock::new(Vec::new())),
+                on_disconnect: Arc::new(RwLock::new(Vec::new())),
+                panic_hook: Arc::new(RwLock::new(None)),
+                lag_hook: Arc::new(RwLock::new(None)),
+                error_hook: Arc::new(RwLock::new(None)),
+                unacknowledged_hook: Arc::new(RwLock::new(None)),
+                acks: Acks::default(),
+                any_change: Arc::new(RwLock::new(Vec::new())),
+                clients: Connections::new(config.channel_size, config.public_metadata.clone()),
+                rooms: Rooms::default(),
+                rpc_handlers: Arc::new(RwLock::new(HashMap::new())),
+                pending_calls: PendingCalls::default(),
+                authenticator: RwLock::new(None),
+                router: Router::new(config.channel_size, config.overflow, config.replay_size),
+                server: Mutex::new(None),
+                #[cfg(feature = "webtransport")]
+                webtransport: Mutex::new(None),
+                app_routes: Arc::new(app_routes),
+                window_options,
+                window_handler: Mutex::new(None),
+                config,
+                connections: Arc::new(AtomicUsize::new(0)),
+                connection_closed: Arc::new(Notify::new()),
+                next_client_id: Arc::new(AtomicU64::new(0)),
+                expirations: Expirations::default(),
+                expiry_task: Mutex::new(None),
+                stores: Arc::new(RwLock::new(HashMap::new())),
+                deny_list: Arc::new(RwLock::new(DenyList::default())),
+                sessions: Sessions::default(),
+                middlewares: Middlewares::default(),
+                frames: Frames::default(),
+                metrics: Arc::new(Metrics::default()),
+                recorder: SharedRecorder::default(),
+                verbose: Arc::new(AtomicBool::new(true)),
+                persistence: Mutex::new(None),
+                persist_task: Mutex::new(None),
+                cluster: Mutex::new(None),
+                cluster_task: Mutex::new(None),
+                #[cfg(feature = "federation")]
+                peers: Mutex::new(Vec::new()),
+                #[cfg(feature = "federation")]
+                peer_tasks: Mutex::new(Vec::new()),
+                #[cfg(feature = "mqtt")]
+                mqtt_bridges: Mutex::new(Vec::new()),
+                #[cfg(feature = "mqtt")]
+                mqtt_tasks: Mutex::new(Vec::new()),
+                #[cfg(feature = "nats")]
+                nats_bridges: Mutex::new(Vec::new()),
+                #[cfg(feature = "nats")]
+                nats_tasks: Mutex::new(Vec::new()),
+            }),
+        };
+        if poca.inner.config.metrics {
+            let metrics = poca.inner.metrics.clone();
+            poca.inner
+                .router
+                .tap(move |message| metrics.routed(message));
+        }
+        let weak = poca.downgrade();
+        poca.inner.router.on_cut_off(move |client| {
+            if let Some(poca) = weak.upgrade() {
+                poca.disconnect(client, "Client fell behind");
+            }
+        });
+        let online = poca.data(CLIENTS_KEY, Vec::<ClientSummary>::new());
+        online.set_access(Access::ReadOnly);
+        poca.inner.clients.set_online(online);
+        poca
+    }
+
+    pub fn data<T: Synchronizable>(&self, key: &str, data: T) -> DataHandle<T> {
+        self.try_data(key, data)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_data<T: Synchronizable>(
+        &self,
+        key: &str,
+        data: T,
+    ) -> Result<DataHandle<T>, KeyError> {
+        let mut guard = self.inner.store.shard(key);
         if guard.contains_key(key) {
-            panic!("Key {} already exists", key);
+            return Err(KeyError::AlreadyExists(key.to_string()));
         }
+        // the persisted value, unless the type of the key changed since
+        let restored = self
+            .inner
+            .persistence
+            .lock()
+            .as_mut()
+            .and_then(|persistence| persistence.restored.remove(key))
+            .and_then(|value| data.try_deserialize(&value.to_string()).ok());
         let data = Arc::new(RwLock::new(DataElementInner {
-            data: data.clone_synchronizable(),
+            key: key.to_string(),
+            data: restored.unwrap_or_else(|| data.clone_synchronizable()),
+            type_name: std::any::type_name::<T>(),
             on_change: Vec::new(),
+            pending_changes: VecDeque::new(),
+            notifying: false,
+            panic_hook: self.inner.panic_hook.clone(),
+            any_change: self.inner.any_change.clone(),
+            access: Access::default(),
+            room: None,
+            require_ack: false,
+            version: 0,
+            conflict_policy: ConflictPolicy::default(),
+            merge_handler: None,
+            validators: Vec::new(),
+            transforms: Vec::new(),
+            schema: None,
+            history: VecDeque::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
         }));
         guard.insert(key.to_string(), data.clone());
-        let sender = self.broadcast.0.clone();
-        DataHandle::new(key.to_string(), sender, data)
+        // clients catching up after reconnecting learn about the key this way
+        self.inner.router.record(Message::Register {
+            key: key.to_string(),
+            data: payload(data.read().data.as_ref()),
+        });
+        let sender = self.inner.router.clone();
+        Ok(DataHandle::new(
+            key.to_string(),
+            sender,
+            data,
+            self.inner.store.clone(),
+        ))
+    }
+
+    // existing handles keep working on their own copy of the value
+    pub fn remove(&self, key: &str) -> Result<(), KeyError> {
+        self.inner
+            .store
+            .remove(key)
+            .ok_or_else(|| KeyError::NotFound(key.to_string()))?;
+        self.inner.router.send(Message::Remove {
+            key: key.to_string(),
+        });
+        Ok(())
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.inner.store.contains_key(key)
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = self.inner.store.keys();
+        keys.sort();
+        keys
+    }
+
+    pub fn type_name(&self, key: &str) -> Option<&'static str> {
+        self.inner
+            .store
+            .get(key)
+            .map(|element| element.read().type_name)
+    }
+
+    // TypeScript interfaces and typed accessors for the keys, for the types
+    // of keys with a schema. Others are typed as `unknown`.
+    pub fn typescript(&self) -> String {
+        codegen::typescript(&codegen::schemas(&self.inner.store, |_| true))
+    }
+
+    // A JSON Schema document with a property for every key, clients can fetch
+    // it at runtime to validate incoming data. Keys without a schema accept
+    // any value, see `DataHandle::set_schema`.
+    pub fn schema(&self) -> serde_json::Value {
+        codegen::schema(&codegen::schemas(&self.inner.store, |_| true))
+    }
+
+    // meant for build scripts or a small binary next to the server
+    pub fn write_typescript(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.typescript())
+    }
+
+    // Every changed key with its value at the time the change is received.
+    // Changes are skipped if the stream falls too far behind.
+    pub fn all_changes(&self) -> impl Stream<Item = (String, Box<dyn Synchronizable>)> + Unpin {
+        let store = self.inner.store.clone();
+        self.inner
+            .router
+            .subscribe(None, true, None)
+            .filter_map(|message| future::ready(message.ok().map(|(_, message)| message)))
+            .flat_map(|message| {
+                stream::iter(match message {
+                    Message::Batch(messages) => messages,
+                    message => vec![message],
+                })
+            })
+            .filter_map(move |message| {
+                let changed = message.change().and_then(|(key, _)| {
+                    let element = store.get(key)?;
+                    let data = element.read().data.clone();
+                    Some((key.to_string(), data))
+                });
+                future::ready(changed)
+            })
+    }
+
+    // clients receive all changes in a single message
+    pub fn transaction(&self, build: impl FnOnce(&mut Transaction)) -> Result<(), KeyError> {
+        let mut transaction = Transaction::default();
+        build(&mut transaction);
+        transaction.commit(&self.inner.store, &self.inner.router)
+    }
+
+    // another handle to an already registered key
+    pub fn handle<T: Synchronizable>(&self, key: &str) -> Result<DataHandle<T>, TypeError> {
+        let element = self
+            .inner
+            .store
+            .get(key)
+            .ok_or_else(|| TypeError::NotFound(key.to_string()))?;
+        {
+            let guard = element.read();
+            if !guard.data.as_any_ref().is::<T>() {
+                return Err(TypeError::Mismatch {
+                    key: key.to_string(),
+                    expected: std::any::type_name::<T>(),
+                    found: guard.type_name,
+                });
+            }
+        }
+        Ok(DataHandle::new(
+            key.to_string(),
+            self.inner.router.clone(),
+            element,
+            self.inner.store.clone(),
+        ))
+    }
+
+    // the key is removed once the ttl has passed, while the server is running
+    pub fn data_with_ttl<T: Synchronizable>(
+        &self,
+        key: &str,
+        data: T,
+        ttl: Duration,
+    ) -> DataHandle<T> {
+        self.try_data_with_ttl(key, data, ttl)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_data_with_ttl<T: Synchronizable>(
+        &self,
+        key: &str,
+        data: T,
+        ttl: Duration,
+    ) -> Result<DataHandle<T>, KeyError> {
+        let handle = self.try_data(key, data)?;
+        self.expire_after(key, ttl)?;
+        Ok(handle)
+    }
+
+    // replaces an earlier ttl of the key
+    pub fn expire_after(&self, key: &str, ttl: Duration) -> Result<(), KeyError> {
+        let element = self
+            .inner
+            .store
+            .get(key)
+            .ok_or_else(|| KeyError::NotFound(key.to_string()))?;
+        self.inner.expirations.insert(key, ttl, element);
+        Ok(())
+    }
+
+    pub fn counter(&self, key: &str, initial: i64) -> CounterHandle {
+        CounterHandle::new(self.data(key, initial))
+    }
+
+    pub fn try_counter(&self, key: &str, initial: i64) -> Result<CounterHandle, KeyError> {
+        self.try_data(key, initial).map(CounterHandle::new)
+    }
+
+    pub fn text(&self, key: &str, initial: &str) -> TextHandle {
+        TextHandle::new(self.data(key, Text::new(initial)))
+    }
+
+    pub fn try_text(&self, key: &str, initial: &str) -> Result<TextHandle, KeyError> {
+        self.try_data(key, Text::new(initial)).map(TextHandle::new)
+    }
+
+    pub fn list<T>(&self, key: &str, data: Vec<T>) -> ListHandle<T>
+    where
+        T: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+    {
+        ListHandle::new(self.data(key, data))
+    }
+
+    pub fn try_list<T>(&self, key: &str, data: Vec<T>) -> Result<ListHandle<T>, KeyError>
+    where
+        T: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+    {
+        self.try_data(key, data).map(ListHandle::new)
+    }
+
+    pub fn map<K, V>(&self, key: &str, data: HashMap<K, V>) -> MapHandle<K, V>
+    where
+        K: Serialize
+            + DeserializeOwned
+            + ToString
+            + Eq
+            + Hash
+            + Clone
+            + Debug
+            + Send
+            + Sync
+            + 'static,
+        V: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+    {
+        MapHandle::new(self.data(key, data))
+    }
+
+    pub fn try_map<K, V>(&self, key: &str, data: HashMap<K, V>) -> Result<MapHandle<K, V>, KeyError>
+    where
+        K: Serialize
+            + DeserializeOwned
+            + ToString
+            + Eq
+            + Hash
+            + Clone
+            + Debug
+            + Send
+            + Sync
+            + 'static,
+        V: Serialize + DeserializeOwned + Clone + Debug + Send + Sync + 'static,
+    {
+        self.try_data(key, data).map(MapHandle::new)
     }
 
     pub fn event(&self, key: &str, handler: impl Fn() + Send + Sync + 'static) {
-        let mut lock = self.event_handler_store.write();
+        self.event_with_client(key, move |_| handler());
+    }
+
+    pub fn event_with_client(
+        &self,
+        key: &str,
+        handler: impl Fn(&ClientInfo) + Send + Sync + 'static,
+    ) {
+        self.add_event_callback(
+            key,
+            Box::new(move |client, _| {
+                handler(client);
+                Ok(())
+            }),
+        );
+    }
+
+    // A missing payload is read as null, so `()` and `Option` accept it.
+    // Clients get an error if the payload can't be deserialized.
+    pub fn on_event<P: DeserializeOwned>(
+        &self,
+        key: &str,
+        handler: impl Fn(&ClientInfo, P) + Send + Sync + 'static,
+    ) {
+        self.add_event_callback(
+            key,
+            Box::new(move |client, payload| {
+                let payload = serde_json::from_str(payload.unwrap_or("null"))
+                    .map_err(|error| error.to_string())?;
+                handler(client, payload);
+                Ok(())
+            }),
+        );
+    }
+
+    fn add_event_callback(&self, key: &str, callback: EventCallback) {
+        let mut lock = self.inner.event_handler_store.write();
         match lock.get_mut(key) {
             Some(list) => {
-                list.push(Box::new(handler));
+                list.push(callback);
             }
             None => {
-                lock.insert(key.to_string(), vec![Box::new(handler)]);
+                lock.insert(key.to_string(), vec![callback]);
+            }
+        }
+    }
+
+    // also available to clients as the `CLIENTS_KEY` key
+    pub fn clients(&self) -> Vec<ClientInfo> {
+        self.inner.clients.clients()
+    }
+
+    // how far behind the connections are, ordered by client
+    pub fn queues(&self) -> Vec<QueueStats> {
+        self.inner.router.stats()
+    }
+
+    // clients with changes of the key they haven't acknowledged yet
+    pub fn unacknowledged(&self, key: &str) -> Vec<ClientId> {
+        self.inner.acks.unacknowledged(key)
+    }
+
+    // False if the client is not connected. Unlike `ClientInfo::set_metadata`
+    // this updates the `CLIENTS_KEY` key right away.
+    pub fn set_client_metadata(
+        &self,
+        client: ClientId,
+        key: &str,
+        value: impl Into<String>,
+    ) -> bool {
+        match self.inner.clients.get(client) {
+            Some(info) => {
+                info.set_metadata(key, value);
+                self.inner.clients.publish();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // closes the connection with a policy violation, false if not connected
+    pub fn disconnect(&self, client: ClientId, reason: &str) -> bool {
+        let message = Message::Close {
+            code: POLICY_VIOLATION,
+            reason: reason.to_string(),
+        };
+        self.inner.clients.send(client, message)
+    }
+
+    // Connections from the address are refused with 403,
+    // connected clients from it are disconnected.
+    pub fn deny_address(&self, address: IpAddr) {
+        self.inner.deny_list.write().addresses.insert(address);
+        self.disconnect_denied();
+    }
+
+    pub fn allow_address(&self, address: IpAddr) {
+        self.inner.deny_list.write().addresses.remove(&address);
+    }
+
+    // like `deny_address`, for clients whose `IDENTITY_METADATA` matches
+    pub fn deny_identity(&self, identity: &str) {
+        self.inner
+            .deny_list
+            .write()
+            .identities
+            .insert(identity.to_string());
+        self.disconnect_denied();
+    }
+
+    pub fn allow_identity(&self, identity: &str) {
+        self.inner.deny_list.write().identities.remove(identity);
+    }
+
+    fn disconnect_denied(&self) {
+        let stores = self
+            .inner
+            .stores
+            .read()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        for store in std::iter::once(self).chain(stores.iter()) {
+            for client in store.clients() {
+                if self.inner.deny_list.read().is_denied(&client) {
+                    store.disconnect(client.id, "Access denied");
+                }
             }
         }
     }
 
+    // sent to all connected clients, nothing is kept in the store
+    pub fn emit<E: Serialize>(&self, event: &str, payload: E) -> Result<(), serde_json::Error> {
+        let message = Message::event(event, &payload)?;
+        self.inner.router.send(message);
+        Ok(())
+    }
+
+    // false if the client is not connected
+    pub fn send_to<E: Serialize>(
+        &self,
+        client: ClientId,
+        event: &str,
+        payload: E,
+    ) -> Result<bool, serde_json::Error> {
+        let message = Message::event(event, &payload)?;
+        Ok(self.inner.clients.send(client, message))
+    }
+
+    // usually excludes the client whose action caused the event
+    pub fn broadcast_except<E: Serialize>(
+        &self,
+        client: ClientId,
+        event: &str,
+        payload: E,
+    ) -> Result<(), serde_json::Error> {
+        let message = Message::event(event, &payload)?;
+        self.inner.clients.send_except(client, message);
+        Ok(())
+    }
+
+    // The client receives the keys of the room right away.
+    // False if the client is not connected.
+    pub fn join(&self, client: ClientId, room: &str) -> bool {
+        if !self.inner.clients.is_connected(client) {
+            return false;
+        }
+        if self.inner.rooms.join(room, client) {
+            let values = snapshot(&self.inner.store, |each| each == Some(room));
+            self.inner
+                .clients
+                .send(client, Message::Snapshot { values });
+        }
+        true
+    }
+
+    // the client is told to drop the keys of the room
+    pub fn leave(&self, client: ClientId, room: &str) {
+        if self.inner.rooms.leave(room, client) {
+            for key in snapshot(&self.inner.store, |each| each == Some(room)).keys() {
+                let message = Message::Remove { key: key.clone() };
+                self.inner.clients.send(client, message);
+            }
+        }
+    }
+
+    pub fn room_members(&self, room: &str) -> Vec<ClientId> {
+        self.inner.rooms.members(room)
+    }
+
+    pub fn emit_to_room<E: Serialize>(
+        &self,
+        room: &str,
+        event: &str,
+        payload: E,
+    ) -> Result<(), serde_json::Error> {
+        let message = Message::event(event, &payload)?;
+        for client in self.inner.rooms.members(room) {
+            self.inner.clients.send(client, message.clone());
+        }
+        Ok(())
+    }
+
+    // Answers requests of clients, replacing an earlier handler of the method.
+    // A missing request is read as null, an error is handed to the client.
+    pub fn on_request<Req, Res, F, Fut>(&self, method: &str, handler: F)
+    where
+        Req: DeserializeOwned + 'static,
+        Res: Serialize + 'static,
+        F: Fn(ClientInfo, Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Res, String>> + Send + 'static,
+    {
+        let handler = Arc::new(move |client: ClientInfo, request: Option<String>| {
+            let request = serde_json::from_str(request.as_deref().unwrap_or("null"));
+            let response = request.map(|request| handler(client, request));
+            Box::pin(async move {
+                let response = response.map_err(|error| error.to_string())?.await?;
+                serde_json::to_string(&response).map_err(|error| error.to_string())
+            }) as RpcFuture
+        }) as RpcHandler;
+        self.inner
+            .rpc_handlers
+            .write()
+            .insert(method.to_string(), handler);
+    }
+
+    // waits until the client answers or disconnects, at most `call_timeout`
+    pub async fn call<Req, Res>(
+        &self,
+        client: ClientId,
+        method: &str,
+        request: Req,
+    ) -> Result<Res, RpcError>
+    where
+        Req: Serialize,
+        Res: DeserializeOwned,
+    {
+        let payload = serde_json::to_string(&request)
+            .map_err(|error| RpcError::InvalidRequest(error.to_string()))?;
+        let (id, receiver) = self
+            .inner
+            .pending_calls
+            .register(client)
+            .ok_or(RpcError::Disconnected(client))?;
+        let message = Message::Request {
+            id,
+            method: method.to_string(),
+            payload,
+            client,
+        };
+        self.inner.router.send(message);
+        let response = match tokio::time::timeout(self.inner.config.call_timeout, receiver).await {
+            Ok(response) => response.map_err(|_| RpcError::Disconnected(client))?,
+            Err(_) => {
+                self.inner.pending_calls.cancel(id);
+                return Err(RpcError::Timeout(client));
+            }
+        };
+        let response = response.map_err(RpcError::Remote)?;
+        serde_json::from_str(&response)
+            .map_err(|error| RpcError::InvalidResponse(error.to_string()))
+    }
+
+    pub fn on_connect(&self, handler: impl Fn(ClientInfo) + Send + Sync + 'static) {
+        self.inner.on_connect.write().push(Box::new(handler));
+    }
+
+    pub fn on_disconnect(&self, handler: impl Fn(ClientInfo) + Send + Sync + 'static) {
+        self.inner.on_disconnect.write().push(Box::new(handler));
+    }
+
+    // called after every change of any key, including keys registered later
+    pub fn on_any_change(
+        &self,
+        handler: impl Fn(&str, &dyn Synchronizable, Origin) + Send + Sync + 'static,
+    ) -> CallbackId {
+        let id = CallbackId::next();
+        let handler = Arc::new(handler) as AnyChangeHandler;
+        self.inner.any_change.write().push((id, handler));
+        id
+    }
+
+    // false if the handler was already removed
+    pub fn remove_on_any_change(&self, id: CallbackId) -> bool {
+        let mut guard = self.inner.any_change.write();
+        let count = guard.len();
+        guard.retain(|(each, _)| *each != id);
+        guard.len() != count
+    }
+
+    // called when a user callback panics, the panic doesn't propagate further
+    pub fn on_panic(&self, hook: impl Fn(&CallbackPanic) + Send + Sync + 'static) {
+        *self.inner.panic_hook.write() = Some(Box::new(hook));
+    }
+
+    // Called with errors that are otherwise only answered to the client or
+    // end its connection, like malformed messages or failed authentication.
+    pub fn on_error(&self, hook: impl Fn(&PocaError) + Send + Sync + 'static) {
+        *self.inner.error_hook.write() = Some(Box::new(hook));
+    }
+
+    // called with the number of changes a client missed, see `LagPolicy`
+    pub fn on_lag(&self, hook: impl Fn(&ClientInfo, u64) + Send + Sync + 'static) {
+        *self.inner.lag_hook.write() = Some(Box::new(hook));
+    }
+
+    // called when a client didn't acknowledge a change after all retries
+    pub fn on_unacknowledged(&self, hook: impl Fn(&ClientInfo, &str) + Send + Sync + 'static) {
+        *self.inner.unacknowledged_hook.write() = Some(Box::new(hook));
+    }
+
+    // must be set before `start` to take effect
+    pub fn set_authenticator(&self, authenticator: impl Authenticator) {
+        *self.inner.authenticator.write() = Some(Arc::new(authenticator));
+    }
+
+    // Restores the values saved in the file, then keeps saving the store to
+    // it while the server is running and when it stops, see `persist_with`.
+    pub fn persist_to(&self, path: impl Into<PathBuf>) -> Result<(), PocaError> {
+        self.persist_with(FileStorage::new(path))
+    }
+
+    // Restores the values saved in the backend, then keeps saving the store
+    // to it while the server is running and when it stops. Keys registered
+    // later get their saved value too, keys of other stores aren't saved.
+    // With `PocaBuilder::write_ahead_log`, every change is logged as well.
+    pub fn persist_with(&self, backend: impl StorageBackend) -> Result<(), PocaError> {
+        self.restore(Arc::new(backend))
+    }
+
+    pub(crate) fn restore(&self, backend: Arc<dyn StorageBackend>) -> Result<(), PocaError> {
+        let journal = match self.inner.config.write_ahead_log {
+            true => Some(
+                Journal::open(
+                    backend.clone(),
+                    self.inner.config.compact_after,
+                    self.inner.config.durability,
+                )
+                .map_err(PocaError::Persistence)?,
+            ),
+            false => None,
+        };
+        let mut restored = match &journal {
+            Some(journal) => journal.values().clone(),
+            None => backend.load().map_err(PocaError::Persistence)?,
+        };
+        for (key, element) in self.inner.store.elements() {
+            let value = match restored.remove(&key) {
+                Some(value) if key != CLIENTS_KEY => value,
+                _ => continue,
+            };
+            let mut element = element.write();
+            if let Ok(data) = element.data.try_deserialize(&value.to_string()) {
+                element.data = data;
+                element.version += 1;
+                self.inner.router.send(Message::Set {
+                    key,
+                    data: payload(element.data.as_ref()),
+                    origin: Origin::Server,
+                    version: element.version,
+                });
+            }
+        }
+        let journal = journal.map(|journal| {
+            let journal = JournalWriter::spawn(journal);
+            let appending = journal.clone();
+            self.inner
+                .router
+                .tap(move |message| appending.append(message));
+            // changes of keys registered before are applied to their current value
+            for (key, element) in self.inner.store.elements() {
+                let element = element.read();
+                journal.append(&Message::Set {
+                    key,
+                    data: payload(element.data.as_ref()),
+                    origin: Origin::Server,
+                    version: element.version,
+                });
+            }
+            journal
+        });
+        *self.inner.persistence.lock() = Some(Persistence {
+            backend,
+            restored,
+            journal,
+        });
+        if self.get_state() == ServerState::Up {
+            self.start_persistence();
+        }
+        Ok(())
+    }
+
+    // saves the store right away, if it is persisted
+    pub fn persist(&self) -> Result<(), StorageError> {
+        self.inner.persist()
+    }
+
+    // Saves the store like `persist`, on a blocking thread of the runtime.
+    // `stop` saves it in the background, this waits until it is saved.
+    pub async fn flush(&self) -> Result<(), StorageError> {
+        let save = match self.inner.persistence.lock().as_ref() {
+            Some(persistence) => persistence.saver(&self.inner.store),
+            None => return Ok(()),
+        };
+        match tokio::task::spawn_blocking(save).await {
+            Ok(result) => result,
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(_) => Ok(()),
+        }
+    }
+
+    // Shares the keys with the other servers of the cluster: changes made here
+    // are published, the ones of other servers are applied and broadcast to
+    // the clients connected here. Only keys registered on both servers are
+    // shared, a server joins a single cluster.
+    pub fn join_cluster(&self, backend: impl ClusterBackend) {
+        self.connect_cluster(Arc::new(backend));
+    }
+
+    pub(crate) fn connect_cluster(&self, backend: Arc<dyn ClusterBackend>) {
+        let mut cluster = self.inner.cluster.lock();
+        if cluster.is_some() {
+            return;
+        }
+        *cluster = Some(Arc::new(Cluster::join(backend, &self.inner.router)));
+        drop(cluster);
+        if self.get_state() == ServerState::Up {
+            self.start_cluster();
+        }
+    }
+
+    // Connects to another server as a client and mirrors the keys with it in
+    // both directions, like an edge server with a central one. The keys are
+    // registered on both servers, the peer's values win when connecting.
+    #[cfg(feature = "federation")]
+    pub fn add_peer(&self, url: impl Into<String>, keys: &[&str]) {
+        let peer = Arc::new(Peer {
+            url: url.into(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            link: ClientId::new(self.inner.next_client_id.fetch_add(1, Ordering::SeqCst)),
+        });
+        self.inner.peers.lock().push(peer.clone());
+        if self.get_state() == ServerState::Up {
+            self.start_peer(peer);
+        }
+    }
+
+    // Publishes changes of the bridged keys to their MQTT topics and applies
+    // the messages on them, for devices that don't speak the WebSocket protocol.
+    #[cfg(feature = "mqtt")]
+    pub fn add_mqtt_bridge(&self, bridge: MqttBridge) {
+        let link = ClientId::new(self.inner.next_client_id.fetch_add(1, Ordering::SeqCst));
+        let bridge = (Arc::new(bridge), link);
+        self.inner.mqtt_bridges.lock().push(bridge.clone());
+        if self.get_state() == ServerState::Up {
+            self.start_mqtt_bridge(bridge);
+        }
+    }
+
+    // lets other services react to changes and events without connecting as clients
+    #[cfg(feature = "nats")]
+    pub fn add_nats_bridge(&self, bridge: NatsBridge) {
+        let bridge = Arc::new(bridge);
+        self.inner.nats_bridges.lock().push(bridge.clone());
+        if self.get_state() == ServerState::Up {
+            self.start_nats_bridge(bridge);
+        }
+    }
+
+    // in the Prometheus text format, like served at `/metrics`, writes are only
+    // counted with `PocaBuilder::metrics`
+    pub fn metrics(&self) -> String {
+        self.inner
+            .metrics
+            .render(&self.inner.store, &self.inner.connections)
+    }
+
+    // Records every change from now on with the client that made it and the
+    // hash of the value before, for answering who changed a key and when.
+    pub fn audit(&self, sink: impl AuditSink) {
+        let audit = Audit::new(sink, &self.inner.store);
+        self.inner.router.tap(move |message| audit.routed(message));
+    }
+
+    // Records every message clients send from now on, with the time and the
+    // client, for reproducing what happened with `replay`. Replaces the
+    // current recording if there is one.
+    pub fn record_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        *self.inner.recorder.write() = Some(Recorder::create(path)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&self) {
+        self.inner.recorder.write().take();
+    }
+
+    // Sends the recorded messages again, as if the clients had connected to
+    // this server, at `speed` times the original pace. Meant for a fresh
+    // server with the same keys as the recorded one, it doesn't have to be
+    // running. Returns once every message has been handled, `speed` has to
+    // be positive.
+    pub async fn replay(&self, path: impl AsRef<Path>, speed: f64) -> io::Result<()> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The replay speed has to be positive",
+            ));
+        }
+        let recorded = replay::load(path)?;
+        replay::replay(
+            recorded,
+            speed,
+            self.handler_context(),
+            self.inner.next_client_id.clone(),
+        )
+        .await;
+        Ok(())
+    }
+
+    // whether every message clients send is traced, on by default
+    pub fn set_verbose(&self, verbose: bool) {
+        self.inner.verbose.store(verbose, Ordering::Relaxed);
+    }
+
+    // every value, rooms or not
+    pub(crate) fn dump(&self) -> serde_json::Map<String, serde_json::Value> {
+        snapshot(&self.inner.store, |_| true)
+    }
+
+    pub(crate) fn report(&self, error: PocaError) {
+        report(&self.inner.error_hook, &self.inner.panic_hook, error);
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakPoca {
+        WeakPoca(Arc::downgrade(&self.inner))
+    }
+
+    // applies to connected clients as well, see `Middleware`
+    pub fn add_middleware(&self, middleware: impl Middleware) {
+        self.inner.middlewares.write().push(Arc::new(middleware));
+    }
+
     pub fn get_state(&self) -> ServerState {
-        *self.state.lock()
+        *self.inner.state.lock()
     }
 
     //@TODO: choose if the program should end when window is closed
     pub fn show_window(&self) {
-        if self.window_handler.lock().is_none() {
+        if self.inner.window_handler.lock().is_none() {
+            let address = self
+                .local_addr()
+                .or_else(|| self.inner.addresses.first().copied())
+                .expect("Server address cannot be resolved");
             let window = web_view::builder()
-                .title(self.window_options.title.as_str())
-                .content(web_view::Content::Url(format!("http://{}/", self.address)))
+                .title(self.inner.window_options.title.as_str())
+                .content(web_view::Content::Url(format!("http://{}/", address)))
                 .size(
-                    self.window_options.size.0 as i32,
-                    self.window_options.size.1 as i32,
+                    self.inner.window_options.size.0 as i32,
+                    self.inner.window_options.size.1 as i32,
                 )
-                .resizable(self.window_options.resizable)
+                .resizable(self.inner.window_options.resizable)
                 .debug(false)
                 .user_data(())
                 .invoke_handler(|_webview, _argument| Ok(()))
                 .build()
                 .expect("Failed to build Webview window");
             let handle = window.handle();
-            *(self.window_handler.lock()) = Some(handle);
+            *(self.inner.window_handler.lock()) = Some(handle);
             window.run().ok();
         } else {
             panic!("Window already shown")
@@ -159,31 +1197,437 @@ impl Poca {
     }
 
     pub fn kill_window(&self) {
-        if let Some(handle) = self.window_handler.lock().take() {
+        if let Some(handle) = self.inner.window_handler.lock().take() {
             handle.dispatch(|webview| Ok(webview.exit())).ok();
         }
     }
 
-    pub async fn start(&'static self) {
+    // listens on all addresses, or on none if any of them can't be bound
+    pub async fn start(&self) -> Result<(), PocaError> {
+        let _span = info_span!("start").entered();
+        let addresses = self.prepare_start()?;
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let shutdown_receiver = shutdown_receiver.map(|_| ()).shared();
+
+        let mut local_addrs = Vec::new();
+        let mut servers = Vec::new();
+        for address in addresses {
+            let incoming = listener::bind(address, &self.inner.config)?;
+            local_addrs.push(incoming.local_addr());
+            servers.push(listener::serve(
+                self.routes(),
+                incoming,
+                shutdown_receiver.clone(),
+            ));
+        }
+
+        let server = future::join_all(servers).map(|_| ());
+        self.finish_start(local_addrs, tokio::spawn(server), shutdown_sender);
+        Ok(())
+    }
+
+    #[cfg(feature = "tls")]
+    pub async fn start_tls(&self, tls_config: TlsConfig) -> Result<(), PocaError> {
+        let _span = info_span!("start", tls = true).entered();
+        let addresses = self.prepare_start()?;
         let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let shutdown_receiver = shutdown_receiver.map(|_| ()).shared();
+
+        let mut local_addrs = Vec::new();
+        let mut servers = Vec::new();
+        for address in addresses {
+            let incoming = listener::bind(address, &self.inner.config)?;
+            local_addrs.push(incoming.local_addr());
+            let incoming = tls::incoming(incoming, tls_config.clone())?;
+            servers.push(
+                warp::serve(self.routes())
+                    .serve_incoming_with_graceful_shutdown(incoming, shutdown_receiver.clone()),
+            );
+        }
+
+        let server = future::join_all(servers).map(|_| ());
+        self.finish_start(local_addrs, tokio::spawn(server), shutdown_sender);
+        Ok(())
+    }
+
+    // Experimental, accepts WebTransport sessions over QUIC next to the
+    // listener started by `start` or `start_tls`, until the server stops.
+    #[cfg(feature = "webtransport")]
+    pub async fn start_webtransport(
+        &self,
+        address: impl ToSocketAddrs,
+        tls_config: TlsConfig,
+    ) -> Result<SocketAddr, PocaError> {
+        let mut webtransport = self.inner.webtransport.lock();
+        if webtransport.is_some() {
+            return Err(PocaError::AlreadyRunning);
+        }
+        let address = address
+            .to_socket_addrs()
+            .map_err(|_| PocaError::InvalidAddress)?
+            .next()
+            .ok_or(PocaError::InvalidAddress)?;
+        let endpoint = webtransport::bind(address, &tls_config)?;
+        let local_addr = endpoint.local_addr().map_err(|error| PocaError::Bind {
+            address,
+            source: Box::new(error),
+        })?;
+        *webtransport = Some(tokio::spawn(webtransport::serve(
+            endpoint,
+            self.admission(),
+            self.inner.config.max_message_size,
+        )));
+        Ok(local_addr)
+    }
+
+    // Listens on a unix domain socket instead of the configured addresses,
+    // the socket file is removed once the server stops.
+    #[cfg(all(unix, feature = "unix"))]
+    pub async fn start_unix(&self, path: impl AsRef<std::path::Path>) -> Result<(), PocaError> {
+        if self.get_state() == ServerState::Up {
+            return Err(PocaError::AlreadyRunning);
+        }
+        let path = path.as_ref();
+        let incoming = unix::incoming(path)?;
+        let socket_file = SocketFile(path.to_path_buf());
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+
+        let server = warp::serve(self.routes())
+            .serve_incoming_with_graceful_shutdown(incoming, shutdown_receiver.map(|_| ()));
+        let server = async move {
+            server.await;
+            drop(socket_file);
+        };
+
+        self.finish_start(Vec::new(), tokio::spawn(server), shutdown_sender);
+        Ok(())
+    }
+
+    // Starts the store without a listener of its own, clients connect through
+    // the warp server the filter is mounted on. Must be called inside a runtime.
+    pub fn filter(
+        &self,
+    ) -> Result<
+        impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static,
+        PocaError,
+    > {
+        if self.get_state() == ServerState::Up {
+            return Err(PocaError::AlreadyRunning);
+        }
+        self.set_up();
+        Ok(self.routes())
+    }
+
+    // Like `filter`, for hyper based servers like axum, see `warp::service`.
+    // Remote addresses of clients are unknown this way.
+    #[allow(clippy::type_complexity)]
+    pub fn service(
+        &self,
+    ) -> Result<
+        impl Service<
+                Request<Body>,
+                Response = Response<Body>,
+                Error = Infallible,
+                Future = impl Future<Output = Result<Response<Body>, Infallible>> + Send,
+            > + Clone
+            + Send
+            + Sync
+            + 'static,
+        PocaError,
+    > {
+        self.filter().map(warp::service)
+    }
+
+    // The first address the server is listening on, None while it is down.
+    // Unlike the configured address, this has the actual port if it was 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.local_addrs.lock().first().copied()
+    }
+
+    // every address the server is listening on, see `PocaBuilder::address`, with
+    // port 0 their ports differ
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.inner.local_addrs.lock().clone()
+    }
+
+    fn prepare_start(&self) -> Result<Vec<SocketAddr>, PocaError> {
+        if self.get_state() == ServerState::Up {
+            return Err(PocaError::AlreadyRunning);
+        }
+        if self.inner.addresses.is_empty() {
+            return Err(PocaError::InvalidAddress);
+        }
+        Ok(self.inner.addresses.clone())
+    }
+
+    fn finish_start(
+        &self,
+        local_addrs: Vec<SocketAddr>,
+        server: JoinHandle<()>,
+        shutdown_sender: oneshot::Sender<()>,
+    ) {
+        info!(addresses = ?local_addrs, "Server started");
+        *(self.inner.local_addrs.lock()) = local_addrs;
+        *(self.inner.server.lock()) = Some(server);
+        *(self.inner.shutdown.lock()) = Some(shutdown_sender);
+        self.set_up();
+    }
+
+    fn set_up(&self) {
+        self.start_expiry();
+        self.start_persistence();
+        self.start_cluster();
+        #[cfg(feature = "federation")]
+        for peer in self.inner.peers.lock().clone() {
+            self.start_peer(peer);
+        }
+        #[cfg(feature = "mqtt")]
+        for bridge in self.inner.mqtt_bridges.lock().clone() {
+            self.start_mqtt_bridge(bridge);
+        }
+        #[cfg(feature = "nats")]
+        for bridge in self.inner.nats_bridges.lock().clone() {
+            self.start_nats_bridge(bridge);
+        }
+        for store in self.inner.stores.read().values() {
+            store.start_expiry();
+        }
+        *(self.inner.state.lock()) = ServerState::Up;
+    }
+
+    fn start_expiry(&self) {
+        *(self.inner.expiry_task.lock()) = Some(tokio::spawn(
+            self.inner
+                .expirations
+                .clone()
+                .run(self.inner.store.clone(), self.inner.router.clone()),
+        ));
+    }
+
+    fn start_persistence(&self) {
+        let (backend, journal) = match self.inner.persistence.lock().as_ref() {
+            Some(persistence) => (persistence.backend.clone(), persistence.journal.clone()),
+            None => return,
+        };
+        let task = tokio::spawn(persistence::run(
+            backend,
+            self.inner.config.persist_interval,
+            self.inner.store.clone(),
+            journal,
+        ));
+        if let Some(previous) = self.inner.persist_task.lock().replace(task) {
+            previous.abort();
+        }
+    }
+
+    fn stop_expiry(&self) {
+        if let Some(task) = self.inner.expiry_task.lock().take() {
+            task.abort();
+        }
+    }
+
+    // Another store on the same listener, for clients connecting to the path,
+    // below `PocaConfig::ws_path` if there is one.
+    // Keys, handlers, rooms and clients of the stores are separate, clients
+    // connecting to any other path get this store.
+    pub fn create_store(&self, path: &str) -> Result<Poca, StoreError> {
+        let path = store_path(path);
+        let mut stores = self.inner.stores.write();
+        if stores.contains_key(path) {
+            return Err(StoreError::AlreadyExists(path.to_string()));
+        }
+        let app_routes = AppRoutes {
+            root: "",
+            routes: Vec::new(),
+            content: &[],
+        };
+        let store = Poca::from_parts(
+            Vec::new(),
+            app_routes,
+            WindowOptions::default(),
+            self.inner.config.clone(),
+        );
+        if self.get_state() == ServerState::Up {
+            store.start_expiry();
+        }
+        stores.insert(path.to_string(), store.clone());
+        Ok(store)
+    }
+
+    pub fn get_store(&self, path: &str) -> Option<Poca> {
+        self.inner.stores.read().get(store_path(path)).cloned()
+    }
+
+    // Clients of the store are disconnected.
+    // Handles to its keys keep working, but nobody sees the changes anymore.
+    pub fn destroy_store(&self, path: &str) -> Result<(), StoreError> {
+        let path = store_path(path);
+        let store = self
+            .inner
+            .stores
+            .write()
+            .remove(path)
+            .ok_or_else(|| StoreError::NotFound(path.to_string()))?;
+        store.stop_expiry();
+        store.inner.router.send(Message::Close {
+            code: 1001,
+            reason: "Store destroyed".to_string(),
+        });
+        Ok(())
+    }
+
+    pub(crate) fn handler_context(&self) -> HandlerContext {
+        HandlerContext {
+            store: self.inner.store.clone(),
+            event_handler_store: self.inner.event_handler_store.clone(),
+            router: self.inner.router.clone(),
+            on_connect: self.inner.on_connect.clone(),
+            on_disconnect: self.inner.on_disconnect.clone(),
+            panic_hook: self.inner.panic_hook.clone(),
+            connections: self.inner.clients.clone(),
+            rooms: self.inner.rooms.clone(),
+            rpc_handlers: self.inner.rpc_handlers.clone(),
+            pending_calls: self.inner.pending_calls.clone(),
+            ping_interval: self.inner.config.ping_interval,
+            max_missed_pongs: self.inner.config.max_missed_pongs,
+            idle_timeout: self.inner.config.idle_timeout,
+            rate_limit: self.inner.config.rate_limit,
+            lag_policy: self.inner.config.lag_policy,
+            flush_interval: self.inner.config.flush_interval,
+            lag_hook: self.inner.lag_hook.clone(),
+            error_hook: self.inner.error_hook.clone(),
+            acks: self.inner.acks.clone(),
+            unacknowledged_hook: self.inner.unacknowledged_hook.clone(),
+            ack_timeout: self.inner.config.ack_timeout,
+            ack_retries: self.inner.config.ack_retries,
+            anti_entropy_interval: self.inner.config.anti_entropy_interval,
+            middlewares: self.inner.middlewares.clone(),
+            frames: self.inner.frames.clone(),
+            metrics: self.inner.metrics.clone(),
+            recorder: self.inner.recorder.clone(),
+            verbose: self.inner.verbose.clone(),
+        }
+    }
+
+    fn admission(&self) -> Admission {
+        Admission {
+            context: self.handler_context(),
+            stores: self.inner.stores.clone(),
+            connections: self.inner.connections.clone(),
+            connection_closed: self.inner.connection_closed.clone(),
+            next_client_id: self.inner.next_client_id.clone(),
+            authenticator: self.inner.authenticator.read().clone(),
+            deny_list: self.inner.deny_list.clone(),
+            max_connections: self.inner.config.max_connections,
+        }
+    }
+
+    fn routes(
+        &self,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static
+    {
+        let authenticator = self.inner.authenticator.read().clone();
+        let config = self.inner.config.clone();
+        let admission = self.admission();
+        let app_routes = self.inner.app_routes.clone();
+        let ws_path = config.ws_path.clone();
+        let static_dir = enabled(config.static_dir.is_some())
+            .and(warp::fs::dir(config.static_dir.clone().unwrap_or_default()));
+        let rest_api = enabled(config.rest_api).and(rest::routes(
+            self.inner.store.clone(),
+            authenticator,
+            self.inner.deny_list.clone(),
+            self.inner.next_client_id.clone(),
+        ));
+        let metrics = {
+            let (metrics, store, connections) = (
+                self.inner.metrics.clone(),
+                self.inner.store.clone(),
+                self.inner.connections.clone(),
+            );
+            enabled(config.metrics)
+                .and(warp::path!("metrics"))
+                .map(move || {
+                    warp::reply::with_header(
+                        metrics.render(&store, &connections),
+                        "content-type",
+                        "text/plain; version=0.0.4",
+                    )
+                })
+        };
+        let admin = enabled(config.admin_token.is_some()).and(admin::routes(
+            config.admin_token.clone().unwrap_or_default(),
+            self.downgrade(),
+        ));
+        let sse = enabled(config.sse).and(sse::routes(
+            admission.clone(),
+            self.inner.sessions.clone(),
+            config.max_message_size,
+        ));
 
-        let routes = warp::get().and(
-            warp::any()
-                .and(warp::ws().map(|websocket: warp::ws::Ws| {
-                    let store = self.store.clone();
-                    let event_handler_store = self.event_handler_store.clone();
-                    let broadcast_receiver = self.broadcast.0.subscribe();
-                    let broadcast_sender = self.broadcast.0.clone();
-                    websocket.on_upgrade(|websocket| {
-                        websocket_handler(
-                            websocket,
-                            store,
-                            event_handler_store,
-                            broadcast_receiver,
-                            broadcast_sender,
-                        )
-                    })
-                }))
+        sse.or(admin).or(warp::get().and(
+            warp::path::full()
+                .and_then(move |path: FullPath| {
+                    let path = store_of(ws_path.as_deref(), path.as_str()).map(str::to_string);
+                    async move { path.ok_or_else(warp::reject::not_found) }
+                })
+                .and(warp::ws())
+                .and(listener::remote_addr())
+                .and(warp::header::headers_cloned())
+                .and(
+                    warp::query::raw()
+                        .map(Some)
+                        .or(warp::any().map(|| None::<String>))
+                        .unify(),
+                )
+                .map(
+                    move |path: String,
+                          websocket: warp::ws::Ws,
+                          address: Option<SocketAddr>,
+                          headers: HeaderMap,
+                          query: Option<String>| {
+                        let resume = resume_from(query.as_deref());
+                        let (encoding, subprotocol) = encoding::negotiate(
+                            headers
+                                .get("sec-websocket-protocol")
+                                .and_then(|value| value.to_str().ok()),
+                            config.compression_threshold,
+                        );
+                        let Admitted {
+                            context,
+                            client,
+                            slot,
+                        } = match admission.admit(&path, address, headers, query) {
+                            Ok(admitted) => admitted,
+                            Err(response) => return *response,
+                        };
+                        let websocket = match config.max_message_size {
+                            Some(size) => websocket.max_message_size(size),
+                            None => websocket,
+                        };
+                        let websocket = match config.max_frame_size {
+                            Some(size) => websocket.max_frame_size(size),
+                            None => websocket,
+                        };
+                        let mut response = websocket
+                            .on_upgrade(move |websocket| async move {
+                                let _slot = slot;
+                                connection_handler(websocket, context, client, encoding, resume)
+                                    .await;
+                            })
+                            .into_response();
+                        if let Some(subprotocol) = subprotocol {
+                            response.headers_mut().insert(
+                                "sec-websocket-protocol",
+                                HeaderValue::from_static(subprotocol),
+                            );
+                        }
+                        response
+                    },
+                )
+                .or(rest_api)
+                .or(metrics)
+                .or(static_dir)
                 .or(warp::any()
                     .and(warp::path::full())
                     .map(move |path: FullPath| {
@@ -218,37 +1662,236 @@ impl Poca {
                             },
                             None => "text/html",
                         };
-                        let content = self.app_routes.get_route(&path, true).unwrap_or(&[]);
-                        warp::reply::with_header(content, "content-type", content_type)
+                        match app_routes.get_route(&path, true) {
+                            Some(content) => {
+                                warp::reply::with_header(content, "content-type", content_type)
+                                    .into_response()
+                            }
+                            None => warp::reply::with_status("Not found", StatusCode::NOT_FOUND)
+                                .into_response(),
+                        }
                     })),
-        );
+        ))
+    }
 
-        let address = self.address;
+    pub async fn shutdown(&self, code: u16, reason: impl Into<String>) {
+        if self.get_state() == ServerState::Down {
+            return;
+        }
+        let close = Message::Close {
+            code,
+            reason: reason.into(),
+        };
+        for store in self.inner.stores.read().values() {
+            store.inner.router.send(close.clone());
+        }
+        self.inner.router.send(close);
 
-        *(self.server.lock()) = Some(tokio::spawn(async move {
-            let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(address, async {
-                shutdown_receiver.await.ok();
-            });
-            server.await;
-        }));
+        let drained = async {
+            loop {
+                let notified = self.inner.connection_closed.notified();
+                if self.inner.connections.load(Ordering::SeqCst) == 0 {
+                    break;
+                }
+                notified.await;
+            }
+        };
+        tokio::time::timeout(self.inner.config.shutdown_timeout, drained)
+            .await
+            .ok();
+
+        self.stop();
+        let server = self.inner.server.lock().take();
+        if let Some(server) = server {
+            server.await.ok();
+        }
+    }
 
-        *(self.shutdown.lock()) = Some(shutdown_sender);
-        *(self.state.lock()) = ServerState::Up;
+    fn start_cluster(&self) {
+        let cluster = match self.inner.cluster.lock().clone() {
+            Some(cluster) => cluster,
+            None => return,
+        };
+        let task = tokio::spawn(cluster::run(
+            cluster,
+            self.inner.store.clone(),
+            self.inner.router.clone(),
+        ));
+        if let Some(previous) = self.inner.cluster_task.lock().replace(task) {
+            previous.abort();
+        }
+    }
+
+    #[cfg(feature = "federation")]
+    fn start_peer(&self, peer: Arc<Peer>) {
+        let task = tokio::spawn(federation::run(
+            peer,
+            self.inner.store.clone(),
+            self.inner.router.clone(),
+        ));
+        self.inner.peer_tasks.lock().push(task);
+    }
+
+    #[cfg(feature = "mqtt")]
+    fn start_mqtt_bridge(&self, (bridge, link): (Arc<MqttBridge>, ClientId)) {
+        let task = tokio::spawn(mqtt::run(
+            bridge,
+            link,
+            self.inner.store.clone(),
+            self.inner.router.clone(),
+        ));
+        self.inner.mqtt_tasks.lock().push(task);
+    }
+
+    #[cfg(feature = "nats")]
+    fn start_nats_bridge(&self, bridge: Arc<NatsBridge>) {
+        let task = tokio::spawn(nats::run(
+            bridge,
+            self.inner.store.clone(),
+            self.inner.router.clone(),
+        ));
+        self.inner.nats_tasks.lock().push(task);
     }
 
     pub fn stop(&self) {
-        if *(self.state.lock()) == ServerState::Up {
+        #[cfg(feature = "webtransport")]
+        if let Some(task) = self.inner.webtransport.lock().take() {
+            task.abort();
+        }
+        if *(self.inner.state.lock()) == ServerState::Up {
             self.kill_window();
-            if let Some(sender) = self.shutdown.lock().take() {
+            if let Some(sender) = self.inner.shutdown.lock().take() {
                 let _ = sender.send(());
             }
-            *(self.state.lock()) = ServerState::Down;
+            self.stop_expiry();
+            if let Some(task) = self.inner.persist_task.lock().take() {
+                task.abort();
+            }
+            if let Some(task) = self.inner.cluster_task.lock().take() {
+                task.abort();
+            }
+            #[cfg(feature = "federation")]
+            for task in self.inner.peer_tasks.lock().drain(..) {
+                task.abort();
+            }
+            #[cfg(feature = "mqtt")]
+            for task in self.inner.mqtt_tasks.lock().drain(..) {
+                task.abort();
+            }
+            #[cfg(feature = "nats")]
+            for task in self.inner.nats_tasks.lock().drain(..) {
+                task.abort();
+            }
+            self.inner.persist_in_background();
+            for store in self.inner.stores.read().values() {
+                store.stop_expiry();
+            }
+            self.inner.local_addrs.lock().clear();
+            *(self.inner.state.lock()) = ServerState::Down;
         }
     }
 }
 
-impl Drop for Poca {
+impl PocaInner {
+    fn persist(&self) -> Result<(), StorageError> {
+        match self.persistence.lock().as_ref() {
+            Some(persistence) => persistence.save(&self.store),
+            None => Ok(()),
+        }
+    }
+
+    // On a runtime the store is saved on one of its blocking threads, which
+    // it waits for when shutting down. Elsewhere nothing is left to block. A
+    // failure is only logged with the `tracing` feature.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn persist_in_background(&self) {
+        let save = match self.persistence.lock().as_ref() {
+            Some(persistence) => persistence.saver(&self.store),
+            None => return,
+        };
+        let save = move || {
+            if let Err(error) = save() {
+                warn!(%error, "Failed to save the store");
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(save)),
+            Err(_) => save(),
+        }
+    }
+}
+
+impl Drop for PocaInner {
     fn drop(&mut self) {
-        self.stop();
+        if let Some(handle) = self.window_handler.lock().take() {
+            handle.dispatch(|webview| Ok(webview.exit())).ok();
+        }
+        if let Some(sender) = self.shutdown.lock().take() {
+            let _ = sender.send(());
+        }
+        if let Some(task) = self.expiry_task.lock().take() {
+            task.abort();
+        }
+        if let Some(task) = self.persist_task.lock().take() {
+            task.abort();
+        }
+        if let Some(task) = self.cluster_task.lock().take() {
+            task.abort();
+        }
+        #[cfg(feature = "federation")]
+        for task in self.peer_tasks.lock().drain(..) {
+            task.abort();
+        }
+        #[cfg(feature = "mqtt")]
+        for task in self.mqtt_tasks.lock().drain(..) {
+            task.abort();
+        }
+        #[cfg(feature = "nats")]
+        for task in self.nats_tasks.lock().drain(..) {
+            task.abort();
+        }
+        self.persist_in_background();
+        #[cfg(feature = "webtransport")]
+        if let Some(task) = self.webtransport.lock().take() {
+            task.abort();
+        }
     }
 }
+
+fn store_path(path: &str) -> &str {
+    path.trim_matches('/')
+}
+
+// rejects every request unless enabled, for optional routes
+fn enabled(enabled: bool) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::any()
+        .and_then(move || async move {
+            if enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+// the store clients connecting to the path get, None outside of `PocaConfig::ws_path`
+fn store_of<'a>(ws_path: Option<&str>, path: &'a str) -> Option<&'a str> {
+    let path = store_path(path);
+    let ws_path = match ws_path.map(store_path) {
+        Some(ws_path) if !ws_path.is_empty() => ws_path,
+        _ => return Some(path),
+    };
+    match path.strip_prefix(ws_path)? {
+        "" => Some(""),
+        rest => rest.strip_prefix('/'),
+    }
+}
+
+// the sequence number of the last message a reconnecting client saw
+pub(crate) fn resume_from(query: Option<&str>) -> Option<u64> {
+    query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("resume="))
+        .and_then(|seq| seq.parse().ok())
+}
@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+// what happens to messages of a client that exceeds its rate limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    // messages are handled late, at the allowed rate
+    Throttle,
+    Drop,
+    Disconnect,
+}
+
+// limits per connection, a burst of one second worth is allowed, rates must
+// not be zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub messages_per_second: Option<u32>,
+    pub bytes_per_second: Option<u32>,
+    pub policy: RateLimitPolicy,
+}
+
+pub enum Verdict {
+    Allow,
+    Wait(Duration),
+    Exceeded,
+    // more bytes than the limit allows in a second, so never allowed when dropping
+    TooLarge { limit: u32 },
+}
+
+pub struct RateLimiter {
+    policy: RateLimitPolicy,
+    messages: Option<Bucket>,
+    bytes: Option<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            policy: limit.policy,
+            messages: limit.messages_per_second.map(Bucket::new),
+            bytes: limit.bytes_per_second.map(Bucket::new),
+        }
+    }
+
+    pub fn check(&mut self, size: usize) -> Verdict {
+        if let (RateLimitPolicy::Drop, Some(bytes)) = (self.policy, &self.bytes) {
+            if size as f64 > bytes.rate {
+                return Verdict::TooLarge {
+                    limit: bytes.rate as u32,
+                };
+            }
+        }
+        let now = Instant::now();
+        let mut buckets = [(&mut self.messages, 1.0), (&mut self.bytes, size as f64)];
+        let mut wait = Duration::ZERO;
+        for (bucket, amount) in buckets.iter_mut() {
+            if let Some(bucket) = bucket {
+                bucket.refill(now);
+                wait = wait.max(bucket.wait_for(*amount));
+            }
+        }
+        if !wait.is_zero() && self.policy != RateLimitPolicy::Throttle {
+            return Verdict::Exceeded;
+        }
+        // throttled messages are paid for in advance, so later ones wait longer
+        for (bucket, amount) in buckets.iter_mut() {
+            if let Some(bucket) = bucket {
+                bucket.tokens -= *amount;
+            }
+        }
+        if wait.is_zero() {
+            Verdict::Allow
+        } else {
+            Verdict::Wait(wait)
+        }
+    }
+}
+
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled_at = now;
+    }
+
+    fn wait_for(&self, amount: f64) -> Duration {
+        if self.tokens >= amount {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((amount - self.tokens) / self.rate)
+        }
+    }
+}
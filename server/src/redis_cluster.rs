@@ -0,0 +1,87 @@
+use std::{thread, time::Duration};
+
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+
+use crate::{
+    cluster::{ClusterBackend, ClusterError},
+    trace::warn,
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+// how long the subscriber waits for a message before checking whether the
+// server is gone
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Servers sharing a Redis pub/sub channel.
+pub struct RedisCluster {
+    client: redis::Client,
+    channel: String,
+    // reconnected by the next publish after an error
+    connection: Mutex<Option<redis::Connection>>,
+}
+
+impl RedisCluster {
+    // like `redis://127.0.0.1/`
+    pub fn new(url: &str, channel: &str) -> Result<Self, ClusterError> {
+        Ok(RedisCluster {
+            client: redis::Client::open(url)?,
+            channel: channel.to_string(),
+            connection: Mutex::new(None),
+        })
+    }
+}
+
+impl ClusterBackend for RedisCluster {
+    fn publish(&self, payload: String) -> Result<(), ClusterError> {
+        let mut connection = self.connection.lock();
+        if connection.is_none() {
+            *connection = Some(self.client.get_connection()?);
+        }
+        let published = redis::cmd("PUBLISH")
+            .arg(&self.channel)
+            .arg(payload)
+            .query::<()>(connection.as_mut().unwrap());
+        if published.is_err() {
+            *connection = None;
+        }
+        Ok(published?)
+    }
+
+    // received on a thread of its own, which reconnects until the server is
+    // gone, lost connections are only logged with the `tracing` feature
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn subscribe(&self) -> mpsc::UnboundedReceiver<String> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (client, channel) = (self.client.clone(), self.channel.clone());
+        thread::spawn(move || loop {
+            let received = (|| -> redis::RedisResult<()> {
+                let mut connection = client.get_connection()?;
+                let mut pubsub = connection.as_pubsub();
+                pubsub.subscribe(&channel)?;
+                pubsub.set_read_timeout(Some(POLL_INTERVAL))?;
+                while !sender.is_closed() {
+                    let message = match pubsub.get_message() {
+                        Ok(message) => message,
+                        Err(error) if error.is_timeout() => continue,
+                        Err(error) => return Err(error),
+                    };
+                    if sender.send(message.get_payload()?).is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            })();
+            match received {
+                // the server is gone
+                Ok(()) => return,
+                Err(_) if sender.is_closed() => return,
+                Err(error) => {
+                    warn!(%error, "Lost the connection to Redis");
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        });
+        receiver
+    }
+}
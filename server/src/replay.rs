@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures_util::{sink, SinkExt};
+use parking_lot::{Mutex, RwLock};
+use serde::Deserialize;
+use tokio::{
+    sync::mpsc,
+    time::{sleep_until, Instant},
+};
+use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
+use warp::ws;
+
+use crate::{
+    client::{ClientId, ClientInfo},
+    encoding::{Encoding, JsonEncoding},
+    message::WSMessage,
+    trace::warn,
+    transport::{FrameSink, FrameStream, Transport, TransportError},
+    ws_handler::{connection_handler, HandlerContext},
+};
+
+// Writes every decoded inbound message with the client that sent it, one
+// JSON object per line, see `Poca::record_to`.
+pub struct Recorder {
+    started: Instant,
+    file: Mutex<File>,
+}
+
+pub type SharedRecorder = Arc<RwLock<Option<Recorder>>>;
+
+impl Recorder {
+    // truncated if it exists
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Recorder {
+            started: Instant::now(),
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+
+    // failures are only logged with the `tracing` feature
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub fn record(&self, client: ClientId, message: &WSMessage) {
+        let entry = serde_json::json!({
+            "at": self.started.elapsed().as_millis() as u64,
+            "client": client,
+            "message": message,
+        });
+        let mut line = entry.to_string();
+        line.push('\n');
+        if let Err(error) = self.file.lock().write_all(line.as_bytes()) {
+            warn!(%error, "Failed to write to the recording");
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Recorded {
+    // milliseconds since the recording started
+    at: u64,
+    client: ClientId,
+    message: WSMessage,
+}
+
+pub(crate) fn load(path: impl AsRef<Path>) -> io::Result<Vec<Recorded>> {
+    let mut recorded = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        recorded.push(entry);
+    }
+    Ok(recorded)
+}
+
+// Every recorded client connects anew when its first message is due and
+// stays connected until the end. Messages of a client are applied in order,
+// the ones of different clients due at the same time in any order.
+pub(crate) async fn replay(
+    recorded: Vec<Recorded>,
+    speed: f64,
+    context: HandlerContext,
+    next_client_id: Arc<AtomicU64>,
+) {
+    let started = Instant::now();
+    let mut senders = HashMap::new();
+    let mut connections = Vec::new();
+    for Recorded {
+        at,
+        client,
+        message,
+    } in recorded
+    {
+        sleep_until(started + Duration::from_secs_f64(at as f64 / 1000.0 / speed)).await;
+        let sender = senders.entry(client).or_insert_with(|| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let id = ClientId::new(next_client_id.fetch_add(1, Ordering::SeqCst));
+            connections.push(tokio::spawn(connection_handler(
+                Replayed(receiver),
+                context.clone(),
+                ClientInfo::new(id, None),
+                Arc::new(JsonEncoding),
+                None,
+            )));
+            sender
+        });
+        sender.send(JsonEncoding.encode(&message)).ok();
+    }
+    // the connections close once they have handled everything sent to them
+    drop(senders);
+    for connection in connections {
+        connection.await.ok();
+    }
+}
+
+// feeds recorded frames to a connection handler, whatever it sends is dropped
+struct Replayed(mpsc::UnboundedReceiver<ws::Message>);
+
+impl Transport for Replayed {
+    fn split(self) -> (FrameSink, FrameStream) {
+        (
+            Box::pin(sink::drain().sink_map_err(|never: Infallible| match never {})),
+            Box::pin(UnboundedReceiverStream::new(self.0).map(Ok::<_, TransportError>)),
+        )
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::RwLock;
+use warp::{
+    http::{HeaderMap, StatusCode},
+    reply::Response,
+    Filter, Rejection, Reply,
+};
+
+use crate::{
+    auth::{AuthRequest, Authenticator},
+    client::{ClientId, ClientInfo},
+    deny_list::DenyList,
+    listener,
+    poca::Store,
+};
+
+// Read-only access to the values for clients that don't speak the WebSocket
+// protocol, like curl or dashboards. Requests are authenticated like
+// connections, keys in rooms are left out.
+pub(crate) fn routes(
+    store: Store,
+    authenticator: Option<Arc<dyn Authenticator>>,
+    deny_list: Arc<RwLock<DenyList>>,
+    next_client_id: Arc<AtomicU64>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone + Send + Sync + 'static {
+    // the response for requests that are turned away
+    let denied = warp::any()
+        .and(listener::remote_addr())
+        .and(warp::header::headers_cloned())
+        .and(
+            warp::query::raw()
+                .map(Some)
+                .or(warp::any().map(|| None::<String>))
+                .unify(),
+        )
+        .map(
+            move |address: Option<SocketAddr>, headers: HeaderMap, query: Option<String>| {
+                let client_id = ClientId::new(next_client_id.fetch_add(1, Ordering::SeqCst));
+                let client = ClientInfo::new(client_id, address);
+                if let Some(authenticator) = &authenticator {
+                    let request = AuthRequest {
+                        headers,
+                        query,
+                        address,
+                    };
+                    if let Err(reason) = authenticator.authenticate(&request, &client) {
+                        return Some(
+                            warp::reply::with_status(reason, StatusCode::UNAUTHORIZED)
+                                .into_response(),
+                        );
+                    }
+                }
+                if deny_list.read().is_denied(&client) {
+                    return Some(
+                        warp::reply::with_status(
+                            "Access denied".to_string(),
+                            StatusCode::FORBIDDEN,
+                        )
+                        .into_response(),
+                    );
+                }
+                None
+            },
+        );
+
+    let keys_store = store.clone();
+    let keys =
+        warp::path!("api" / "keys")
+            .and(denied.clone())
+            .map(move |denied: Option<Response>| {
+                if let Some(response) = denied {
+                    return response;
+                }
+                let keys = keys_store
+                    .elements()
+                    .into_iter()
+                    .filter_map(|(key, element)| {
+                        let guard = element.read();
+                        guard
+                            .room
+                            .is_none()
+                            .then(|| (key, guard.type_name.into()))
+                    })
+                    .collect::<serde_json::Map<_, _>>();
+                warp::reply::json(&keys).into_response()
+            });
+
+    let data = warp::path!("api" / "data" / String).and(denied).map(
+        move |key: String, denied: Option<Response>| {
+            if let Some(response) = denied {
+                return response;
+            }
+            let element = store.get(&key);
+            let data = element.and_then(|element| {
+                let guard = element.read();
+                guard.room.is_none().then(|| guard.data.serialize())
+            });
+            match data {
+                Some(data) => warp::reply::with_header(data, "content-type", "application/json")
+                    .into_response(),
+                None => warp::reply::with_status(
+                    format!("Key {} does not exist", key),
+                    StatusCode::NOT_FOUND,
+                )
+                .into_response(),
+            }
+        },
+    );
+
+    keys.or(data).unify()
+}
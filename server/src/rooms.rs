@@ -0,0 +1,67 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use parking_lot::RwLock;
+
+use crate::client::ClientId;
+
+// Keys scoped to a room are only visible to its members,
+// keys outside of any room to every client.
+#[derive(Clone, Default)]
+pub struct Rooms {
+    members: Arc<RwLock<HashMap<String, HashSet<ClientId>>>>,
+}
+
+impl Rooms {
+    // false if the client already was a member
+    pub fn join(&self, room: &str, client: ClientId) -> bool {
+        self.members
+            .write()
+            .entry(room.to_string())
+            .or_default()
+            .insert(client)
+    }
+
+    // false if the client wasn't a member
+    pub fn leave(&self, room: &str, client: ClientId) -> bool {
+        let mut members = self.members.write();
+        let left = match members.get_mut(room) {
+            Some(clients) => clients.remove(&client),
+            None => false,
+        };
+        members.retain(|_, clients| !clients.is_empty());
+        left
+    }
+
+    pub fn leave_all(&self, client: ClientId) {
+        let mut members = self.members.write();
+        for clients in members.values_mut() {
+            clients.remove(&client);
+        }
+        members.retain(|_, clients| !clients.is_empty());
+    }
+
+    pub fn members(&self, room: &str) -> Vec<ClientId> {
+        let mut members = self
+            .members
+            .read()
+            .get(room)
+            .map(|clients| clients.iter().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+        members.sort();
+        members
+    }
+
+    pub fn is_visible(&self, room: Option<&str>, client: ClientId) -> bool {
+        match room {
+            Some(room) => self
+                .members
+                .read()
+                .get(room)
+                .is_some_and(|clients| clients.contains(&client)),
+            None => true,
+        }
+    }
+}
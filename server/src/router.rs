@@ -0,0 +1,729 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::{task::noop_waker, Stream, StreamExt};
+use parking_lot::{Mutex, MutexGuard, RwLock};
+use serde::Serialize;
+use tokio::{
+    runtime::Handle,
+    sync::{broadcast, mpsc},
+    time::{interval_at, sleep_until, Instant},
+};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+use crate::{
+    client::ClientId, journal::Change, message::Message, throttle::Throttles, trace::debug_span,
+};
+
+const SHARDS: usize = 16;
+// messages that may pile up while waiting for a full blocking queue, see
+// `Overflow::Block`
+const MAX_BACKLOG: u64 = 1024;
+// how often the pile is checked meanwhile
+const BACKLOG_CHECK: Duration = Duration::from_millis(10);
+
+// Everything that changes goes through a single router task, which copies
+// each message into one queue per connection. Messages for a single client
+// only end up in that client's queue. The queues are split into shards with
+// a lock each, so numbering new messages never waits for a message to be
+// copied into hundreds of queues, and connections coming and going only
+// hold up the queues of their shard, see `benches/fanout.rs`.
+//
+// Every message gets the next sequence number. The latest messages for all
+// clients are kept, so a client that reconnects can catch up from the last
+// number it saw instead of receiving a snapshot.
+#[derive(Clone)]
+pub struct Router {
+    ingress: mpsc::UnboundedSender<(u64, Message)>,
+    inner: Arc<RouterInner>,
+}
+
+struct RouterInner {
+    // until the task is started, see `Router::subscribe`
+    ingress: Mutex<Option<mpsc::UnboundedReceiver<(u64, Message)>>>,
+    state: Mutex<RouterState>,
+    // by the id of the subscription, see `shard`
+    queues: Vec<Mutex<HashMap<u64, Queue>>>,
+    // queues in all shards
+    subscribed: AtomicUsize,
+    next_id: AtomicU64,
+    capacity: usize,
+    overflow: Overflow,
+    replay_size: usize,
+    taps: RwLock<Vec<Tap>>,
+    cut_off: RwLock<Vec<CutOff>>,
+    throttles: Mutex<Throttles>,
+    priorities: RwLock<HashMap<String, Priority>>,
+}
+
+// sees every message in the order of the sequence, see `Router::tap`
+pub type Tap = Box<dyn Fn(&Message) + Send + Sync>;
+
+// called for clients whose queue was cut off, see `Router::on_cut_off`
+pub type CutOff = Box<dyn Fn(ClientId) + Send + Sync>;
+
+struct RouterState {
+    // of the latest message
+    seq: u64,
+    replay: VecDeque<(u64, Message)>,
+    // the latest message that can't be replayed anymore
+    evicted: u64,
+    // Of connections that ended with a message possibly missed while a later
+    // one was sent, the oldest such message and the latest sent, see
+    // `Priority`. Clients don't resume from in between.
+    gaps: Vec<(u64, u64)>,
+}
+
+impl RouterState {
+    // whether a client that saw the message may have missed an older one
+    fn in_gap(&self, seq: u64) -> bool {
+        self.gaps
+            .iter()
+            .any(|(oldest, latest)| (*oldest..=*latest).contains(&seq))
+    }
+}
+
+enum QueueSender {
+    Bounded(mpsc::Sender<(u64, Message)>),
+    Blocking(mpsc::Sender<(u64, Message)>),
+    // drops the oldest message once it is full
+    Ring(broadcast::Sender<(u64, Message)>),
+    Unbounded(mpsc::UnboundedSender<(u64, Message)>),
+}
+
+// What happens to a message for a bounded queue that is full, see
+// `PocaBuilder::overflow`. Clients that missed messages are handled according
+// to their `LagPolicy`, which can also disconnect them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    // the client misses the new message
+    #[default]
+    DropNewest,
+    // The client misses the oldest message it didn't get yet. The capacity
+    // is rounded up to a power of two.
+    DropOldest,
+    // Nothing is routed until the client took a message, so no client misses
+    // any and a slow one holds up all the others. Writes aren't held up, the
+    // messages wait for the router instead. Once over a thousand of them
+    // piled up that way, the client is disconnected.
+    Block,
+}
+
+// a message for a full queue, sent once there is room, see `Overflow::Block`
+struct Blocked {
+    id: u64,
+    sender: mpsc::Sender<(u64, Message)>,
+    message: (u64, Message),
+    counters: Arc<Counters>,
+}
+
+// How urgently changes of a key are delivered, see `DataHandle::set_priority`.
+// Every connection queues the messages of each priority separately and sends
+// the ones of a higher priority first, so when a client falls behind, the
+// changes of important keys overtake the rest. Messages without a key, like
+// events, are of normal priority. Clients resume from the highest sequence
+// number they saw, so a client that may have missed an overtaken message when
+// its connection dropped gets a snapshot instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    // highest first, as the queues are polled
+    const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|each| *each == self).unwrap()
+    }
+}
+
+struct Queue {
+    client: Option<ClientId>,
+    // messages up to here were sent before the queue existed
+    after: u64,
+    // one per priority, highest first
+    senders: Vec<QueueSender>,
+    counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicUsize,
+    // since the subscription last reported it
+    missed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+// backpressure of a single connection
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueueStats {
+    pub client: ClientId,
+    pub queued: usize,
+    pub dropped: u64,
+}
+
+impl Queue {
+    // False once the subscription is gone. Messages for a full blocking queue
+    // are added to `blocked`, without it they are dropped.
+    fn push(
+        &self,
+        id: u64,
+        priority: Priority,
+        message: (u64, Message),
+        blocked: Option<&mut Vec<Blocked>>,
+    ) -> bool {
+        let result = match &self.senders[priority.index()] {
+            QueueSender::Bounded(sender) | QueueSender::Blocking(sender) => {
+                match sender.try_send(message) {
+                    Ok(()) => Ok(()),
+                    Err(mpsc::error::TrySendError::Full(message)) => {
+                        match (&self.senders[priority.index()], blocked) {
+                            (QueueSender::Blocking(_), Some(blocked)) => blocked.push(Blocked {
+                                id,
+                                sender: sender.clone(),
+                                message,
+                                counters: self.counters.clone(),
+                            }),
+                            _ => {
+                                self.counters.missed.fetch_add(1, Ordering::Relaxed);
+                                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                        return true;
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => Err(()),
+                }
+            }
+            QueueSender::Ring(sender) => sender.send(message).map(|_| ()).map_err(|_| ()),
+            QueueSender::Unbounded(sender) => sender.send(message).map_err(|_| ()),
+        };
+        if result.is_ok() {
+            self.counters.queued.fetch_add(1, Ordering::Relaxed);
+        }
+        result.is_ok()
+    }
+}
+
+impl Router {
+    // `capacity` is the length of every bounded queue, `overflow` what happens
+    // once one is full, `replay_size` the number of messages kept for
+    // reconnecting clients
+    pub fn new(capacity: usize, overflow: Overflow, replay_size: usize) -> Self {
+        let (ingress, receiver) = mpsc::unbounded_channel();
+        // a restarted server doesn't continue the sequence of the previous one
+        let seq = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_micros() as u64);
+        Self {
+            ingress,
+            inner: Arc::new(RouterInner {
+                ingress: Mutex::new(Some(receiver)),
+                state: Mutex::new(RouterState {
+                    seq,
+                    replay: VecDeque::new(),
+                    evicted: seq,
+                    gaps: Vec::new(),
+                }),
+                queues: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+                subscribed: AtomicUsize::new(0),
+                next_id: AtomicU64::new(0),
+                capacity,
+                overflow,
+                replay_size,
+                taps: RwLock::new(Vec::new()),
+                cut_off: RwLock::new(Vec::new()),
+                throttles: Mutex::new(Throttles::default()),
+                priorities: RwLock::new(HashMap::new()),
+            }),
+        }
+    }
+
+    // only routed while somebody is subscribed
+    pub fn send(&self, message: Message) {
+        let mut state = self.inner.state.lock();
+        let seq = self.stamp(&mut state, &message);
+        if self.inner.subscribed.load(Ordering::Relaxed) > 0 {
+            self.ingress.send((seq, message)).ok();
+        }
+    }
+
+    // only kept for reconnecting clients, nobody else receives it
+    pub fn record(&self, message: Message) {
+        let mut state = self.inner.state.lock();
+        self.stamp(&mut state, &message);
+    }
+
+    // Unbounded queues never miss a message, bounded ones report how many they
+    // missed as `Err`. Clients only get messages for everyone and for themselves.
+    // If `resume` is the sequence number of the last message a client saw and
+    // everything after it is still kept, the queue starts with those messages.
+    pub fn subscribe(
+        &self,
+        client: Option<ClientId>,
+        bounded: bool,
+        resume: Option<u64>,
+    ) -> Subscription {
+        self.start();
+        let counters = Arc::new(Counters::default());
+        let state = self.inner.state.lock();
+        let resume = resume
+            .filter(|seq| (state.evicted..=state.seq).contains(seq))
+            .filter(|seq| !state.in_gap(*seq));
+        let replayed = state
+            .replay
+            .iter()
+            .filter(|(seq, _)| resume.is_some_and(|resume| *seq > resume))
+            .map(|(seq, message)| (self.priority(message), (*seq, message.clone())))
+            .collect::<Vec<_>>();
+        let (senders, receivers): (Vec<_>, Vec<_>) = Priority::ALL
+            .iter()
+            .map(|priority| {
+                if bounded {
+                    // the replayed messages weren't missed, so they always fit
+                    let replayed = replayed.iter().filter(|(each, _)| each == priority).count();
+                    self.bounded(self.inner.capacity.max(replayed))
+                } else {
+                    let (sender, receiver) = mpsc::unbounded_channel();
+                    (
+                        QueueSender::Unbounded(sender),
+                        QueueReceiver::Unbounded(receiver),
+                    )
+                }
+            })
+            .unzip();
+        let queue = Queue {
+            client,
+            after: state.seq,
+            senders,
+            counters: counters.clone(),
+        };
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        for (priority, message) in replayed {
+            queue.push(id, priority, message, None);
+        }
+        let seq = state.seq;
+        // while the state is locked, so no message after `seq` is routed before
+        shard(&self.inner, id).insert(id, queue);
+        self.inner.subscribed.fetch_add(1, Ordering::Relaxed);
+        Subscription {
+            id,
+            router: Arc::downgrade(&self.inner),
+            receivers,
+            counters,
+            seq,
+            resumed: resume.is_some(),
+            // the replayed messages come after it
+            latest: resume.unwrap_or(seq),
+            overtaken: None,
+        }
+    }
+
+    fn bounded(&self, capacity: usize) -> (QueueSender, QueueReceiver) {
+        match self.inner.overflow {
+            Overflow::DropNewest => {
+                let (sender, receiver) = mpsc::channel(capacity);
+                (
+                    QueueSender::Bounded(sender),
+                    QueueReceiver::Bounded(receiver),
+                )
+            }
+            Overflow::Block => {
+                let (sender, receiver) = mpsc::channel(capacity);
+                (
+                    QueueSender::Blocking(sender),
+                    QueueReceiver::Bounded(receiver),
+                )
+            }
+            Overflow::DropOldest => {
+                let (sender, receiver) = broadcast::channel(capacity);
+                (
+                    QueueSender::Ring(sender),
+                    QueueReceiver::Ring(BroadcastStream::new(receiver)),
+                )
+            }
+        }
+    }
+
+    // Called for every message while it is stamped, so it must not send
+    // messages itself. Changed values may still be locked, so it must not
+    // access them either. Recorded messages are included.
+    pub fn tap(&self, tap: impl Fn(&Message) + Send + Sync + 'static) {
+        self.inner.taps.write().push(Box::new(tap));
+    }
+
+    // Called with the client of a blocking queue that held up the others for
+    // too long, once the queue was removed, see `Overflow::Block`.
+    pub fn on_cut_off(&self, hook: impl Fn(ClientId) + Send + Sync + 'static) {
+        self.inner.cut_off.write().push(Box::new(hook));
+    }
+
+    // Changes of the key are broadcast at most once per interval, starting
+    // from `value`. Ends once the key is removed.
+    pub fn throttle(&self, key: &str, interval: Duration, value: serde_json::Value) {
+        let mut throttles = self.inner.throttles.lock();
+        throttles.insert(key.to_string(), interval, value);
+    }
+
+    // Changes of the key are delivered before the ones of lower priorities
+    // still queued. Ends once the key is removed.
+    pub fn prioritize(&self, key: &str, priority: Priority) {
+        let mut priorities = self.inner.priorities.write();
+        match priority {
+            Priority::Normal => priorities.remove(key),
+            priority => priorities.insert(key.to_string(), priority),
+        };
+    }
+
+    pub fn priority_of(&self, key: &str) -> Priority {
+        priority_of(&self.inner, key)
+    }
+
+    // of the most important key a message changes
+    fn priority(&self, message: &Message) -> Priority {
+        priority(&self.inner, message)
+    }
+
+    // only connections of clients, ordered by client
+    pub fn stats(&self) -> Vec<QueueStats> {
+        let mut stats = self
+            .inner
+            .queues
+            .iter()
+            .flat_map(|queues| {
+                queues
+                    .lock()
+                    .values()
+                    .filter_map(|queue| {
+                        Some(QueueStats {
+                            client: queue.client?,
+                            queued: queue.counters.queued.load(Ordering::Relaxed),
+                            dropped: queue.counters.dropped.load(Ordering::Relaxed),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        stats.sort_by_key(|stats| stats.client);
+        stats
+    }
+
+    // The task needs a runtime, so it is started by the first subscription
+    // made inside one. It ends once every `Router` is dropped.
+    fn start(&self) {
+        let handle = match Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => return,
+        };
+        let receiver = match self.inner.ingress.lock().take() {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        handle.spawn(route(receiver, Arc::downgrade(&self.inner)));
+    }
+
+    // Messages for a single client, like errors, are never replayed.
+    // Neither are close frames.
+    fn stamp(&self, state: &mut RouterState, message: &Message) -> u64 {
+        state.seq += 1;
+        let seq = state.seq;
+        for tap in self.inner.taps.read().iter() {
+            tap(message);
+        }
+        if message.recipient().is_some() || matches!(message, Message::Close { .. }) {
+            return seq;
+        }
+        if self.inner.replay_size == 0 {
+            state.evicted = seq;
+            return seq;
+        }
+        if state.replay.len() == self.inner.replay_size {
+            if let Some((evicted, _)) = state.replay.pop_front() {
+                state.evicted = evicted;
+            }
+        }
+        state.replay.push_back((seq, message.clone()));
+        seq
+    }
+}
+
+async fn route(mut ingress: mpsc::UnboundedReceiver<(u64, Message)>, inner: Weak<RouterInner>) {
+    loop {
+        let next_flush = match inner.upgrade() {
+            Some(inner) => inner.throttles.lock().next_flush(),
+            None => break,
+        };
+        let received = tokio::select! {
+            received = ingress.recv() => match received {
+                Some(received) => Some(received),
+                None => break,
+            },
+            _ = sleep_until(next_flush.unwrap_or_else(Instant::now)), if next_flush.is_some() => None,
+        };
+        let routed = match inner.upgrade() {
+            Some(inner) => match received {
+                Some((seq, message)) => inner.throttles.lock().route(seq, message),
+                None => inner.throttles.lock().flush(),
+            },
+            None => break,
+        };
+        for (seq, message) in routed {
+            let blocked = match inner.upgrade() {
+                Some(inner) => broadcast(&inner, seq, message),
+                None => return,
+            };
+            // in order, before the next message is routed
+            for blocked in blocked {
+                wait_for_room(&inner, blocked).await;
+            }
+        }
+    }
+}
+
+// Sends a message to a full blocking queue once there is room. If more than
+// `MAX_BACKLOG` messages arrived meanwhile, the queue is removed instead, so
+// a client that stopped reading doesn't hold up the others for good.
+async fn wait_for_room(inner: &Weak<RouterInner>, blocked: Blocked) {
+    let Blocked {
+        id,
+        sender,
+        message,
+        counters,
+    } = blocked;
+    let started = match inner.upgrade() {
+        Some(inner) => inner.state.lock().seq,
+        None => return,
+    };
+    let send = sender.send(message);
+    tokio::pin!(send);
+    let mut checks = interval_at(Instant::now() + BACKLOG_CHECK, BACKLOG_CHECK);
+    loop {
+        tokio::select! {
+            sent = &mut send => {
+                if sent.is_ok() {
+                    counters.queued.fetch_add(1, Ordering::Relaxed);
+                }
+                return;
+            }
+            _ = checks.tick() => {
+                let inner = match inner.upgrade() {
+                    Some(inner) => inner,
+                    None => return,
+                };
+                if inner.state.lock().seq - started > MAX_BACKLOG {
+                    cut_off(&inner, id);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// the queue ends once the messages in it are taken
+fn cut_off(inner: &RouterInner, id: u64) {
+    let queue = shard(inner, id).remove(&id);
+    if let Some(queue) = queue {
+        inner.subscribed.fetch_sub(1, Ordering::Relaxed);
+        queue.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        if let Some(client) = queue.client {
+            for hook in inner.cut_off.read().iter() {
+                hook(client);
+            }
+        }
+    }
+}
+
+fn priority_of(inner: &RouterInner, key: &str) -> Priority {
+    inner
+        .priorities
+        .read()
+        .get(key)
+        .copied()
+        .unwrap_or_default()
+}
+
+fn priority(inner: &RouterInner, message: &Message) -> Priority {
+    let priorities = inner.priorities.read();
+    if priorities.is_empty() {
+        return Priority::Normal;
+    }
+    Change::from_message(message)
+        .iter()
+        .filter_map(|change| priorities.get(&change.key).copied())
+        .max()
+        .unwrap_or_default()
+}
+
+// the messages for full blocking queues, see `Overflow::Block`
+fn broadcast(inner: &RouterInner, seq: u64, message: Message) -> Vec<Blocked> {
+    let priority = priority(inner, &message);
+    if let Message::Remove { key } = &message {
+        inner.priorities.write().remove(key);
+    }
+    let recipient = message.recipient();
+    let _span = debug_span!("broadcast", seq, recipient = ?recipient).entered();
+    let mut blocked = Vec::new();
+    for queues in &inner.queues {
+        let mut queues = queues.lock();
+        let before = queues.len();
+        queues.retain(|id, queue| {
+            if seq <= queue.after {
+                return true;
+            }
+            match (recipient, queue.client) {
+                (Some(recipient), Some(client)) if recipient != client => true,
+                _ => queue.push(*id, priority, (seq, message.clone()), Some(&mut blocked)),
+            }
+        });
+        let gone = before - queues.len();
+        inner.subscribed.fetch_sub(gone, Ordering::Relaxed);
+    }
+    blocked
+}
+
+fn shard(inner: &RouterInner, id: u64) -> MutexGuard<'_, HashMap<u64, Queue>> {
+    inner.queues[id as usize % SHARDS].lock()
+}
+
+enum QueueReceiver {
+    Bounded(mpsc::Receiver<(u64, Message)>),
+    Ring(BroadcastStream<(u64, Message)>),
+    Unbounded(mpsc::UnboundedReceiver<(u64, Message)>),
+}
+
+// the queue of a single connection, removed from the router once dropped
+pub struct Subscription {
+    id: u64,
+    router: Weak<RouterInner>,
+    // one per priority, highest first
+    receivers: Vec<QueueReceiver>,
+    counters: Arc<Counters>,
+    seq: u64,
+    resumed: bool,
+    // of the latest message taken, and the oldest one taken after a later one
+    latest: u64,
+    overtaken: Option<u64>,
+}
+
+impl Subscription {
+    // of the latest message before the subscription
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    // whether the queue starts with the messages the client missed
+    pub fn resumed(&self) -> bool {
+        self.resumed
+    }
+
+    fn taken(&mut self, seq: u64) {
+        if seq < self.latest {
+            self.overtaken = Some(self.overtaken.map_or(seq, |oldest| oldest.min(seq)));
+        }
+        self.latest = self.latest.max(seq);
+    }
+
+    // The oldest message before the latest one taken that the client may not
+    // have gotten, as it was taken later or is still queued.
+    fn missed(&mut self) -> Option<u64> {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut oldest = self.overtaken;
+        for receiver in &mut self.receivers {
+            // the queue of each priority is in order
+            let next = match receiver {
+                QueueReceiver::Bounded(receiver) => receiver.poll_recv(&mut cx),
+                QueueReceiver::Ring(receiver) => receiver
+                    .poll_next_unpin(&mut cx)
+                    .map(|message| message.and_then(Result::ok)),
+                QueueReceiver::Unbounded(receiver) => receiver.poll_recv(&mut cx),
+            };
+            if let Poll::Ready(Some((seq, _))) = next {
+                if seq < self.latest {
+                    oldest = Some(oldest.map_or(seq, |oldest| oldest.min(seq)));
+                }
+            }
+        }
+        oldest
+    }
+}
+
+// otherwise the queue would linger until the next message, and so would its stats
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(router) = self.router.upgrade() {
+            if shard(&router, self.id).remove(&self.id).is_some() {
+                router.subscribed.fetch_sub(1, Ordering::Relaxed);
+            }
+            if let Some(oldest) = self.missed() {
+                let mut state = router.state.lock();
+                // nobody resumes from before the replayed messages anyway
+                let evicted = state.evicted;
+                state.gaps.retain(|(_, latest)| *latest >= evicted);
+                state.gaps.push((oldest, self.latest));
+            }
+        }
+    }
+}
+
+impl Stream for Subscription {
+    // messages with their sequence number, the number of missed messages as error
+    type Item = Result<(u64, Message), u64>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let missed = self.counters.missed.swap(0, Ordering::Relaxed);
+        if missed > 0 {
+            return Poll::Ready(Some(Err(missed)));
+        }
+        // the senders are dropped together, so the queue ends with all of them
+        let mut polled = Poll::Ready(None);
+        // the oldest messages of a ring that were overwritten
+        let mut overwritten = None;
+        for receiver in &mut self.receivers {
+            let next = match receiver {
+                QueueReceiver::Bounded(receiver) => receiver.poll_recv(cx),
+                QueueReceiver::Ring(receiver) => match receiver.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(lagged)))) => {
+                        overwritten = Some(lagged);
+                        break;
+                    }
+                    next => next.map(|message| message.and_then(Result::ok)),
+                },
+                QueueReceiver::Unbounded(receiver) => receiver.poll_recv(cx),
+            };
+            match next {
+                Poll::Ready(Some(message)) => {
+                    polled = Poll::Ready(Some(message));
+                    break;
+                }
+                Poll::Pending => polled = Poll::Pending,
+                Poll::Ready(None) => {}
+            }
+        }
+        if let Some(overwritten) = overwritten {
+            self.counters
+                .queued
+                .fetch_sub(overwritten as usize, Ordering::Relaxed);
+            self.counters
+                .dropped
+                .fetch_add(overwritten, Ordering::Relaxed);
+            return Poll::Ready(Some(Err(overwritten)));
+        }
+        if let Poll::Ready(Some((seq, _))) = &polled {
+            self.counters.queued.fetch_sub(1, Ordering::Relaxed);
+            self.taken(*seq);
+        }
+        polled.map(|message| message.map(Ok))
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+};
+
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::oneshot;
+
+use crate::client::{ClientId, ClientInfo};
+
+pub type RpcFuture = Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'static>>;
+// gets the serialized request, if the client sent one,
+// and resolves to the serialized response or a reason for the client
+pub type RpcHandler = Arc<dyn Fn(ClientInfo, Option<String>) -> RpcFuture + Send + Sync + 'static>;
+pub type RpcHandlerStore = Arc<RwLock<HashMap<String, RpcHandler>>>;
+
+type PendingCall = (ClientId, oneshot::Sender<Result<String, String>>);
+
+// calls made by the server, waiting for the answer of a client
+#[derive(Clone, Default)]
+pub struct PendingCalls {
+    inner: Arc<Mutex<PendingCallsInner>>,
+}
+
+#[derive(Default)]
+struct PendingCallsInner {
+    next_id: u64,
+    connected: HashSet<ClientId>,
+    calls: HashMap<u64, PendingCall>,
+}
+
+impl PendingCalls {
+    pub fn connect(&self, client: ClientId) {
+        self.inner.lock().connected.insert(client);
+    }
+
+    // calls still waiting for the client fail
+    pub fn disconnect(&self, client: ClientId) {
+        let mut inner = self.inner.lock();
+        inner.connected.remove(&client);
+        inner.calls.retain(|_, (target, _)| *target != client);
+    }
+
+    // none if the client is not connected
+    pub fn register(
+        &self,
+        client: ClientId,
+    ) -> Option<(u64, oneshot::Receiver<Result<String, String>>)> {
+        let mut inner = self.inner.lock();
+        if !inner.connected.contains(&client) {
+            return None;
+        }
+        inner.next_id += 1;
+        let id = inner.next_id;
+        let (sender, receiver) = oneshot::channel();
+        inner.calls.insert(id, (client, sender));
+        Some((id, receiver))
+    }
+
+    // the caller stopped waiting, a late answer is ignored
+    pub fn cancel(&self, id: u64) {
+        self.inner.lock().calls.remove(&id);
+    }
+
+    // answers are only accepted from the client that was called
+    pub fn resolve(&self, client: ClientId, id: u64, result: Result<String, String>) {
+        let mut inner = self.inner.lock();
+        if matches!(inner.calls.get(&id), Some((target, _)) if *target == client) {
+            if let Some((_, sender)) = inner.calls.remove(&id) {
+                sender.send(result).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use serde_json::{Map, Value};
+use sled::{transaction::ConflictableTransactionResult, Transactional};
+
+use crate::{
+    journal::Change,
+    storage::{StorageBackend, StorageError},
+};
+
+// A sled database, the values and the changes in a tree each.
+pub struct SledStorage {
+    db: sled::Db,
+    values: sled::Tree,
+    changes: sled::Tree,
+}
+
+impl SledStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let db = sled::open(path)?;
+        Ok(SledStorage {
+            values: db.open_tree("values")?,
+            changes: db.open_tree("changes")?,
+            db,
+        })
+    }
+}
+
+impl StorageBackend for SledStorage {
+    fn load(&self) -> Result<Map<String, Value>, StorageError> {
+        let mut values = Map::new();
+        for entry in self.values.iter() {
+            let (key, value) = entry?;
+            values.insert(
+                String::from_utf8(key.to_vec())?,
+                serde_json::from_slice(&value)?,
+            );
+        }
+        // keyed by a growing id, so in the order they were appended
+        for entry in self.changes.iter() {
+            let (_, change) = entry?;
+            serde_json::from_slice::<Change>(&change)?.apply(&mut values);
+        }
+        Ok(values)
+    }
+
+    // in one transaction, so the values are never left half saved and the
+    // changes are never lost without them
+    fn save(&self, values: &Map<String, Value>) -> Result<(), StorageError> {
+        let encoded = values
+            .iter()
+            .map(|(key, value)| Ok((key.as_bytes(), serde_json::to_vec(value)?)))
+            .collect::<Result<Vec<_>, serde_json::Error>>()?;
+        let saved = self.values.iter().keys().collect::<Result<Vec<_>, _>>()?;
+        let appended = self.changes.iter().keys().collect::<Result<Vec<_>, _>>()?;
+        (&self.values, &self.changes).transaction(
+            |(values, changes)| -> ConflictableTransactionResult<(), sled::Error> {
+                for key in &saved {
+                    values.remove(key.clone())?;
+                }
+                for (key, value) in &encoded {
+                    values.insert(*key, value.as_slice())?;
+                }
+                for id in &appended {
+                    changes.remove(id.clone())?;
+                }
+                Ok(())
+            },
+        )?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn append_change(&self, change: &Change) -> Result<(), StorageError> {
+        let id = self.db.generate_id()?;
+        self.changes
+            .insert(id.to_be_bytes(), serde_json::to_vec(change)?)?;
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<(), StorageError> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
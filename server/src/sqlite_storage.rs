@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use parking_lot::Mutex;
+use rusqlite::{params, Connection};
+use serde_json::{Map, Value};
+
+use crate::{
+    journal::Change,
+    storage::{StorageBackend, StorageError},
+};
+
+// An SQLite database, with a row per key and per change.
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS poca_values (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS poca_changes (id INTEGER PRIMARY KEY AUTOINCREMENT, change TEXT NOT NULL);",
+        )?;
+        Ok(SqliteStorage {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl StorageBackend for SqliteStorage {
+    fn load(&self) -> Result<Map<String, Value>, StorageError> {
+        let connection = self.connection.lock();
+        let mut values = Map::new();
+        let mut statement = connection.prepare("SELECT key, value FROM poca_values")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let value: String = row.get(1)?;
+            values.insert(row.get(0)?, serde_json::from_str(&value)?);
+        }
+        let mut statement = connection.prepare("SELECT change FROM poca_changes ORDER BY id")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let change: String = row.get(0)?;
+            serde_json::from_str::<Change>(&change)?.apply(&mut values);
+        }
+        Ok(values)
+    }
+
+    // in one transaction, so the changes are never lost without the values
+    fn save(&self, values: &Map<String, Value>) -> Result<(), StorageError> {
+        let mut connection = self.connection.lock();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM poca_values", [])?;
+        for (key, value) in values {
+            transaction.execute(
+                "INSERT INTO poca_values (key, value) VALUES (?1, ?2)",
+                params![key, value.to_string()],
+            )?;
+        }
+        transaction.execute("DELETE FROM poca_changes", [])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn append_change(&self, change: &Change) -> Result<(), StorageError> {
+        self.connection.lock().execute(
+            "INSERT INTO poca_changes (change) VALUES (?1)",
+            params![serde_json::to_string(change)?],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use std::{collections::HashMap, convert::Infallible, io, net::SocketAddr, sync::Arc};
+
+use futures_util::{future, stream, StreamExt};
+use hyper::body::Bytes;
+use parking_lot::Mutex;
+use rand::{rngs::OsRng, RngCore};
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use warp::{
+    http::{HeaderMap, StatusCode},
+    path::Tail,
+    reply::Response,
+    sse::Event,
+    ws, Filter, Rejection, Reply,
+};
+
+use crate::{
+    admission::{Admission, Admitted},
+    client::ClientId,
+    encoding::JsonEncoding,
+    listener,
+    poca::resume_from,
+    transport::{FrameSink, FrameStream, Transport, TransportError},
+    ws_handler::connection_handler,
+};
+
+// the frames posted by clients, by the token of their event stream
+pub(crate) type Sessions = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<ws::Message>>>>;
+
+// For environments where WebSockets are blocked. `GET /sse/{store}` streams
+// the messages a WebSocket would get as events, starting with a `session`
+// event holding a token. Clients send their messages as the body of
+// `POST /sse?session={token}`. Messages are always JSON.
+pub(crate) fn routes(
+    admission: Admission,
+    sessions: Sessions,
+    max_message_size: Option<usize>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone + Send + Sync + 'static {
+    let stream_sessions = sessions.clone();
+    let events = warp::get()
+        .and(warp::path("sse"))
+        .and(warp::path::tail())
+        .and(listener::remote_addr())
+        .and(warp::header::headers_cloned())
+        .and(
+            warp::query::raw()
+                .map(Some)
+                .or(warp::any().map(|| None::<String>))
+                .unify(),
+        )
+        .map(
+            move |path: Tail,
+                  address: Option<SocketAddr>,
+                  headers: HeaderMap,
+                  query: Option<String>| {
+                let resume = resume_from(query.as_deref());
+                let path = path.as_str().trim_matches('/');
+                let Admitted {
+                    context,
+                    client,
+                    slot,
+                } = match admission.admit(path, address, headers, query) {
+                    Ok(admitted) => admitted,
+                    Err(response) => return *response,
+                };
+                let token = session_token(client.id);
+                let (transport, events) = channel(token.clone(), stream_sessions.clone());
+                tokio::spawn(async move {
+                    let _slot = slot;
+                    let encoding = Arc::new(JsonEncoding);
+                    connection_handler(transport, context, client, encoding, resume).await;
+                });
+                let session = Event::default().event("session").data(token);
+                let events = stream::once(future::ok(session)).chain(events);
+                warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response()
+            },
+        );
+
+    let messages = warp::post()
+        .and(warp::path!("sse"))
+        .and(warp::query::<SessionQuery>())
+        // refused before the body is read
+        .and(warp::body::content_length_limit(
+            max_message_size.map_or(u64::MAX, |max| max as u64),
+        ))
+        .and(warp::body::bytes())
+        .map(move |query: SessionQuery, body: Bytes| {
+            let sender = match sessions.lock().get(&query.session) {
+                Some(sender) => sender.clone(),
+                None => {
+                    return warp::reply::with_status(
+                        "Unknown session".to_string(),
+                        StatusCode::NOT_FOUND,
+                    )
+                    .into_response()
+                }
+            };
+            match String::from_utf8(body.to_vec()) {
+                Ok(text) => {
+                    sender.send(ws::Message::text(text)).ok();
+                    StatusCode::NO_CONTENT.into_response()
+                }
+                Err(_) => {
+                    warp::reply::with_status("Expected UTF-8".to_string(), StatusCode::BAD_REQUEST)
+                        .into_response()
+                }
+            }
+        });
+
+    events.or(messages).unify()
+}
+
+#[derive(Deserialize)]
+struct SessionQuery {
+    session: String,
+}
+
+pub(crate) struct EventStream {
+    outgoing: mpsc::UnboundedSender<ws::Message>,
+    incoming: mpsc::UnboundedReceiver<ws::Message>,
+    // resolves once the response is dropped
+    closed: oneshot::Receiver<()>,
+}
+
+impl Transport for EventStream {
+    fn split(self) -> (FrameSink, FrameStream) {
+        let sink = futures_util::sink::unfold(self.outgoing, |outgoing, frame| async move {
+            match outgoing.send(frame) {
+                Ok(()) => Ok(outgoing),
+                Err(_) => Err(TransportError::new(io::Error::from(
+                    io::ErrorKind::BrokenPipe,
+                ))),
+            }
+        });
+        let stream = UnboundedReceiverStream::new(self.incoming)
+            .map(Ok)
+            .take_until(self.closed);
+        (Box::pin(sink), Box::pin(stream))
+    }
+}
+
+// the registered session, removed with the response it belongs to
+struct Session {
+    token: String,
+    sessions: Sessions,
+    incoming: mpsc::UnboundedSender<ws::Message>,
+    _closed: oneshot::Sender<()>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.sessions.lock().remove(&self.token);
+    }
+}
+
+fn channel(
+    token: String,
+    sessions: Sessions,
+) -> (
+    EventStream,
+    impl futures_util::Stream<Item = Result<Event, Infallible>> + Send + 'static,
+) {
+    let (outgoing, outgoing_receiver) = mpsc::unbounded_channel();
+    let (incoming, incoming_receiver) = mpsc::unbounded_channel();
+    let (closed_sender, closed) = oneshot::channel();
+    sessions.lock().insert(token.clone(), incoming.clone());
+    let session = Session {
+        token,
+        sessions,
+        incoming,
+        _closed: closed_sender,
+    };
+    let events = stream::unfold(
+        Some((outgoing_receiver, session)),
+        |state: Option<(mpsc::UnboundedReceiver<ws::Message>, Session)>| async move {
+            let (mut frames, session) = state?;
+            loop {
+                let frame = frames.recv().await?;
+                if frame.is_close() {
+                    let (code, reason) = frame.close_frame().unwrap_or((1000, ""));
+                    let data = serde_json::json!({ "code": code, "reason": reason });
+                    let event = Event::default().event("close").data(data.to_string());
+                    return Some((Ok(event), None));
+                }
+                // there is no way for the client to answer, so the stream
+                // being read counts as the answer
+                if frame.is_ping() {
+                    session.incoming.send(ws::Message::pong(Vec::new())).ok();
+                    continue;
+                }
+                if let Ok(text) = frame.to_str() {
+                    let event = Event::default().data(text);
+                    return Some((Ok(event), Some((frames, session))));
+                }
+            }
+        },
+    );
+    let transport = EventStream {
+        outgoing,
+        incoming: incoming_receiver,
+        closed,
+    };
+    (transport, events)
+}
+
+// unguessable, as it is all it takes to write as the client
+fn session_token(client: ClientId) -> String {
+    format!(
+        "{}-{:016x}{:016x}",
+        client.as_u64(),
+        OsRng.next_u64(),
+        OsRng.next_u64()
+    )
+}
@@ -0,0 +1,203 @@
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::journal::Change;
+
+pub type StorageError = Box<dyn Error + Send + Sync>;
+
+// Where the store is persisted, see `PocaBuilder::storage`. Values are saved
+// as a whole, changes are appended in between with a write-ahead log.
+pub trait StorageBackend: Send + Sync + 'static {
+    // the saved values with the changes appended since applied on top
+    fn load(&self) -> Result<Map<String, Value>, StorageError>;
+
+    // replaces the saved values and the changes appended before
+    fn save(&self, values: &Map<String, Value>) -> Result<(), StorageError>;
+
+    fn append_change(&self, change: &Change) -> Result<(), StorageError>;
+
+    // makes the changes appended so far survive a crash of the system, see
+    // `Durability`
+    fn sync(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+// shared, like between a server and the one restarted after it
+impl<T: StorageBackend> StorageBackend for Arc<T> {
+    fn load(&self) -> Result<Map<String, Value>, StorageError> {
+        (**self).load()
+    }
+
+    fn save(&self, values: &Map<String, Value>) -> Result<(), StorageError> {
+        (**self).save(values)
+    }
+
+    fn append_change(&self, change: &Change) -> Result<(), StorageError> {
+        (**self).append_change(change)
+    }
+
+    fn sync(&self) -> Result<(), StorageError> {
+        (**self).sync()
+    }
+}
+
+// lost with the process, for tests and for servers that only need a log in memory
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: Mutex<(Map<String, Value>, Vec<Value>)>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn load(&self) -> Result<Map<String, Value>, StorageError> {
+        let (values, changes) = &*self.state.lock();
+        let mut values = values.clone();
+        for change in changes {
+            serde_json::from_value::<Change>(change.clone())?.apply(&mut values);
+        }
+        Ok(values)
+    }
+
+    fn save(&self, values: &Map<String, Value>) -> Result<(), StorageError> {
+        *self.state.lock() = (values.clone(), Vec::new());
+        Ok(())
+    }
+
+    fn append_change(&self, change: &Change) -> Result<(), StorageError> {
+        self.state.lock().1.push(serde_json::to_value(change)?);
+        Ok(())
+    }
+}
+
+// bumped whenever the layout of the file changes
+const FORMAT: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    format: u32,
+    values: Map<String, Value>,
+}
+
+// A JSON file with the values, written next to it first so a crash never
+// leaves half of it. Changes go to `<path>.log`, one per line.
+pub struct FileStorage {
+    path: PathBuf,
+    // opened by the first change
+    log: Mutex<Option<File>>,
+}
+
+impl FileStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileStorage {
+            path: path.into(),
+            log: Mutex::new(None),
+        }
+    }
+
+    fn context(&self, error: impl Into<StorageError>) -> StorageError {
+        format!("{}: {}", self.path.display(), error.into()).into()
+    }
+}
+
+impl StorageBackend for FileStorage {
+    // nothing to restore if the file doesn't exist yet
+    fn load(&self) -> Result<Map<String, Value>, StorageError> {
+        let mut values = match fs::read_to_string(&self.path) {
+            Ok(contents) => {
+                let snapshot: Snapshot =
+                    serde_json::from_str(&contents).map_err(|error| self.context(error))?;
+                if snapshot.format != FORMAT {
+                    let error = format!("Unsupported format {}", snapshot.format);
+                    return Err(self.context(error));
+                }
+                snapshot.values
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Map::new(),
+            Err(error) => return Err(self.context(error)),
+        };
+        match File::open(with_suffix(&self.path, ".log")) {
+            Ok(log) => {
+                for line in BufReader::new(log).lines() {
+                    // the last line may be cut off by a crash
+                    match serde_json::from_str::<Change>(&line?) {
+                        Ok(change) => change.apply(&mut values),
+                        Err(_) => break,
+                    }
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(self.context(error)),
+        }
+        Ok(values)
+    }
+
+    // the values are complete before the log is emptied
+    fn save(&self, values: &Map<String, Value>) -> Result<(), StorageError> {
+        let snapshot = serde_json::to_string(&Snapshot {
+            format: FORMAT,
+            values: values.clone(),
+        })?;
+        let temporary = with_suffix(&self.path, ".tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(snapshot.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        match self.log.lock().as_ref() {
+            Some(log) => log.set_len(0)?,
+            // left by an earlier run
+            None => match OpenOptions::new()
+                .write(true)
+                .open(with_suffix(&self.path, ".log"))
+            {
+                Ok(log) => log.set_len(0)?,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            },
+        }
+        Ok(())
+    }
+
+    fn append_change(&self, change: &Change) -> Result<(), StorageError> {
+        let mut line = serde_json::to_string(change)?;
+        line.push('\n');
+        let mut log = self.log.lock();
+        if log.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(with_suffix(&self.path, ".log"))?;
+            *log = Some(file);
+        }
+        log.as_mut().unwrap().write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<(), StorageError> {
+        if let Some(log) = self.log.lock().as_ref() {
+            log.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+// like `state.json.log` for `state.json`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
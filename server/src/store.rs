@@ -0,0 +1,100 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::poca::DataElement;
+
+const SHARDS: usize = 16;
+
+type Shard = HashMap<String, DataElement>;
+
+// The keys of a `Poca`, split into shards by the hash of the key so that
+// connections working on unrelated keys don't wait for each other. Whatever
+// goes through all keys locks one shard after the other, so it may miss keys
+// added or removed meanwhile.
+pub struct ShardedStore {
+    shards: Vec<Mutex<Shard>>,
+}
+
+impl Default for ShardedStore {
+    fn default() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+        }
+    }
+}
+
+impl ShardedStore {
+    // the shard the key belongs in, whether it is registered or not
+    pub fn shard(&self, key: &str) -> MutexGuard<'_, Shard> {
+        self.shards[index(key)].lock()
+    }
+
+    pub fn get(&self, key: &str) -> Option<DataElement> {
+        self.shard(key).get(key).cloned()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.shard(key).contains_key(key)
+    }
+
+    pub fn remove(&self, key: &str) -> Option<DataElement> {
+        self.shard(key).remove(key)
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.lock().keys().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    pub fn elements(&self) -> Vec<(String, DataElement)> {
+        self.shards
+            .iter()
+            .flat_map(|shard| {
+                shard
+                    .lock()
+                    .iter()
+                    .map(|(key, element)| (key.clone(), element.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // Holds the shards of all of the keys at once, e.g. for a transaction.
+    // They are always locked in the same order, so this can't deadlock.
+    pub fn lock<'a>(&self, keys: impl IntoIterator<Item = &'a str>) -> Locked<'_> {
+        let mut indices = keys.into_iter().map(index).collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        Locked(
+            indices
+                .into_iter()
+                .map(|index| (index, self.shards[index].lock()))
+                .collect(),
+        )
+    }
+}
+
+pub struct Locked<'a>(Vec<(usize, MutexGuard<'a, Shard>)>);
+
+impl Locked<'_> {
+    // only keys the shards were locked for
+    pub fn get(&self, key: &str) -> Option<&DataElement> {
+        let index = index(key);
+        self.0
+            .iter()
+            .find(|(each, _)| *each == index)
+            .and_then(|(_, shard)| shard.get(key))
+    }
+}
+
+fn index(key: &str) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as usize % SHARDS
+}
@@ -0,0 +1,48 @@
+use std::collections::HashSet;
+
+// Connections receive every key until they explicitly subscribe to one,
+// after which only the subscribed keys are forwarded.
+#[derive(Debug)]
+pub enum Subscriptions {
+    All { excluded: HashSet<String> },
+    Keys(HashSet<String>),
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Subscriptions::All {
+            excluded: HashSet::new(),
+        }
+    }
+}
+
+impl Subscriptions {
+    pub fn contains(&self, key: &str) -> bool {
+        match self {
+            Subscriptions::All { excluded } => !excluded.contains(key),
+            Subscriptions::Keys(keys) => keys.contains(key),
+        }
+    }
+
+    pub fn subscribe(&mut self, key: String) {
+        match self {
+            Subscriptions::All { .. } => {
+                *self = Subscriptions::Keys(HashSet::from([key]));
+            }
+            Subscriptions::Keys(keys) => {
+                keys.insert(key);
+            }
+        }
+    }
+
+    pub fn unsubscribe(&mut self, key: String) {
+        match self {
+            Subscriptions::All { excluded } => {
+                excluded.insert(key);
+            }
+            Subscriptions::Keys(keys) => {
+                keys.remove(&key);
+            }
+        }
+    }
+}
@@ -21,11 +21,14 @@ impl<T: 'static + Send + Sync> DataToAny for T {
 pub trait SynchronizableClone {
     fn clone_any_box(&self) -> Box<dyn Any>;
     fn clone_synchronizable(&self) -> Box<dyn Synchronizable>;
+    fn as_any_ref(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
 pub trait Synchronizable: 'static + Sync + Send + Debug + DynClone + SynchronizableClone {
     fn serialize(&self) -> String;
     fn deserialize(&self, data: &str) -> Box<dyn Synchronizable>;
+    fn try_deserialize(&self, data: &str) -> Result<Box<dyn Synchronizable>, String>;
 }
 
 impl<T> SynchronizableClone for T
@@ -39,8 +42,18 @@ where
     fn clone_synchronizable(&self) -> Box<dyn Synchronizable> {
         Box::new(self.clone())
     }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
+// covers primitives, String, Option, Vec, HashMap, BTreeMap, tuples...
+// and any user type deriving Serialize, Deserialize, Clone and Debug
 impl<T> Synchronizable for T
 where
     T: 'static + Sync + Send + Debug + Clone + Serialize + DeserializeOwned,
@@ -54,6 +67,16 @@ where
         let data: T = serde_json::from_str(data).unwrap();
         Box::new(data)
     }
+
+    fn try_deserialize(&self, data: &str) -> Result<Box<dyn Synchronizable>, String> {
+        let data: T = serde_json::from_str(data).map_err(|error| error.to_string())?;
+        Ok(Box::new(data))
+    }
 }
 
 dyn_clone::clone_trait_object!(Synchronizable);
+
+// implemented by #[derive(Synchronizable)], which can't implement
+// Synchronizable itself without conflicting with the impl above
+#[doc(hidden)]
+pub trait DeriveSynchronizable: Synchronizable {}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+// Replicated text as an RGA sequence CRDT: every character gets a unique id and
+// is inserted after the character it followed where it was typed. Concurrent
+// inserts after the same character are ordered by id, and deletions only mark
+// characters, so all replicas converge regardless of the order ops arrive in.
+
+// ordered by counter first, so ids work as Lamport timestamps
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CharId {
+    pub counter: u64,
+    pub site: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum TextOp {
+    Insert {
+        id: CharId,
+        after: Option<CharId>,
+        ch: char,
+    },
+    Delete {
+        id: CharId,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct TextChar {
+    id: CharId,
+    ch: char,
+    deleted: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Text {
+    chars: Vec<TextChar>,
+    clock: u64,
+}
+
+// the server edits as site 0
+pub(crate) const SERVER_SITE: u64 = 0;
+
+impl Text {
+    pub fn new(initial: &str) -> Self {
+        let mut text = Text::default();
+        text.insert(SERVER_SITE, 0, initial);
+        text
+    }
+
+    pub fn value(&self) -> String {
+        self.chars
+            .iter()
+            .filter(|each| !each.deleted)
+            .map(|each| each.ch)
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chars.iter().filter(|each| !each.deleted).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn position_of(&self, id: CharId) -> Option<usize> {
+        self.chars.iter().position(|each| each.id == id)
+    }
+
+    // position in `chars` of the visible character at `index`
+    fn visible_position(&self, index: usize) -> Option<usize> {
+        self.chars
+            .iter()
+            .enumerate()
+            .filter(|(_, each)| !each.deleted)
+            .nth(index)
+            .map(|(position, _)| position)
+    }
+
+    pub fn insert(&mut self, site: u64, index: usize, value: &str) -> Vec<TextOp> {
+        let mut after = match index {
+            0 => None,
+            _ => {
+                let position = self
+                    .visible_position(index - 1)
+                    .expect("text index out of bounds");
+                Some(self.chars[position].id)
+            }
+        };
+        let mut ops = Vec::new();
+        for ch in value.chars() {
+            let id = CharId {
+                counter: self.clock + 1,
+                site,
+            };
+            let op = TextOp::Insert { id, after, ch };
+            self.apply(&op).unwrap();
+            ops.push(op);
+            after = Some(id);
+        }
+        ops
+    }
+
+    pub fn delete(&mut self, index: usize, length: usize) -> Vec<TextOp> {
+        let ids = self
+            .chars
+            .iter()
+            .filter(|each| !each.deleted)
+            .skip(index)
+            .take(length)
+            .map(|each| each.id)
+            .collect::<Vec<_>>();
+        ids.into_iter()
+            .map(|id| {
+                let op = TextOp::Delete { id };
+                self.apply(&op).unwrap();
+                op
+            })
+            .collect()
+    }
+
+    // applying the same op twice has no further effect
+    pub fn apply(&mut self, op: &TextOp) -> Result<(), String> {
+        match op {
+            TextOp::Insert { id, after, ch } => {
+                if self.position_of(*id).is_some() {
+                    return Ok(());
+                }
+                let mut position = match after {
+                    Some(after) => {
+                        self.position_of(*after)
+                            .ok_or_else(|| format!("Unknown character {:?}", after))?
+                            + 1
+                    }
+                    None => 0,
+                };
+                // later concurrent inserts at the same place go first
+                while position < self.chars.len() && self.chars[position].id > *id {
+                    position += 1;
+                }
+                self.chars.insert(
+                    position,
+                    TextChar {
+                        id: *id,
+                        ch: *ch,
+                        deleted: false,
+                    },
+                );
+                self.clock = self.clock.max(id.counter);
+            }
+            TextOp::Delete { id } => {
+                let position = self
+                    .position_of(*id)
+                    .ok_or_else(|| format!("Unknown character {:?}", id))?;
+                self.chars[position].deleted = true;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::ops::Deref;
+
+use crate::{
+    client::Origin,
+    data_handle::DataHandle,
+    message::Message,
+    text::{Text, TextOp, SERVER_SITE},
+};
+
+// Edits are broadcast as CRDT operations, see `text::Text`.
+#[derive(Clone)]
+pub struct TextHandle {
+    handle: DataHandle<Text>,
+}
+
+impl Deref for TextHandle {
+    type Target = DataHandle<Text>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl TextHandle {
+    pub fn new(handle: DataHandle<Text>) -> Self {
+        Self { handle }
+    }
+
+    pub fn value(&self) -> String {
+        self.handle.read().value()
+    }
+
+    pub fn insert(&self, index: usize, value: &str) {
+        self.handle.update_with_message(|text, version| {
+            let ops = text.insert(SERVER_SITE, index, value);
+            ((), self.message(ops, version))
+        })
+    }
+
+    pub fn push_str(&self, value: &str) {
+        self.handle.update_with_message(|text, version| {
+            let ops = text.insert(SERVER_SITE, text.len(), value);
+            ((), self.message(ops, version))
+        })
+    }
+
+    pub fn delete(&self, index: usize, length: usize) {
+        self.handle.update_with_message(|text, version| {
+            let ops = text.delete(index, length);
+            ((), self.message(ops, version))
+        })
+    }
+
+    fn message(&self, ops: Vec<TextOp>, version: u64) -> Option<Message> {
+        (!ops.is_empty()).then(|| Message::TextOps {
+            key: self.handle.get_key().to_owned(),
+            ops,
+            origin: Origin::Server,
+            version,
+        })
+    }
+}
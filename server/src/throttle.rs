@@ -0,0 +1,144 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde_json::{Map, Value};
+use tokio::time::Instant;
+
+use crate::{
+    client::Origin,
+    journal::Change,
+    message::{Message, Payload},
+};
+
+// Keys with a maximum update rate, see `DataHandle::max_update_rate`. Changes
+// of a key within the interval after the latest broadcast one are held back.
+// Once the interval is over, the value they lead to is broadcast as a whole,
+// numbered like the last of them.
+#[derive(Default)]
+pub struct Throttles(HashMap<String, Throttle>);
+
+struct Throttle {
+    interval: Duration,
+    // as of the latest routed change
+    value: Value,
+    version: u64,
+    sent: Option<Instant>,
+    held: Option<Held>,
+}
+
+struct Held {
+    seq: u64,
+    // the client that made all of the held changes, so it isn't sent its own value
+    origin: Origin,
+}
+
+impl Throttles {
+    pub fn insert(&mut self, key: String, interval: Duration, value: Value) {
+        self.0.insert(
+            key,
+            Throttle {
+                interval,
+                value,
+                version: 0,
+                sent: None,
+                held: None,
+            },
+        );
+    }
+
+    // the messages to broadcast now in place of the routed one
+    pub fn route(&mut self, seq: u64, message: Message) -> Vec<(u64, Message)> {
+        let changes = Change::from_message(&message);
+        if !changes
+            .iter()
+            .any(|change| self.0.contains_key(&change.key))
+        {
+            return vec![(seq, message)];
+        }
+        let now = Instant::now();
+        if let (Some((key, origin)), Some(version)) = (message.change(), message.version()) {
+            let throttle = self.0.get_mut(key).unwrap();
+            apply(throttle, changes);
+            throttle.version = version;
+            if matches!(throttle.sent, Some(sent) if now < sent + throttle.interval) {
+                throttle.held = Some(Held {
+                    seq,
+                    origin: match &throttle.held {
+                        Some(held) if held.origin != origin => Origin::Server,
+                        _ => origin,
+                    },
+                });
+                return Vec::new();
+            }
+            throttle.sent = Some(now);
+            // the message alone would miss the held changes
+            return match throttle.held.take() {
+                Some(held) => vec![(seq, set(key, throttle, held.origin))],
+                None => vec![(seq, message)],
+            };
+        }
+        // removals and transactions aren't held back, the changes held
+        // before them are broadcast first
+        let mut routed = Vec::new();
+        for change in changes {
+            if let Some(throttle) = self.0.get_mut(&change.key) {
+                if let Some(held) = throttle.held.take() {
+                    routed.push((held.seq, set(&change.key, throttle, held.origin)));
+                }
+                throttle.sent = Some(now);
+                apply(throttle, vec![change]);
+            }
+        }
+        if let Message::Remove { key } = &message {
+            self.0.remove(key);
+        }
+        routed.push((seq, message));
+        routed
+    }
+
+    // when the next held change is due
+    pub fn next_flush(&self) -> Option<Instant> {
+        self.0
+            .values()
+            .filter(|throttle| throttle.held.is_some())
+            .filter_map(|throttle| Some(throttle.sent? + throttle.interval))
+            .min()
+    }
+
+    // the values of keys with held changes that are due by now
+    pub fn flush(&mut self) -> Vec<(u64, Message)> {
+        let now = Instant::now();
+        let mut flushed = self
+            .0
+            .iter_mut()
+            .filter(|(_, throttle)| {
+                !matches!(throttle.sent, Some(sent) if now < sent + throttle.interval)
+            })
+            .filter_map(|(key, throttle)| {
+                let held = throttle.held.take()?;
+                throttle.sent = Some(now);
+                Some((held.seq, set(key, throttle, held.origin)))
+            })
+            .collect::<Vec<_>>();
+        flushed.sort_by_key(|(seq, _)| *seq);
+        flushed
+    }
+}
+
+fn apply(throttle: &mut Throttle, changes: Vec<Change>) {
+    for change in changes {
+        let mut values = Map::new();
+        values.insert(change.key.clone(), throttle.value.take());
+        let key = change.key.clone();
+        change.apply(&mut values);
+        throttle.value = values.remove(&key).unwrap_or_default();
+    }
+}
+
+fn set(key: &str, throttle: &Throttle, origin: Origin) -> Message {
+    Message::Set {
+        key: key.to_string(),
+        data: Payload::from(throttle.value.to_string()),
+        origin,
+        version: throttle.version,
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    io::{self, BufReader},
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+};
+
+use futures_util::{Stream, StreamExt};
+use hyper::server::{
+    accept::Accept,
+    conn::{AddrIncoming, AddrStream},
+};
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+use crate::{error::PocaError, trace::debug};
+
+// handshakes in flight at once, a slow client should not block the others
+const HANDSHAKE_CONCURRENCY: usize = 64;
+
+#[derive(Clone)]
+pub struct TlsConfig {
+    cert_pem: Vec<u8>,
+    key_pem: Vec<u8>,
+}
+
+impl TlsConfig {
+    pub fn from_pem(cert_pem: impl Into<Vec<u8>>, key_pem: impl Into<Vec<u8>>) -> Self {
+        TlsConfig {
+            cert_pem: cert_pem.into(),
+            key_pem: key_pem.into(),
+        }
+    }
+
+    pub fn from_files(cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::from_pem(
+            std::fs::read(cert_path)?,
+            std::fs::read(key_path)?,
+        ))
+    }
+
+    fn acceptor(&self) -> Result<TlsAcceptor, PocaError> {
+        let (certs, key) = self.der()?;
+        let certs = certs.into_iter().map(Certificate).collect::<Vec<_>>();
+        let key = PrivateKey(key);
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|error| PocaError::Tls(error.to_string()))?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+
+    // the certificate chain and the PKCS#8 private key
+    pub(crate) fn der(&self) -> Result<(Vec<Vec<u8>>, Vec<u8>), PocaError> {
+        let certs = rustls_pemfile::certs(&mut BufReader::new(self.cert_pem.as_slice()))
+            .map_err(|error| PocaError::Tls(error.to_string()))?;
+        let mut key_reader = BufReader::new(self.key_pem.as_slice());
+        let key = rustls_pemfile::pkcs8_private_keys(&mut key_reader)
+            .map_err(|error| PocaError::Tls(error.to_string()))?
+            .pop()
+            .ok_or_else(|| PocaError::Tls("No PKCS#8 private key found".to_string()))?;
+        Ok((certs, key))
+    }
+}
+
+// connections of the listener, once their handshake is done, failed handshakes
+// are only logged with the `tracing` feature
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn incoming(
+    mut incoming: AddrIncoming,
+    tls_config: TlsConfig,
+) -> Result<impl Stream<Item = io::Result<TlsStream<AddrStream>>> + Send, PocaError> {
+    let acceptor = tls_config.acceptor()?;
+
+    let incoming = futures_util::stream::poll_fn(move |cx| Pin::new(&mut incoming).poll_accept(cx))
+        .map(move |stream| {
+            let acceptor = acceptor.clone();
+            async move { acceptor.accept(stream?).await }
+        })
+        .buffer_unordered(HANDSHAKE_CONCURRENCY)
+        .filter_map(|stream| async move {
+            match stream {
+                Ok(stream) => Some(Ok(stream)),
+                Err(error) => {
+                    debug!(%error, "TLS handshake failed");
+                    None
+                }
+            }
+        });
+    Ok(incoming)
+}
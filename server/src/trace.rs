@@ -0,0 +1,44 @@
+// Spans and events of the `tracing` crate, which compile to nothing without
+// the `tracing` feature. Fields only name values that are used elsewhere as
+// well, so nothing is left unused then.
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, debug_span, info, info_span, warn, Instrument};
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use disabled::{debug, debug_span, info, info_span, warn, Instrument};
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) mod disabled {
+    // stands in for `tracing::Span`
+    pub struct Span;
+
+    impl Span {
+        pub fn entered(self) -> Span {
+            self
+        }
+    }
+
+    pub trait Instrument: Sized {
+        fn instrument(self, _span: Span) -> Self {
+            self
+        }
+    }
+
+    impl<T> Instrument for T {}
+
+    macro_rules! span {
+        ($($field:tt)*) => {
+            $crate::trace::disabled::Span
+        };
+    }
+
+    macro_rules! event {
+        ($($field:tt)*) => {{}};
+    }
+
+    pub(crate) use event as debug;
+    pub(crate) use event as info;
+    pub(crate) use event as warn;
+    pub(crate) use span as debug_span;
+    pub(crate) use span as info_span;
+}
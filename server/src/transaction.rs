@@ -0,0 +1,78 @@
+use crate::{
+    client::Origin,
+    error::KeyError,
+    event_handler::notify_change,
+    message::{payload, Message},
+    poca::Store,
+    router::Router,
+    synchronizable::Synchronizable,
+};
+
+// Changes staged by `Poca::transaction`, applied together once it returns.
+#[derive(Default)]
+pub struct Transaction {
+    changes: Vec<(String, Box<dyn Synchronizable>)>,
+}
+
+impl Transaction {
+    // a later set on the same key replaces the earlier one
+    pub fn set<T: Synchronizable>(&mut self, key: &str, value: T) -> &mut Self {
+        let value: Box<dyn Synchronizable> = Box::new(value);
+        match self.changes.iter_mut().find(|(each, _)| each == key) {
+            Some((_, staged)) => *staged = value,
+            None => self.changes.push((key.to_string(), value)),
+        }
+        self
+    }
+
+    // Every key is checked before anything is written, and all of them are
+    // written and sent while holding the shards of the keys and the write lock
+    // of each value, before any handler runs.
+    pub(crate) fn commit(self, store: &Store, sender: &Router) -> Result<(), KeyError> {
+        if self.changes.is_empty() {
+            return Ok(());
+        }
+        let store_lock = store.lock(self.changes.iter().map(|(key, _)| key.as_str()));
+        let mut elements = Vec::with_capacity(self.changes.len());
+        for (key, value) in &self.changes {
+            let element = store_lock
+                .get(key)
+                .ok_or_else(|| KeyError::NotFound(key.clone()))?;
+            if element.read().data.as_any_ref().type_id() != value.as_any_ref().type_id() {
+                return Err(KeyError::TypeMismatch(key.clone()));
+            }
+            elements.push(element.clone());
+        }
+
+        let mut guards = elements
+            .iter()
+            .map(|element| element.write())
+            .collect::<Vec<_>>();
+        let mut old = Vec::with_capacity(elements.len());
+        let messages = self
+            .changes
+            .into_iter()
+            .zip(guards.iter_mut())
+            .map(|((key, data), guard)| {
+                let replaced = std::mem::replace(&mut guard.data, data);
+                guard.remember(replaced.as_ref());
+                old.push(replaced);
+                guard.version += 1;
+                Message::Set {
+                    key,
+                    data: payload(guard.data.as_ref()),
+                    origin: Origin::Server,
+                    version: guard.version,
+                }
+            })
+            .collect::<Vec<_>>();
+        sender.send(Message::Batch(messages));
+        drop(guards);
+        drop(store_lock);
+
+        for (element, old) in elements.iter().zip(old) {
+            notify_change(element, old, Origin::Server);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use crate::{poca::DataElementInner, synchronizable::Synchronizable};
+
+// rewrites a value written by a client before it is applied, see `DataHandle::transform`
+pub type Transform = Box<dyn Fn(Box<dyn Synchronizable>) -> Box<dyn Synchronizable> + Send + Sync>;
+
+// The value after all transforms of the key, if they changed it. The sender
+// doesn't hold the transformed value yet, so it has to be sent to it as well.
+pub fn transform(
+    element: &DataElementInner,
+    value: &dyn Synchronizable,
+) -> Option<Box<dyn Synchronizable>> {
+    if element.transforms.is_empty() {
+        return None;
+    }
+    let transformed = element
+        .transforms
+        .iter()
+        .fold(value.clone_synchronizable(), |value, transform| {
+            transform(value)
+        });
+    if transformed.serialize() == value.serialize() {
+        None
+    } else {
+        Some(transformed)
+    }
+}
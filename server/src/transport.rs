@@ -0,0 +1,49 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    pin::Pin,
+};
+
+use futures_util::{Sink, SinkExt, Stream, StreamExt, TryStreamExt};
+use warp::ws::{self, WebSocket};
+
+pub type FrameSink = Pin<Box<dyn Sink<ws::Message, Error = TransportError> + Send>>;
+pub type FrameStream = Pin<Box<dyn Stream<Item = Result<ws::Message, TransportError>> + Send>>;
+
+// Carries frames between a client and its connection handler. Frames keep
+// the shape of WebSocket messages, other transports translate them.
+pub trait Transport: Send + 'static {
+    fn split(self) -> (FrameSink, FrameStream);
+}
+
+// whatever broke the transport, as its source
+#[derive(Debug)]
+pub struct TransportError(Box<dyn Error + Send + Sync + 'static>);
+
+impl TransportError {
+    pub fn new(error: impl Error + Send + Sync + 'static) -> Self {
+        TransportError(Box::new(error))
+    }
+}
+
+impl Display for TransportError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "Transport error: {}", self.0)
+    }
+}
+
+impl Error for TransportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+impl Transport for WebSocket {
+    fn split(self) -> (FrameSink, FrameStream) {
+        let (sender, receiver) = StreamExt::split(self);
+        (
+            Box::pin(sender.sink_map_err(TransportError::new)),
+            Box::pin(receiver.map_err(TransportError::new)),
+        )
+    }
+}
@@ -0,0 +1,39 @@
+use std::{
+    io,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+};
+
+use futures_util::Stream;
+use tokio::net::{UnixListener, UnixStream};
+use tokio_stream::wrappers::UnixListenerStream;
+
+use crate::error::PocaError;
+
+// A socket left behind by a previous run is replaced.
+// Clients connecting this way have no address, see `ClientInfo::address`.
+pub(crate) fn incoming(
+    path: &Path,
+) -> Result<impl Stream<Item = io::Result<UnixStream>> + Send, PocaError> {
+    let error = |source: io::Error| PocaError::BindUnix {
+        path: path.to_path_buf(),
+        source: source.into(),
+    };
+    // anything else at the path is left alone, so binding fails
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path).map_err(error)?;
+        }
+    }
+    let listener = UnixListener::bind(path).map_err(error)?;
+    Ok(UnixListenerStream::new(listener))
+}
+
+// removes the socket file once the server stops
+pub(crate) struct SocketFile(pub PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
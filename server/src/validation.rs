@@ -0,0 +1,17 @@
+use crate::{poca::DataElementInner, synchronizable::Synchronizable};
+
+// checks a value written by a client before it is applied, see `DataHandle::validate`
+pub type Validator = Box<dyn Fn(&dyn Synchronizable) -> Result<(), String> + Send + Sync>;
+
+// the reason of the first validator rejecting the value
+pub fn validate(
+    element: &DataElementInner,
+    key: &str,
+    value: &dyn Synchronizable,
+) -> Result<(), String> {
+    element
+        .validators
+        .iter()
+        .try_for_each(|validator| validator(value))
+        .map_err(|reason| format!("Rejected value for key {}: {}", key, reason))
+}
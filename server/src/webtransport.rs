@@ -0,0 +1,196 @@
+use std::{io, net::SocketAddr, sync::Arc};
+
+use futures_util::{future, stream, Stream, StreamExt};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader},
+    sync::mpsc,
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use warp::{
+    http::{header::HeaderName, HeaderMap, HeaderValue},
+    ws,
+};
+use wtransport::{
+    endpoint::{endpoint_side::Server, IncomingSession},
+    Certificate, Connection, Endpoint, RecvStream, SendStream, ServerConfig, VarInt,
+};
+
+use crate::{
+    admission::{Admission, Admitted},
+    encoding::JsonEncoding,
+    error::PocaError,
+    poca::resume_from,
+    tls::TlsConfig,
+    trace::warn,
+    transport::{FrameSink, FrameStream, Transport, TransportError},
+    ws_handler::connection_handler,
+};
+
+// the longest line without `PocaConfig::max_message_size`, like the largest
+// WebSocket message by default
+const DEFAULT_MAX_LINE: usize = 64 << 20;
+
+pub(crate) fn bind(
+    address: SocketAddr,
+    tls_config: &TlsConfig,
+) -> Result<Endpoint<Server>, PocaError> {
+    let (certs, key) = tls_config.der()?;
+    let certificate =
+        Certificate::new(certs, key).map_err(|error| PocaError::Tls(error.to_string()))?;
+    let config = ServerConfig::builder()
+        .with_bind_address(address)
+        .with_certificate(certificate)
+        .build();
+    Endpoint::server(config).map_err(|error| PocaError::Bind {
+        address,
+        source: Box::new(error),
+    })
+}
+
+// Accepts sessions until dropped. Each session opens one bidirectional
+// stream carrying the messages a WebSocket would, one JSON message per line.
+// Stores are found by the path of the session like for WebSockets. Longer
+// lines than `max_message_size` end the session.
+pub(crate) async fn serve(
+    endpoint: Endpoint<Server>,
+    admission: Admission,
+    max_message_size: Option<usize>,
+) {
+    let max_line = max_message_size.unwrap_or(DEFAULT_MAX_LINE);
+    loop {
+        let session = endpoint.accept().await;
+        tokio::spawn(handle_session(session, admission.clone(), max_line));
+    }
+}
+
+// the error of a failed handshake is only logged with the `tracing` feature
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+async fn handle_session(session: IncomingSession, admission: Admission, max_line: usize) {
+    let request = match session.await {
+        Ok(request) => request,
+        Err(error) => {
+            warn!(%error, "WebTransport handshake failed");
+            return;
+        }
+    };
+    let (path, query) = match request.path().split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (request.path().to_string(), None),
+    };
+    let headers = request
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = HeaderName::from_bytes(name.as_bytes()).ok()?;
+            Some((name, HeaderValue::from_str(value).ok()?))
+        })
+        .collect::<HeaderMap>();
+    let resume = resume_from(query.as_deref());
+    let address = Some(request.remote_address());
+    let Admitted {
+        context,
+        client,
+        slot,
+    } = match admission.admit(path.trim_matches('/'), address, headers, query) {
+        Ok(admitted) => admitted,
+        // there is no room for the reason in the response
+        Err(_) => {
+            request.forbidden().await;
+            return;
+        }
+    };
+    let _slot = slot;
+    let connection = match request.accept().await {
+        Ok(connection) => connection,
+        Err(_) => return,
+    };
+    let (send, receive) = match connection.accept_bi().await {
+        Ok(streams) => streams,
+        Err(_) => return,
+    };
+    let session = Session {
+        connection,
+        send,
+        receive,
+        max_line,
+    };
+    let encoding = Arc::new(JsonEncoding);
+    connection_handler(session, context, client, encoding, resume).await;
+}
+
+struct Session {
+    connection: Connection,
+    send: SendStream,
+    receive: RecvStream,
+    max_line: usize,
+}
+
+impl Transport for Session {
+    fn split(self) -> (FrameSink, FrameStream) {
+        // QUIC keeps the connection alive by itself, pings are answered here
+        let (pongs, pong_receiver) = mpsc::unbounded_channel();
+        let sink = futures_util::sink::unfold(
+            (self.send, self.connection, pongs),
+            |(mut send, connection, pongs), frame: ws::Message| async move {
+                if let Some((code, reason)) = frame.close_frame() {
+                    connection.close(VarInt::from_u32(code.into()), reason.as_bytes());
+                } else if frame.is_ping() {
+                    pongs.send(ws::Message::pong(Vec::new())).ok();
+                } else if let Ok(text) = frame.to_str() {
+                    let line = format!("{}\n", text);
+                    send.write_all(line.as_bytes())
+                        .await
+                        .map_err(TransportError::new)?;
+                }
+                Ok((send, connection, pongs))
+            },
+        );
+        // ends with the incoming stream, whether or not pongs are pending
+        let lines = lines(BufReader::new(self.receive), self.max_line)
+            .map(|line| Some(line.map(ws::Message::text).map_err(TransportError::new)))
+            .chain(stream::once(future::ready(None)));
+        let pongs = UnboundedReceiverStream::new(pong_receiver).map(|pong| Some(Ok(pong)));
+        let stream = stream::select(lines, pongs)
+            .take_while(|frame| future::ready(frame.is_some()))
+            .filter_map(future::ready);
+        (Box::pin(sink), Box::pin(stream))
+    }
+}
+
+// Like `AsyncBufReadExt::lines`, but a line longer than `max` ends the stream
+// with an error instead of being buffered until the peer runs out of data.
+fn lines(reader: impl AsyncBufRead + Unpin, max: usize) -> impl Stream<Item = io::Result<String>> {
+    stream::unfold(Some(reader), move |reader| async move {
+        let mut reader = reader?;
+        let mut line = Vec::new();
+        loop {
+            let available = match reader.fill_buf().await {
+                Ok(available) => available,
+                Err(error) => return Some((Err(error), None)),
+            };
+            // the rest of the stream, if it doesn't end with a newline
+            if available.is_empty() {
+                return (!line.is_empty()).then(|| (decode(line), None));
+            }
+            let end = available.iter().position(|byte| *byte == b'\n');
+            let used = end.map_or(available.len(), |end| end + 1);
+            line.extend_from_slice(&available[..used]);
+            reader.consume(used);
+            if line.len() - usize::from(end.is_some()) > max {
+                let error = io::Error::new(io::ErrorKind::InvalidData, "Line too long");
+                return Some((Err(error), None));
+            }
+            if end.is_some() {
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Some((decode(line), Some(reader)));
+            }
+        }
+    })
+}
+
+fn decode(line: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(line).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
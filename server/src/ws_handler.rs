@@ -1,128 +1,1456 @@
-use std::ops::Deref;
+use std::{
+    cell::Cell,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::Duration,
+};
 
-use futures_util::pin_mut;
-use tokio_stream::{wrappers::BroadcastStream, StreamExt};
-use warp::ws::{self, WebSocket};
+use futures_util::{future::Either, pin_mut, ready, Stream};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use tokio::{
+    sync::Notify,
+    time::{interval_at, sleep_until, timeout, Instant},
+};
+use tokio_stream::{
+    wrappers::{IntervalStream, ReceiverStream, UnboundedReceiverStream},
+    StreamExt,
+};
+use warp::ws;
 
 use crate::{
-    event_handler::EventHandlerStore,
-    message::{Message, WSMessage, WSMessageType},
-    poca::{BroadcastReceiver, BroadcastSender, Store},
+    access::Access,
+    ack::Acks,
+    builder::LagPolicy,
+    checksum::checksums,
+    client::{ClientInfo, Origin},
+    coalesce::coalesce,
+    codegen,
+    conflict::ConflictPolicy,
+    connections::{Connections, Direct},
+    encoding::Encoding,
+    error::PocaError,
+    event_handler::{
+        catch_panic, notify_change, ConnectionHandlerStore, ErrorHook, EventHandlerStore, LagHook,
+        PanicHook, UnacknowledgedHook,
+    },
+    frames::Frames,
+    message::{payload, text, Message, Payload, WSMessage, WSMessageType, POLICY_VIOLATION},
+    metrics::SharedMetrics,
+    middleware::{self, Flow, Middlewares},
+    patch::apply_patch,
+    poca::Store,
+    rate_limit::{RateLimit, RateLimitPolicy, RateLimiter, Verdict},
+    replay::SharedRecorder,
+    rooms::Rooms,
+    router::Router,
+    rpc::{PendingCalls, RpcHandlerStore},
+    subscription::Subscriptions,
+    synchronizable::Synchronizable,
+    text::{Text, TextOp},
+    trace::{debug, debug_span, info, info_span, warn, Instrument},
+    transform::transform,
+    transport::Transport,
+    validation::validate,
 };
 
-pub async fn websocket_handler<'a>(
-    websocket: WebSocket,
-    store: Store,
-    event_handler_store: EventHandlerStore,
-    broadcast_receiver: BroadcastReceiver,
-    broadcast_sender: BroadcastSender,
+const GOING_AWAY: u16 = 1001;
+const PROTOCOL_ERROR: u16 = 1002;
+// how long a close frame may take to go out and be answered
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct HandlerContext {
+    pub store: Store,
+    pub event_handler_store: EventHandlerStore,
+    pub router: Router,
+    pub on_connect: ConnectionHandlerStore,
+    pub on_disconnect: ConnectionHandlerStore,
+    pub panic_hook: PanicHook,
+    pub connections: Connections,
+    pub rooms: Rooms,
+    pub rpc_handlers: RpcHandlerStore,
+    pub pending_calls: PendingCalls,
+    pub ping_interval: Option<Duration>,
+    pub max_missed_pongs: u32,
+    pub idle_timeout: Option<Duration>,
+    pub rate_limit: Option<RateLimit>,
+    pub lag_policy: LagPolicy,
+    pub flush_interval: Option<Duration>,
+    pub lag_hook: LagHook,
+    pub error_hook: ErrorHook,
+    pub acks: Acks,
+    pub unacknowledged_hook: UnacknowledgedHook,
+    pub ack_timeout: Duration,
+    pub ack_retries: u32,
+    pub anti_entropy_interval: Option<Duration>,
+    pub middlewares: Middlewares,
+    pub frames: Frames,
+    pub metrics: SharedMetrics,
+    pub recorder: SharedRecorder,
+    // inbound frames are traced, see `Poca::set_verbose`
+    pub verbose: Arc<AtomicBool>,
+}
+
+pub async fn connection_handler(
+    transport: impl Transport,
+    context: HandlerContext,
+    client: ClientInfo,
+    encoding: Arc<dyn Encoding>,
+    resume: Option<u64>,
+) {
+    let direct = context.connections.connect(&client);
+    context.pending_calls.connect(client.id);
+    let span = info_span!("connection", client = %client.id, address = ?client.address);
+    async {
+        info!("Client connected");
+        handle_connection(
+            transport,
+            &context,
+            &client,
+            encoding.as_ref(),
+            direct,
+            resume,
+        )
+        .await;
+        info!("Client disconnected");
+    }
+    .instrument(span)
+    .await;
+    context.pending_calls.disconnect(client.id);
+    context.acks.forget(client.id);
+    context.connections.disconnect(client.id);
+    context.rooms.leave_all(client.id);
+
+    for handler in context.on_disconnect.read().iter() {
+        catch_panic(&context.panic_hook, None, || handler(client.clone()));
+    }
+}
+
+async fn handle_connection(
+    transport: impl Transport,
+    context: &HandlerContext,
+    client: &ClientInfo,
+    encoding: &dyn Encoding,
+    direct: Direct,
+    resume: Option<u64>,
 ) {
-    let (ws_sender, ws_receiver) = futures_util::StreamExt::split(websocket);
+    let HandlerContext {
+        store,
+        event_handler_store,
+        router,
+        ping_interval,
+        max_missed_pongs,
+        idle_timeout,
+        panic_hook,
+        rpc_handlers,
+        pending_calls,
+        rooms,
+        connections,
+        rate_limit,
+        lag_policy,
+        flush_interval,
+        lag_hook,
+        error_hook,
+        acks,
+        unacknowledged_hook,
+        ack_timeout,
+        ack_retries,
+        anti_entropy_interval,
+        middlewares,
+        frames,
+        metrics,
+        recorder,
+        verbose,
+        ..
+    } = context;
+    let (ws_sender, ws_receiver) = transport.split();
 
     //TODO: handshake, but let's skip it until basic frontend is done
 
-    let broadcast_stream = BroadcastStream::from(broadcast_receiver);
-    let broadcast_dealer = futures_util::StreamExt::forward(
-        broadcast_stream.filter_map(|message| {
-            match message {
-                Ok(inner) => match inner {
-                    Message::Set { key, data } => Some(Ok(ws::Message::text(
-                        serde_json::to_string(&WSMessage {
-                            message_type: WSMessageType::Set,
-                            key: Some(key),
-                            data: Some(data.serialize()),
-                        })
-                        .unwrap(),
-                    ))),
-                    Message::Get { key, data } => Some(Ok(ws::Message::text(
-                        serde_json::to_string(&WSMessage {
-                            message_type: WSMessageType::Get,
-                            key: Some(key),
-                            data: Some(data.serialize()),
-                        })
-                        .unwrap(),
-                    ))),
-                },
-                Err(error) => {
-                    //TODO: uniformed logging
-                    println!("Error when receiving from broadcast channel: {}", error);
-                    None
+    let subscriptions = Mutex::new(Subscriptions::default());
+
+    // subscribe before taking the snapshot so no change in between is lost
+    let queue = router.subscribe(Some(client.id), *lag_policy != LagPolicy::Grow, resume);
+    // once the client can be sent messages and called
+    for handler in context.on_connect.read().iter() {
+        catch_panic(panic_hook, None, || handler(client.clone()));
+    }
+    // the encoded message, unless a middleware dropped it
+    let outbound = |message: WSMessage| {
+        middleware::outbound(middlewares, client, message).map(|message| encoding.encode(&message))
+    };
+    let client_snapshot = |seq: Option<u64>| {
+        ws_message(Message::Snapshot {
+            values: snapshot(store, |room| rooms.is_visible(room, client.id)),
+        })
+        .and_then(|mut message| {
+            message.seq = seq;
+            outbound(message)
+        })
+    };
+    // a resumed client only gets the changes it missed
+    let snapshot = (!queue.resumed())
+        .then(|| client_snapshot(Some(queue.seq())))
+        .flatten()
+        .map(Ok);
+    let queue = match flush_interval {
+        Some(interval) => Either::Left(coalesce(queue, *interval)),
+        None => Either::Right(queue),
+    };
+    // of the latest message in either direction, pings aside
+    let activity = Mutex::new(Instant::now());
+    let heartbeat = Mutex::new(Heartbeat::default());
+    let dead = Notify::new();
+    let ping_stream =
+        futures_util::StreamExt::flat_map(futures_util::stream::iter(*ping_interval), |period| {
+            IntervalStream::new(interval_at(Instant::now() + period, period))
+        })
+        .filter_map(|_| {
+            let mut heartbeat = heartbeat.lock();
+            if heartbeat.sent.is_some() {
+                heartbeat.missed += 1;
+            }
+            if heartbeat.missed >= *max_missed_pongs {
+                dead.notify_one();
+                return None;
+            }
+            heartbeat.sent = Some(Instant::now());
+            Some(Ok(ws::Message::ping(Vec::new())))
+        });
+    // messages addressed to this client only, see `Connections`
+    let direct_stream = ReceiverStream::new(direct.messages)
+        .merge(UnboundedReceiverStream::new(direct.close))
+        .filter_map(|message| match message {
+            Message::Close { code, reason } => Some(Ok(ws::Message::close_with(code, reason))),
+            message => ws_message(message).and_then(outbound).map(Ok),
+        });
+    let is_for_client = |message: &Message| {
+        if let Some((key, origin)) = message.change() {
+            // clients already hold the values they have sent
+            if origin == Origin::Client(client.id) || !subscriptions.lock().contains(key) {
+                return false;
+            }
+            if !rooms.is_visible(room_of(store, key).as_deref(), client.id) {
+                return false;
+            }
+        }
+        // messages for other clients never reach the queue
+        true
+    };
+    // the key of a change that has to be acknowledged
+    let ack_key = |message: &Message| {
+        message
+            .change()
+            .map(|(key, _)| key)
+            .filter(|key| requires_ack(store, key))
+            .map(str::to_string)
+    };
+    // the current value of keys with overdue acknowledgements
+    let retransmit_stream = futures_util::StreamExt::flat_map(
+        IntervalStream::new(interval_at(Instant::now() + *ack_timeout, *ack_timeout)),
+        |_| {
+            let overdue = acks.overdue(client.id, *ack_timeout, *ack_retries);
+            if let Some(hook) = unacknowledged_hook.read().deref() {
+                for key in &overdue.failed {
+                    catch_panic(panic_hook, Some(key), || hook(client, key));
                 }
             }
-        }),
+            let messages = overdue
+                .retransmit
+                .into_iter()
+                .filter_map(|(seq, key)| {
+                    let element = store.get(&key)?;
+                    let element = element.read();
+                    if !rooms.is_visible(element.room.as_deref(), client.id) {
+                        return None;
+                    }
+                    let mut message = ws_message(Message::Set {
+                        data: payload(element.data.as_ref()),
+                        origin: Origin::Server,
+                        version: element.version,
+                        key,
+                    })?;
+                    message.seq = Some(seq);
+                    message.ack = true;
+                    let message = middleware::outbound(middlewares, client, message);
+                    // a change the middlewares dropped or rejected isn't waited for
+                    if !message.as_ref().is_some_and(|message| message.ack) {
+                        acks.cancel(client.id, seq);
+                    }
+                    message.map(|message| Ok(encoding.encode(&message)))
+                })
+                .collect::<Vec<_>>();
+            futures_util::stream::iter(messages)
+        },
+    );
+    // lets the client find values that drifted, it asks for them again
+    let checksum_stream = futures_util::StreamExt::flat_map(
+        futures_util::stream::iter(*anti_entropy_interval),
+        |period| IntervalStream::new(interval_at(Instant::now() + period, period)),
+    )
+    .filter_map(|_| {
+        let checksums = checksums(store, |key, room| {
+            subscriptions.lock().contains(key) && rooms.is_visible(room, client.id)
+        });
+        outbound(WSMessage {
+            message_type: WSMessageType::Checksums,
+            key: None,
+            data: Some(serde_json::Value::Object(checksums).to_string()),
+            version: None,
+            id: None,
+            seq: None,
+            ack: false,
+        })
+        .map(Ok)
+    });
+    let queue_dealer = futures_util::StreamExt::forward(
+        until_closed(
+            tokio_stream::iter(snapshot)
+                .chain(queue.filter_map(|message| {
+                    match message {
+                        Ok((_, Message::Close { code, reason })) => {
+                            Some(Ok(ws::Message::close_with(code, reason)))
+                        }
+                        Ok((seq, Message::Batch(messages))) => {
+                            let mut ack = false;
+                            let batch = messages
+                                .into_iter()
+                                .filter(|each| is_for_client(each))
+                                .filter_map(|each| {
+                                    let key = ack_key(&each);
+                                    let message = middleware::outbound(
+                                        middlewares,
+                                        client,
+                                        ws_message(each)?,
+                                    )?;
+                                    // rejected changes aren't waited for
+                                    if let Some(key) =
+                                        key.filter(|_| message.message_type != WSMessageType::Error)
+                                    {
+                                        acks.expect(client.id, seq, &key);
+                                        ack = true;
+                                    }
+                                    Some(message)
+                                })
+                                .collect::<Vec<_>>();
+                            (!batch.is_empty()).then(|| {
+                                Ok(encoding.encode(&WSMessage {
+                                    message_type: WSMessageType::Batch,
+                                    key: None,
+                                    data: Some(serde_json::to_string(&batch).unwrap()),
+                                    version: None,
+                                    id: None,
+                                    seq: Some(seq),
+                                    ack,
+                                }))
+                            })
+                        }
+                        Ok((seq, inner)) => {
+                            if !is_for_client(&inner) {
+                                return None;
+                            }
+                            let key = ack_key(&inner);
+                            let ack = key.is_some();
+                            // middlewares may change what each client gets
+                            let cached = encoding
+                                .name()
+                                .filter(|_| inner.recipient().is_none())
+                                .filter(|_| middlewares.read().is_empty());
+                            // unless the middlewares dropped or rejected the change
+                            let expected = Cell::new(ack);
+                            let encode = || {
+                                ws_message(inner).and_then(|mut message| {
+                                    message.seq = Some(seq);
+                                    message.ack = ack;
+                                    let message =
+                                        middleware::outbound(middlewares, client, message);
+                                    expected
+                                        .set(message.as_ref().is_some_and(|message| message.ack));
+                                    message.map(|message| encoding.encode(&message))
+                                })
+                            };
+                            let message = match cached {
+                                Some(name) => frames.get_or_encode(seq, name, ack, encode),
+                                None => encode(),
+                            };
+                            if let Some(key) = key.filter(|_| expected.get() && message.is_some()) {
+                                acks.expect(client.id, seq, &key);
+                            }
+                            message.map(Ok)
+                        }
+                        Err(skipped) => {
+                            warn!(missed = skipped, "Client fell behind");
+                            metrics.lagged();
+                            if let Some(hook) = lag_hook.read().deref() {
+                                catch_panic(panic_hook, None, || hook(client, skipped));
+                            }
+                            match lag_policy {
+                                LagPolicy::Disconnect => Some(Ok(ws::Message::close_with(
+                                    POLICY_VIOLATION,
+                                    "Client fell behind",
+                                ))),
+                                _ => client_snapshot(None).map(Ok),
+                            }
+                        }
+                    }
+                }))
+                .merge(ping_stream)
+                .merge(direct_stream)
+                .merge(retransmit_stream)
+                .merge(checksum_stream)
+                .map(|message| {
+                    if matches!(&message, Ok(message) if !message.is_ping()) {
+                        *activity.lock() = Instant::now();
+                        metrics.sent();
+                    }
+                    message
+                }),
+        ),
         ws_sender,
     );
 
+    // messages over the rate limit never reach the handlers below
+    let mut limiter = rate_limit.map(RateLimiter::new);
+    let mut disconnected = false;
+    let ws_receiver = futures_util::TryStreamExt::try_filter_map(ws_receiver, move |message| {
+        let verdict = match limiter.as_mut() {
+            _ if disconnected => Verdict::Exceeded,
+            Some(limiter) if message.is_text() || message.is_binary() => {
+                limiter.check(message.as_bytes().len())
+            }
+            _ => Verdict::Allow,
+        };
+        if let (Verdict::Exceeded, Some(RateLimitPolicy::Disconnect)) =
+            (&verdict, rate_limit.map(|limit| limit.policy))
+        {
+            if !disconnected {
+                disconnected = true;
+                let message = Message::Close {
+                    code: POLICY_VIOLATION,
+                    reason: "Rate limit exceeded".to_string(),
+                };
+                connections.send(client.id, message);
+            }
+        }
+        if let Verdict::TooLarge { limit } = verdict {
+            let reason = format!(
+                "Message of {} bytes exceeds the rate limit of {} bytes per second",
+                message.as_bytes().len(),
+                limit
+            );
+            router.send(Message::Error {
+                key: None,
+                reason,
+                client: client.id,
+            });
+        }
+        async move {
+            match verdict {
+                Verdict::Allow => Ok(Some(message)),
+                Verdict::Wait(wait) => {
+                    tokio::time::sleep(wait).await;
+                    Ok(Some(message))
+                }
+                Verdict::Exceeded | Verdict::TooLarge { .. } => Ok(None),
+            }
+        }
+    });
+
     let ws_dealer = futures_util::TryStreamExt::try_for_each(ws_receiver, |message| {
-        //TODO: uniformed logging
         //TODO: use bytes instead of string
-        let text = message.to_str().unwrap();
-        println!("Got Websocket message: {:?}", &text);
-        let message: WSMessage = serde_json::from_str(text).unwrap();
+        if message.is_pong() {
+            let mut heartbeat = heartbeat.lock();
+            if let Some(sent) = heartbeat.sent.take() {
+                client.set_latency(sent.elapsed());
+            }
+            heartbeat.missed = 0;
+        }
+        // pings, pongs and close frames are handled by the transport itself
+        if !message.is_text() && !message.is_binary() {
+            return futures_util::future::ok(());
+        }
+        *activity.lock() = Instant::now();
+        metrics.received();
+        if verbose.load(Ordering::Relaxed) {
+            debug!(frame = ?message, "Inbound frame");
+        }
+        let message = match encoding.decode(&message) {
+            Ok(message) => message,
+            // treated like frames the transport can't parse
+            Err(reason) => {
+                debug!(reason = %reason, "Undecodable message");
+                report(error_hook, panic_hook, protocol_error(client, &reason));
+                let message = Message::Close {
+                    code: PROTOCOL_ERROR,
+                    reason: "Malformed message".to_string(),
+                };
+                connections.send(client.id, message);
+                return futures_util::future::ok(());
+            }
+        };
+        if let Some(recorder) = recorder.read().as_ref() {
+            recorder.record(client.id, &message);
+        }
+        let _span = debug_span!(
+            "apply",
+            message_type = ?message.message_type,
+            key = ?message.key,
+            size = message.data.as_ref().map_or(0, String::len),
+        )
+        .entered();
+        let key = message.key.clone();
+        let message = match middleware::inbound(middlewares, client, message) {
+            Flow::Continue(message) => message,
+            Flow::Drop => return futures_util::future::ok(()),
+            Flow::Reject(reason) => {
+                router.send(Message::Error {
+                    key,
+                    reason,
+                    client: client.id,
+                });
+                return futures_util::future::ok(());
+            }
+        };
+        if requires_key(&message.message_type) && message.key.is_none() {
+            let reason = "Message is missing a key".to_string();
+            report(error_hook, panic_hook, protocol_error(client, &reason));
+            router.send(Message::Error {
+                key: None,
+                reason,
+                client: client.id,
+            });
+            return futures_util::future::ok(());
+        }
+        // keys of rooms the client is not in don't exist as far as it knows
+        if let (true, Some(key)) = (addresses_data(&message.message_type), &message.key) {
+            if !rooms.is_visible(room_of(store, key).as_deref(), client.id) {
+                send_error(router, client, key.clone(), unknown_key(key));
+                return futures_util::future::ok(());
+            }
+        }
         match message.message_type {
             WSMessageType::Set => {
                 let key = message.key.unwrap();
-                let store_lock = store.lock();
-                let element_entry = store_lock.get(&key).unwrap();
+                // cloned, so the store isn't locked while handlers run
+                let element_entry = match store.get(&key) {
+                    Some(element) => element,
+                    None => {
+                        send_error(router, client, key.clone(), unknown_key(&key));
+                        return futures_util::future::ok(());
+                    }
+                };
                 let element = element_entry.deref();
                 let new_data;
                 {
                     let handle = element.read();
-                    new_data = handle.data.deserialize(message.data.unwrap().as_str());
+                    if handle.access == Access::ReadOnly {
+                        router.send(Message::Error {
+                            key: Some(key.clone()),
+                            reason: format!("Key {} is read-only", key),
+                            client: client.id,
+                        });
+                        return futures_util::future::ok(());
+                    }
+                    // the value in the store is only ever replaced by one of its type
+                    let data = message.data.as_deref().unwrap_or_default();
+                    new_data = match handle.data.try_deserialize(data) {
+                        Ok(data) => data,
+                        Err(reason) => {
+                            let error = PocaError::Serialization {
+                                key: key.clone(),
+                                type_name: handle.type_name,
+                                reason,
+                            };
+                            // the hook may access the key
+                            drop(handle);
+                            send_error(router, client, key, error.to_string());
+                            report(error_hook, panic_hook, error);
+                            return futures_util::future::ok(());
+                        }
+                    };
                 }
-                {
+                let mut new_data = new_data;
+                let mut origin = Origin::Client(client.id);
+                // refused writes are answered once the value is unlocked
+                let result = {
                     let mut handle = element.write();
-                    handle.data = new_data;
-                }
+                    let stale = message.version.is_some_and(|base| base < handle.version);
+                    if stale
+                        && handle.merge_handler.is_none()
+                        && handle.conflict_policy == ConflictPolicy::RejectStale
+                    {
+                        Err(stale_write(&key, handle.version))
+                    } else {
+                        if let (true, Some(merge)) = (stale, &handle.merge_handler) {
+                            new_data = merge(handle.data.deref(), new_data);
+                            // the sender doesn't hold the merged value yet
+                            origin = Origin::Server;
+                        }
+                        if let Some(transformed) = transform(&handle, new_data.as_ref()) {
+                            new_data = transformed;
+                            origin = Origin::Server;
+                        }
+                        validate(&handle, &key, new_data.as_ref()).map(|()| {
+                            let old = std::mem::replace(&mut handle.data, new_data);
+                            handle.remember(old.as_ref());
+                            handle.version += 1;
+                            let stored = payload(handle.data.as_ref());
+                            let sent = message.data.unwrap_or_default();
+                            let data = match origin {
+                                // relayed as the client sent it if that is how it's stored
+                                Origin::Client(_) if sent.as_bytes() == stored => {
+                                    Payload::from(sent)
+                                }
+                                // Unknown fields or numbers in another form. The
+                                // sender doesn't hold the stored value either.
+                                Origin::Client(_) => {
+                                    origin = Origin::Server;
+                                    stored
+                                }
+                                Origin::Server => stored,
+                            };
+                            (old, handle.version, data)
+                        })
+                    }
+                };
+                let (old, version, data) = match result {
+                    Ok(written) => written,
+                    Err(reason) => {
+                        send_error(router, client, key, reason);
+                        return futures_util::future::ok(());
+                    }
+                };
+                router.send(Message::Set {
+                    key,
+                    data,
+                    origin,
+                    version,
+                });
                 //TODO: emit events
-                {
-                    let handle = element.read();
-                    for each in handle.on_change.deref() {
-                        let handler = each.deref();
-                        handler()
+                notify_change(&element_entry, old, Origin::Client(client.id));
+            }
+            WSMessageType::Patch => {
+                let key = message.key.unwrap();
+                let element = match store.get(&key) {
+                    Some(element) => element,
+                    None => {
+                        send_error(router, client, key.clone(), unknown_key(&key));
+                        return futures_util::future::ok(());
+                    }
+                };
+                let result = match serde_json::from_str::<json_patch::Patch>(
+                    message.data.as_deref().unwrap_or_default(),
+                ) {
+                    Ok(ops) => {
+                        let mut handle = element.write();
+                        let stale = message.version.is_some_and(|base| base < handle.version);
+                        if handle.access == Access::ReadOnly {
+                            Err(format!("Key {} is read-only", key))
+                        } else if stale
+                            && handle.merge_handler.is_none()
+                            && handle.conflict_policy == ConflictPolicy::RejectStale
+                        {
+                            Err(stale_write(&key, handle.version))
+                        } else {
+                            let old = handle.data.clone();
+                            let applied = apply_patch(&mut handle, &ops)
+                                .map_err(|error| error.to_string())
+                                .and_then(|()| {
+                                    // merged like a stale write of the patched value
+                                    let merged = match (stale, &handle.merge_handler) {
+                                        (true, Some(merge)) => {
+                                            Some(merge(old.deref(), handle.data.clone()))
+                                        }
+                                        _ => None,
+                                    };
+                                    if let Some(data) = &merged {
+                                        handle.data = data.clone();
+                                    }
+                                    let transformed = transform(&handle, handle.data.as_ref());
+                                    if let Some(data) = &transformed {
+                                        handle.data = data.clone();
+                                    }
+                                    validate(&handle, &key, handle.data.as_ref())
+                                        .map(|()| transformed.or(merged))
+                                });
+                            match applied {
+                                Ok(transformed) => {
+                                    handle.remember(old.as_ref());
+                                    handle.version += 1;
+                                    Ok((old, ops, handle.version, transformed))
+                                }
+                                Err(reason) => {
+                                    handle.data = old;
+                                    Err(reason)
+                                }
+                            }
+                        }
+                    }
+                    Err(error) => Err(error.to_string()),
+                };
+                match result {
+                    Ok((old, ops, version, transformed)) => {
+                        router.send(match transformed {
+                            Some(data) => transformed_set(key, data, version),
+                            None => Message::Patch {
+                                key,
+                                ops,
+                                origin: Origin::Client(client.id),
+                                version,
+                            },
+                        });
+                        notify_change(&element, old, Origin::Client(client.id));
+                    }
+                    Err(reason) => {
+                        router.send(Message::Error {
+                            key: Some(key),
+                            reason,
+                            client: client.id,
+                        });
+                    }
+                }
+            }
+            WSMessageType::Increment => {
+                let key = message.key.unwrap();
+                let element = match store.get(&key) {
+                    Some(element) => element,
+                    None => {
+                        send_error(router, client, key.clone(), unknown_key(&key));
+                        return futures_util::future::ok(());
+                    }
+                };
+                let result = match message.data.as_deref().unwrap_or_default().parse::<i64>() {
+                    Ok(by) => {
+                        let mut handle = element.write();
+                        if handle.access == Access::ReadOnly {
+                            Err(format!("Key {} is read-only", key))
+                        } else {
+                            let old = handle.data.clone();
+                            match handle.data.as_any_ref().downcast_ref::<i64>() {
+                                Some(value) => {
+                                    let mut value: Box<dyn Synchronizable> =
+                                        Box::new(value.wrapping_add(by));
+                                    let transformed = transform(&handle, value.as_ref());
+                                    if let Some(data) = &transformed {
+                                        value = data.clone();
+                                    }
+                                    validate(&handle, &key, value.as_ref()).map(|()| {
+                                        handle.data = value;
+                                        handle.remember(old.as_ref());
+                                        handle.version += 1;
+                                        (old, by, handle.version, transformed)
+                                    })
+                                }
+                                None => Err(format!("Key {} is not a counter", key)),
+                            }
+                        }
+                    }
+                    Err(error) => Err(error.to_string()),
+                };
+                match result {
+                    Ok((old, by, version, transformed)) => {
+                        router.send(match transformed {
+                            Some(data) => transformed_set(key, data, version),
+                            None => Message::Increment {
+                                key,
+                                by,
+                                origin: Origin::Client(client.id),
+                                version,
+                            },
+                        });
+                        notify_change(&element, old, Origin::Client(client.id));
+                    }
+                    Err(reason) => {
+                        router.send(Message::Error {
+                            key: Some(key),
+                            reason,
+                            client: client.id,
+                        });
+                    }
+                }
+            }
+            WSMessageType::TextOps => {
+                let key = message.key.unwrap();
+                let element = match store.get(&key) {
+                    Some(element) => element,
+                    None => {
+                        send_error(router, client, key.clone(), unknown_key(&key));
+                        return futures_util::future::ok(());
+                    }
+                };
+                let result = match serde_json::from_str::<Vec<TextOp>>(
+                    message.data.as_deref().unwrap_or_default(),
+                ) {
+                    Ok(ops) => {
+                        let mut handle = element.write();
+                        if handle.access == Access::ReadOnly {
+                            Err(format!("Key {} is read-only", key))
+                        } else {
+                            let old = handle.data.clone();
+                            let applied = match handle.data.as_any_ref().downcast_ref::<Text>() {
+                                // all or nothing
+                                Some(text) => {
+                                    let mut updated = text.clone();
+                                    ops.iter()
+                                        .try_for_each(|op| updated.apply(op))
+                                        .and_then(|()| {
+                                            let mut updated: Box<dyn Synchronizable> =
+                                                Box::new(updated);
+                                            let transformed = transform(&handle, updated.as_ref());
+                                            if let Some(data) = &transformed {
+                                                updated = data.clone();
+                                            }
+                                            validate(&handle, &key, updated.as_ref())
+                                                .map(|()| (updated, transformed))
+                                        })
+                                        .map(|(updated, transformed)| {
+                                            handle.data = updated;
+                                            (ops, transformed)
+                                        })
+                                }
+                                None => Err(format!("Key {} is not a text", key)),
+                            };
+                            applied.map(|(ops, transformed)| {
+                                handle.remember(old.as_ref());
+                                handle.version += 1;
+                                (old, ops, handle.version, transformed)
+                            })
+                        }
+                    }
+                    Err(error) => Err(error.to_string()),
+                };
+                match result {
+                    Ok((old, ops, version, transformed)) => {
+                        router.send(match transformed {
+                            Some(data) => transformed_set(key, data, version),
+                            None => Message::TextOps {
+                                key,
+                                ops,
+                                origin: Origin::Client(client.id),
+                                version,
+                            },
+                        });
+                        notify_change(&element, old, Origin::Client(client.id));
+                    }
+                    Err(reason) => {
+                        router.send(Message::Error {
+                            key: Some(key),
+                            reason,
+                            client: client.id,
+                        });
+                    }
+                }
+            }
+            WSMessageType::CompareAndSet => {
+                let key = message.key.unwrap();
+                let element = match store.get(&key) {
+                    Some(element) => element,
+                    None => {
+                        send_error(router, client, key.clone(), unknown_key(&key));
+                        return futures_util::future::ok(());
+                    }
+                };
+                let mut invalid = None;
+                let result = match serde_json::from_str::<CompareAndSet>(
+                    message.data.as_deref().unwrap_or_default(),
+                ) {
+                    Ok(CompareAndSet { expected, new }) => {
+                        let mut handle = element.write();
+                        if handle.access == Access::ReadOnly {
+                            Err(format!("Key {} is read-only", key))
+                        } else if !matches_value(handle.data.as_ref(), &expected) {
+                            Err(format!("Value of key {} does not match", key))
+                        } else {
+                            let data = handle
+                                .data
+                                .try_deserialize(&new.to_string())
+                                .map_err(|reason| {
+                                    let error = PocaError::Serialization {
+                                        key: key.clone(),
+                                        type_name: handle.type_name,
+                                        reason,
+                                    };
+                                    let reason = error.to_string();
+                                    invalid = Some(error);
+                                    reason
+                                })
+                                .and_then(|data| {
+                                    let data = transform(&handle, data.as_ref()).unwrap_or(data);
+                                    validate(&handle, &key, data.as_ref()).map(|()| data)
+                                });
+                            match data {
+                                Ok(data) => {
+                                    let old = std::mem::replace(&mut handle.data, data);
+                                    handle.remember(old.as_ref());
+                                    handle.version += 1;
+                                    Ok((old, payload(handle.data.as_ref()), handle.version))
+                                }
+                                Err(reason) => Err(reason),
+                            }
+                        }
+                    }
+                    Err(error) => Err(error.to_string()),
+                };
+                if let Some(error) = invalid {
+                    report(error_hook, panic_hook, error);
+                }
+                match result {
+                    Ok((old, data, version)) => {
+                        // sent back to the client as well, to confirm the write
+                        router.send(Message::Set {
+                            key,
+                            data,
+                            origin: Origin::Server,
+                            version,
+                        });
+                        notify_change(&element, old, Origin::Client(client.id));
+                    }
+                    Err(reason) => {
+                        router.send(Message::Error {
+                            key: Some(key),
+                            reason,
+                            client: client.id,
+                        });
                     }
                 }
             }
             WSMessageType::Get => {
                 let key = message.key.unwrap();
                 let data;
+                let version;
                 {
-                    let store_lock = store.lock();
-                    let element_entry = store_lock
-                        .get(&key)
-                        .expect(format!("Element with key {} cannot be found", key).as_str());
+                    let store_lock = store.shard(&key);
+                    let element_entry = match store_lock.get(&key) {
+                        Some(element) => element,
+                        None => {
+                            send_error(router, client, key.clone(), unknown_key(&key));
+                            return futures_util::future::ok(());
+                        }
+                    };
                     let element = element_entry.deref();
                     let handle = element.read();
                     data = handle.data.serialize();
+                    version = handle.version;
                 }
-                broadcast_sender
-                    .send(Message::Get {
-                        key,
-                        data: Box::new(data),
-                    })
-                    .ok();
+                router.send(Message::Get {
+                    key,
+                    data: payload(&data),
+                    client: client.id,
+                    version,
+                });
             }
             WSMessageType::Emit => {
                 let key = message.key.unwrap();
                 let lock = event_handler_store.read();
-                let handlers = lock
-                    .get(&key)
-                    .expect(format!("Event handler with key {} cannot be found", key).as_str());
+                let handlers = match lock.get(&key) {
+                    Some(handlers) => handlers,
+                    None => {
+                        let reason = format!("Event {} does not exist", key);
+                        send_error(router, client, key, reason);
+                        return futures_util::future::ok(());
+                    }
+                };
                 for handler in handlers {
-                    handler();
+                    let mut result = Ok(());
+                    catch_panic(panic_hook, Some(&key), || {
+                        result = handler(client, message.data.as_deref())
+                    });
+                    if let Err(error) = result {
+                        // the other handlers may accept the payload
+                        let reason = format!("Invalid payload for event {}: {}", key, error);
+                        send_error(router, client, key.clone(), reason);
+                    }
+                }
+            }
+            WSMessageType::Request => {
+                let method = message.key.unwrap();
+                let id = match message.id {
+                    Some(id) => id,
+                    None => {
+                        let reason = format!("Request for {} has no id", method);
+                        send_error(router, client, method, reason);
+                        return futures_util::future::ok(());
+                    }
+                };
+                let handler = rpc_handlers.read().get(&method).cloned();
+                match handler {
+                    Some(handler) => {
+                        let sender = router.clone();
+                        let client = client.clone();
+                        let request = message.data;
+                        tokio::spawn(async move {
+                            let client_id = client.id;
+                            let result = handler(client, request).await;
+                            sender.send(Message::Response {
+                                id,
+                                result,
+                                client: client_id,
+                            });
+                        });
+                    }
+                    None => {
+                        router.send(Message::Response {
+                            id,
+                            result: Err(format!("Method {} does not exist", method)),
+                            client: client.id,
+                        });
+                    }
+                }
+            }
+            // answers to `Poca::call`
+            WSMessageType::Response | WSMessageType::Error if message.id.is_some() => {
+                let data = message.data.unwrap_or_else(|| "null".to_string());
+                let result = match message.message_type {
+                    WSMessageType::Response => Ok(data),
+                    _ => Err(data),
+                };
+                pending_calls.resolve(client.id, message.id.unwrap(), result);
+            }
+            WSMessageType::Keys => {
+                // like the snapshot, without the rooms the client isn't in
+                let keys = store
+                    .elements()
+                    .into_iter()
+                    .filter_map(|(key, element)| {
+                        let guard = element.read();
+                        rooms
+                            .is_visible(guard.room.as_deref(), client.id)
+                            .then(|| (key, guard.type_name.into()))
+                    })
+                    .collect();
+                router.send(Message::Keys {
+                    keys,
+                    client: client.id,
+                });
+            }
+            WSMessageType::Schema => {
+                router.send(Message::Schema {
+                    schema: codegen::schema(&codegen::schemas(store, |room| {
+                        rooms.is_visible(room, client.id)
+                    })),
+                    client: client.id,
+                });
+            }
+            WSMessageType::Ack => {
+                if let Some(seq) = message.seq {
+                    acks.acknowledge(client.id, seq);
                 }
             }
-            _ => {
-                todo!("handle other message types")
+            WSMessageType::Subscribe => {
+                subscriptions.lock().subscribe(message.key.unwrap());
+            }
+            WSMessageType::Unsubscribe => {
+                subscriptions.lock().unsubscribe(message.key.unwrap());
+            }
+            // only sent by the server
+            message_type => {
+                let reason = format!("Unsupported message type {:?}", message_type);
+                report(error_hook, panic_hook, protocol_error(client, &reason));
+                router.send(Message::Error {
+                    key: message.key,
+                    reason,
+                    client: client.id,
+                });
             }
         }
         futures_util::future::ok(())
     });
 
-    pin_mut!(broadcast_dealer, ws_dealer);
+    let idle = async {
+        let idle_timeout = match idle_timeout {
+            Some(idle_timeout) => *idle_timeout,
+            None => return futures_util::future::pending().await,
+        };
+        loop {
+            let deadline = *activity.lock() + idle_timeout;
+            if deadline <= Instant::now() {
+                break;
+            }
+            sleep_until(deadline).await;
+        }
+    };
+
+    pin_mut!(queue_dealer, ws_dealer);
     //TODO: future::select on the dealers
     tokio::select! {
-        _ = broadcast_dealer => {},
-        _ = ws_dealer => {},
+        result = &mut queue_dealer => {
+            match result {
+                Err(_) => report(error_hook, panic_hook, PocaError::ChannelClosed(client.id)),
+                // the close frame went out, the client may never answer it
+                Ok(()) => {
+                    timeout(CLOSE_TIMEOUT, ws_dealer).await.ok();
+                }
+            }
+        },
+        // most likely a half-open connection, so the close frame may never arrive
+        _ = dead.notified() => {
+            warn!("Client stopped answering pings");
+            let message = Message::Close {
+                code: GOING_AWAY,
+                reason: "Heartbeat timeout".to_string(),
+            };
+            connections.send(client.id, message);
+            timeout(CLOSE_TIMEOUT, async {
+                if queue_dealer.await.is_ok() {
+                    ws_dealer.await.ok();
+                }
+            })
+            .await
+            .ok();
+        },
+        _ = idle => {
+            let message = Message::Close {
+                code: GOING_AWAY,
+                reason: "Idle timeout".to_string(),
+            };
+            connections.send(client.id, message);
+            timeout(CLOSE_TIMEOUT, async {
+                if queue_dealer.await.is_ok() {
+                    ws_dealer.await.ok();
+                }
+            })
+            .await
+            .ok();
+        },
+        result = &mut ws_dealer => {
+            // Oversized or malformed frames. The close frame goes out after
+            // whatever is queued, unless the socket itself is broken.
+            if let Err(error) = result {
+                if is_io_error(&error) {
+                    report(error_hook, panic_hook, PocaError::ChannelClosed(client.id));
+                } else {
+                    report(error_hook, panic_hook, protocol_error(client, &error.to_string()));
+                    let message = Message::Close {
+                        code: PROTOCOL_ERROR,
+                        reason: "Protocol error".to_string(),
+                    };
+                    connections.send(client.id, message);
+                    timeout(CLOSE_TIMEOUT, queue_dealer).await.ok();
+                }
+            }
+        },
     }
 }
+
+// Ends right after the close frame, nothing can be sent after it. The
+// transport would fail as if the connection broke down.
+fn until_closed<E>(
+    messages: impl Stream<Item = Result<ws::Message, E>>,
+) -> impl Stream<Item = Result<ws::Message, E>> {
+    let mut messages = Box::pin(messages);
+    let mut closed = false;
+    futures_util::stream::poll_fn(move |cx| {
+        if closed {
+            return Poll::Ready(None);
+        }
+        let message = ready!(messages.as_mut().poll_next(cx));
+        closed = matches!(&message, Some(Ok(message)) if message.is_close());
+        Poll::Ready(message)
+    })
+}
+
+fn is_io_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if error.is::<std::io::Error>() {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+// pings sent to the client, see `PocaConfig::max_missed_pongs`
+#[derive(Default)]
+struct Heartbeat {
+    // of the ping that wasn't answered yet
+    sent: Option<Instant>,
+    // in a row
+    missed: u32,
+}
+
+#[derive(Deserialize)]
+struct CompareAndSet {
+    expected: serde_json::Value,
+    new: serde_json::Value,
+}
+
+// Compared in the form the type serializes to, so that a float sent as 1
+// matches 1.0 and the order of map entries doesn't matter.
+fn matches_value(data: &dyn Synchronizable, expected: &serde_json::Value) -> bool {
+    let value = |data: &dyn Synchronizable| {
+        serde_json::from_str::<serde_json::Value>(&data.serialize()).ok()
+    };
+    match data.try_deserialize(&expected.to_string()) {
+        Ok(expected) => value(expected.as_ref()) == value(data),
+        Err(_) => false,
+    }
+}
+
+// close frames and batches are handled by the queue dealer itself
+fn ws_message(message: Message) -> Option<WSMessage> {
+    let message = match message {
+        Message::Set {
+            key, data, version, ..
+        } => WSMessage {
+            message_type: WSMessageType::Set,
+            key: Some(key),
+            data: Some(text(&data)),
+            version: Some(version),
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::MergePatch {
+            key,
+            fields,
+            version,
+            ..
+        } => WSMessage {
+            message_type: WSMessageType::MergePatch,
+            key: Some(key),
+            data: Some(serde_json::Value::Object(fields).to_string()),
+            version: Some(version),
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Patch {
+            key, ops, version, ..
+        } => WSMessage {
+            message_type: WSMessageType::Patch,
+            key: Some(key),
+            data: Some(serde_json::to_string(&ops).unwrap()),
+            version: Some(version),
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Increment {
+            key, by, version, ..
+        } => WSMessage {
+            message_type: WSMessageType::Increment,
+            key: Some(key),
+            data: Some(by.to_string()),
+            version: Some(version),
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::TextOps {
+            key, ops, version, ..
+        } => WSMessage {
+            message_type: WSMessageType::TextOps,
+            key: Some(key),
+            data: Some(serde_json::to_string(&ops).unwrap()),
+            version: Some(version),
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Register { key, data } => WSMessage {
+            message_type: WSMessageType::Set,
+            key: Some(key),
+            data: Some(text(&data)),
+            version: Some(0),
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Get {
+            key, data, version, ..
+        } => WSMessage {
+            message_type: WSMessageType::Get,
+            key: Some(key),
+            data: Some(text(&data)),
+            version: Some(version),
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Remove { key } => WSMessage {
+            message_type: WSMessageType::Remove,
+            key: Some(key),
+            data: None,
+            version: None,
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Keys { keys, .. } => WSMessage {
+            message_type: WSMessageType::Keys,
+            key: None,
+            data: Some(serde_json::Value::Object(keys).to_string()),
+            version: None,
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Schema { schema, .. } => WSMessage {
+            message_type: WSMessageType::Schema,
+            key: None,
+            data: Some(schema.to_string()),
+            version: None,
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Snapshot { values } => WSMessage {
+            message_type: WSMessageType::Snapshot,
+            key: None,
+            data: Some(serde_json::Value::Object(values).to_string()),
+            version: None,
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Event { name, payload } => WSMessage {
+            message_type: WSMessageType::Event,
+            key: Some(name),
+            data: Some(payload),
+            version: None,
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Request {
+            id,
+            method,
+            payload,
+            ..
+        } => WSMessage {
+            message_type: WSMessageType::Request,
+            key: Some(method),
+            data: Some(payload),
+            version: None,
+            id: Some(id),
+            seq: None,
+            ack: false,
+        },
+        Message::Response { id, result, .. } => {
+            let (message_type, data) = match result {
+                Ok(payload) => (WSMessageType::Response, payload),
+                Err(reason) => (WSMessageType::Error, reason),
+            };
+            WSMessage {
+                message_type,
+                key: None,
+                data: Some(data),
+                version: None,
+                id: Some(id),
+                seq: None,
+                ack: false,
+            }
+        }
+        Message::Error { key, reason, .. } => WSMessage {
+            message_type: WSMessageType::Error,
+            key,
+            data: Some(reason),
+            version: None,
+            id: None,
+            seq: None,
+            ack: false,
+        },
+        Message::Close { .. } | Message::Batch(_) => return None,
+    };
+    Some(message)
+}
+
+fn send_error(router: &Router, client: &ClientInfo, key: String, reason: String) {
+    router.send(Message::Error {
+        key: Some(key),
+        reason,
+        client: client.id,
+    });
+}
+
+fn unknown_key(key: &str) -> String {
+    format!("Key {} does not exist", key)
+}
+
+// replaces a change of a client that the transforms of the key changed further
+fn transformed_set(key: String, data: Box<dyn Synchronizable>, version: u64) -> Message {
+    Message::Set {
+        key,
+        data: payload(data.as_ref()),
+        origin: Origin::Server,
+        version,
+    }
+}
+
+// to the hook of `Poca::on_error`, if there is one
+pub(crate) fn report(hook: &ErrorHook, panic_hook: &PanicHook, error: PocaError) {
+    if let Some(hook) = hook.read().deref() {
+        catch_panic(panic_hook, None, || hook(&error));
+    }
+}
+
+fn protocol_error(client: &ClientInfo, reason: &str) -> PocaError {
+    PocaError::Protocol {
+        client: client.id,
+        reason: reason.to_string(),
+    }
+}
+
+fn stale_write(key: &str, version: u64) -> String {
+    format!("Stale write to key {}, current version is {}", key, version)
+}
+
+// values of all keys in rooms the filter accepts
+pub fn snapshot(
+    store: &Store,
+    filter: impl Fn(Option<&str>) -> bool,
+) -> serde_json::Map<String, serde_json::Value> {
+    store
+        .elements()
+        .into_iter()
+        .filter_map(|(key, element)| {
+            let guard = element.read();
+            filter(guard.room.as_deref()).then(|| {
+                let data = guard.data.serialize();
+                (key, serde_json::from_str(&data).unwrap())
+            })
+        })
+        .collect()
+}
+
+fn requires_ack(store: &Store, key: &str) -> bool {
+    let element = store.get(key);
+    element.is_some_and(|element| element.read().require_ack)
+}
+
+fn room_of(store: &Store, key: &str) -> Option<String> {
+    let element = store.get(key)?;
+    let room = element.read().room.clone();
+    room
+}
+
+// message types that are meaningless without a key
+fn requires_key(message_type: &WSMessageType) -> bool {
+    addresses_data(message_type)
+        || matches!(
+            message_type,
+            WSMessageType::Subscribe
+                | WSMessageType::Unsubscribe
+                | WSMessageType::Emit
+                | WSMessageType::Request
+        )
+}
+
+// message types whose key names a value rather than an event or method
+fn addresses_data(message_type: &WSMessageType) -> bool {
+    matches!(
+        message_type,
+        WSMessageType::Set
+            | WSMessageType::Get
+            | WSMessageType::Patch
+            | WSMessageType::Increment
+            | WSMessageType::TextOps
+            | WSMessageType::CompareAndSet
+    )
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use poca::{Poca, PocaError};
+use tokio::{sync::mpsc, time::timeout};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+mod common;
+
+use common::next_reply;
+
+#[tokio::test]
+async fn retransmitting_unacknowledged_changes() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .ack_timeout(Duration::from_millis(100))
+        .ack_retries(2)
+        .build();
+    let counter = poca.data("counter", 0);
+    counter.set_require_ack(true);
+    let (sender, mut failed) = mpsc::unbounded_channel();
+    poca.on_unacknowledged(move |_, key| sender.send(key.to_string()).unwrap());
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+    let client = poca.clients()[0].id;
+
+    counter.set(1);
+    let change = next_reply(&mut socket).await;
+    assert_eq!(change["key"], "counter");
+    assert_eq!(change["ack"], true);
+    assert_eq!(poca.unacknowledged("counter"), vec![client]);
+    // with the same sequence number, once for every retry
+    for _ in 0..2 {
+        let retransmitted = next_reply(&mut socket).await;
+        assert_eq!(retransmitted["data"], "1");
+        assert_eq!(retransmitted["seq"], change["seq"]);
+        assert_eq!(retransmitted["ack"], true);
+    }
+    let key = timeout(Duration::from_secs(1), failed.recv())
+        .await
+        .unwrap();
+    assert_eq!(key.as_deref(), Some("counter"));
+    assert!(poca.unacknowledged("counter").is_empty());
+    poca.stop();
+}
+
+#[tokio::test]
+async fn acknowledging_changes() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .ack_timeout(Duration::from_millis(100))
+        .build();
+    let counter = poca.data("counter", 0);
+    counter.set_require_ack(true);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+
+    counter.set(1);
+    next_reply(&mut socket).await;
+    // covers every sequence number, up to the last one there is
+    let ack = serde_json::json!({"message_type": 19, "key": null, "data": null, "seq": u64::MAX});
+    socket.send(Message::Text(ack.to_string())).await.unwrap();
+    for _ in 0..100 {
+        if poca.unacknowledged("counter").is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(poca.unacknowledged("counter").is_empty());
+    // nothing is sent again
+    let retransmitted = timeout(Duration::from_millis(300), next_reply(&mut socket)).await;
+    assert!(retransmitted.is_err());
+    poca.stop();
+}
+
+#[test]
+fn refusing_a_zero_ack_timeout() {
+    let built = Poca::builder().ack_timeout(Duration::ZERO).try_build();
+    assert!(matches!(built, Err(PocaError::Config(_))));
+}
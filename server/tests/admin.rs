@@ -0,0 +1,66 @@
+use futures_util::{SinkExt, StreamExt};
+use poca::Poca;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+async fn command(admin: &mut WebSocketStream<MaybeTlsStream<TcpStream>>, command: Value) -> Value {
+    admin
+        .send(Message::Text(command.to_string()))
+        .await
+        .unwrap();
+    match admin.next().await.unwrap().unwrap() {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        other => panic!("Unexpected frame {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn operating_over_the_admin_channel() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .admin_token("secret")
+        .build();
+    poca.data("count", 3u32);
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    assert!(connect_async(format!("ws://{}/admin", address))
+        .await
+        .is_err());
+    assert!(connect_async(format!("ws://{}/admin?token=guess", address))
+        .await
+        .is_err());
+    assert!(connect_async(format!("ws://{}/admin?token=secreT", address))
+        .await
+        .is_err());
+    let (mut client, _) = connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    let (mut admin, _) = connect_async(format!("ws://{}/admin?token=secret", address))
+        .await
+        .unwrap();
+    let clients = command(&mut admin, json!({ "command": "list_clients" })).await;
+    let id = clients["clients"][0]["id"].clone();
+    assert_eq!(clients["clients"].as_array().unwrap().len(), 1);
+    let dump = command(&mut admin, json!({ "command": "dump_store" })).await;
+    assert_eq!(dump["values"]["count"], json!(3));
+    let verbose = command(
+        &mut admin,
+        json!({ "command": "verbose", "enabled": false }),
+    )
+    .await;
+    assert_eq!(verbose, json!({ "verbose": false }));
+    assert!(command(&mut admin, json!({ "command": "reboot" })).await["error"].is_string());
+    let kicked = command(&mut admin, json!({ "command": "kick", "client": id })).await;
+    assert_eq!(kicked, json!({ "kicked": true }));
+
+    loop {
+        match client.next().await {
+            Some(Ok(Message::Close(frame))) => {
+                assert_eq!(frame.unwrap().reason, "Kicked by an administrator");
+                break;
+            }
+            Some(Ok(_)) => continue,
+            other => panic!("Expected a close frame, got {:?}", other),
+        }
+    }
+}
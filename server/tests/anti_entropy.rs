@@ -0,0 +1,11 @@
+use std::time::Duration;
+
+use poca::{Poca, PocaError};
+
+#[test]
+fn refusing_a_zero_anti_entropy_interval() {
+    let built = Poca::builder()
+        .anti_entropy_interval(Duration::ZERO)
+        .try_build();
+    assert!(matches!(built, Err(PocaError::Config(_))));
+}
@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use poca::{include_app_dir, Poca};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn async_on_change_handler() {
+    let poca = Poca::new(
+        "localhost:1123",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let handle = poca.data("async", 0);
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    handle.on_change_async(move |value| {
+        let sender = sender.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            sender.send(value).unwrap();
+        }
+    });
+
+    handle.set(1);
+    handle.set(2);
+    let mut received = vec![
+        receiver.recv().await.unwrap(),
+        receiver.recv().await.unwrap(),
+    ];
+    received.sort();
+    assert_eq!(received, vec![1, 2]);
+}
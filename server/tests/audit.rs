@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use poca::{AuditEntry, Poca};
+use serde_json::json;
+
+#[test]
+fn auditing_changes() {
+    let poca = Poca::builder().build();
+    let score = poca.counter("score", 1);
+    let entries = Arc::new(Mutex::new(Vec::<AuditEntry>::new()));
+    let recorded = entries.clone();
+    poca.audit(move |entry: &AuditEntry| recorded.lock().push(entry.clone()));
+
+    score.increment(2);
+    let name = poca.data("name", "poca".to_string());
+    name.set("unroll".to_string());
+    poca.remove("name").unwrap();
+
+    let entries = entries.lock();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].key, "score");
+    assert_eq!(entries[0].new_value, Some(json!(3)));
+    assert_eq!(entries[0].client, None);
+    // of "poca" and of "unroll"
+    let (first, second) = (entries[1].old_hash.clone(), entries[2].old_hash.clone());
+    assert!(first.is_some() && second.is_some() && first != second);
+    assert_eq!(entries[1].new_value, Some(json!("unroll")));
+    assert_eq!(entries[2].new_value, None);
+}
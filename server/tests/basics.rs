@@ -2,21 +2,39 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod common;
+
 mod tests {
-    use std::sync::{Arc, Mutex};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
 
-    use poca::{include_app_dir, DataHandle, Poca};
+    use futures_util::StreamExt;
+    use poca::{
+        include_app_dir, Access, ConflictPolicy, DataHandle, IndexError, KeyError, Origin,
+        PatchError, Poca, Synchronizable, TypeError,
+    };
     use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use tokio::time::timeout;
+    use tokio_tungstenite::connect_async;
+
+    use crate::common::next_reply;
 
-    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Synchronizable)]
     struct TestStruct {
         test_field: String,
         test_bool: bool,
     }
 
     lazy_static! {
-        static ref POCA: Poca =
-            Poca::new("localhost:1120", include_app_dir!("tests/empty_assets/"));
+        static ref POCA: Poca = Poca::new(
+            "localhost:1120",
+            include_app_dir!("tests/empty_assets/"),
+            None
+        );
         static ref HANDLE1: DataHandle<i32> = POCA.data("test1", 1);
         static ref HANDLE2: DataHandle<String> = POCA.data("test2", "test2".to_string());
         static ref HANDLE3: DataHandle<TestStruct> = POCA.data(
@@ -32,18 +50,18 @@ mod tests {
     #[test]
     fn setting_and_getting() {
         HANDLE1.set(1);
-        assert_eq!(*HANDLE1.get(), 1);
+        assert_eq!(HANDLE1.get(), 1);
 
         HANDLE1.set(2);
-        assert_eq!(*HANDLE1.get(), 2);
+        assert_eq!(HANDLE1.get(), 2);
 
-        assert_eq!(*HANDLE2.get(), "test2".to_string());
+        assert_eq!(HANDLE2.get(), "test2".to_string());
 
         HANDLE2.set("test3".to_string());
-        assert_eq!(*HANDLE2.get(), "test3".to_string());
+        assert_eq!(HANDLE2.get(), "test3".to_string());
 
         assert_eq!(
-            *HANDLE3.get(),
+            HANDLE3.get(),
             TestStruct {
                 test_field: "test_field".to_string(),
                 test_bool: true
@@ -55,7 +73,7 @@ mod tests {
             test_bool: false,
         });
         assert_eq!(
-            *HANDLE3.get(),
+            HANDLE3.get(),
             TestStruct {
                 test_field: "test_field2".to_string(),
                 test_bool: false
@@ -63,6 +81,218 @@ mod tests {
         );
     }
 
+    #[test]
+    fn updating_in_place() {
+        let handle = POCA.data("test6", vec![1]);
+        handle.update(|value| value.push(2));
+        assert_eq!(handle.get(), vec![1, 2]);
+    }
+
+    #[test]
+    fn duplicate_key() {
+        let _handle = POCA.data("test7", 7);
+        assert_eq!(
+            POCA.try_data("test7", 8).err(),
+            Some(KeyError::AlreadyExists("test7".to_string()))
+        );
+    }
+
+    #[test]
+    fn access_control() {
+        let handle = POCA.data("test8", 8);
+        assert_eq!(handle.get_access(), Access::ClientWritable);
+        handle.set_access(Access::ReadOnly);
+        assert_eq!(handle.get_access(), Access::ReadOnly);
+        handle.set(9);
+        assert_eq!(handle.get(), 9);
+    }
+
+    #[test]
+    fn json_patch() {
+        let handle = POCA.data(
+            "test9",
+            TestStruct {
+                test_field: "test_field".to_string(),
+                test_bool: true,
+            },
+        );
+        let ops =
+            serde_json::from_str(r#"[{ "op": "replace", "path": "/test_bool", "value": false }]"#)
+                .unwrap();
+        handle.patch(ops).unwrap();
+        assert!(!handle.get().test_bool);
+
+        let ops = serde_json::from_str(
+            r#"[{ "op": "replace", "path": "/test_bool", "value": "not a bool" }]"#,
+        )
+        .unwrap();
+        assert!(matches!(handle.patch(ops), Err(PatchError::Type(_))));
+        assert!(!handle.get().test_bool);
+    }
+
+    #[test]
+    fn counter_operations() {
+        let counter = POCA.counter("test_counter", 10);
+        assert_eq!(counter.increment(5), 15);
+        assert_eq!(counter.decrement(20), -5);
+        assert_eq!(counter.get(), -5);
+    }
+
+    #[test]
+    fn text_operations() {
+        let text = POCA.text("test_text", "hello");
+        text.push_str(" world");
+        text.insert(0, ">");
+        text.delete(1, 1);
+        assert_eq!(text.value(), ">ello world");
+    }
+
+    #[test]
+    fn versions_and_conflict_policy() {
+        let handle = POCA.data("test_versions", 0);
+        assert_eq!(handle.version(), 0);
+        handle.set(1);
+        handle.update(|value| *value += 1);
+        assert_eq!(handle.version(), 2);
+
+        assert_eq!(handle.get_conflict_policy(), ConflictPolicy::LastWriteWins);
+        handle.set_conflict_policy(ConflictPolicy::RejectStale);
+        assert_eq!(handle.get_conflict_policy(), ConflictPolicy::RejectStale);
+        handle.on_conflict(|current, incoming| (*current).max(incoming));
+    }
+
+    #[test]
+    fn compare_and_set() {
+        let handle = POCA.data("test_cas", 1);
+        assert_eq!(handle.compare_and_set(&1, 2), Ok(()));
+        assert_eq!(handle.compare_and_set(&1, 3), Err(2));
+        assert_eq!(handle.get(), 2);
+    }
+
+    #[test]
+    fn transaction() {
+        let first = POCA.data("test_txn_a", 1);
+        let second = POCA.data("test_txn_b", "one".to_string());
+        POCA.transaction(|txn| {
+            txn.set("test_txn_a", 2)
+                .set("test_txn_b", "two".to_string());
+        })
+        .unwrap();
+        assert_eq!(first.get(), 2);
+        assert_eq!(second.get(), "two");
+
+        let result = POCA.transaction(|txn| {
+            txn.set("test_txn_a", 3).set("test_txn_b", 3);
+        });
+        assert_eq!(
+            result,
+            Err(KeyError::TypeMismatch("test_txn_b".to_string()))
+        );
+        assert_eq!(first.get(), 2);
+
+        let result = POCA.transaction(|txn| {
+            txn.set("test_txn_missing", 0);
+        });
+        assert_eq!(
+            result,
+            Err(KeyError::NotFound("test_txn_missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn removing_keys() {
+        let handle = POCA.data("test_remove", 1);
+        assert!(POCA.contains("test_remove"));
+        handle.clone().delete().unwrap();
+        assert!(!POCA.contains("test_remove"));
+        assert_eq!(
+            handle.delete(),
+            Err(KeyError::NotFound("test_remove".to_string()))
+        );
+
+        POCA.data("test_remove", 2);
+        assert_eq!(POCA.remove("test_remove"), Ok(()));
+        assert!(POCA.try_data("test_remove", 3).is_ok());
+    }
+
+    #[test]
+    fn key_reflection() {
+        POCA.data("test_reflection", 1u8);
+        assert!(POCA.keys().contains(&"test_reflection".to_string()));
+        assert_eq!(POCA.type_name("test_reflection"), Some("u8"));
+        assert_eq!(POCA.type_name("test_reflection_missing"), None);
+    }
+
+    #[test]
+    fn handle_by_key() {
+        let original = POCA.data("test_handle_by_key", 1);
+        let handle = POCA.handle::<i32>("test_handle_by_key").unwrap();
+        handle.set(2);
+        assert_eq!(original.get(), 2);
+
+        assert!(matches!(
+            POCA.handle::<String>("test_handle_by_key"),
+            Err(TypeError::Mismatch { .. })
+        ));
+        assert!(matches!(
+            POCA.handle::<i32>("test_handle_by_key_missing"),
+            Err(TypeError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn list_operations() {
+        let list = POCA.list("test_list", vec![1, 2, 3]);
+        list.push(4);
+        list.insert(0, 0).unwrap();
+        assert_eq!(list.get(), vec![0, 1, 2, 3, 4]);
+
+        assert_eq!(list.remove(1), Some(1));
+        list.swap(0, 3).unwrap();
+        list.set_at(1, 7).unwrap();
+        assert_eq!(list.get(), vec![4, 7, 3, 0]);
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.get_at(2), Some(3));
+    }
+
+    #[test]
+    fn list_operations_out_of_bounds() {
+        let list = POCA.list("test_list_bounds", vec![1, 2]);
+        let version = list.version();
+        assert_eq!(list.insert(3, 0), Err(IndexError { index: 3, len: 2 }));
+        assert_eq!(list.remove(2), None);
+        assert_eq!(list.swap(0, 5), Err(IndexError { index: 5, len: 2 }));
+        assert_eq!(list.set_at(2, 0), Err(IndexError { index: 2, len: 2 }));
+        assert_eq!(list.get(), vec![1, 2]);
+        // nothing was broadcast
+        assert_eq!(list.version(), version);
+
+        list.insert(2, 3).unwrap();
+        assert_eq!(list.get(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn map_operations() {
+        let map = POCA.map("test_map", HashMap::new());
+        assert_eq!(map.insert("a".to_string(), 1), None);
+        assert_eq!(map.insert("a/b".to_string(), 2), None);
+        assert_eq!(map.insert("a".to_string(), 3), Some(1));
+        map.entry("c".to_string(), || 0, |value| *value += 5);
+        map.entry("c".to_string(), || 0, |value| *value += 5);
+
+        assert_eq!(map.get_entry(&"a".to_string()), Some(3));
+        assert_eq!(map.get_entry(&"c".to_string()), Some(10));
+        assert_eq!(map.remove(&"a/b".to_string()), Some(2));
+        assert_eq!(map.remove(&"missing".to_string()), None);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn read_guard() {
+        let guard = HANDLE4.read();
+        assert_eq!(guard.len(), 3);
+    }
+
     #[test]
     fn on_change_handler() {
         let watcher = Arc::new(Mutex::new(false));
@@ -77,6 +307,128 @@ mod tests {
         assert_eq!(*(watcher.lock().unwrap()), true);
     }
 
+    #[test]
+    fn on_change_handler_with_old_value() {
+        let handle = POCA.data("test_on_change_old", 1);
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let changes_clone = changes.clone();
+        handle.on_change_with_old(move |old, new, origin| {
+            changes_clone.lock().unwrap().push((*old, *new, origin));
+        });
+
+        handle.set(2);
+        handle.update(|value| *value *= 5);
+        assert_eq!(
+            *changes.lock().unwrap(),
+            vec![(1, 2, Origin::Server), (2, 10, Origin::Server)]
+        );
+    }
+
+    #[test]
+    fn removing_on_change_handlers() {
+        let handle = POCA.data("test_remove_handler", 0);
+        let calls = Arc::new(Mutex::new(0));
+        let calls_clone = calls.clone();
+        let id = handle.on_change(move |_| *calls_clone.lock().unwrap() += 1);
+        let calls_clone = calls.clone();
+        let guard = handle.guard(handle.on_change(move |_| *calls_clone.lock().unwrap() += 10));
+
+        handle.set(1);
+        assert_eq!(*calls.lock().unwrap(), 11);
+
+        drop(guard);
+        handle.set(2);
+        assert_eq!(*calls.lock().unwrap(), 12);
+
+        assert!(handle.remove_on_change(id));
+        assert!(!handle.remove_on_change(id));
+        handle.set(3);
+        assert_eq!(*calls.lock().unwrap(), 12);
+    }
+
+    #[tokio::test]
+    async fn on_change_handler_setting_own_key() {
+        let poca = Poca::builder().address("localhost:0").build();
+        let handle = poca.data("test_reentrant", 0);
+        poca.start().await.unwrap();
+        let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+        let (mut socket, _) = connect_async(&url).await.unwrap();
+        socket.next().await.unwrap().unwrap();
+        let inner_handle = handle.clone();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_clone = seen.clone();
+        handle.on_change(move |value| {
+            seen_clone.lock().unwrap().push(value);
+            if value < 3 {
+                inner_handle.set(value + 1);
+                // applied right away, handlers run once this one returns
+                assert_eq!(inner_handle.get(), value + 1);
+                assert_eq!(seen_clone.lock().unwrap().last(), Some(&value));
+            }
+        });
+
+        handle.set(1);
+        assert_eq!(handle.get(), 3);
+        assert_eq!(*seen.lock().unwrap(), vec![1, 2, 3]);
+
+        // the outer change is broadcast before the ones of the handler
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            let message = timeout(Duration::from_secs(5), next_reply(&mut socket))
+                .await
+                .unwrap();
+            if message["key"] == "test_reentrant" {
+                received.push((message["data"].clone(), message["version"].clone()));
+            }
+        }
+        assert_eq!(
+            received,
+            vec![
+                (json!("1"), json!(1)),
+                (json!("2"), json!(2)),
+                (json!("3"), json!(3))
+            ]
+        );
+        poca.stop();
+    }
+
+    #[test]
+    fn on_any_change_handler() {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let changes_clone = changes.clone();
+        let id = POCA.on_any_change(move |key, value, origin| {
+            if key.starts_with("test_any_change") {
+                changes_clone
+                    .lock()
+                    .unwrap()
+                    .push((key.to_string(), value.serialize(), origin));
+            }
+        });
+        let first = POCA.data("test_any_change_a", 1);
+        let second = POCA.data("test_any_change_b", "b".to_string());
+        first.set(2);
+        second.set("c".to_string());
+        assert_eq!(
+            *changes.lock().unwrap(),
+            vec![
+                (
+                    "test_any_change_a".to_string(),
+                    "2".to_string(),
+                    Origin::Server
+                ),
+                (
+                    "test_any_change_b".to_string(),
+                    r#""c""#.to_string(),
+                    Origin::Server
+                ),
+            ]
+        );
+
+        assert!(POCA.remove_on_any_change(id));
+        first.set(3);
+        assert_eq!(changes.lock().unwrap().len(), 2);
+    }
+
     #[test]
     fn on_change_handler_with_inner_set() {
         let handle5 = POCA.data("test5", true);
@@ -85,4 +437,15 @@ mod tests {
         });
         HANDLE4.set(vec![4, 5, 6]);
     }
+
+    #[test]
+    fn required_acknowledgement() {
+        let handle = POCA.data("test_ack", 0);
+        assert!(!handle.get_require_ack());
+        handle.set_require_ack(true);
+        assert!(handle.get_require_ack());
+        handle.set(1);
+        // nobody is connected, so nobody is waiting
+        assert!(POCA.unacknowledged("test_ack").is_empty());
+    }
 }
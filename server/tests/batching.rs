@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use poca::Poca;
+use serde_json::Value;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[tokio::test]
+async fn coalescing_changes() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .flush_interval(Duration::from_millis(50))
+        .build();
+    let slider = poca.data("slider", 0u32);
+    let note = poca.data("note", String::new());
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    let (mut socket, _) = connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    // the snapshot
+    socket.next().await.unwrap().unwrap();
+    for value in 1..=3 {
+        slider.set(value);
+    }
+    note.set("moved".to_string());
+
+    let frame = match socket.next().await.unwrap().unwrap() {
+        Message::Text(text) => serde_json::from_str::<Value>(&text).unwrap(),
+        other => panic!("Unexpected frame {:?}", other),
+    };
+    assert_eq!(frame["message_type"], 13);
+    let batch: Vec<Value> = serde_json::from_str(frame["data"].as_str().unwrap()).unwrap();
+    let changes = batch
+        .iter()
+        .map(|message| {
+            (
+                message["key"].as_str().unwrap(),
+                message["data"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(changes, vec![("slider", "3"), ("note", "\"moved\"")]);
+}
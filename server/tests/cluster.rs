@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use poca::{MemoryCluster, Poca};
+
+#[tokio::test]
+async fn changes_are_shared_within_the_cluster() {
+    let cluster = MemoryCluster::new();
+    let first = Poca::builder()
+        .address("localhost:0")
+        .cluster(cluster.clone())
+        .build();
+    let second = Poca::builder().address("localhost:0").build();
+    second.join_cluster(cluster);
+    first.start().await.unwrap();
+    second.start().await.unwrap();
+
+    let hits = (first.counter("hits", 0), second.counter("hits", 0));
+    let names = (
+        first.list("names", Vec::<String>::new()),
+        second.list("names", Vec::<String>::new()),
+    );
+    hits.0.increment(2);
+    names.1.push("poca".to_string());
+    hits.1.increment(3);
+    for _ in 0..100 {
+        if hits.0.get() == 5 && hits.1.get() == 5 && names.0.get().len() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // applied changes aren't published again
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!((hits.0.get(), hits.1.get()), (5, 5));
+    assert_eq!(names.0.get(), vec!["poca".to_string()]);
+}
+
+#[tokio::test]
+async fn concurrent_sets_converge() {
+    let cluster = MemoryCluster::new();
+    let nodes = [(); 2].map(|_| {
+        Poca::builder()
+            .address("localhost:0")
+            .cluster(cluster.clone())
+            .build()
+    });
+    for node in &nodes {
+        node.start().await.unwrap();
+    }
+    let scores = nodes.each_ref().map(|node| node.data("score", 0));
+
+    for round in 0..10 {
+        // before either of them received the other change
+        scores[0].set(round * 2 + 1);
+        scores[1].set(round * 2 + 2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(scores[0].get(), scores[1].get());
+        assert_eq!(scores[0].version(), scores[1].version());
+    }
+    for node in &nodes {
+        node.stop();
+    }
+}
+
+#[tokio::test]
+async fn registering_keys_keeps_shared_values() {
+    let cluster = MemoryCluster::new();
+    let first = Poca::builder()
+        .address("localhost:0")
+        .cluster(cluster.clone())
+        .build();
+    let second = Poca::builder()
+        .address("localhost:0")
+        .cluster(cluster)
+        .build();
+    first.start().await.unwrap();
+    second.start().await.unwrap();
+
+    let score = first.data("score", 0);
+    score.set(5);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    // registered with another value later, which isn't published
+    let other = second.data("score", 1);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(score.get(), 5);
+    assert_eq!(other.get(), 1);
+    first.stop();
+    second.stop();
+}
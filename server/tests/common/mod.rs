@@ -0,0 +1,18 @@
+use futures_util::StreamExt;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+// the next message that isn't a snapshot or a change of the connected clients
+pub async fn next_reply<S>(socket: &mut S) -> Value
+where
+    S: StreamExt<Item = Result<Message, Error>> + Unpin,
+{
+    loop {
+        if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+            let message: Value = serde_json::from_str(&text).unwrap();
+            if message["message_type"] != 7 && message["key"] != "$clients" {
+                return message;
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::HashMap, time::Duration};
+
+use futures_util::SinkExt;
+use poca::{ConflictPolicy, Poca};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+mod common;
+
+use common::next_reply;
+
+#[tokio::test]
+async fn rejecting_stale_writes() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1u32);
+    counter.set_conflict_policy(ConflictPolicy::RejectStale);
+    counter.set(2);
+    let version = counter.version();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    let stale = serde_json::json!({
+        "message_type": 1, "key": "counter", "data": "5", "version": version - 1
+    });
+    socket.send(Message::Text(stale.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 4);
+    assert_eq!(
+        reply["data"],
+        format!("Stale write to key counter, current version is {}", version)
+    );
+    assert_eq!(counter.get(), 2);
+
+    // based on the current version
+    let set = serde_json::json!({
+        "message_type": 1, "key": "counter", "data": "5", "version": version
+    });
+    socket.send(Message::Text(set.to_string())).await.unwrap();
+    for _ in 0..100 {
+        if counter.get() == 5 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(counter.get(), 5);
+    assert_eq!(counter.version(), version + 1);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn merging_stale_writes() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1u32);
+    // takes precedence over the policy
+    counter.set_conflict_policy(ConflictPolicy::RejectStale);
+    counter.on_conflict(|current, incoming| current + incoming);
+    counter.set(2);
+    let version = counter.version();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    let stale = serde_json::json!({
+        "message_type": 1, "key": "counter", "data": "5", "version": version - 1
+    });
+    socket.send(Message::Text(stale.to_string())).await.unwrap();
+    // the sender gets the merged value as well
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 1);
+    assert_eq!(reply["key"], "counter");
+    assert_eq!(reply["data"], "7");
+    assert_eq!(reply["version"], version + 1);
+    assert_eq!(counter.get(), 7);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn merging_stale_patches() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1u32);
+    counter.set_conflict_policy(ConflictPolicy::RejectStale);
+    counter.on_conflict(|current, incoming| current + incoming);
+    counter.set(2);
+    let version = counter.version();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    // merged with the patched value, like a stale write of it
+    let stale = serde_json::json!({
+        "message_type": 9,
+        "key": "counter",
+        "data": r#"[{"op":"replace","path":"","value":5}]"#,
+        "version": version - 1
+    });
+    socket.send(Message::Text(stale.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 1);
+    assert_eq!(reply["data"], "7");
+    assert_eq!(reply["version"], version + 1);
+    assert_eq!(counter.get(), 7);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn comparing_values_in_their_serialized_form() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let ratio = poca.data("ratio", 1.0f64);
+    let scores = poca.data(
+        "scores",
+        HashMap::from([("a".to_string(), 1u32), ("b".to_string(), 2)]),
+    );
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    // as JavaScript serializes 1.0
+    let cas = serde_json::json!({
+        "message_type": 12, "key": "ratio", "data": r#"{"expected":1,"new":0.5}"#
+    });
+    socket.send(Message::Text(cas.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 1);
+    assert_eq!(ratio.get(), 0.5);
+
+    let cas = serde_json::json!({
+        "message_type": 12, "key": "scores", "data": r#"{"expected":{"b":2,"a":1},"new":{}}"#
+    });
+    socket.send(Message::Text(cas.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 1);
+    assert!(scores.get().is_empty());
+
+    let cas = serde_json::json!({
+        "message_type": 12, "key": "ratio", "data": r#"{"expected":"0.5","new":1}"#
+    });
+    socket.send(Message::Text(cas.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 4);
+    assert_eq!(reply["data"], "Value of key ratio does not match");
+    assert_eq!(ratio.get(), 0.5);
+    poca.stop();
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use poca::{Poca, IDENTITY_METADATA};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{http::StatusCode, Error, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+// skips everything before the close frame
+async fn close_reason(socket: &mut WebSocketStream<MaybeTlsStream<TcpStream>>) -> String {
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Close(frame))) => return frame.unwrap().reason.to_string(),
+            Some(Ok(_)) => continue,
+            other => panic!("Expected a close frame, got {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn denying_addresses() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+    let url = format!("ws://{}/ws", address);
+    let (mut socket, _) = connect_async(&url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+
+    // the client connects from the loopback address as well
+    poca.deny_address(address.ip());
+    assert_eq!(close_reason(&mut socket).await, "Access denied");
+    match connect_async(&url).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), StatusCode::FORBIDDEN),
+        other => panic!("Expected the upgrade to be refused, got {:?}", other),
+    }
+
+    poca.allow_address(address.ip());
+    assert!(connect_async(&url).await.is_ok());
+    poca.stop();
+}
+
+#[tokio::test]
+async fn denying_identities() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut denied, _) = connect_async(&url).await.unwrap();
+    denied.next().await.unwrap().unwrap();
+    let (mut other, _) = connect_async(&url).await.unwrap();
+    other.next().await.unwrap().unwrap();
+    let denied_id = poca.clients()[0].id;
+    poca.set_client_metadata(denied_id, IDENTITY_METADATA, "mallory");
+
+    poca.deny_identity("mallory");
+    assert_eq!(close_reason(&mut denied).await, "Access denied");
+    // without an answer to the close frame, it is dropped after a second
+    for _ in 0..200 {
+        if poca.clients().len() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let remaining = poca.clients();
+    assert_eq!(remaining.len(), 1);
+    assert_ne!(remaining[0].id, denied_id);
+
+    // connected clients are only checked when an identity is denied
+    poca.allow_identity("mallory");
+    poca.deny_identity("someone else");
+    assert_eq!(poca.clients().len(), 1);
+    drop(other);
+    poca.stop();
+}
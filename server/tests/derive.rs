@@ -0,0 +1,7 @@
+#[test]
+fn derive_synchronizable() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/derive/plain.rs");
+    cases.pass("tests/derive/generic.rs");
+    cases.pass("tests/derive/crate_path.rs");
+}
@@ -0,0 +1,18 @@
+// for crates re-exporting poca under another name
+mod reexported {
+    pub use poca::*;
+}
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, reexported::Synchronizable)]
+#[synchronizable(crate = "crate::reexported")]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+fn main() {
+    let poca = reexported::Poca::builder().build();
+    poca.data("point", Point { x: 1, y: 2 });
+}
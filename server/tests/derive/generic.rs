@@ -0,0 +1,15 @@
+use poca::Synchronizable;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Synchronizable)]
+struct Tagged<T> {
+    tag: String,
+    value: T,
+}
+
+fn assert_synchronizable<T: Synchronizable>() {}
+
+fn main() {
+    assert_synchronizable::<Tagged<u32>>();
+    assert_synchronizable::<Tagged<Vec<String>>>();
+}
@@ -0,0 +1,20 @@
+use poca::{Poca, Synchronizable};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Synchronizable)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Synchronizable)]
+enum Shape {
+    Dot(Point),
+    Line(Point, Point),
+}
+
+fn main() {
+    let poca = Poca::builder().build();
+    poca.data("point", Point { x: 1, y: 2 });
+    poca.data("shape", Shape::Dot(Point { x: 0, y: 0 }));
+}
@@ -0,0 +1,53 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::SinkExt;
+use parking_lot::Mutex;
+use poca::Poca;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[tokio::test]
+async fn reporting_errors_of_connections() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.data("count", 0u32);
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let reported = errors.clone();
+    poca.on_error(move |error| reported.lock().push(error.to_string()));
+    poca.set_authenticator(|request: &poca::AuthRequest, _: &poca::ClientInfo| {
+        match request.query_param("token") {
+            Some("secret") => Ok(()),
+            _ => Err("Invalid token".to_string()),
+        }
+    });
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    assert!(connect_async(format!("ws://{}/ws", address)).await.is_err());
+    let (mut socket, _) = connect_async(format!("ws://{}/ws?token=secret", address))
+        .await
+        .unwrap();
+    // the connection is closed after the last one
+    for message in [
+        r#"{"message_type":1,"key":"count","data":"-1"}"#,
+        r#"{"message_type":7,"key":null,"data":null}"#,
+        "not json",
+    ] {
+        socket
+            .send(Message::Text(message.to_string()))
+            .await
+            .unwrap();
+    }
+    for _ in 0..100 {
+        if errors.lock().len() == 4 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let errors = errors.lock().clone();
+    assert_eq!(errors.len(), 4);
+    assert!(errors[0].starts_with("Authentication of "));
+    assert!(errors[0].ends_with(": Invalid token"));
+    assert!(errors[1].starts_with("Invalid value for key count of type u32: "));
+    assert!(errors[2].ends_with("Unsupported message type Snapshot"));
+    assert!(errors[3].starts_with("Protocol error of client "));
+}
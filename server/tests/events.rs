@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use futures_util::{SinkExt, StreamExt};
+use poca::Poca;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+mod common;
+
+use common::next_reply;
+
+#[tokio::test]
+async fn emitting_events() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    // the snapshot, sent once the client is connected
+    socket.next().await.unwrap().unwrap();
+    let client = poca.clients()[0].id;
+
+    poca.emit("toast", "hello").unwrap();
+    let event = next_reply(&mut socket).await;
+    assert_eq!(event["message_type"], 16);
+    assert_eq!(event["key"], "toast");
+    assert_eq!(event["data"], r#""hello""#);
+
+    assert!(poca.send_to(client, "toast", 42).unwrap());
+    let event = next_reply(&mut socket).await;
+    assert_eq!(event["data"], "42");
+    poca.stop();
+}
+
+#[tokio::test]
+async fn sending_to_clients_as_they_connect() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let greeter = poca.clone();
+    let (sender, mut sent) = mpsc::unbounded_channel();
+    poca.on_connect(move |client| {
+        let sent = greeter.send_to(client.id, "welcome", "hello").unwrap();
+        sender.send(sent).unwrap();
+    });
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    assert!(sent.recv().await.unwrap());
+    let event = next_reply(&mut socket).await;
+    assert_eq!(event["key"], "welcome");
+    assert_eq!(event["data"], r#""hello""#);
+    poca.stop();
+}
+
+#[test]
+fn unserializable_payloads() {
+    let poca = Poca::builder().build();
+    // JSON only has string keys
+    let payload = HashMap::from([((1, 2), "point")]);
+    assert!(poca.emit("points", &payload).is_err());
+    let client = serde_json::from_str("42").unwrap();
+    assert!(poca.send_to(client, "points", &payload).is_err());
+    assert!(poca.broadcast_except(client, "points", &payload).is_err());
+    assert!(poca.emit_to_room("lobby", "points", &payload).is_err());
+}
+
+#[tokio::test]
+async fn handling_events_of_clients() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let (sender, mut received) = mpsc::unbounded_channel();
+    let numbers = sender.clone();
+    poca.on_event("guess", move |_, guess: u32| {
+        numbers.send(guess.to_string()).unwrap();
+    });
+    poca.on_event("guess", move |_, guess: String| {
+        sender.send(guess).unwrap();
+    });
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    let emit = serde_json::json!({"message_type": 2, "key": "guess", "data": "5"});
+    socket.send(Message::Text(emit.to_string())).await.unwrap();
+    assert_eq!(received.recv().await.unwrap(), "5");
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 4);
+    assert_eq!(reply["key"], "guess");
+
+    // the second handler still gets a payload the first one rejects
+    let emit = serde_json::json!({"message_type": 2, "key": "guess", "data": r#""five""#});
+    socket.send(Message::Text(emit.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 4);
+    assert!(reply["data"]
+        .as_str()
+        .unwrap()
+        .starts_with("Invalid payload for event guess"));
+    assert_eq!(received.recv().await.unwrap(), "five");
+
+    for (key, reason) in [
+        (Value::from("unknown"), "Event unknown does not exist"),
+        (Value::Null, "Message is missing a key"),
+    ] {
+        let emit = serde_json::json!({"message_type": 2, "key": key, "data": null});
+        socket.send(Message::Text(emit.to_string())).await.unwrap();
+        let reply = next_reply(&mut socket).await;
+        assert_eq!(reply["message_type"], 4);
+        assert_eq!(reply["data"], reason);
+    }
+    poca.stop();
+}
+
+#[tokio::test]
+async fn broadcasting_to_other_clients() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut sender, _) = connect_async(&url).await.unwrap();
+    sender.next().await.unwrap().unwrap();
+    let sender_id = poca.clients()[0].id;
+    let (mut other, _) = connect_async(&url).await.unwrap();
+    other.next().await.unwrap().unwrap();
+
+    poca.broadcast_except(sender_id, "joined", "someone")
+        .unwrap();
+    poca.send_to(sender_id, "welcome", ()).unwrap();
+    let event = next_reply(&mut other).await;
+    assert_eq!(event["key"], "joined");
+    // queued after the broadcast, had the sender got it
+    let event = next_reply(&mut sender).await;
+    assert_eq!(event["key"], "welcome");
+    poca.stop();
+}
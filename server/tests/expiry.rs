@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use poca::{include_app_dir, Poca};
+
+#[tokio::test]
+async fn keys_expire_after_ttl() {
+    let poca = Poca::new(
+        "localhost:1122",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    poca.start().await.unwrap();
+
+    poca.data_with_ttl("short", 1, Duration::from_millis(20));
+    poca.data_with_ttl("long", 2, Duration::from_secs(60));
+    poca.data("forever", 3);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(!poca.contains("short"));
+    assert!(poca.contains("long"));
+    assert!(poca.contains("forever"));
+    poca.stop();
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "federation")]
+
+use std::time::Duration;
+
+use poca::Poca;
+
+async fn until(condition: impl Fn() -> bool) {
+    for _ in 0..200 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn mirroring_keys_with_a_peer() {
+    let central = Poca::builder().address("localhost:0").build();
+    let central_hits = central.counter("hits", 10);
+    let central_notes = central.data("notes", "central".to_string());
+    central.start().await.unwrap();
+
+    let edge = Poca::builder().address("localhost:0").build();
+    let edge_hits = edge.counter("hits", 0);
+    let edge_notes = edge.data("notes", "edge".to_string());
+    edge.add_peer(
+        format!("ws://{}/ws", central.local_addr().unwrap()),
+        &["hits"],
+    );
+    edge.start().await.unwrap();
+
+    // the peer's value when connecting
+    until(|| edge_hits.get() == 10).await;
+    assert_eq!(edge_hits.get(), 10);
+    edge_hits.increment(2);
+    central_hits.increment(3);
+    until(|| central_hits.get() == 15 && edge_hits.get() == 15).await;
+    // mirrored changes aren't sent back
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!((central_hits.get(), edge_hits.get()), (15, 15));
+
+    edge_notes.set("changed".to_string());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(central_notes.get(), "central");
+}
@@ -9,4 +9,12 @@ mod tests {
         let route = routes.get_route(&["layer1", "layer2", "layer3-1"], true);
         println!("{:?}", route);
     }
+
+    #[test]
+    fn missing_route() {
+        let routes = include_app_dir!("tests/routes_test/");
+        assert!(routes.get_route(&["layer1-1"], true).is_some());
+        assert!(routes.get_route(&["layer1", "missing"], true).is_none());
+        assert!(routes.get_route(&["missing"], true).is_none());
+    }
 }
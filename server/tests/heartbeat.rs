@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use poca::{Poca, PocaError};
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{protocol::frame::coding::CloseCode, Message},
+};
+
+#[tokio::test]
+async fn closing_connections_that_miss_pongs() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .ping_interval(Duration::from_millis(50))
+        .max_missed_pongs(2)
+        .build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+
+    // pongs are only sent while the client reads
+    sleep(Duration::from_millis(500)).await;
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Close(frame))) => {
+                let frame = frame.unwrap();
+                assert_eq!(frame.code, CloseCode::Away);
+                assert_eq!(frame.reason, "Heartbeat timeout");
+                break;
+            }
+            Some(Ok(_)) => continue,
+            other => panic!("Expected a close frame, got {:?}", other),
+        }
+    }
+    poca.stop();
+}
+
+#[tokio::test]
+async fn keeping_connections_that_answer_pings() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .ping_interval(Duration::from_millis(50))
+        .max_missed_pongs(2)
+        .build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    let mut pings = 0;
+    let reading = async {
+        while let Some(Ok(message)) = socket.next().await {
+            assert!(!message.is_close());
+            if message.is_ping() {
+                pings += 1;
+            }
+        }
+    };
+    assert!(timeout(Duration::from_millis(500), reading).await.is_err());
+    assert!(pings >= 4);
+    assert_eq!(poca.clients().len(), 1);
+    poca.stop();
+}
+
+#[test]
+fn refusing_zero_missed_pongs() {
+    let built = Poca::builder().max_missed_pongs(0).try_build();
+    assert!(matches!(built, Err(PocaError::Config(_))));
+}
+
+#[test]
+fn refusing_a_zero_ping_interval() {
+    let built = Poca::builder().ping_interval(Duration::ZERO).try_build();
+    assert!(matches!(built, Err(PocaError::Config(_))));
+}
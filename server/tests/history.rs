@@ -0,0 +1,57 @@
+use std::{sync::Arc, thread};
+
+use parking_lot::Mutex;
+use poca::Poca;
+
+#[test]
+fn undoing_changes() {
+    let poca = Poca::builder().build();
+    let title = poca.data("title", "first".to_string());
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let recorded = seen.clone();
+    title.on_change(move |value| recorded.lock().push(value));
+
+    title.set("second".to_string());
+    title.set("third".to_string());
+    assert_eq!(title.history(5), vec!["second", "first"]);
+    assert_eq!(title.history(1), vec!["second"]);
+
+    assert!(title.undo());
+    assert_eq!(title.get(), "second");
+    assert!(title.undo());
+    assert_eq!(title.get(), "first");
+    assert!(!title.undo());
+    assert_eq!(title.version(), 4);
+    assert_eq!(*seen.lock(), vec!["second", "third", "second", "first"]);
+
+    title.set_history_limit(1);
+    title.set("fourth".to_string());
+    title.set("fifth".to_string());
+    assert_eq!(title.history(5), vec!["fourth"]);
+}
+
+#[test]
+fn recording_concurrent_changes_in_order() {
+    let poca = Poca::builder().build();
+    let counter = poca.data("counter", 0u32);
+    counter.set_history_limit(1000);
+    let writers = (0..4)
+        .map(|_| {
+            let counter = counter.clone();
+            thread::spawn(move || {
+                for _ in 0..200 {
+                    counter.update(|value| *value += 1);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    // every value replaced the one before it
+    let history = counter.history(1000);
+    assert_eq!(history, (0..800).rev().collect::<Vec<_>>());
+    assert!(counter.undo());
+    assert_eq!(counter.get(), 799);
+}
@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use poca::Poca;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{protocol::frame::coding::CloseCode, Message},
+};
+
+#[tokio::test]
+async fn closing_idle_connections() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .idle_timeout(Duration::from_millis(300))
+        // which don't keep the connection from being idle
+        .ping_interval(Duration::from_millis(50))
+        .build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let connected = Instant::now();
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    loop {
+        match timeout(Duration::from_secs(2), socket.next()).await {
+            Ok(Some(Ok(Message::Close(frame)))) => {
+                let frame = frame.unwrap();
+                assert_eq!(frame.code, CloseCode::Away);
+                assert_eq!(frame.reason, "Idle timeout");
+                break;
+            }
+            Ok(Some(Ok(_))) => continue,
+            other => panic!("Expected a close frame, got {:?}", other),
+        }
+    }
+    assert!(connected.elapsed() >= Duration::from_millis(300));
+    poca.stop();
+}
+
+#[tokio::test]
+async fn keeping_active_connections() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .idle_timeout(Duration::from_millis(300))
+        .build();
+    let counter = poca.data("counter", 0);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    let reading = async {
+        while let Some(Ok(message)) = socket.next().await {
+            assert!(!message.is_close());
+        }
+    };
+    let changing = async {
+        for value in 1..=8 {
+            sleep(Duration::from_millis(100)).await;
+            counter.set(value);
+        }
+    };
+    tokio::select! {
+        _ = reading => panic!("The connection was closed"),
+        _ = changing => {}
+    }
+    assert_eq!(poca.clients().len(), 1);
+    poca.stop();
+}
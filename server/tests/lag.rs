@@ -0,0 +1,132 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use poca::{DataHandle, LagPolicy, Poca};
+use serde_json::Value;
+use tokio::{net::TcpStream, time::timeout};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{protocol::frame::coding::CloseCode, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const CHANGES: u32 = 20;
+
+// a server whose clients can queue 4 messages, with a client that read its
+// snapshot and the handles of the keys it subscribed to
+async fn lagging_client(lag_policy: LagPolicy) -> (Poca, Socket, Vec<DataHandle<u32>>) {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .channel_size(4)
+        .lag_policy(lag_policy)
+        .build();
+    let handles = (0..CHANGES)
+        .map(|index| poca.data(&format!("key{:02}", index), 0))
+        .collect();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+    (poca, socket, handles)
+}
+
+// without awaiting in between, so all of them are routed before the client
+// takes any from its queue
+fn change_all(handles: &[DataHandle<u32>]) {
+    for handle in handles {
+        handle.set(1);
+    }
+}
+
+// the messages the client gets until it is idle or disconnected
+async fn received(socket: &mut Socket) -> Vec<Message> {
+    let mut messages = Vec::new();
+    while let Ok(Some(Ok(message))) = timeout(Duration::from_millis(300), socket.next()).await {
+        messages.push(message);
+    }
+    messages
+}
+
+fn decoded(messages: &[Message]) -> Vec<Value> {
+    messages
+        .iter()
+        .filter_map(|message| match message {
+            Message::Text(text) => serde_json::from_str(text).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn resyncing_lagging_clients() {
+    let (poca, mut socket, handles) = lagging_client(LagPolicy::Resync).await;
+    let missed = Arc::new(AtomicU64::new(0));
+    let reported = missed.clone();
+    poca.on_lag(move |_, skipped| {
+        reported.fetch_add(skipped, Ordering::Relaxed);
+    });
+    change_all(&handles);
+
+    let messages = received(&mut socket).await;
+    let snapshot = decoded(&messages)
+        .into_iter()
+        .find(|message| message["message_type"] == 7)
+        .expect("a snapshot after falling behind");
+    // with the values of all changes, also the ones it missed
+    let values = serde_json::from_str::<Value>(snapshot["data"].as_str().unwrap()).unwrap();
+    for index in 0..CHANGES {
+        assert_eq!(values[format!("key{:02}", index)], 1);
+    }
+    assert!(missed.load(Ordering::Relaxed) >= (CHANGES - 4) as u64);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn disconnecting_lagging_clients() {
+    let (poca, mut socket, handles) = lagging_client(LagPolicy::Disconnect).await;
+    change_all(&handles);
+
+    let messages = received(&mut socket).await;
+    assert!(decoded(&messages)
+        .iter()
+        .all(|message| message["message_type"] != 7));
+    match messages.last() {
+        Some(Message::Close(Some(frame))) => {
+            assert_eq!(frame.code, CloseCode::Policy);
+            assert_eq!(frame.reason, "Client fell behind");
+        }
+        other => panic!("Expected a close frame, got {:?}", other),
+    }
+    poca.stop();
+}
+
+#[tokio::test]
+async fn growing_queues_of_lagging_clients() {
+    let (poca, mut socket, handles) = lagging_client(LagPolicy::Grow).await;
+    let lagged = Arc::new(AtomicU64::new(0));
+    let reported = lagged.clone();
+    poca.on_lag(move |_, _| {
+        reported.fetch_add(1, Ordering::Relaxed);
+    });
+    change_all(&handles);
+
+    let messages = received(&mut socket).await;
+    let messages = decoded(&messages);
+    // besides changes of the connected clients
+    let changes = messages
+        .iter()
+        .filter(|message| message["message_type"] == 1 && message["key"] != "$clients")
+        .count();
+    assert_eq!(changes, CHANGES as usize);
+    assert!(messages.iter().all(|message| message["message_type"] != 7));
+    assert_eq!(lagged.load(Ordering::Relaxed), 0);
+    poca.stop();
+}
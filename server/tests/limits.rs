@@ -0,0 +1,76 @@
+use futures_util::{SinkExt, StreamExt};
+use poca::{Poca, PocaBuilder};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{protocol::frame::coding::CloseCode, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(builder: PocaBuilder) -> (Poca, Socket) {
+    let poca = builder.address("localhost:0").build();
+    poca.data("note", String::new());
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+    (poca, socket)
+}
+
+// skips everything before the close frame
+async fn close_frame(socket: &mut Socket) -> (CloseCode, String) {
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Close(Some(frame)))) => return (frame.code, frame.reason.to_string()),
+            Some(Ok(_)) => continue,
+            other => panic!("Expected a close frame, got {:?}", other),
+        }
+    }
+}
+
+fn note_of_size(size: usize) -> String {
+    let data = serde_json::to_string(&"x".repeat(size)).unwrap();
+    serde_json::json!({"message_type": 1, "key": "note", "data": data}).to_string()
+}
+
+#[tokio::test]
+async fn closing_on_oversized_messages() {
+    let (poca, mut socket) = connect(Poca::builder().max_message_size(256)).await;
+    // fits
+    socket.send(Message::Text(note_of_size(64))).await.unwrap();
+    socket.send(Message::Text(note_of_size(512))).await.unwrap();
+    assert_eq!(
+        close_frame(&mut socket).await,
+        (CloseCode::Protocol, "Protocol error".to_string())
+    );
+    assert_eq!(poca.handle::<String>("note").unwrap().get().len(), 64);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn closing_on_oversized_frames() {
+    let (poca, mut socket) = connect(Poca::builder().max_frame_size(256)).await;
+    socket.send(Message::Text(note_of_size(512))).await.unwrap();
+    assert_eq!(
+        close_frame(&mut socket).await,
+        (CloseCode::Protocol, "Protocol error".to_string())
+    );
+    assert_eq!(poca.handle::<String>("note").unwrap().get(), "");
+    poca.stop();
+}
+
+#[tokio::test]
+async fn closing_on_malformed_messages() {
+    let (poca, mut socket) = connect(Poca::builder()).await;
+    socket
+        .send(Message::Text("not json".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(
+        close_frame(&mut socket).await,
+        (CloseCode::Protocol, "Malformed message".to_string())
+    );
+    poca.stop();
+}
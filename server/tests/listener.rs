@@ -0,0 +1,56 @@
+#![cfg(unix)]
+
+use std::{net::SocketAddr, os::unix::io::RawFd, time::Duration};
+
+use poca::Poca;
+use socket2::SockRef;
+
+// of the socket the server accepted the connection of `client` with, which is
+// open in the process of the test as well
+fn accepted(client: SocketAddr) -> RawFd {
+    (0..1024)
+        .find(|fd| {
+            let socket = SockRef::from(fd);
+            socket
+                .peer_addr()
+                .ok()
+                .and_then(|address| address.as_socket())
+                == Some(client)
+        })
+        .expect("the accepted socket")
+}
+
+#[tokio::test]
+async fn socket_options() {
+    let poca = Poca::builder()
+        .address("127.0.0.1:0")
+        .nodelay(false)
+        .tcp_keepalive(Duration::from_secs(30))
+        .backlog(16)
+        .build();
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    let (socket, _) = tokio::task::spawn_blocking(move || {
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        tungstenite::client(format!("ws://{}/ws", address), stream).unwrap()
+    })
+    .await
+    .unwrap();
+    while poca.clients().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // the remote address is known for connections of own listeners as well
+    let client = poca.clients().remove(0);
+    assert_eq!(
+        client.address.map(|address| address.ip()),
+        Some(address.ip())
+    );
+
+    let fd = accepted(socket.get_ref().local_addr().unwrap());
+    let accepted = SockRef::from(&fd);
+    assert!(!accepted.nodelay().unwrap());
+    assert!(accepted.keepalive().unwrap());
+    assert_eq!(accepted.keepalive_time().unwrap(), Duration::from_secs(30));
+    poca.stop();
+}
@@ -0,0 +1,49 @@
+use std::net::SocketAddr;
+
+use futures_util::StreamExt;
+use poca::{include_app_dir, Poca};
+use serde_json::Value;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[tokio::test]
+async fn binding_to_port_zero() {
+    let poca = Poca::new("localhost:0", include_app_dir!("tests/empty_assets/"), None);
+    assert_eq!(poca.local_addr(), None);
+    poca.start().await.unwrap();
+
+    let address = poca.local_addr().unwrap();
+    assert_ne!(address.port(), 0);
+    assert!(tokio::net::TcpStream::connect(address).await.is_ok());
+
+    poca.stop();
+    assert_eq!(poca.local_addr(), None);
+}
+
+#[tokio::test]
+async fn binding_to_several_addresses() {
+    let addresses = [
+        SocketAddr::from(([127, 0, 0, 1], 0)),
+        SocketAddr::from(([0, 0, 0, 0], 0)),
+    ];
+    let poca = Poca::builder().address(&addresses[..]).build();
+    let counter = poca.data("counter", 7);
+    poca.start().await.unwrap();
+
+    let local_addrs = poca.local_addrs();
+    assert_eq!(local_addrs.len(), 2);
+    assert_eq!(poca.local_addr(), Some(local_addrs[0]));
+    // every address got a port of its own
+    assert_ne!(local_addrs[0].port(), local_addrs[1].port());
+    for address in local_addrs {
+        assert_ne!(address.port(), 0);
+        let url = format!("ws://127.0.0.1:{}/ws", address.port());
+        let (mut socket, _) = connect_async(url).await.unwrap();
+        let snapshot = match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str::<Value>(&text).unwrap(),
+            other => panic!("Expected the snapshot, got {:?}", other),
+        };
+        let values = serde_json::from_str::<Value>(snapshot["data"].as_str().unwrap()).unwrap();
+        assert_eq!(values["counter"], counter.get());
+    }
+    poca.stop();
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use poca::Poca;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{http::StatusCode, Error},
+};
+
+#[tokio::test]
+async fn refusing_connections_over_the_limit() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .max_connections(1)
+        .build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut first, _) = connect_async(&url).await.unwrap();
+    first.next().await.unwrap().unwrap();
+
+    match connect_async(&url).await {
+        Err(Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE)
+        }
+        other => panic!("Expected the upgrade to be refused, got {:?}", other),
+    }
+
+    // the slot is free again once the first client is gone
+    first.close(None).await.unwrap();
+    let mut reconnected = false;
+    for _ in 0..100 {
+        if connect_async(&url).await.is_ok() {
+            reconnected = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(reconnected);
+    poca.stop();
+}
@@ -0,0 +1,35 @@
+use poca::Poca;
+use warp::http::StatusCode;
+
+#[tokio::test]
+async fn serving_metrics() {
+    let poca = Poca::builder().metrics(true).build();
+    let score = poca.counter("score", 0);
+    score.increment(1);
+    score.increment(2);
+    poca.data("name", "poca".to_string());
+    let filter = poca.filter().unwrap();
+
+    let response = warp::test::request().path("/metrics").reply(&filter).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = String::from_utf8(response.body().to_vec()).unwrap();
+    assert!(body.contains("# TYPE poca_connected_clients gauge\npoca_connected_clients 0\n"));
+    assert!(body.contains("poca_writes_total{key=\"score\"} 2\n"));
+    // `$clients` counts as well
+    assert!(body.contains("poca_store_keys 3\n"));
+    assert_eq!(body, poca.metrics());
+    poca.stop();
+}
+
+#[tokio::test]
+async fn metrics_are_off_by_default() {
+    let poca = Poca::builder().build();
+    let score = poca.counter("score", 0);
+    score.increment(1);
+    let filter = poca.filter().unwrap();
+    let response = warp::test::request().path("/metrics").reply(&filter).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    // nor are writes counted
+    assert!(!poca.metrics().contains("poca_writes_total{"));
+    poca.stop();
+}
@@ -0,0 +1,118 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures_util::SinkExt;
+use poca::{ClientInfo, Flow, Middleware, Poca, WSMessage, WSMessageType};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+mod common;
+
+use common::next_reply;
+
+struct Locked {
+    inbound: Arc<AtomicUsize>,
+}
+
+impl Middleware for Locked {
+    fn inbound(&self, _client: &ClientInfo, message: WSMessage) -> Flow {
+        self.inbound.fetch_add(1, Ordering::SeqCst);
+        match message.key.as_deref() {
+            Some("locked") => Flow::Reject("Key locked is locked".to_string()),
+            _ => Flow::Continue(message),
+        }
+    }
+
+    fn outbound(&self, _client: &ClientInfo, mut message: WSMessage) -> Flow {
+        if message.message_type == WSMessageType::Set {
+            message.data = message.data.map(|data| data.replace("secret", "*"));
+        }
+        Flow::Continue(message)
+    }
+}
+
+#[tokio::test]
+async fn inbound_and_outbound_middleware() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let locked = poca.data("locked", 1);
+    let note = poca.data("note", String::new());
+    let inbound = Arc::new(AtomicUsize::new(0));
+    poca.add_middleware(Locked {
+        inbound: inbound.clone(),
+    });
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    let subscribe = r#"{"message_type":5,"key":"note","data":null}"#;
+    socket
+        .send(Message::Text(subscribe.to_string()))
+        .await
+        .unwrap();
+    let set = r#"{"message_type":1,"key":"locked","data":"2"}"#;
+    socket.send(Message::Text(set.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 4);
+    assert_eq!(reply["key"], "locked");
+    assert_eq!(reply["data"], "Key locked is locked");
+    assert_eq!(locked.get(), 1);
+    assert_eq!(inbound.load(Ordering::SeqCst), 2);
+
+    note.set("a secret note".to_string());
+    let reply = tokio::time::timeout(Duration::from_secs(5), next_reply(&mut socket))
+        .await
+        .unwrap();
+    assert_eq!(reply["message_type"], 1);
+    assert_eq!(reply["data"], r#""a * note""#);
+    poca.stop();
+}
+
+// drops changes of one key and rejects changes of another
+struct Hiding;
+
+impl Middleware for Hiding {
+    fn outbound(&self, _client: &ClientInfo, message: WSMessage) -> Flow {
+        match message.key.as_deref() {
+            Some("hidden") => Flow::Drop,
+            Some("refused") => Flow::Reject("Key refused is refused".to_string()),
+            _ => Flow::Continue(message),
+        }
+    }
+}
+
+#[tokio::test]
+async fn not_waiting_for_acks_of_dropped_changes() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .ack_timeout(Duration::from_millis(100))
+        .build();
+    let hidden = poca.data("hidden", 0);
+    let refused = poca.data("refused", 0);
+    hidden.set_require_ack(true);
+    refused.set_require_ack(true);
+    let failed = Arc::new(AtomicUsize::new(0));
+    let counter = failed.clone();
+    poca.on_unacknowledged(move |_, _| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    poca.add_middleware(Hiding);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    hidden.set(1);
+    refused.set(1);
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 4);
+    assert_eq!(reply["key"], "refused");
+    assert!(poca.unacknowledged("hidden").is_empty());
+    assert!(poca.unacknowledged("refused").is_empty());
+    // nothing is sent again or reported
+    let retransmitted =
+        tokio::time::timeout(Duration::from_millis(500), next_reply(&mut socket)).await;
+    assert!(retransmitted.is_err());
+    assert_eq!(failed.load(Ordering::SeqCst), 0);
+    poca.stop();
+}
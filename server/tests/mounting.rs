@@ -0,0 +1,30 @@
+use std::{convert::Infallible, time::Duration};
+
+use hyper::{server::conn::AddrStream, service::make_service_fn};
+use poca::{Poca, PocaError};
+
+#[tokio::test]
+async fn mounting_on_another_server() {
+    let poca = Poca::builder().build();
+    let service = poca.service().unwrap();
+    assert!(matches!(poca.start().await, Err(PocaError::AlreadyRunning)));
+
+    let make_service = make_service_fn(move |_: &AddrStream| {
+        let service = service.clone();
+        async move { Ok::<_, Infallible>(service) }
+    });
+    let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let address = server.local_addr();
+    tokio::spawn(server);
+
+    let (_socket, _) = tokio::task::spawn_blocking(move || {
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        tungstenite::client(format!("ws://{}/ws", address), stream).unwrap()
+    })
+    .await
+    .unwrap();
+    while poca.clients().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    poca.stop();
+}
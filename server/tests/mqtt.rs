@@ -0,0 +1,127 @@
+#![cfg(feature = "mqtt")]
+
+use std::time::Duration;
+
+use poca::{Access, MqttBridge, Poca};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use tokio::{sync::mpsc, time::sleep};
+
+// of the broker at `POCA_MQTT_BROKER`, like `localhost:1883`, the tests are
+// skipped without one
+fn broker() -> Option<(String, u16)> {
+    let address = match std::env::var("POCA_MQTT_BROKER") {
+        Ok(address) => address,
+        Err(_) => {
+            eprintln!("Skipped, POCA_MQTT_BROKER isn't set");
+            return None;
+        }
+    };
+    let (host, port) = address.rsplit_once(':').expect("host:port");
+    Some((host.to_string(), port.parse().unwrap()))
+}
+
+// a device subscribed to the topics below `prefix`, with the payloads
+// published on them by topic
+async fn device(
+    host: &str,
+    port: u16,
+    prefix: &str,
+) -> (AsyncClient, mpsc::UnboundedReceiver<(String, String)>) {
+    let options = MqttOptions::new(format!("{}-device", prefix), host, port);
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+    client
+        .subscribe(format!("{}/#", prefix), QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    let (sender, received) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok(event) = eventloop.poll().await {
+            if let Event::Incoming(Packet::Publish(publish)) = event {
+                let payload = String::from_utf8_lossy(&publish.payload).into_owned();
+                if sender.send((publish.topic, payload)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    (client, received)
+}
+
+async fn eventually(condition: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert!(condition());
+}
+
+#[tokio::test]
+async fn checking_values_from_devices() {
+    let (host, port) = match broker() {
+        Some(broker) => broker,
+        None => return,
+    };
+    let prefix = format!("poca-test-{}", std::process::id());
+    let topic = |key: &str| format!("{}/{}", prefix, key);
+    let poca = Poca::builder().address("localhost:0").build();
+    let temperature = poca.data("temperature", 0);
+    temperature.validate(|temperature| match temperature {
+        -50..=100 => Ok(()),
+        _ => Err("out of range".to_string()),
+    });
+    let level = poca.data("level", 0);
+    level.transform(|level: i32| level.min(10));
+    let setpoint = poca.data("setpoint", 0);
+    setpoint.set_access(Access::ReadOnly);
+    let secret = poca.data("secret", 0);
+    secret.set_room(Some("admins"));
+    let bridge = ["temperature", "level", "setpoint", "secret"].iter().fold(
+        MqttBridge::new(&format!("{}-poca", prefix), &host, port),
+        |bridge, key| bridge.key(key, &topic(key)),
+    );
+    poca.add_mqtt_bridge(bridge);
+    poca.start().await.unwrap();
+    let (device, mut received) = device(&host, port, &prefix).await;
+    // for the bridge to subscribe
+    sleep(Duration::from_millis(500)).await;
+
+    // published by the same device, so they arrive in this order
+    for (key, payload) in [
+        ("setpoint", "5"),
+        ("secret", "7"),
+        ("level", "50"),
+        ("temperature", "500"),
+        ("temperature", "20"),
+    ] {
+        device
+            .publish(topic(key), QoS::AtLeastOnce, false, payload)
+            .await
+            .unwrap();
+    }
+    eventually(|| temperature.get() == 20).await;
+    assert_eq!(setpoint.get(), 0);
+    assert_eq!(secret.get(), 0);
+    // the device is told about the transformed value
+    assert_eq!(level.get(), 10);
+    let level_topic = topic("level");
+    loop {
+        let (topic, payload) = received.recv().await.unwrap();
+        if topic == level_topic && payload == "10" {
+            break;
+        }
+    }
+
+    // values of keys in rooms aren't published
+    secret.set(1);
+    temperature.set(21);
+    loop {
+        let (topic, payload) = received.recv().await.unwrap();
+        assert_ne!(topic, format!("{}/secret", prefix));
+        if payload == "21" {
+            break;
+        }
+    }
+    poca.stop();
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "nats")]
+
+use std::time::Duration;
+
+use poca::{Access, NatsBridge, Poca};
+use tokio::time::{sleep, timeout};
+
+// of the server at `POCA_NATS_URL`, like `nats://localhost:4222`, the tests
+// are skipped without one
+fn server() -> Option<String> {
+    match std::env::var("POCA_NATS_URL") {
+        Ok(url) => Some(url),
+        Err(_) => {
+            eprintln!("Skipped, POCA_NATS_URL isn't set");
+            None
+        }
+    }
+}
+
+async fn eventually(condition: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert!(condition());
+}
+
+#[tokio::test]
+async fn checking_changes_from_services() {
+    let url = match server() {
+        Some(url) => url,
+        None => return,
+    };
+    let prefix = format!("poca-test-{}", std::process::id());
+    let poca = Poca::builder().address("localhost:0").build();
+    let score = poca.data("score", 0);
+    score.validate(|score| match *score < 0 {
+        true => Err("must not be negative".to_string()),
+        false => Ok(()),
+    });
+    let locked = poca.data("locked", 0);
+    locked.set_access(Access::ReadOnly);
+    let hidden = poca.data("hidden", 0);
+    hidden.set_room(Some("admins"));
+    poca.add_nats_bridge(NatsBridge::new(&url).prefix(&prefix));
+    poca.start().await.unwrap();
+    let service = async_nats::connect(url.as_str()).await.unwrap();
+    let changes = service
+        .subscribe(&format!("{}.changes.>", prefix))
+        .await
+        .unwrap();
+    // for the bridge to subscribe
+    sleep(Duration::from_millis(500)).await;
+
+    // published by the same service, so they arrive in this order
+    for (key, change) in [
+        ("locked", r#"{"op":"set","data":5}"#),
+        ("hidden", r#"{"op":"set","data":5}"#),
+        ("score", r#"{"op":"set","data":-1}"#),
+        ("score", r#"{"op":"increment","data":2}"#),
+    ] {
+        let subject = format!("{}.apply.{}", prefix, key);
+        service.publish(&subject, change).await.unwrap();
+    }
+    eventually(|| score.get() == 2).await;
+    assert_eq!(locked.get(), 0);
+    assert_eq!(hidden.get(), 0);
+    let published = timeout(Duration::from_secs(1), changes.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(published.subject, format!("{}.changes.score", prefix));
+
+    // changes of keys in rooms aren't published
+    hidden.set(1);
+    score.set(3);
+    let published = timeout(Duration::from_secs(1), changes.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(published.subject, format!("{}.changes.score", prefix));
+    poca.stop();
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use poca::{Overflow, Poca, PocaError};
+use tokio::time::{sleep, timeout};
+use tokio_stream::StreamExt;
+
+// the keys changed, in the order the stream gets them
+async fn changed_keys(overflow: Overflow) -> Vec<String> {
+    let poca = Poca::builder().channel_size(4).overflow(overflow).build();
+    let handles = (0..20)
+        .map(|index| poca.data(&format!("key{:02}", index), 0))
+        .collect::<Vec<_>>();
+    let mut all_changes = poca.all_changes();
+    for handle in &handles {
+        handle.set(1);
+    }
+    // routed while nobody takes from the queue
+    sleep(Duration::from_millis(100)).await;
+
+    let mut keys = Vec::new();
+    while let Ok(Some((key, _))) = timeout(Duration::from_millis(100), all_changes.next()).await {
+        keys.push(key);
+    }
+    keys
+}
+
+#[tokio::test]
+async fn dropping_the_oldest() {
+    let keys = changed_keys(Overflow::DropOldest).await;
+    assert_eq!(keys, vec!["key16", "key17", "key18", "key19"]);
+}
+
+#[tokio::test]
+async fn dropping_the_newest() {
+    let keys = changed_keys(Overflow::DropNewest).await;
+    assert_eq!(keys, vec!["key00", "key01", "key02", "key03"]);
+}
+
+#[tokio::test]
+async fn blocking_until_there_is_room() {
+    let keys = changed_keys(Overflow::Block).await;
+    assert_eq!(keys.len(), 20);
+    assert_eq!(keys.first().map(String::as_str), Some("key00"));
+    assert_eq!(keys.last().map(String::as_str), Some("key19"));
+}
+
+#[test]
+fn refusing_zero_channel_size() {
+    let built = Poca::builder().channel_size(0).try_build();
+    assert!(matches!(built, Err(PocaError::Config(_))));
+}
+
+#[tokio::test]
+async fn cutting_off_clients_that_stopped_reading() {
+    let poca = Poca::builder()
+        .channel_size(4)
+        .overflow(Overflow::Block)
+        .build();
+    let counter = poca.data("counter", 0);
+    let mut stalled = poca.all_changes();
+    let mut reading = poca.all_changes();
+    let reader = tokio::spawn(async move {
+        let mut changes = 0;
+        while changes < 2000 {
+            reading.next().await.unwrap();
+            changes += 1;
+        }
+    });
+    for value in 1..=2000 {
+        counter.set(value);
+        tokio::task::yield_now().await;
+    }
+
+    // the reader isn't held up for good
+    timeout(Duration::from_secs(5), reader)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut changes = 0;
+    while let Ok(Some(_)) = timeout(Duration::from_millis(100), stalled.next()).await {
+        changes += 1;
+    }
+    assert!(changes < 2000);
+    // it ended rather than waiting for more
+    assert!(matches!(
+        timeout(Duration::from_millis(100), stalled.next()).await,
+        Ok(None)
+    ));
+}
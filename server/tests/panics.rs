@@ -0,0 +1,38 @@
+use std::sync::{Arc, Mutex};
+
+use poca::{include_app_dir, Poca};
+
+#[test]
+fn panicking_handler_is_isolated() {
+    let poca = Poca::new(
+        "localhost:1124",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let panics = Arc::new(Mutex::new(Vec::new()));
+    let panics_clone = panics.clone();
+    poca.on_panic(move |panic| {
+        panics_clone
+            .lock()
+            .unwrap()
+            .push((panic.key.clone(), panic.message.clone()));
+    });
+
+    let handle = poca.data("panicky", 0);
+    let calls = Arc::new(Mutex::new(0));
+    let calls_clone = calls.clone();
+    handle.on_change(|value| panic!("bad value {}", value));
+    handle.on_change(move |_| *calls_clone.lock().unwrap() += 1);
+
+    handle.set(1);
+    handle.set(2);
+    assert_eq!(handle.get(), 2);
+    assert_eq!(*calls.lock().unwrap(), 2);
+    assert_eq!(
+        *panics.lock().unwrap(),
+        vec![
+            (Some("panicky".to_string()), "bad value 1".to_string()),
+            (Some("panicky".to_string()), "bad value 2".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,267 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use poca::{
+    include_app_dir, Change, Durability, FileStorage, MemoryStorage, Poca, PocaError,
+    StorageBackend, StorageError,
+};
+use serde_json::{json, Map, Value};
+
+fn poca() -> Poca {
+    Poca::new(
+        "localhost:1137",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    )
+}
+
+// changes are logged on a thread of their own
+fn eventually(condition: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(condition());
+}
+
+#[test]
+fn restoring_saved_values() {
+    let path = std::env::temp_dir().join(format!("poca-persist-{}.json", std::process::id()));
+    std::fs::remove_file(&path).ok();
+    {
+        let poca = poca();
+        poca.persist_to(&path).unwrap();
+        poca.data("score", 1).set(5);
+        poca.data("name", "poca".to_string());
+        poca.persist().unwrap();
+    }
+
+    let poca = poca();
+    // registered before restoring
+    let score = poca.data("score", 0);
+    poca.persist_to(&path).unwrap();
+    assert_eq!(score.get(), 5);
+    // saved with another type
+    assert_eq!(poca.data("name", 1.5).get(), 1.5);
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn unreadable_file() {
+    let path = std::env::temp_dir().join(format!("poca-invalid-{}.json", std::process::id()));
+    std::fs::write(&path, "{").unwrap();
+    let result = poca().persist_to(&path);
+    assert!(matches!(result, Err(PocaError::Persistence(_))));
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn refusing_unreadable_storage() {
+    let path = std::env::temp_dir().join(format!("poca-unreadable-{}.json", std::process::id()));
+    std::fs::write(&path, "{").unwrap();
+    let built = Poca::builder().storage(FileStorage::new(&path)).try_build();
+    assert!(matches!(built, Err(PocaError::Persistence(_))));
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn replaying_the_log_after_a_crash() {
+    let path = std::env::temp_dir().join(format!("poca-log-{}.json", std::process::id()));
+    std::fs::remove_file(&path).ok();
+    let log = path.with_extension("json.log");
+    let builder = || Poca::builder().write_ahead_log(true).compact_after(100);
+    {
+        let poca = builder().build();
+        poca.persist_to(&path).unwrap();
+        let counter = poca.counter("counter", 1);
+        counter.increment(2);
+        counter.increment(3);
+        poca.list("items", Vec::<u32>::new()).push(7);
+        // nothing but the log was written, like after a crash
+        eventually(|| std::fs::metadata(&log).is_ok_and(|log| log.len() > 0));
+        std::mem::forget(poca);
+    }
+
+    let poca = builder().build();
+    poca.persist_to(&path).unwrap();
+    // folded into the file when restored
+    assert_eq!(std::fs::metadata(&log).unwrap().len(), 0);
+    assert_eq!(poca.counter("counter", 0).get(), 6);
+    assert_eq!(poca.list("items", Vec::<u32>::new()).get(), vec![7]);
+    std::fs::remove_file(&path).ok();
+    std::fs::remove_file(&log).ok();
+}
+
+#[test]
+fn storage_from_the_builder() {
+    let storage = Arc::new(MemoryStorage::new());
+    let builder = |write_ahead_log| {
+        Poca::builder()
+            .storage(storage.clone())
+            .write_ahead_log(write_ahead_log)
+    };
+    builder(false).build().data("score", 1).set(5);
+
+    let poca = builder(true).build();
+    let score = poca.data("score", 0);
+    assert_eq!(score.get(), 5);
+    score.set(7);
+    // only logged, like after a crash
+    std::mem::forget(poca);
+    eventually(|| storage.load().unwrap()["score"] == 7);
+
+    assert_eq!(builder(false).build().data("score", 0).get(), 7);
+}
+
+#[tokio::test]
+async fn saving_when_stopped() {
+    let storage = Arc::new(MemoryStorage::new());
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .storage(storage.clone())
+        .build();
+    poca.start().await.unwrap();
+    let score = poca.data("score", 1);
+    score.set(5);
+    poca.flush().await.unwrap();
+    assert_eq!(storage.load().unwrap()["score"], 5);
+
+    score.set(7);
+    poca.stop();
+    // on a blocking thread of the runtime
+    for _ in 0..100 {
+        if storage.load().unwrap()["score"] == 7 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(storage.load().unwrap()["score"], 7);
+}
+
+#[test]
+fn refusing_a_zero_persist_interval() {
+    let built = Poca::builder().persist_interval(Duration::ZERO).try_build();
+    assert!(matches!(built, Err(PocaError::Config(_))));
+}
+
+// in memory, counting how often it is synced and slow to append to
+#[derive(Default)]
+struct SlowStorage {
+    inner: MemoryStorage,
+    syncs: AtomicUsize,
+}
+
+impl StorageBackend for SlowStorage {
+    fn load(&self) -> Result<Map<String, Value>, StorageError> {
+        self.inner.load()
+    }
+
+    fn save(&self, values: &Map<String, Value>) -> Result<(), StorageError> {
+        self.inner.save(values)
+    }
+
+    fn append_change(&self, change: &Change) -> Result<(), StorageError> {
+        thread::sleep(Duration::from_millis(50));
+        self.inner.append_change(change)
+    }
+
+    fn sync(&self) -> Result<(), StorageError> {
+        self.syncs.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+fn logging_without_blocking_changes() {
+    let storage = Arc::new(SlowStorage::default());
+    let poca = Poca::builder()
+        .storage(storage.clone())
+        .write_ahead_log(true)
+        .build();
+    let score = poca.data("score", 0);
+    let started = Instant::now();
+    for value in 1..=5 {
+        score.set(value);
+    }
+    assert!(started.elapsed() < Duration::from_millis(250));
+    eventually(|| storage.load().unwrap().get("score") == Some(&json!(5)));
+    assert!(storage.syncs.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn syncing_the_log() {
+    let syncs = |durability| {
+        let storage = Arc::new(SlowStorage::default());
+        let poca = Poca::builder()
+            .storage(storage.clone())
+            .write_ahead_log(true)
+            .durability(durability)
+            .build();
+        let score = poca.data("score", 0);
+        for value in 1..=3 {
+            score.set(value);
+        }
+        eventually(|| storage.load().unwrap().get("score") == Some(&json!(3)));
+        // the sync follows the last change
+        thread::sleep(Duration::from_millis(50));
+        storage.syncs.load(Ordering::SeqCst)
+    };
+    assert_eq!(syncs(Durability::Os), 0);
+    assert!(syncs(Durability::Always) >= 3);
+}
+
+// the saved values with a change on top, then replaced by the next save
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn round_trip(storage: &dyn StorageBackend) {
+    use poca::Op;
+
+    let mut values = Map::new();
+    values.insert("score".to_string(), json!(1));
+    values.insert("name".to_string(), json!("poca"));
+    storage.save(&values).unwrap();
+    let change = Change::new("score".to_string(), Op::Set(json!(2)), None);
+    storage.append_change(&change).unwrap();
+    let loaded = storage.load().unwrap();
+    assert_eq!(loaded["score"], 2);
+    assert_eq!(loaded["name"], "poca");
+
+    values.remove("name");
+    storage.save(&values).unwrap();
+    assert_eq!(storage.load().unwrap(), values);
+}
+
+#[cfg(feature = "sled")]
+#[test]
+fn sled_round_trip() {
+    use poca::SledStorage;
+
+    let path = std::env::temp_dir().join(format!("poca-sled-{}", std::process::id()));
+    std::fs::remove_dir_all(&path).ok();
+    round_trip(&SledStorage::open(&path).unwrap());
+    // once the database is closed as well
+    let loaded = SledStorage::open(&path).unwrap().load().unwrap();
+    assert_eq!(loaded, json!({"score": 1}).as_object().unwrap().clone());
+    std::fs::remove_dir_all(&path).ok();
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_round_trip() {
+    use poca::SqliteStorage;
+
+    let path = std::env::temp_dir().join(format!("poca-sqlite-{}.db", std::process::id()));
+    std::fs::remove_file(&path).ok();
+    round_trip(&SqliteStorage::open(&path).unwrap());
+    // once the database is closed as well
+    let loaded = SqliteStorage::open(&path).unwrap().load().unwrap();
+    assert_eq!(loaded, json!({"score": 1}).as_object().unwrap().clone());
+    std::fs::remove_file(&path).ok();
+}
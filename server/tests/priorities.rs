@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use poca::{Poca, Priority};
+use serde_json::Value;
+use tokio::time::timeout;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[tokio::test]
+async fn high_priority_keys_overtake() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let telemetry = poca.data("telemetry", 0u32);
+    let control = poca.data("control", false);
+    telemetry.set_priority(Priority::Low);
+    control.set_priority(Priority::High);
+    assert_eq!(control.priority(), Priority::High);
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    let (mut socket, _) = connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    // the snapshot
+    socket.next().await.unwrap().unwrap();
+    // queued all at once, before the connection gets to send any of them
+    for value in 1..=20 {
+        telemetry.set(value);
+    }
+    control.set(true);
+
+    let mut received = Vec::new();
+    while let Ok(Some(Ok(Message::Text(text)))) =
+        timeout(Duration::from_millis(300), socket.next()).await
+    {
+        let message = serde_json::from_str::<Value>(&text).unwrap();
+        if let Some(key) = message["key"].as_str() {
+            received.push(key.to_string());
+        }
+    }
+    assert_eq!(received.len(), 21);
+    assert_eq!(received[0], "control");
+}
+
+#[tokio::test]
+async fn not_resuming_past_overtaken_changes() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let telemetry = poca.data("telemetry", 0u32);
+    let control = poca.data("control", false);
+    telemetry.set_priority(Priority::Low);
+    control.set_priority(Priority::High);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+
+    let (mut socket, _) = connect_async(&url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+    for value in 1..=20 {
+        telemetry.set(value);
+    }
+    control.set(true);
+    // the latest change, sent before the ones of telemetry
+    let seq = match socket.next().await.unwrap().unwrap() {
+        Message::Text(text) => {
+            let message = serde_json::from_str::<Value>(&text).unwrap();
+            assert_eq!(message["key"], "control");
+            message["seq"].as_u64().unwrap()
+        }
+        other => panic!("Unexpected frame {:?}", other),
+    };
+    drop(socket);
+    // until the server noticed
+    while !poca.clients().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let (mut socket, _) = connect_async(format!("{}?resume={}", url, seq))
+        .await
+        .unwrap();
+    let snapshot = match socket.next().await.unwrap().unwrap() {
+        Message::Text(text) => serde_json::from_str::<Value>(&text).unwrap(),
+        other => panic!("Unexpected frame {:?}", other),
+    };
+    assert_eq!(snapshot["message_type"], 7);
+    let values = serde_json::from_str::<Value>(snapshot["data"].as_str().unwrap()).unwrap();
+    assert_eq!(values["telemetry"], 20);
+    assert_eq!(values["control"], true);
+    poca.stop();
+}
@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use poca::{Poca, PocaError, RateLimit, RateLimitPolicy};
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(limit: RateLimit) -> (Poca, Socket) {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .rate_limit(limit)
+        .build();
+    poca.counter("count", 0);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+    (poca, socket)
+}
+
+async fn increment(socket: &mut Socket, times: usize) {
+    let increment = r#"{"message_type":10,"key":"count","data":"1"}"#;
+    for _ in 0..times {
+        socket
+            .send(Message::Text(increment.to_string()))
+            .await
+            .unwrap();
+    }
+}
+
+// waits until the count stops changing
+async fn settled_count(poca: &Poca) -> i64 {
+    let count = poca.handle::<i64>("count").unwrap();
+    let mut last = count.get();
+    loop {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        if count.get() == last {
+            return last;
+        }
+        last = count.get();
+    }
+}
+
+#[tokio::test]
+async fn dropping_messages_over_the_limit() {
+    let (poca, mut socket) = connect(RateLimit {
+        messages_per_second: Some(5),
+        bytes_per_second: None,
+        policy: RateLimitPolicy::Drop,
+    })
+    .await;
+    increment(&mut socket, 20).await;
+    // the burst of one second worth
+    assert_eq!(settled_count(&poca).await, 5);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn throttling_messages_over_the_limit() {
+    let (poca, mut socket) = connect(RateLimit {
+        messages_per_second: Some(10),
+        bytes_per_second: None,
+        policy: RateLimitPolicy::Throttle,
+    })
+    .await;
+    let started = Instant::now();
+    increment(&mut socket, 20).await;
+    assert_eq!(settled_count(&poca).await, 20);
+    assert!(started.elapsed() >= Duration::from_millis(900));
+    poca.stop();
+}
+
+#[tokio::test]
+async fn disconnecting_clients_over_the_limit() {
+    let (poca, mut socket) = connect(RateLimit {
+        messages_per_second: Some(5),
+        bytes_per_second: None,
+        policy: RateLimitPolicy::Disconnect,
+    })
+    .await;
+    increment(&mut socket, 20).await;
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Close(frame))) => {
+                assert_eq!(frame.unwrap().reason, "Rate limit exceeded");
+                break;
+            }
+            Some(Ok(_)) => continue,
+            other => panic!("Expected a close frame, got {:?}", other),
+        }
+    }
+    poca.stop();
+}
+
+#[tokio::test]
+async fn rejecting_messages_larger_than_the_byte_limit() {
+    let (poca, mut socket) = connect(RateLimit {
+        messages_per_second: None,
+        bytes_per_second: Some(64),
+        policy: RateLimitPolicy::Drop,
+    })
+    .await;
+    let padded = format!(
+        r#"{{"message_type":10,"key":"count","data":"1","padding":"{}"}}"#,
+        " ".repeat(64)
+    );
+    let size = padded.len();
+    socket.send(Message::Text(padded)).await.unwrap();
+    loop {
+        if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+            let reply: Value = serde_json::from_str(&text).unwrap();
+            if reply["message_type"] == 4 {
+                assert_eq!(
+                    reply["data"],
+                    format!(
+                        "Message of {} bytes exceeds the rate limit of 64 bytes per second",
+                        size
+                    )
+                );
+                break;
+            }
+        }
+    }
+    // smaller ones still pass
+    increment(&mut socket, 1).await;
+    assert_eq!(settled_count(&poca).await, 1);
+    poca.stop();
+}
+
+#[test]
+fn refusing_zero_rates() {
+    for (messages_per_second, bytes_per_second) in [(Some(0), None), (None, Some(0))] {
+        let built = Poca::builder()
+            .rate_limit(RateLimit {
+                messages_per_second,
+                bytes_per_second,
+                policy: RateLimitPolicy::Drop,
+            })
+            .try_build();
+        assert!(matches!(built, Err(PocaError::Config(_))));
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use poca::Poca;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::time::timeout;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Error, Message},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+// the next change of the key, skipping the snapshot and other messages
+async fn next_change<S>(socket: &mut S, key: &str) -> Value
+where
+    S: StreamExt<Item = Result<Message, Error>> + Unpin,
+{
+    loop {
+        let text = match timeout(Duration::from_secs(5), socket.next()).await {
+            Ok(Some(Ok(Message::Text(text)))) => text,
+            other => panic!("Unexpected frame {:?}", other),
+        };
+        let message = serde_json::from_str::<Value>(&text).unwrap();
+        if message["key"] == key {
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn changes_of_clients_are_relayed_as_sent() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let point = poca.data("point", Point { x: 0, y: 0 });
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+
+    let (mut writer, _) = connect_async(&url).await.unwrap();
+    writer.next().await.unwrap().unwrap();
+    let (mut reader, _) = connect_async(&url).await.unwrap();
+    reader.next().await.unwrap().unwrap();
+
+    let set = r#"{"message_type":1,"key":"point","data":"{\"x\":1,\"y\":2}"}"#;
+    writer.send(Message::Text(set.to_string())).await.unwrap();
+    let relayed = next_change(&mut reader, "point").await;
+    assert_eq!(relayed["data"], r#"{"x":1,"y":2}"#);
+    assert_eq!(point.get(), Point { x: 1, y: 2 });
+    poca.stop();
+}
+
+#[tokio::test]
+async fn changes_of_clients_are_relayed_as_stored() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let point = poca.data("point", Point { x: 0, y: 0 });
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+
+    let (mut writer, _) = connect_async(&url).await.unwrap();
+    writer.next().await.unwrap().unwrap();
+    let (mut reader, _) = connect_async(&url).await.unwrap();
+    reader.next().await.unwrap().unwrap();
+
+    let set = r#"{"message_type":1,"key":"point","data":"{\"y\": 2, \"x\": 1, \"z\": 3}"}"#;
+    writer.send(Message::Text(set.to_string())).await.unwrap();
+    let relayed = next_change(&mut reader, "point").await;
+    assert_eq!(relayed["data"], r#"{"x":1,"y":2}"#);
+    // the writer doesn't hold the stored value either
+    let echoed = next_change(&mut writer, "point").await;
+    assert_eq!(echoed["data"], r#"{"x":1,"y":2}"#);
+    assert_eq!(point.get(), Point { x: 1, y: 2 });
+    poca.stop();
+}
@@ -0,0 +1,49 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::SinkExt;
+use parking_lot::Mutex;
+use poca::Poca;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[tokio::test]
+async fn replaying_a_recording() {
+    let path = std::env::temp_dir().join(format!("poca-replay-{}.jsonl", std::process::id()));
+    let poca = Poca::builder().address("localhost:0").build();
+    let count = poca.data("count", 0u32);
+    poca.record_to(&path).unwrap();
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    let (mut socket, _) = connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    for value in ["5", "7"] {
+        let message = format!(r#"{{"message_type":1,"key":"count","data":"{}"}}"#, value);
+        socket.send(Message::Text(message)).await.unwrap();
+    }
+    for _ in 0..100 {
+        if count.get() == 7 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    poca.stop_recording();
+    assert_eq!(count.get(), 7);
+
+    let fresh = Poca::builder().build();
+    let count = fresh.data("count", 0u32);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let recorded = seen.clone();
+    count.on_change(move |value| recorded.lock().push(value));
+    fresh.replay(&path, 100.0).await.unwrap();
+    assert_eq!(*seen.lock(), vec![5, 7]);
+    std::fs::remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn refusing_speeds_that_are_not_positive() {
+    let poca = Poca::builder().build();
+    let path = std::env::temp_dir().join("poca-replay-never-read.jsonl");
+    for speed in [0.0, -1.0, f64::NAN] {
+        let error = poca.replay(&path, speed).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,45 @@
+use poca::Poca;
+use warp::http::StatusCode;
+
+#[tokio::test]
+async fn reading_over_http() {
+    let poca = Poca::builder().rest_api(true).build();
+    poca.data("visible", vec![1, 2]);
+    let hidden = poca.data("hidden", 0);
+    hidden.set_room(Some("lobby"));
+    poca.set_authenticator(|request: &poca::AuthRequest, _: &poca::ClientInfo| {
+        match request.query_param("token") {
+            Some("secret") => Ok(()),
+            _ => Err("Invalid token".to_string()),
+        }
+    });
+    let filter = poca.filter().unwrap();
+
+    let response = warp::test::request()
+        .path("/api/data/visible?token=secret")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body(), "[1,2]");
+
+    let response = warp::test::request()
+        .path("/api/keys?token=secret")
+        .reply(&filter)
+        .await;
+    let keys: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(response.body()).unwrap();
+    assert!(keys.contains_key("visible"));
+    assert!(!keys.contains_key("hidden"));
+
+    let response = warp::test::request()
+        .path("/api/data/hidden?token=secret")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = warp::test::request()
+        .path("/api/data/visible")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    poca.stop();
+}
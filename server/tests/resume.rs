@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use poca::Poca;
+use serde_json::Value;
+use tokio::time::timeout;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+// the changes of the values a client gets until it is idle, snapshots
+// included but not changes of the connected clients
+async fn received<S>(socket: &mut S) -> Vec<Value>
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let mut messages = Vec::new();
+    while let Ok(Some(Ok(message))) = timeout(Duration::from_millis(300), socket.next()).await {
+        if let Message::Text(text) = message {
+            let message: Value = serde_json::from_str(&text).unwrap();
+            if message["key"] != "$clients" {
+                messages.push(message);
+            }
+        }
+    }
+    messages
+}
+
+// connects, returning the sequence number of the snapshot once disconnected
+async fn last_seen(url: &str) -> u64 {
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    let snapshot = match socket.next().await.unwrap().unwrap() {
+        Message::Text(text) => serde_json::from_str::<Value>(&text).unwrap(),
+        other => panic!("Expected the snapshot, got {:?}", other),
+    };
+    socket.close(None).await.unwrap();
+    snapshot["seq"].as_u64().unwrap()
+}
+
+#[tokio::test]
+async fn resuming_after_reconnecting() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 0);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let seq = last_seen(&url).await;
+    for value in 1..=3 {
+        counter.set(value);
+    }
+
+    let (mut socket, _) = connect_async(format!("{}?resume={}", url, seq))
+        .await
+        .unwrap();
+    let data = received(&mut socket)
+        .await
+        .into_iter()
+        .map(|message| {
+            // only the changes it missed
+            assert_eq!(message["message_type"], 1);
+            message["data"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(data, ["1", "2", "3"]);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn resuming_with_more_changes_than_a_queue_holds() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .channel_size(4)
+        .build();
+    let handles = (0..20)
+        .map(|index| poca.data(&format!("key{:02}", index), 0))
+        .collect::<Vec<_>>();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let seq = last_seen(&url).await;
+    for handle in &handles {
+        handle.set(1);
+    }
+
+    let (mut socket, _) = connect_async(format!("{}?resume={}", url, seq))
+        .await
+        .unwrap();
+    let messages = received(&mut socket).await;
+    // replayed messages aren't missed, so the client isn't resynced
+    assert!(messages.iter().all(|message| message["message_type"] == 1));
+    assert_eq!(messages.len(), handles.len());
+    poca.stop();
+}
+
+#[tokio::test]
+async fn resuming_after_the_replay_moved_on() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .replay_size(2)
+        .build();
+    let counter = poca.data("counter", 0);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let seq = last_seen(&url).await;
+    for value in 1..=5 {
+        counter.set(value);
+    }
+
+    let (mut socket, _) = connect_async(format!("{}?resume={}", url, seq))
+        .await
+        .unwrap();
+    let messages = received(&mut socket).await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["message_type"], 7);
+    let values = serde_json::from_str::<Value>(messages[0]["data"].as_str().unwrap()).unwrap();
+    assert_eq!(values["counter"], 5);
+    poca.stop();
+}
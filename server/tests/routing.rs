@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use poca::Poca;
+use tokio::time::sleep;
+use tokio_tungstenite::connect_async;
+
+mod common;
+
+use common::next_reply;
+
+#[tokio::test]
+async fn routing_to_single_clients() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut first, _) = connect_async(&url).await.unwrap();
+    first.next().await.unwrap().unwrap();
+    let (mut second, _) = connect_async(&url).await.unwrap();
+    second.next().await.unwrap().unwrap();
+    let clients = poca.clients();
+
+    assert!(poca.send_to(clients[0].id, "private", 1).unwrap());
+    assert!(poca.send_to(clients[1].id, "private", 2).unwrap());
+    poca.emit("public", ()).unwrap();
+    for (socket, data) in [(&mut first, "1"), (&mut second, "2")] {
+        let event = next_reply(socket).await;
+        assert_eq!(event["key"], "private");
+        assert_eq!(event["data"], data);
+        // the other private event would have been queued before
+        assert_eq!(next_reply(socket).await["key"], "public");
+    }
+    poca.stop();
+}
+
+#[tokio::test]
+async fn dropping_messages_for_full_queues() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .channel_size(4)
+        .build();
+    let handles = (0..20)
+        .map(|index| poca.data(&format!("key{:02}", index), 0))
+        .collect::<Vec<_>>();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+    let client = poca.clients()[0].id;
+    assert_eq!(poca.queues()[0].dropped, 0);
+
+    // without awaiting in between, so all of them are routed before the
+    // client takes any from its queue
+    for handle in &handles {
+        handle.set(1);
+    }
+    sleep(Duration::from_millis(100)).await;
+    let stats = poca.queues();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].client, client);
+    assert!(stats[0].dropped >= 16);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn removing_queues_of_disconnected_clients() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    for _ in 0..10 {
+        let (mut socket, _) = connect_async(&url).await.unwrap();
+        socket.next().await.unwrap().unwrap();
+        assert_eq!(poca.queues().len(), 1);
+        socket.close(None).await.unwrap();
+        for _ in 0..100 {
+            if poca.queues().is_empty() && poca.clients().is_empty() {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert!(poca.queues().is_empty());
+        assert!(poca.clients().is_empty());
+    }
+    poca.stop();
+}
@@ -0,0 +1,227 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use poca::{
+    include_app_dir, Access, ClientId, ClientSummary, Poca, RpcError, CLIENTS_KEY,
+    IDENTITY_METADATA,
+};
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+mod common;
+
+use common::next_reply;
+
+#[tokio::test]
+async fn calling_unknown_client() {
+    let poca = Poca::new(
+        "localhost:1127",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    poca.on_request("double", |_, value: i32| async move { Ok(value * 2) });
+
+    let client: ClientId = serde_json::from_str("42").unwrap();
+    let result = poca.call::<_, String>(client, "name", ()).await;
+    assert_eq!(result, Err(RpcError::Disconnected(client)));
+}
+
+#[test]
+fn sending_to_unknown_client() {
+    let poca = Poca::new(
+        "localhost:1128",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let client: ClientId = serde_json::from_str("42").unwrap();
+    assert!(!poca.send_to(client, "toast", "hello").unwrap());
+    poca.broadcast_except(client, "toast", "hello").unwrap();
+}
+
+#[test]
+fn rooms_need_connected_clients() {
+    let poca = Poca::new(
+        "localhost:1129",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let handle = poca.data("lobby_state", 0);
+    assert_eq!(handle.get_room(), None);
+    handle.set_room(Some("lobby"));
+    assert_eq!(handle.get_room(), Some("lobby".to_string()));
+
+    let client: ClientId = serde_json::from_str("42").unwrap();
+    assert!(!poca.join(client, "lobby"));
+    assert!(poca.room_members("lobby").is_empty());
+    poca.emit_to_room("lobby", "start", ()).unwrap();
+}
+
+// the keys in the reply to a request for the keys or the schema
+async fn listed(
+    socket: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+    message_type: u8,
+) -> Value {
+    let request = serde_json::json!({"message_type": message_type, "key": null});
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+    let reply = next_reply(socket).await;
+    let listed: Value = serde_json::from_str(reply["data"].as_str().unwrap()).unwrap();
+    match message_type {
+        20 => listed["properties"].clone(),
+        _ => listed,
+    }
+}
+
+#[tokio::test]
+async fn listing_keys_of_rooms_to_members_only() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.data("visible", 0);
+    poca.data("lobby_state", 0).set_room(Some("lobby"));
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+    let client = poca.clients()[0].id;
+
+    for message_type in [15, 20] {
+        let keys = listed(&mut socket, message_type).await;
+        assert!(keys.get("visible").is_some());
+        assert!(keys.get("lobby_state").is_none());
+    }
+    assert!(poca.join(client, "lobby"));
+    for message_type in [15, 20] {
+        let keys = listed(&mut socket, message_type).await;
+        assert!(keys.get("lobby_state").is_some());
+    }
+    poca.stop();
+}
+
+#[test]
+fn online_clients() {
+    let poca = Poca::new(
+        "localhost:1131",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    assert!(poca.clients().is_empty());
+    let online = poca.handle::<Vec<ClientSummary>>(CLIENTS_KEY).unwrap();
+    assert_eq!(online.get(), Vec::new());
+    assert_eq!(online.get_access(), Access::ReadOnly);
+
+    let client: ClientId = serde_json::from_str("42").unwrap();
+    assert!(!poca.set_client_metadata(client, "name", "someone"));
+}
+
+#[test]
+fn removing_clients() {
+    let poca = Poca::new(
+        "localhost:1132",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let client: ClientId = serde_json::from_str("42").unwrap();
+    assert!(!poca.disconnect(client, "misbehaving"));
+}
+
+#[tokio::test]
+async fn calling_clients() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .call_timeout(Duration::from_millis(200))
+        .build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    // the snapshot, sent once the client is connected
+    socket.next().await.unwrap().unwrap();
+    let client = poca.clients()[0].id;
+
+    let call = tokio::spawn({
+        let poca = poca.clone();
+        async move { poca.call::<_, i32>(client, "double", 21).await }
+    });
+    let request = next_reply(&mut socket).await;
+    assert_eq!(request["message_type"], 17);
+    assert_eq!(request["key"], "double");
+    assert_eq!(request["data"], "21");
+    let response = serde_json::json!({"message_type": 18, "id": request["id"], "data": "42"});
+    socket
+        .send(Message::Text(response.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(call.await.unwrap(), Ok(42));
+
+    // the client never answers
+    let result = poca.call::<_, i32>(client, "double", 21).await;
+    assert_eq!(result, Err(RpcError::Timeout(client)));
+    poca.stop();
+}
+
+#[tokio::test]
+async fn answering_clients() {
+    let poca = Poca::builder().address("localhost:0").build();
+    poca.on_request("double", |_, value: i32| async move { Ok(value * 2) });
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    let request = serde_json::json!({"message_type": 17, "key": "double", "id": 1, "data": "21"});
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+    let response = next_reply(&mut socket).await;
+    assert_eq!(response["message_type"], 18);
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["data"], "42");
+
+    let request = serde_json::json!({"message_type": 17, "key": "triple", "id": 2});
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+    let response = next_reply(&mut socket).await;
+    assert_eq!(response["message_type"], 4);
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["data"], "Method triple does not exist");
+
+    let request = serde_json::json!({"message_type": 17, "key": null, "id": 3});
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+    let response = next_reply(&mut socket).await;
+    assert_eq!(response["message_type"], 4);
+    assert_eq!(response["data"], "Message is missing a key");
+    poca.stop();
+}
+
+#[tokio::test]
+async fn publishing_metadata() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .public_metadata("name")
+        .build();
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+    let client = poca.clients()[0].id;
+
+    assert!(poca.set_client_metadata(client, "name", "someone"));
+    assert!(poca.set_client_metadata(client, IDENTITY_METADATA, "someone@example.com"));
+    let online = poca.handle::<Vec<ClientSummary>>(CLIENTS_KEY).unwrap();
+    let summary = &online.get()[0];
+    assert_eq!(summary.id, client);
+    assert_eq!(summary.metadata.len(), 1);
+    assert_eq!(summary.metadata["name"], "someone");
+    // the server still sees all of it
+    assert_eq!(
+        poca.clients()[0].get_metadata(IDENTITY_METADATA).as_deref(),
+        Some("someone@example.com")
+    );
+    poca.stop();
+}
@@ -0,0 +1,44 @@
+use std::thread;
+
+use poca::Poca;
+
+#[test]
+fn keys_across_shards() {
+    let poca = Poca::builder().build();
+    let handles = (0..64)
+        .map(|index| poca.data(&format!("key{:02}", index), 0u32))
+        .collect::<Vec<_>>();
+    // besides the one about connected clients
+    let keys = poca
+        .keys()
+        .into_iter()
+        .filter(|key| key.starts_with("key"))
+        .collect::<Vec<_>>();
+    assert_eq!(keys.len(), 64);
+    assert_eq!(keys.first().map(String::as_str), Some("key00"));
+    assert_eq!(keys.last().map(String::as_str), Some("key63"));
+
+    // unrelated keys are written concurrently
+    thread::scope(|scope| {
+        for handle in &handles {
+            scope.spawn(move || {
+                for _ in 0..100 {
+                    handle.update(|value| *value += 1);
+                }
+            });
+        }
+    });
+    assert!(handles.iter().all(|handle| handle.get() == 100));
+
+    poca.transaction(|txn| {
+        for index in 0..64 {
+            txn.set(&format!("key{:02}", index), 7u32);
+        }
+    })
+    .unwrap();
+    assert!(handles.iter().all(|handle| handle.get() == 7));
+
+    poca.remove("key10").unwrap();
+    assert!(!poca.contains("key10"));
+    assert!(poca.keys().iter().all(|key| key != "key10"));
+}
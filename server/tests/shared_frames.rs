@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use poca::{ClientInfo, Flow, Middleware, Poca, WSMessage, WSMessageType};
+use serde_json::Value;
+use tokio::time::timeout;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[tokio::test]
+async fn clients_get_the_same_frames() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 0u32);
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    let mut sockets = Vec::new();
+    for _ in 0..3 {
+        let (mut socket, _) = connect_async(format!("ws://{}/ws", address)).await.unwrap();
+        // the snapshot
+        socket.next().await.unwrap().unwrap();
+        sockets.push(socket);
+    }
+    for value in 1..=5 {
+        counter.set(value);
+    }
+
+    let mut received = Vec::new();
+    for socket in &mut sockets {
+        let mut frames = Vec::new();
+        while let Ok(Some(Ok(Message::Text(text)))) =
+            timeout(Duration::from_millis(300), socket.next()).await
+        {
+            // besides changes of the connected clients
+            if serde_json::from_str::<Value>(&text).unwrap()["key"] == "counter" {
+                frames.push(text);
+            }
+        }
+        received.push(frames);
+    }
+    assert_eq!(received[0].len(), 5);
+    assert!(received.iter().all(|frames| *frames == received[0]));
+    let last = serde_json::from_str::<Value>(&received[0][4]).unwrap();
+    assert_eq!(last["data"], "5");
+}
+
+// changes each message for the client it is sent to
+struct Tagged;
+
+impl Middleware for Tagged {
+    fn outbound(&self, client: &ClientInfo, mut message: WSMessage) -> Flow {
+        if message.message_type == WSMessageType::Set {
+            message.data = Some(format!("\"{}\"", client.id));
+        }
+        Flow::Continue(message)
+    }
+}
+
+#[tokio::test]
+async fn middlewares_still_see_every_client() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let note = poca.data("note", String::new());
+    poca.add_middleware(Tagged);
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    let mut sockets = Vec::new();
+    for _ in 0..2 {
+        let (mut socket, _) = connect_async(format!("ws://{}/ws", address)).await.unwrap();
+        socket.next().await.unwrap().unwrap();
+        sockets.push(socket);
+    }
+    note.set("hello".to_string());
+
+    let mut received = Vec::new();
+    for socket in &mut sockets {
+        while let Some(Ok(Message::Text(text))) = socket.next().await {
+            let message = serde_json::from_str::<Value>(&text).unwrap();
+            if message["key"] == "note" {
+                received.push(message["data"].as_str().unwrap().to_string());
+                break;
+            }
+        }
+    }
+    assert_ne!(received[0], received[1]);
+}
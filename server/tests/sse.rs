@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use poca::Poca;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+#[tokio::test]
+async fn event_stream_with_posted_writes() {
+    let poca = Poca::builder().address("localhost:0").sse(true).build();
+    let counter = poca.data("counter", 1);
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream
+        .write_all(b"GET /sse HTTP/1.1\r\nhost: localhost\r\naccept: text/event-stream\r\n\r\n")
+        .await
+        .unwrap();
+    let mut events = BufReader::new(stream);
+    let mut line = String::new();
+    let mut token = None;
+    loop {
+        line.clear();
+        events.read_line(&mut line).await.unwrap();
+        if let Some(data) = line.strip_prefix("data:") {
+            if token.is_none() {
+                token = Some(data.trim().to_string());
+            } else {
+                // the snapshot, right after the session
+                assert!(data.contains("counter"));
+                break;
+            }
+        }
+    }
+
+    let body = r#"{"message_type":1,"key":"counter","data":"5"}"#;
+    let status = post(address, &format!("/sse?session={}", token.unwrap()), body).await;
+    assert!(status.starts_with("HTTP/1.1 204"));
+    for _ in 0..100 {
+        if counter.get() == 5 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(counter.get(), 5);
+
+    let status = post(address, "/sse?session=unknown", body).await;
+    assert!(status.starts_with("HTTP/1.1 404"));
+    poca.stop();
+}
+
+#[tokio::test]
+async fn refusing_large_posts() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .sse(true)
+        .max_message_size(16)
+        .build();
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    // by its length, before the session is looked up
+    let body = r#"{"message_type":1,"key":"counter","data":"5"}"#;
+    let status = post(address, "/sse?session=unknown", body).await;
+    assert!(status.starts_with("HTTP/1.1 413"));
+    let status = post(address, "/sse?session=unknown", "{}").await;
+    assert!(status.starts_with("HTTP/1.1 404"));
+    poca.stop();
+}
+
+// the status line of the response
+async fn post(address: std::net::SocketAddr, path: &str, body: &str) -> String {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!(
+        "POST {} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+        path,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response.lines().next().unwrap_or_default().to_string()
+}
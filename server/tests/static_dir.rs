@@ -0,0 +1,17 @@
+use poca::Poca;
+use warp::http::StatusCode;
+
+#[tokio::test]
+async fn serving_a_directory() {
+    let poca = Poca::builder().static_dir("tests/routes_test").build();
+    let filter = poca.filter().unwrap();
+
+    let response = warp::test::request()
+        .path("/layer1/layer2/layer3-1")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = warp::test::request().path("/missing").reply(&filter).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    poca.stop();
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use poca::{include_app_dir, Poca};
+
+    fn poca() -> Poca {
+        Poca::new(
+            "localhost:1121",
+            include_app_dir!("tests/empty_assets/"),
+            None,
+        )
+    }
+
+    #[test]
+    fn primitives_and_strings() {
+        let poca = poca();
+        let number = poca.data("number", 1.5f64);
+        let flag = poca.data("flag", true);
+        let text = poca.data("text", "hello".to_string());
+
+        number.set(2.5);
+        flag.set(false);
+        text.update(|value| value.push_str(" world"));
+
+        assert_eq!(number.get(), 2.5);
+        assert!(!flag.get());
+        assert_eq!(text.get(), "hello world");
+    }
+
+    #[test]
+    fn options_and_tuples() {
+        let poca = poca();
+        let maybe = poca.data("maybe", None::<u32>);
+        let pair = poca.data("pair", (1u8, "one".to_string()));
+
+        maybe.set(Some(3));
+        pair.update(|value| value.0 += 1);
+
+        assert_eq!(maybe.get(), Some(3));
+        assert_eq!(pair.get(), (2, "one".to_string()));
+    }
+
+    #[test]
+    fn collections() {
+        let poca = poca();
+        let list = poca.data("list", vec![1, 2, 3]);
+        let map = poca.data("map", HashMap::from([("a".to_string(), 1)]));
+        let ordered = poca.data("ordered", BTreeMap::from([(1u32, "one".to_string())]));
+
+        list.update(|value| value.push(4));
+        map.update(|value| {
+            value.insert("b".to_string(), 2);
+        });
+        ordered.update(|value| {
+            value.insert(2, "two".to_string());
+        });
+
+        assert_eq!(list.get(), vec![1, 2, 3, 4]);
+        assert_eq!(map.get().get("b"), Some(&2));
+        assert_eq!(ordered.read().len(), 2);
+    }
+}
@@ -0,0 +1,31 @@
+use poca::{include_app_dir, Poca, StoreError};
+
+#[test]
+fn separate_stores() {
+    let poca = Poca::new(
+        "localhost:1130",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let document = poca.create_store("/doc/1").unwrap();
+    assert_eq!(
+        poca.create_store("doc/1/").err(),
+        Some(StoreError::AlreadyExists("doc/1".to_string()))
+    );
+
+    poca.data("title", "root".to_string());
+    document.data("title", "first document".to_string());
+    let title = poca
+        .get_store("doc/1")
+        .unwrap()
+        .handle::<String>("title")
+        .unwrap();
+    assert_eq!(title.get(), "first document");
+
+    assert_eq!(poca.destroy_store("/doc/1"), Ok(()));
+    assert!(poca.get_store("/doc/1").is_none());
+    assert_eq!(
+        poca.destroy_store("/doc/1"),
+        Err(StoreError::NotFound("doc/1".to_string()))
+    );
+}
@@ -0,0 +1,92 @@
+use poca::{include_app_dir, Poca};
+use tokio_stream::StreamExt;
+
+#[tokio::test]
+async fn change_streams() {
+    let poca = Poca::new(
+        "localhost:1125",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let first = poca.data("first", 0);
+    let second = poca.data("second", "a".to_string());
+    let mut changes = first.changes();
+    let mut all_changes = poca.all_changes();
+
+    first.set(1);
+    first.update(|value| *value += 1);
+    assert_eq!(changes.next().await, Some(1));
+    assert_eq!(changes.next().await, Some(2));
+
+    second.set("b".to_string());
+    let (key, _) = all_changes.next().await.unwrap();
+    assert_eq!(key, "first");
+    let (key, _) = all_changes.next().await.unwrap();
+    assert_eq!(key, "first");
+    let (key, value) = all_changes.next().await.unwrap();
+    assert_eq!(key, "second");
+    assert_eq!(value.serialize(), r#""b""#);
+
+    drop(changes);
+    first.set(3);
+    assert_eq!(first.get(), 3);
+}
+
+#[tokio::test]
+async fn watching_values() {
+    let poca = Poca::new(
+        "localhost:1126",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let handle = poca.data("watched", 0);
+    let mut receiver = handle.watch();
+    assert_eq!(*receiver.borrow(), 0);
+
+    let setter = handle.clone();
+    tokio::spawn(async move { setter.set(1) });
+    receiver.changed().await.unwrap();
+    assert_eq!(*receiver.borrow(), 1);
+
+    drop(receiver);
+    handle.set(2);
+    handle.set(3);
+    assert_eq!(handle.get(), 3);
+}
+
+#[tokio::test]
+async fn queue_stats() {
+    let poca = Poca::new(
+        "localhost:1133",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let counter = poca.data("counter", 0);
+    let mut all_changes = poca.all_changes();
+    counter.set(1);
+    let (key, _) = all_changes.next().await.unwrap();
+    assert_eq!(key, "counter");
+    // only connections of clients are listed
+    assert!(poca.queues().is_empty());
+}
+
+#[tokio::test]
+async fn streams_in_every_shard() {
+    let poca = Poca::builder().build();
+    let counter = poca.data("counter", 0);
+    let mut streams = (0..40).map(|_| poca.all_changes()).collect::<Vec<_>>();
+    counter.set(1);
+    for stream in &mut streams {
+        let (key, value) = stream.next().await.unwrap();
+        assert_eq!(key, "counter");
+        assert_eq!(value.serialize(), "1");
+    }
+
+    // the rest still get changes once some are gone
+    streams.truncate(10);
+    counter.set(2);
+    for stream in &mut streams {
+        let (_, value) = stream.next().await.unwrap();
+        assert_eq!(value.serialize(), "2");
+    }
+}
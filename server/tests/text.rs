@@ -0,0 +1,31 @@
+use poca::Text;
+
+#[test]
+fn concurrent_inserts_converge() {
+    let base = Text::new("ac");
+    let mut first = base.clone();
+    let mut second = base;
+
+    let first_ops = first.insert(1, 1, "b");
+    let second_ops = second.insert(2, 1, "x");
+    let second_delete = second.delete(0, 1);
+
+    for op in second_ops.iter().chain(second_delete.iter()) {
+        first.apply(op).unwrap();
+    }
+    for op in first_ops.iter() {
+        second.apply(op).unwrap();
+    }
+
+    assert_eq!(first.value(), second.value());
+    assert_eq!(first.len(), 3);
+    assert!(first.value().ends_with('c'));
+}
+
+#[test]
+fn duplicate_ops_are_ignored() {
+    let mut text = Text::new("ab");
+    let ops = text.insert(1, 2, "c");
+    text.apply(&ops[0]).unwrap();
+    assert_eq!(text.value(), "abc");
+}
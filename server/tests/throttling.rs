@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use poca::Poca;
+use serde_json::Value;
+use tokio::time::timeout;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[tokio::test]
+async fn throttling_a_key() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let telemetry = poca.data("telemetry", 0u32);
+    telemetry.max_update_rate(Duration::from_millis(100));
+    poca.start().await.unwrap();
+    let address = poca.local_addr().unwrap();
+
+    let (mut socket, _) = connect_async(format!("ws://{}/ws", address)).await.unwrap();
+    // the snapshot
+    socket.next().await.unwrap().unwrap();
+    for value in 1..=50 {
+        telemetry.set(value);
+    }
+
+    let mut received = Vec::new();
+    while let Ok(Some(Ok(Message::Text(text)))) =
+        timeout(Duration::from_millis(300), socket.next()).await
+    {
+        let message = serde_json::from_str::<Value>(&text).unwrap();
+        if message["key"] == "telemetry" {
+            received.push(message["data"].as_str().unwrap().to_string());
+        }
+    }
+    assert_eq!(received, vec!["1", "50"]);
+}
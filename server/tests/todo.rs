@@ -1,21 +1,19 @@
 #[cfg(test)]
-#[macro_use]
-extern crate lazy_static;
-
 mod tests {
-    use poca::{include_app_dir, DataHandle, Poca};
-
-    lazy_static! {
-        static ref POCA: Poca =
-            Poca::new("localhost:1120", include_app_dir!("tests/empty_assets/"));
-        static ref HANDLE1: DataHandle<i32> = POCA.data("test1", 1);
-    }
+    use poca::{include_app_dir, Poca};
 
     //? not sure if this always works
     #[test]
     fn on_change_handler_with_inner_self_set() {
-        HANDLE1.on_change(|_new_value| {
-            HANDLE1.set(2);
-        })
+        let poca = Poca::new(
+            "localhost:1120",
+            include_app_dir!("tests/empty_assets/"),
+            None,
+        );
+        let handle = poca.data("test1", 1);
+        let inner_handle = handle.clone();
+        handle.on_change(move |_new_value| {
+            inner_handle.set(2);
+        });
     }
 }
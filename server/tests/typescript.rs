@@ -0,0 +1,114 @@
+use std::{path::Path, process::Command};
+
+use poca::{include_app_dir, Poca, CLIENTS_KEY};
+use serde_json::json;
+
+#[test]
+fn typescript_from_schemas() {
+    let poca = Poca::new(
+        "localhost:1135",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let position = poca.data("position", vec![(0, 0)]);
+    position.set_schema(json!({
+        "type": "array",
+        "items": {"$ref": "#/definitions/Point"},
+        "definitions": {
+            "Point": {
+                "type": "object",
+                "required": ["x", "y"],
+                "properties": {
+                    "x": {"type": "integer"},
+                    "y": {"type": "integer"},
+                    "label": {"type": ["string", "null"]}
+                }
+            }
+        }
+    }));
+    poca.data("untyped", 0);
+
+    let typescript = poca.typescript();
+    assert!(typescript.contains("import {Poca} from \"poca-client\";"));
+    assert!(typescript.contains(
+        "export interface Point {\n  label?: string | null;\n  x: number;\n  y: number;\n}"
+    ));
+    assert!(typescript.contains("  position: Point[];\n"));
+    assert!(typescript.contains("  untyped: unknown;\n"));
+    assert!(typescript.contains("poca.reactive<Keys[\"position\"] & Object>(\"position\")"));
+}
+
+// with the compiler installed by `yarn install` in `client/`
+#[test]
+fn type_checking_generated_typescript() {
+    let client = Path::new(env!("CARGO_MANIFEST_DIR")).join("../client");
+    let tsc = client.join("node_modules/.bin/tsc");
+    if !tsc.exists() {
+        eprintln!("Skipped, {} isn't installed", tsc.display());
+        return;
+    }
+    let poca = Poca::new(
+        "localhost:1137",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    poca.data("position", vec![(0, 0)]).set_schema(json!({
+        "type": "array",
+        "items": {"type": "array", "items": {"type": "integer"}}
+    }));
+    poca.data("untyped", 0);
+
+    let directory = std::env::temp_dir().join(format!("poca-typescript-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    poca.write_typescript(directory.join("keys.ts")).unwrap();
+    let config = json!({
+        "compilerOptions": {
+            "target": "es6",
+            "lib": ["ES6", "DOM"],
+            "strict": true,
+            "noEmit": true,
+            "baseUrl": ".",
+            "paths": {"poca-client": [client.join("src/index.ts")]}
+        },
+        "files": ["keys.ts"]
+    });
+    std::fs::write(directory.join("tsconfig.json"), config.to_string()).unwrap();
+    let output = Command::new(tsc)
+        .arg("--project")
+        .arg(&directory)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn schema_of_all_keys() {
+    let poca = Poca::new(
+        "localhost:1136",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    let score = poca.data("score", 0u32);
+    score.set_schema(json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "integer",
+        "minimum": 0,
+        "definitions": {"Unused": {"type": "string"}}
+    }));
+    poca.data("untyped", 0);
+
+    let schema = poca.schema();
+    assert_eq!(schema["type"], "object");
+    assert_eq!(
+        schema["properties"]["score"],
+        json!({"type": "integer", "minimum": 0})
+    );
+    assert_eq!(schema["properties"]["untyped"], json!(true));
+    assert_eq!(schema["required"], json!([CLIENTS_KEY, "score", "untyped"]));
+    assert_eq!(schema["definitions"]["Unused"], json!({"type": "string"}));
+}
@@ -0,0 +1,20 @@
+#![cfg(all(unix, feature = "unix"))]
+
+use poca::{include_app_dir, Poca};
+
+#[tokio::test]
+async fn unix_socket_listener() {
+    let path = std::env::temp_dir().join("poca-unix-socket-test.sock");
+    let poca = Poca::new(
+        "localhost:1134",
+        include_app_dir!("tests/empty_assets/"),
+        None,
+    );
+    poca.start_unix(&path).await.unwrap();
+    assert!(tokio::net::UnixStream::connect(&path).await.is_ok());
+    // not listening on the configured address
+    assert_eq!(poca.local_addr(), None);
+
+    poca.shutdown(1001, "Done").await;
+    assert!(!path.exists());
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use futures_util::SinkExt;
+use poca::Poca;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+mod common;
+
+use common::next_reply;
+
+#[tokio::test]
+async fn rejecting_values_of_another_type() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1u32);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    for data in [r#""five""#, "-1", "{", ""] {
+        let set = serde_json::json!({"message_type": 1, "key": "counter", "data": data});
+        socket.send(Message::Text(set.to_string())).await.unwrap();
+        let reply = next_reply(&mut socket).await;
+        assert_eq!(reply["message_type"], 4);
+        assert_eq!(reply["key"], "counter");
+        assert!(reply["data"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid value for key counter of type u32"));
+    }
+
+    let set = r#"{"message_type":1,"key":null,"data":"2"}"#;
+    socket.send(Message::Text(set.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 4);
+    assert_eq!(reply["data"], "Message is missing a key");
+
+    // the connection survives and valid writes still apply
+    let set = r#"{"message_type":1,"key":"counter","data":"2"}"#;
+    socket.send(Message::Text(set.to_string())).await.unwrap();
+    for _ in 0..100 {
+        if counter.get() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(counter.get(), 2);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn subscribing_without_a_key() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1u32);
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    for message_type in [5, 6] {
+        let frame = serde_json::json!({"message_type": message_type, "key": null, "data": null});
+        socket.send(Message::Text(frame.to_string())).await.unwrap();
+        let reply = next_reply(&mut socket).await;
+        assert_eq!(reply["message_type"], 4);
+        assert_eq!(reply["data"], "Message is missing a key");
+    }
+
+    // the connection survives
+    counter.set(2);
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["key"], "counter");
+    poca.stop();
+}
+
+#[tokio::test]
+async fn validation_hooks() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let name = poca.data("name", "poca".to_string());
+    name.validate(|name| match name.trim().is_empty() {
+        true => Err("must not be empty".to_string()),
+        false => Ok(()),
+    });
+    let level = poca.counter("level", 9);
+    level.validate(|level| match level {
+        0..=10 => Ok(()),
+        _ => Err("out of range".to_string()),
+    });
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    let set = r#"{"message_type":1,"key":"name","data":"\" \""}"#;
+    socket.send(Message::Text(set.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 4);
+    assert_eq!(
+        reply["data"],
+        "Rejected value for key name: must not be empty"
+    );
+
+    let increment = r#"{"message_type":10,"key":"level","data":"2"}"#;
+    socket
+        .send(Message::Text(increment.to_string()))
+        .await
+        .unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["data"], "Rejected value for key level: out of range");
+    assert_eq!(name.get(), "poca");
+    assert_eq!(level.get(), 9);
+
+    // only client writes are validated
+    name.set(String::new());
+    assert_eq!(name.get(), "");
+    poca.stop();
+}
+
+#[tokio::test]
+async fn transforms_before_validation() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let name = poca.data("name", "poca".to_string());
+    name.transform(|name| name.trim().to_string());
+    name.validate(|name| match name.is_empty() {
+        true => Err("must not be empty".to_string()),
+        false => Ok(()),
+    });
+    poca.start().await.unwrap();
+    let url = format!("ws://{}/ws", poca.local_addr().unwrap());
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    let set = r#"{"message_type":1,"key":"name","data":"\"  \""}"#;
+    socket.send(Message::Text(set.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(
+        reply["data"],
+        "Rejected value for key name: must not be empty"
+    );
+
+    // the sender is told about the transformed value
+    let set = r#"{"message_type":1,"key":"name","data":"\" tero \""}"#;
+    socket.send(Message::Text(set.to_string())).await.unwrap();
+    let reply = next_reply(&mut socket).await;
+    assert_eq!(reply["message_type"], 1);
+    assert_eq!(reply["data"], r#""tero""#);
+    assert_eq!(name.get(), "tero");
+    poca.stop();
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "webtransport")]
+
+use std::{net::SocketAddr, time::Duration};
+
+use poca::{Poca, TlsConfig};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use wtransport::{ClientConfig, Endpoint, RecvStream, SendStream};
+
+// self-signed, which the client doesn't validate
+fn tls_config() -> TlsConfig {
+    TlsConfig::from_pem(
+        &include_bytes!("certs/localhost.pem")[..],
+        &include_bytes!("certs/localhost.key")[..],
+    )
+}
+
+async fn session(address: SocketAddr) -> (SendStream, RecvStream) {
+    let config = ClientConfig::builder()
+        .with_bind_default()
+        .with_no_cert_validation()
+        .build();
+    let connection = Endpoint::client(config)
+        .unwrap()
+        .connect(format!("https://127.0.0.1:{}/", address.port()))
+        .await
+        .unwrap();
+    connection.open_bi().await.unwrap().await.unwrap()
+}
+
+#[tokio::test]
+async fn exchanging_messages_over_webtransport() {
+    let poca = Poca::builder().address("localhost:0").build();
+    let counter = poca.data("counter", 1);
+    poca.start().await.unwrap();
+    let address = poca
+        .start_webtransport("127.0.0.1:0", tls_config())
+        .await
+        .unwrap();
+
+    let (mut send, receive) = session(address).await;
+    // the server only learns of the stream once something is sent on it
+    send.write_all(b"{\"message_type\":1,\"key\":\"counter\",\"data\":\"5\"}\n")
+        .await
+        .unwrap();
+    let mut lines = BufReader::new(receive).lines();
+    let snapshot: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+    assert_eq!(snapshot["message_type"], 7);
+    for _ in 0..100 {
+        if counter.get() == 5 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(counter.get(), 5);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn ending_sessions_with_too_long_lines() {
+    let poca = Poca::builder()
+        .address("localhost:0")
+        .max_message_size(1024)
+        .build();
+    poca.start().await.unwrap();
+    let address = poca
+        .start_webtransport("127.0.0.1:0", tls_config())
+        .await
+        .unwrap();
+
+    let (mut send, receive) = session(address).await;
+    // without a newline, which the server never waits for
+    send.write_all(&[b' '; 64 * 1024]).await.unwrap();
+    let mut lines = BufReader::new(receive).lines();
+    // the snapshot, then the end of the session
+    while let Ok(Some(_)) = lines.next_line().await {}
+    for _ in 0..100 {
+        if poca.clients().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(poca.clients().is_empty());
+    poca.stop();
+}
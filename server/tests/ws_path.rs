@@ -0,0 +1,59 @@
+use poca::{include_app_dir, Poca};
+use warp::http::StatusCode;
+
+#[tokio::test]
+async fn upgrades_only_on_ws_by_default() {
+    let poca = Poca::builder()
+        .app_routes(include_app_dir!("tests/empty_assets/"))
+        .build();
+    poca.create_store("chat").unwrap();
+    let filter = poca.filter().unwrap();
+
+    assert!(warp::test::ws()
+        .path("/ws")
+        .handshake(filter.clone())
+        .await
+        .is_ok());
+    assert!(warp::test::ws()
+        .path("/ws/chat")
+        .handshake(filter.clone())
+        .await
+        .is_ok());
+    assert!(warp::test::ws()
+        .path("/")
+        .handshake(filter.clone())
+        .await
+        .is_err());
+    assert!(warp::test::ws()
+        .path("/wsx")
+        .handshake(filter.clone())
+        .await
+        .is_err());
+
+    let response = warp::test::request().path("/").reply(&filter).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = warp::test::request().path("/missing").reply(&filter).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    poca.stop();
+}
+
+#[tokio::test]
+async fn upgrades_on_any_path_with_the_root_path() {
+    let poca = Poca::builder()
+        .app_routes(include_app_dir!("tests/empty_assets/"))
+        .ws_path("/")
+        .build();
+    let filter = poca.filter().unwrap();
+
+    assert!(warp::test::ws()
+        .path("/")
+        .handshake(filter.clone())
+        .await
+        .is_ok());
+    assert!(warp::test::ws()
+        .path("/ws")
+        .handshake(filter.clone())
+        .await
+        .is_ok());
+    poca.stop();
+}